@@ -0,0 +1,230 @@
+//! Attribution of the permission changes causing auto prunes to the moderator who made them,
+//! through the guild's audit log, enabled through `/settings attribute-changes`.
+//!
+//! The audit log is fetched at most once per guild per [`INTERVAL`], prunes in the meantime
+//! waiting for and sharing the next fetch.
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex, PoisonError},
+	time::{Duration, Instant},
+};
+
+use twilight_model::{
+	guild::{audit_log::AuditLogEventType, Permissions},
+	id::{
+		marker::{GenericMarker, GuildMarker, UserMarker},
+		Id,
+	},
+};
+
+use crate::{cleanup::Cleanup, clock, BOT};
+
+/// Minimum time between audit log fetches of a guild.
+pub const INTERVAL: Duration = Duration::from_secs(30);
+
+/// Age of the newest entries that are considered.
+const RECENT: Duration = Duration::from_secs(60);
+
+/// Number of entries fetched.
+const LIMIT: u16 = 50;
+
+/// Discord's epoch, the first second of 2015, in milliseconds since the Unix epoch.
+const DISCORD_EPOCH: u64 = 1_420_070_400_000;
+
+/// Recent audit log entry.
+#[derive(Clone, Debug)]
+pub struct Entry {
+	pub kind: AuditLogEventType,
+	pub target: Id<GenericMarker>,
+	pub moderator: Id<UserMarker>,
+	/// Name of the moderator.
+	pub name: String,
+}
+
+/// Audit log fetch of a guild.
+#[derive(Debug)]
+struct Fetch {
+	at: Instant,
+	entries: Arc<[Entry]>,
+}
+
+/// Last fetch of a guild, locked while fetching.
+type Slot = Arc<tokio::sync::Mutex<Option<Fetch>>>;
+
+/// Last audit log fetch of every guild.
+#[derive(Debug, Default)]
+pub struct Attributions(Mutex<HashMap<Id<GuildMarker>, Slot>>);
+
+impl Attributions {
+	/// Recent entries of the guild's audit log fetched after `since`, `None` if unavailable.
+	pub async fn entries(&self, guild: Id<GuildMarker>, since: Instant) -> Option<Arc<[Entry]>> {
+		let slot = Arc::clone(
+			self.0
+				.lock()
+				.unwrap_or_else(PoisonError::into_inner)
+				.entry(guild)
+				.or_default(),
+		);
+		let mut last = slot.lock().await;
+		if let Some(fetch) = last.as_ref() {
+			if fetch.at >= since {
+				return Some(Arc::clone(&fetch.entries));
+			}
+			tokio::time::sleep_until((fetch.at + INTERVAL).into()).await;
+		}
+
+		let entries = fetch(guild).await;
+		*last = Some(Fetch {
+			at: Instant::now(),
+			entries: entries.clone().unwrap_or_else(|| Arc::new([])),
+		});
+		entries
+	}
+}
+
+impl Cleanup for Attributions {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&guild);
+	}
+}
+
+/// Fetches the guild's recent audit log entries, `None` if the bot may not
+/// view the audit log or the request failed.
+async fn fetch(guild: Id<GuildMarker>) -> Option<Arc<[Entry]>> {
+	let permitted = BOT
+		.cache
+		.permissions()
+		.root(BOT.id, guild)
+		.is_ok_and(|permissions| permissions.contains(Permissions::VIEW_AUDIT_LOG));
+	if !permitted {
+		tracing::debug!(guild_id = %guild, "unable to attribute changes: missing permissions");
+		return None;
+	}
+
+	let log = match BOT.http.audit_log(guild).limit(LIMIT).await {
+		Ok(response) => match response.model().await {
+			Ok(log) => log,
+			Err(e) => {
+				tracing::warn!(guild_id = %guild, error = &e as &dyn std::error::Error, "unable to fetch audit log");
+				return None;
+			}
+		},
+		Err(e) => {
+			tracing::warn!(guild_id = %guild, error = &e as &dyn std::error::Error, "unable to fetch audit log");
+			return None;
+		}
+	};
+
+	let now = clock::now();
+	let entries = log
+		.entries
+		.into_iter()
+		.filter(|entry| {
+			let created = Duration::from_millis((entry.id.get() >> 22) + DISCORD_EPOCH);
+			now.saturating_sub(created) <= RECENT
+		})
+		.filter_map(|entry| {
+			let moderator = entry.user_id?;
+			let name = log
+				.users
+				.iter()
+				.find(|user| user.id == moderator)
+				.map_or_else(|| moderator.to_string(), |user| user.name.clone());
+			Some(Entry {
+				kind: entry.action_type,
+				target: entry.target_id?,
+				moderator,
+				name,
+			})
+		})
+		.collect();
+	Some(entries)
+}
+
+/// Moderator of the entries of the kinds targeting the ID, `None` if there are none or they were
+/// made by several moderators.
+pub fn moderator<'a>(
+	entries: &'a [Entry],
+	kinds: &[AuditLogEventType],
+	target: Id<GenericMarker>,
+) -> Option<&'a Entry> {
+	let mut matching = entries
+		.iter()
+		.filter(|entry| entry.target == target && kinds.contains(&entry.kind));
+	let first = matching.next()?;
+	matching
+		.all(|entry| entry.moderator == first.moderator)
+		.then_some(first)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Instant;
+
+	use twilight_model::{guild::audit_log::AuditLogEventType, id::Id};
+
+	use super::{Entry, INTERVAL};
+	use crate::{test::Guild, BOT};
+
+	fn entry(kind: AuditLogEventType, target: u64, moderator: u64) -> Entry {
+		Entry {
+			kind,
+			target: Id::new(target),
+			moderator: Id::new(moderator),
+			name: format!("moderator {moderator}"),
+		}
+	}
+
+	#[test]
+	fn matches_kind_and_target() {
+		let entries = [
+			entry(AuditLogEventType::RoleUpdate, 5, 100),
+			entry(AuditLogEventType::ChannelOverwriteUpdate, 6, 101),
+			entry(AuditLogEventType::MemberRoleUpdate, 5, 102),
+		];
+		let kinds = [
+			AuditLogEventType::ChannelOverwriteCreate,
+			AuditLogEventType::ChannelOverwriteUpdate,
+		];
+
+		let moderator = super::moderator(&entries, &kinds, Id::new(6));
+		assert_eq!(moderator.map(|entry| entry.moderator), Some(Id::new(101)));
+		let moderator = super::moderator(&entries, &[AuditLogEventType::RoleUpdate], Id::new(5));
+		assert_eq!(moderator.map(|entry| entry.moderator), Some(Id::new(100)));
+		assert!(super::moderator(&entries, &kinds, Id::new(5)).is_none());
+	}
+
+	#[test]
+	fn several_moderators_are_not_confident() {
+		let kinds = [AuditLogEventType::RoleUpdate];
+		let mut entries = vec![
+			entry(AuditLogEventType::RoleUpdate, 5, 100),
+			entry(AuditLogEventType::RoleUpdate, 5, 100),
+		];
+		assert!(super::moderator(&entries, &kinds, Id::new(5)).is_some());
+
+		entries.push(entry(AuditLogEventType::RoleUpdate, 5, 101));
+		assert!(super::moderator(&entries, &kinds, Id::new(5)).is_none());
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn fetches_at_most_once_per_interval() {
+		// the bot may not view the audit log, so nothing is requested
+		let guild = Guild::new(3_830_000).create();
+		let start = tokio::time::Instant::now();
+
+		let before = Instant::now();
+		assert!(BOT.attributions.entries(guild, before).await.is_none());
+		// shared by prunes since
+		let shared = BOT.attributions.entries(guild, before).await;
+		assert!(shared.is_some_and(|entries| entries.is_empty()));
+		assert!(start.elapsed() < INTERVAL);
+
+		BOT.attributions.entries(guild, Instant::now()).await;
+		assert!(start.elapsed() >= INTERVAL);
+	}
+}
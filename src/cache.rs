@@ -0,0 +1,333 @@
+//! Cache models trimmed to the fields the bot reads, as members and their users make up most of
+//! the cache in large guilds.
+//!
+//! Every other resource uses twilight's default model.
+
+use twilight_cache_inmemory::{
+	model::{
+		CachedEmoji, CachedGuild, CachedMessage, CachedPresence, CachedSticker, CachedVoiceState,
+		ComputedInteractionMember,
+	},
+	CacheableMember, CacheableModels, CacheableUser, InMemoryCache,
+};
+use twilight_model::{
+	application::interaction::InteractionMember,
+	channel::{Channel, StageInstance},
+	gateway::payload::incoming::MemberUpdate,
+	guild::{GuildIntegration, Member as FullMember, PartialMember, Role},
+	id::{
+		marker::{GuildMarker, RoleMarker, UserMarker},
+		Id,
+	},
+	user::{CurrentUser, User as FullUser},
+	util::{ImageHash, Timestamp},
+};
+
+/// The bot's cache.
+pub type Cache = InMemoryCache<Models>;
+
+/// Models of [`Cache`].
+#[derive(Clone, Debug)]
+pub struct Models;
+
+impl CacheableModels for Models {
+	type Channel = Channel;
+	type CurrentUser = CurrentUser;
+	type Emoji = CachedEmoji;
+	type Guild = CachedGuild;
+	type GuildIntegration = GuildIntegration;
+	type Member = Member;
+	type Message = CachedMessage;
+	type Presence = CachedPresence;
+	type Role = Role;
+	type StageInstance = StageInstance;
+	type Sticker = CachedSticker;
+	type User = User;
+	type VoiceState = CachedVoiceState;
+}
+
+/// Cached member, without e.g. avatars, flags and join or boost dates.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Member {
+	communication_disabled_until: Option<Timestamp>,
+	nick: Option<String>,
+	pending: bool,
+	roles: Box<[Id<RoleMarker>]>,
+	user_id: Id<UserMarker>,
+}
+
+impl Member {
+	/// When the member's timeout ends, which may be in the past.
+	pub const fn communication_disabled_until(&self) -> Option<Timestamp> {
+		self.communication_disabled_until
+	}
+
+	pub fn nick(&self) -> Option<&str> {
+		self.nick.as_deref()
+	}
+
+	/// Whether the member hasn't completed membership screening.
+	pub const fn pending(&self) -> bool {
+		self.pending
+	}
+
+	pub fn roles(&self) -> &[Id<RoleMarker>] {
+		&self.roles
+	}
+}
+
+impl From<FullMember> for Member {
+	fn from(member: FullMember) -> Self {
+		Self {
+			communication_disabled_until: member.communication_disabled_until,
+			nick: member.nick,
+			pending: member.pending,
+			roles: member.roles.into(),
+			user_id: member.user.id,
+		}
+	}
+}
+
+impl From<ComputedInteractionMember> for Member {
+	fn from(member: ComputedInteractionMember) -> Self {
+		let user_id = member.user_id;
+		let member = member.interaction_member;
+		Self {
+			communication_disabled_until: member.communication_disabled_until,
+			nick: member.nick,
+			pending: member.pending,
+			roles: member.roles.into(),
+			user_id,
+		}
+	}
+}
+
+impl From<(Id<UserMarker>, PartialMember)> for Member {
+	fn from((user_id, member): (Id<UserMarker>, PartialMember)) -> Self {
+		Self {
+			communication_disabled_until: member.communication_disabled_until,
+			nick: member.nick,
+			pending: false,
+			roles: member.roles.into(),
+			user_id: member.user.map_or(user_id, |user| user.id),
+		}
+	}
+}
+
+impl PartialEq<FullMember> for Member {
+	fn eq(&self, other: &FullMember) -> bool {
+		self.communication_disabled_until == other.communication_disabled_until
+			&& self.nick == other.nick
+			&& self.pending == other.pending
+			&& *self.roles == other.roles
+			&& self.user_id == other.user.id
+	}
+}
+
+impl PartialEq<PartialMember> for Member {
+	fn eq(&self, other: &PartialMember) -> bool {
+		self.communication_disabled_until == other.communication_disabled_until
+			&& self.nick == other.nick
+			&& *self.roles == other.roles
+	}
+}
+
+impl PartialEq<InteractionMember> for Member {
+	fn eq(&self, other: &InteractionMember) -> bool {
+		self.nick == other.nick && *self.roles == other.roles
+	}
+}
+
+impl CacheableMember for Member {
+	fn roles(&self) -> &[Id<RoleMarker>] {
+		&self.roles
+	}
+
+	fn communication_disabled_until(&self) -> Option<Timestamp> {
+		self.communication_disabled_until
+	}
+
+	fn avatar(&self) -> Option<ImageHash> {
+		None
+	}
+
+	fn deaf(&self) -> Option<bool> {
+		None
+	}
+
+	fn mute(&self) -> Option<bool> {
+		None
+	}
+
+	fn update_with_member_update(&mut self, update: &MemberUpdate) {
+		self.communication_disabled_until = update.communication_disabled_until;
+		self.nick = update.nick.clone();
+		self.pending = update.pending;
+		self.roles = update.roles.as_slice().into();
+	}
+}
+
+/// Cached user, only their names and whether they are a bot.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct User {
+	pub bot: bool,
+	pub global_name: Option<String>,
+	pub id: Id<UserMarker>,
+	pub name: String,
+}
+
+impl From<FullUser> for User {
+	fn from(user: FullUser) -> Self {
+		Self {
+			bot: user.bot,
+			global_name: user.global_name,
+			id: user.id,
+			name: user.name,
+		}
+	}
+}
+
+impl PartialEq<FullUser> for User {
+	fn eq(&self, other: &FullUser) -> bool {
+		self.bot == other.bot
+			&& self.global_name == other.global_name
+			&& self.id == other.id
+			&& self.name == other.name
+	}
+}
+
+impl CacheableUser for User {}
+
+/// Removes the deleted role from the guild's cached members, as Discord sends no member updates
+/// for it.
+pub fn remove_role(cache: &Cache, guild: Id<GuildMarker>, role: Id<RoleMarker>) {
+	let Some(members) = cache.guild_members(guild) else {
+		return;
+	};
+	let members: Vec<_> = members.iter().copied().collect();
+	for user in members {
+		let update = cache
+			.member(guild, user)
+			.filter(|member| member.roles.contains(&role))
+			.map(|member| MemberUpdate {
+				avatar: None,
+				communication_disabled_until: member.communication_disabled_until,
+				guild_id: guild,
+				deaf: None,
+				joined_at: None,
+				mute: None,
+				nick: member.nick.clone(),
+				pending: member.pending,
+				premium_since: None,
+				roles: member
+					.roles
+					.iter()
+					.copied()
+					.filter(|&id| id != role)
+					.collect(),
+				// only the member is updated
+				user: FullUser {
+					accent_color: None,
+					avatar: None,
+					avatar_decoration: None,
+					banner: None,
+					bot: false,
+					discriminator: 0,
+					email: None,
+					flags: None,
+					global_name: None,
+					id: user,
+					locale: None,
+					mfa_enabled: None,
+					name: String::new(),
+					premium_type: None,
+					public_flags: None,
+					system: None,
+					verified: None,
+				},
+			});
+		if let Some(update) = update {
+			cache.update(&update);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use twilight_model::guild::Permissions;
+
+	use super::*;
+	use crate::test::Guild;
+
+	#[test]
+	fn keeps_read_fields() {
+		const GUILD: u64 = 3_990_000;
+		let guild = Guild::new(GUILD)
+			.role(GUILD + 2, Permissions::empty(), 1)
+			.member(GUILD + 100, &[GUILD + 2])
+			.nick("nick")
+			.pending()
+			.create();
+
+		let member = crate::BOT
+			.cache
+			.member(guild, Id::new(GUILD + 100))
+			.unwrap();
+		assert_eq!(member.nick(), Some("nick"));
+		assert!(member.pending());
+		assert_eq!(member.roles(), [Id::new(GUILD + 2)]);
+		let user = crate::BOT.cache.user(Id::new(GUILD + 100)).unwrap();
+		assert_eq!(user.name, format!("user {}", GUILD + 100));
+		assert!(!user.bot);
+	}
+
+	/// Heap usage of the trimmed models, only built with the `heap` feature.
+	#[cfg(feature = "heap")]
+	mod heap {
+		use std::hint::black_box;
+
+		use twilight_cache_inmemory::DefaultCacheModels;
+		use twilight_model::{gateway::event::Event, guild::Permissions};
+
+		use super::super::*;
+		use crate::{
+			test::{heap::allocated, Guild},
+			RESOURCES,
+		};
+
+		/// Heap bytes of a cache of the models holding the event.
+		fn footprint<M: CacheableModels>(event: &Event) -> usize {
+			let before = allocated();
+			let cache = InMemoryCache::<M>::builder()
+				.resource_types(RESOURCES)
+				.build();
+			cache.update(event);
+			let size = allocated() - before;
+			drop(black_box(cache));
+			size
+		}
+
+		/// Run alone, as the process-wide heap usage is measured, see [`crate::test::heap`].
+		#[test]
+		fn large_guild_footprint() {
+			const GUILD: u64 = 3_991_000;
+			const MEMBERS: u64 = 50_000;
+			let mut guild = Guild::new(GUILD)
+				.role(GUILD + 2, Permissions::empty(), 1)
+				.role(GUILD + 3, Permissions::empty(), 2);
+			for member in 0..MEMBERS {
+				let user = 1_000_000_000 + member;
+				guild = match member % 3 {
+					0 => guild.member(user, &[]),
+					1 => guild.member(user, &[GUILD + 2]),
+					_ => guild.member(user, &[GUILD + 2, GUILD + 3]).nick("nickname"),
+				};
+			}
+			let event = guild.event();
+
+			let trimmed = footprint::<Models>(&event);
+			let full = footprint::<DefaultCacheModels>(&event);
+			assert!(trimmed < full);
+		}
+	}
+}
@@ -0,0 +1,421 @@
+//! Permission changes that caused an auto prune, captured from events before updating the cache
+//! and logged for every pruned user, e.g. "lost CONNECT via role @Guests (CONNECT removed)".
+
+use std::{collections::HashMap, time::Instant};
+
+use twilight_model::{
+	channel::{
+		permission_overwrite::{PermissionOverwrite, PermissionOverwriteType},
+		Channel,
+	},
+	guild::{audit_log::AuditLogEventType, Permissions},
+	id::{
+		marker::{ChannelMarker, GenericMarker, GuildMarker, RoleMarker, UserMarker},
+		Id,
+	},
+};
+
+use crate::{attribution, prune::Summary, BOT};
+
+/// Permissions whose loss prevents connecting.
+const RELEVANT: Permissions = Permissions::CONNECT.union(Permissions::VIEW_CHANNEL);
+
+/// Change of a permission overwrite, `None` sides being absent.
+#[derive(Clone, Debug)]
+pub struct Change {
+	old: Option<PermissionOverwrite>,
+	new: Option<PermissionOverwrite>,
+}
+
+impl Change {
+	/// Target of the overwrite.
+	fn overwrite(&self) -> &PermissionOverwrite {
+		self.new
+			.as_ref()
+			.or(self.old.as_ref())
+			.expect("either side is present")
+	}
+
+	/// Relevant permissions no longer allowed, and denied, by the overwrite.
+	fn lost(&self) -> (Permissions, Permissions) {
+		let sides = |overwrite: &Option<PermissionOverwrite>| {
+			overwrite
+				.as_ref()
+				.map_or((Permissions::empty(), Permissions::empty()), |overwrite| {
+					(overwrite.allow, overwrite.deny)
+				})
+		};
+		let (old_allow, old_deny) = sides(&self.old);
+		let (new_allow, new_deny) = sides(&self.new);
+		let denied = new_deny - old_deny;
+		(
+			((old_allow - new_allow) - denied) & RELEVANT,
+			denied & RELEVANT,
+		)
+	}
+}
+
+/// Changes between the cached and new channel's permission overwrites.
+pub fn changes(cached: &Channel, new: &Channel) -> Vec<Change> {
+	let cached = cached.permission_overwrites.as_deref().unwrap_or_default();
+	let new = new.permission_overwrites.as_deref().unwrap_or_default();
+	let find = |overwrites: &[PermissionOverwrite], id| {
+		overwrites
+			.iter()
+			.find(|overwrite| overwrite.id == id)
+			.cloned()
+	};
+
+	let mut changes: Vec<_> = cached
+		.iter()
+		.chain(new)
+		.filter(|overwrite| !cached.contains(overwrite) || !new.contains(overwrite))
+		.map(|overwrite| Change {
+			old: find(cached, overwrite.id),
+			new: find(new, overwrite.id),
+		})
+		.collect();
+	changes.sort_unstable_by_key(|change| change.overwrite().id);
+	changes.dedup_by_key(|change| change.overwrite().id);
+	changes
+}
+
+/// Merges later changes of a channel into the buffered ones, keeping the oldest side.
+pub fn merge(buffered: &mut Vec<Change>, changes: Vec<Change>) {
+	for change in changes {
+		let id = change.overwrite().id;
+		match buffered
+			.iter_mut()
+			.find(|buffered| buffered.overwrite().id == id)
+		{
+			Some(buffered) => buffered.new = change.new,
+			None => buffered.push(change),
+		}
+	}
+}
+
+/// Cause of an auto prune.
+#[derive(Debug)]
+pub enum Cause {
+	/// Permission overwrites of the channels changed.
+	Overwrites(HashMap<Id<ChannelMarker>, Vec<Change>>),
+	/// A role's permissions changed.
+	Role {
+		role: Id<RoleMarker>,
+		old: Permissions,
+		new: Permissions,
+	},
+	/// A member's roles changed.
+	Roles {
+		added: Vec<Id<RoleMarker>>,
+		removed: Vec<Id<RoleMarker>>,
+	},
+}
+
+impl Cause {
+	/// Logs why each pruned user lost access, if known.
+	///
+	/// If the guild attributes changes, the moderator who made the change after `since` is
+	/// included when the audit log confidently names one.
+	pub async fn log(&self, guild: Id<GuildMarker>, summary: &Summary, since: Instant) {
+		if summary.report {
+			return;
+		}
+		let causes: Vec<_> = summary
+			.channels
+			.iter()
+			.flat_map(|prune| prune.users.iter().map(move |&user| (prune.channel, user)))
+			.filter_map(|(channel, user)| {
+				let cause = self.describe(guild, channel, user)?;
+				Some((channel, user, cause))
+			})
+			.collect();
+		if causes.is_empty() {
+			return;
+		}
+
+		let entries = if BOT.settings.get(guild).attribute_changes {
+			BOT.attributions.entries(guild, since).await
+		} else {
+			None
+		};
+		for (channel, user, mut cause) in causes {
+			let (kinds, target) = self.target(channel, user);
+			if let Some(entry) = entries
+				.as_deref()
+				.and_then(|entries| attribution::moderator(entries, kinds, target))
+			{
+				cause.push_str(&format!(", changed by @{}", entry.name));
+			}
+			tracing::info!(guild_id = %guild, channel_id = %channel, user_id = %user, cause, "pruned");
+		}
+	}
+
+	/// Audit log entry kinds and target recording the change.
+	fn target(
+		&self,
+		channel: Id<ChannelMarker>,
+		user: Id<UserMarker>,
+	) -> (&'static [AuditLogEventType], Id<GenericMarker>) {
+		match *self {
+			Self::Overwrites(_) => (
+				&[
+					AuditLogEventType::ChannelOverwriteCreate,
+					AuditLogEventType::ChannelOverwriteUpdate,
+					AuditLogEventType::ChannelOverwriteDelete,
+				],
+				channel.cast(),
+			),
+			Self::Role { role, .. } => (&[AuditLogEventType::RoleUpdate], role.cast()),
+			Self::Roles { .. } => (&[AuditLogEventType::MemberRoleUpdate], user.cast()),
+		}
+	}
+
+	/// Why the user lost access to the channel, `None` if unrelated to the cause.
+	fn describe(
+		&self,
+		guild: Id<GuildMarker>,
+		channel: Id<ChannelMarker>,
+		user: Id<UserMarker>,
+	) -> Option<String> {
+		match self {
+			Self::Overwrites(channels) => {
+				let roles = BOT
+					.cache
+					.member(guild, user)
+					.map(|member| member.roles().to_vec())
+					.unwrap_or_default();
+				let lines: Vec<_> = channels
+					.get(&channel)?
+					.iter()
+					.filter(|change| {
+						let id = change.overwrite().id;
+						id == guild.cast() || id == user.cast() || roles.contains(&id.cast())
+					})
+					.filter_map(|change| {
+						let (unallowed, denied) = change.lost();
+						let details: Vec<_> =
+							[(denied, "denied"), (unallowed, "no longer allowed")]
+								.into_iter()
+								.filter(|(permissions, _)| !permissions.is_empty())
+								.map(|(permissions, what)| format!("{} {what}", names(permissions)))
+								.collect();
+						(!details.is_empty()).then(|| {
+							format!(
+								"lost {} via {} overwrite ({})",
+								names(unallowed | denied),
+								target(guild, change.overwrite()),
+								details.join(", ")
+							)
+						})
+					})
+					.collect();
+				(!lines.is_empty()).then(|| lines.join("; "))
+			}
+			&Self::Role { role, old, new } => {
+				let removed = (old - new) & RELEVANT;
+				let has_role = role == guild.cast()
+					|| BOT
+						.cache
+						.member(guild, user)
+						.is_some_and(|member| member.roles().contains(&role));
+				(has_role && !removed.is_empty()).then(|| {
+					format!(
+						"lost {} via role {} ({} removed)",
+						names(removed),
+						role_name(guild, role),
+						names(removed)
+					)
+				})
+			}
+			Self::Roles { added, removed } => {
+				let list = |roles: &[Id<RoleMarker>]| {
+					roles
+						.iter()
+						.map(|&role| role_name(guild, role))
+						.collect::<Vec<_>>()
+						.join(", ")
+				};
+				let details: Vec<_> = [(removed, "removed"), (added, "added")]
+					.into_iter()
+					.filter(|(roles, _)| !roles.is_empty())
+					.map(|(roles, what)| format!("{what} {}", list(roles)))
+					.collect();
+				(!details.is_empty())
+					.then(|| format!("lost CONNECT via roles ({})", details.join(", ")))
+			}
+		}
+	}
+}
+
+/// Names of the relevant permissions.
+fn names(permissions: Permissions) -> String {
+	[
+		(Permissions::CONNECT, "CONNECT"),
+		(Permissions::VIEW_CHANNEL, "VIEW_CHANNEL"),
+	]
+	.into_iter()
+	.filter(|&(permission, _)| permissions.contains(permission))
+	.map(|(_, name)| name)
+	.collect::<Vec<_>>()
+	.join(" and ")
+}
+
+/// Target of the overwrite, e.g. "role @Guests".
+fn target(guild: Id<GuildMarker>, overwrite: &PermissionOverwrite) -> String {
+	match overwrite.kind {
+		PermissionOverwriteType::Member => "member".to_owned(),
+		_ => format!("role {}", role_name(guild, overwrite.id.cast())),
+	}
+}
+
+/// Cached name of the role, its ID if not cached.
+fn role_name(guild: Id<GuildMarker>, role: Id<RoleMarker>) -> String {
+	if role == guild.cast() {
+		return "@everyone".to_owned();
+	}
+	BOT.cache
+		.role(role)
+		.map_or_else(|| format!("@{role}"), |role| format!("@{}", role.name))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use twilight_model::{
+		channel::{Channel, ChannelType},
+		guild::Permissions,
+		id::{marker::GuildMarker, Id},
+	};
+
+	use super::Cause;
+	use crate::test::{member_overwrite, role_overwrite, Guild};
+
+	const GUILD: u64 = 3_820_000;
+
+	/// Guild whose member `GUILD + 100` has the role `GUILD + 2`.
+	fn guild() -> Id<GuildMarker> {
+		Guild::new(GUILD)
+			.role(GUILD + 2, Permissions::CONNECT, 1)
+			.role(GUILD + 3, Permissions::CONNECT, 2)
+			.voice(GUILD + 10, &[])
+			.member(GUILD + 100, &[GUILD + 2])
+			.create()
+	}
+
+	/// Voice channel `GUILD + 10` with the overwrites.
+	fn channel(overwrites: &[serde_json::Value]) -> Channel {
+		serde_json::from_value(crate::test::channel_payload(
+			GUILD,
+			GUILD + 10,
+			ChannelType::GuildVoice,
+			None,
+			overwrites,
+		))
+		.expect("valid channel")
+	}
+
+	/// Why the user lost access to the voice channel `GUILD + 10`.
+	fn describe(cause: &Cause, user: u64) -> Option<String> {
+		cause.describe(guild(), Id::new(GUILD + 10), Id::new(user))
+	}
+
+	#[test]
+	fn describes_overwrite_changes() {
+		let cached = channel(&[role_overwrite(
+			GUILD + 2,
+			Permissions::CONNECT,
+			Permissions::empty(),
+		)]);
+		let new = channel(&[
+			role_overwrite(GUILD + 2, Permissions::empty(), Permissions::empty()),
+			member_overwrite(GUILD + 100, Permissions::empty(), Permissions::VIEW_CHANNEL),
+			role_overwrite(GUILD + 3, Permissions::empty(), Permissions::CONNECT),
+		]);
+		let cause = Cause::Overwrites(HashMap::from([(
+			Id::new(GUILD + 10),
+			super::changes(&cached, &new),
+		)]));
+
+		assert_eq!(
+			describe(&cause, GUILD + 100).as_deref(),
+			Some(
+				"lost CONNECT via role @role 3820002 overwrite (CONNECT no longer allowed); lost \
+				 VIEW_CHANNEL via member overwrite (VIEW_CHANNEL denied)"
+			)
+		);
+		// the role `GUILD + 3` isn't the member's
+		assert_eq!(describe(&cause, GUILD + 101), None);
+	}
+
+	#[test]
+	fn describes_role_change() {
+		let cause = Cause::Role {
+			role: Id::new(GUILD + 2),
+			old: Permissions::CONNECT | Permissions::SPEAK,
+			new: Permissions::SPEAK,
+		};
+
+		assert_eq!(
+			describe(&cause, GUILD + 100).as_deref(),
+			Some("lost CONNECT via role @role 3820002 (CONNECT removed)")
+		);
+		assert_eq!(describe(&cause, GUILD + 101), None, "without the role");
+
+		let everyone = Cause::Role {
+			role: Id::new(GUILD),
+			old: Permissions::VIEW_CHANNEL | Permissions::CONNECT,
+			new: Permissions::empty(),
+		};
+		assert_eq!(
+			describe(&everyone, GUILD + 101).as_deref(),
+			Some(
+				"lost CONNECT and VIEW_CHANNEL via role @everyone (CONNECT and VIEW_CHANNEL \
+				 removed)"
+			)
+		);
+	}
+
+	#[test]
+	fn describes_member_roles_change() {
+		let cause = Cause::Roles {
+			added: vec![Id::new(GUILD + 3)],
+			removed: vec![Id::new(GUILD + 2), Id::new(GUILD + 4)],
+		};
+
+		assert_eq!(
+			describe(&cause, GUILD + 100).as_deref(),
+			Some("lost CONNECT via roles (removed @role 3820002, @3820004, added @role 3820003)")
+		);
+	}
+
+	#[test]
+	fn merge_keeps_oldest_side() {
+		let first = channel(&[role_overwrite(
+			GUILD + 2,
+			Permissions::CONNECT,
+			Permissions::empty(),
+		)]);
+		let second = channel(&[role_overwrite(
+			GUILD + 2,
+			Permissions::CONNECT | Permissions::SPEAK,
+			Permissions::empty(),
+		)]);
+		let third = channel(&[]);
+
+		let mut buffered = super::changes(&first, &second);
+		super::merge(&mut buffered, super::changes(&second, &third));
+
+		assert_eq!(buffered.len(), 1);
+		assert_eq!(
+			buffered[0].old.as_ref().map(|old| old.allow),
+			Some(Permissions::CONNECT)
+		);
+		assert!(buffered[0].new.is_none());
+		assert_eq!(
+			buffered[0].lost(),
+			(Permissions::CONNECT, Permissions::empty())
+		);
+	}
+}
@@ -0,0 +1,159 @@
+//! Member requests of large guilds, limited to the users connected to voice channels.
+//!
+//! Guilds with more than [`THRESHOLD`] members stay cold after their guild create event until the
+//! requested members are received, see [`crate::readiness`], or until [`TIMEOUT`].
+
+use std::{
+	collections::HashMap,
+	sync::{Mutex, PoisonError},
+	time::Duration,
+};
+
+use twilight_model::{
+	gateway::payload::{incoming::MemberChunk, outgoing::RequestGuildMembers},
+	id::{
+		marker::{GuildMarker, UserMarker},
+		Id,
+	},
+};
+
+use crate::{cleanup::Cleanup, BOT};
+
+/// Member count above which the members in voice channels are requested.
+pub const THRESHOLD: u64 = 1000;
+
+/// Maximum time the requested members are waited for.
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum number of users of a request, set by the gateway.
+const BATCH: usize = 100;
+
+/// Outstanding requests, by nonce.
+#[derive(Debug, Default)]
+pub struct Chunks(Mutex<HashMap<String, Id<GuildMarker>>>);
+
+impl Chunks {
+	/// Requests the members of the users, returning whether any request was sent.
+	pub fn request(&self, guild: Id<GuildMarker>, users: &[Id<UserMarker>]) -> bool {
+		let mut outstanding = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		let mut sent = false;
+		for (nonce, request) in batches(guild, users) {
+			if let Err(e) = BOT.sender.command(&request) {
+				tracing::warn!(guild_id = %guild, error = &e as &dyn std::error::Error, "unable to request members");
+				continue;
+			}
+			outstanding.insert(nonce, guild);
+			sent = true;
+		}
+		tracing::debug!(guild_id = %guild, users = users.len(), sent, "requested members in voice");
+		sent
+	}
+
+	/// Marks the chunk's request as received once its last chunk is, returning the guild if it
+	/// has no outstanding requests left.
+	pub fn receive(&self, chunk: &MemberChunk) -> Option<Id<GuildMarker>> {
+		let nonce = chunk.nonce.as_deref()?;
+		if chunk.chunk_index + 1 < chunk.chunk_count {
+			return None;
+		}
+		let mut outstanding = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		let guild = outstanding.remove(nonce)?;
+		(!outstanding.values().any(|&id| id == guild)).then_some(guild)
+	}
+
+	/// Drops the guild's outstanding requests, returning whether there were any.
+	pub fn expire(&self, guild: Id<GuildMarker>) -> bool {
+		let mut outstanding = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		let count = outstanding.len();
+		outstanding.retain(|_, &mut id| id != guild);
+		outstanding.len() != count
+	}
+}
+
+/// Requests of the users' members, by nonce, each of at most [`BATCH`] users.
+fn batches(
+	guild: Id<GuildMarker>,
+	users: &[Id<UserMarker>],
+) -> impl Iterator<Item = (String, RequestGuildMembers)> + '_ {
+	users.chunks(BATCH).enumerate().map(move |(index, batch)| {
+		let nonce = format!("{guild}:{index}");
+		let request = RequestGuildMembers::builder(guild)
+			.nonce(nonce.clone())
+			.user_ids(batch.to_vec())
+			.expect("at most 100 users");
+		(nonce, request)
+	})
+}
+
+impl Cleanup for Chunks {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		self.expire(guild);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use twilight_model::gateway::payload::outgoing::request_guild_members::RequestGuildMemberId;
+
+	use super::*;
+
+	const GUILD: u64 = 4030000;
+
+	fn users(count: u64) -> Vec<Id<UserMarker>> {
+		(GUILD + 1..=GUILD + count).map(Id::new).collect()
+	}
+
+	/// Last chunk of the response to the request.
+	fn chunk(nonce: &str) -> MemberChunk {
+		MemberChunk {
+			chunk_count: 1,
+			chunk_index: 0,
+			guild_id: Id::new(GUILD),
+			members: Vec::new(),
+			nonce: Some(nonce.to_owned()),
+			not_found: Vec::new(),
+			presences: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn batches_at_gateway_limit() {
+		let users = users(250);
+
+		let batches: Vec<_> = batches(Id::new(GUILD), &users).collect();
+
+		let sizes: Vec<_> = batches
+			.iter()
+			.map(|(_, request)| match &request.d.user_ids {
+				Some(RequestGuildMemberId::Multiple(users)) => users.len(),
+				Some(RequestGuildMemberId::One(_)) => 1,
+				None => 0,
+			})
+			.collect();
+		assert_eq!(sizes, [100, 100, 50]);
+		let nonces: Vec<_> = batches.iter().map(|(nonce, _)| nonce.as_str()).collect();
+		assert_eq!(nonces, ["4030000:0", "4030000:1", "4030000:2"]);
+		assert!(batches
+			.iter()
+			.all(|(nonce, request)| request.d.nonce.as_ref() == Some(nonce)));
+	}
+
+	#[test]
+	fn warm_once_every_batch_received() {
+		crate::test::init();
+		let chunks = Chunks::default();
+
+		assert!(chunks.request(Id::new(GUILD), &users(250)));
+		assert_eq!(chunks.receive(&chunk("4030000:1")), None);
+		// not the last chunk of its request
+		let partial = MemberChunk {
+			chunk_count: 2,
+			..chunk("4030000:0")
+		};
+		assert_eq!(chunks.receive(&partial), None);
+		assert_eq!(chunks.receive(&chunk("4030000:0")), None);
+		// not requested
+		assert_eq!(chunks.receive(&chunk("4030000:3")), None);
+		assert_eq!(chunks.receive(&chunk("4030000:2")), Some(Id::new(GUILD)));
+	}
+}
@@ -0,0 +1,48 @@
+//! Dropping state of deleted guilds and channels, and of members that left.
+//!
+//! Stateful features implement [`Cleanup`] and register themselves in [`BotRef::cleanups`].
+//!
+//! [`BotRef::cleanups`]: crate::BotRef::cleanups
+
+use twilight_model::id::{
+	marker::{ChannelMarker, GuildMarker, UserMarker},
+	Id,
+};
+
+use crate::BOT;
+
+/// State scoped to guilds, channels or members.
+pub trait Cleanup {
+	/// Drop the state of the guild, which the bot is no longer in.
+	fn guild(&self, guild: Id<GuildMarker>);
+
+	/// Drop the state of the deleted channel.
+	fn channel(&self, _guild: Id<GuildMarker>, _channel: Id<ChannelMarker>) {}
+
+	/// Drop the state of the user, who left the guild.
+	fn member(&self, _guild: Id<GuildMarker>, _user: Id<UserMarker>) {}
+}
+
+/// Drop every feature's state of the guild.
+pub fn guild(guild: Id<GuildMarker>) {
+	tracing::info!(guild_id = %guild, "cleaning up guild");
+	for cleanup in BOT.cleanups() {
+		cleanup.guild(guild);
+	}
+}
+
+/// Drop every feature's state of the member.
+pub fn member(guild: Id<GuildMarker>, user: Id<UserMarker>) {
+	tracing::trace!(guild_id = %guild, user_id = %user, "cleaning up member");
+	for cleanup in BOT.cleanups() {
+		cleanup.member(guild, user);
+	}
+}
+
+/// Drop every feature's state of the channel.
+pub fn channel(guild: Id<GuildMarker>, channel: Id<ChannelMarker>) {
+	tracing::debug!(guild_id = %guild, channel_id = %channel, "cleaning up channel");
+	for cleanup in BOT.cleanups() {
+		cleanup.channel(guild, channel);
+	}
+}
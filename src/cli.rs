@@ -0,0 +1,31 @@
+//! Command line arguments.
+
+use std::{env, ffi::OsString, path::PathBuf};
+
+/// Parsed command line arguments.
+#[derive(Debug, Default)]
+pub struct Args {
+	/// Configuration file, see [`crate::config`].
+	pub config: Option<PathBuf>,
+}
+
+impl Args {
+	/// Parses the process's arguments.
+	pub fn parse() -> Result<Self, anyhow::Error> {
+		let mut args = Self::default();
+		let mut iter = env::args_os().skip(1);
+		while let Some(arg) = iter.next() {
+			let path = if arg == "--config" {
+				iter.next()
+					.ok_or_else(|| anyhow::anyhow!("\"--config\" requires a path"))?
+			} else if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+				OsString::from(path)
+			} else {
+				anyhow::bail!("unknown argument {arg:?}, expected \"--config <path>\"");
+			};
+			anyhow::ensure!(args.config.is_none(), "\"--config\" given more than once");
+			args.config = Some(path.into());
+		}
+		Ok(args)
+	}
+}
@@ -0,0 +1,11 @@
+//! Wall clock time, unlike [`std::time::Instant`] comparable across restarts and with Discord's
+//! timestamps.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Time since the Unix epoch.
+pub fn now() -> Duration {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.expect("after the Unix epoch")
+}
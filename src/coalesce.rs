@@ -0,0 +1,150 @@
+//! Coalescing of the channel updates cascading from a category's permission edit.
+//!
+//! Editing a category's permission overwrites updates every synced child channel, so channel
+//! updates of a category are buffered for [`WINDOW`] and pruned together, each channel once.
+
+use std::{
+	collections::HashMap,
+	sync::{Mutex, PoisonError},
+	time::Duration,
+};
+
+use twilight_model::id::{
+	marker::{ChannelMarker, GenericMarker, GuildMarker},
+	Id,
+};
+
+use crate::{cause, cleanup::Cleanup};
+
+/// Time channel updates of a category are buffered for.
+pub const WINDOW: Duration = Duration::from_secs(2);
+
+/// Guild and category.
+type Key = (Id<GuildMarker>, Id<ChannelMarker>);
+
+/// Targets of a channel's changed overwrites, `None` if every user may be affected.
+pub type Targets = Option<Vec<Id<GenericMarker>>>;
+
+/// Channel update to prune after.
+#[derive(Debug)]
+pub struct Update {
+	pub targets: Targets,
+	/// Changed permission overwrites, logged as the cause of the prune.
+	pub changes: Vec<cause::Change>,
+}
+
+/// Buffered channel updates per category.
+#[derive(Debug, Default)]
+pub struct Coalescer(Mutex<HashMap<Key, HashMap<Id<ChannelMarker>, Update>>>);
+
+impl Coalescer {
+	/// Buffers the channel's update, merging it with earlier updates of the channel.
+	///
+	/// Returns whether it is the category's first buffered update, whose handler must flush the
+	/// buffer with [`Self::take`] after [`WINDOW`].
+	pub fn push(
+		&self,
+		guild: Id<GuildMarker>,
+		category: Id<ChannelMarker>,
+		channel: Id<ChannelMarker>,
+		update: Update,
+	) -> bool {
+		let mut categories = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		let first = !categories.contains_key(&(guild, category));
+		let channels = categories.entry((guild, category)).or_default();
+		let Some(buffered) = channels.get_mut(&channel) else {
+			channels.insert(channel, update);
+			return first;
+		};
+		cause::merge(&mut buffered.changes, update.changes);
+		match (&mut buffered.targets, update.targets) {
+			(Some(buffered), Some(targets)) => {
+				buffered.extend(targets);
+				buffered.sort_unstable();
+				buffered.dedup();
+			}
+			(targets, None) => *targets = None,
+			(None, Some(_)) => {}
+		}
+
+		first
+	}
+
+	/// Takes the category's buffered updates.
+	pub fn take(
+		&self,
+		guild: Id<GuildMarker>,
+		category: Id<ChannelMarker>,
+	) -> Vec<(Id<ChannelMarker>, Update)> {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&(guild, category))
+			.map_or_else(Vec::new, |channels| channels.into_iter().collect())
+	}
+}
+
+impl Cleanup for Coalescer {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.retain(|&(id, _), _| id != guild);
+	}
+
+	fn channel(&self, guild: Id<GuildMarker>, channel: Id<ChannelMarker>) {
+		let mut categories = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		categories.remove(&(guild, channel));
+		for channels in categories.values_mut() {
+			channels.remove(&channel);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn update(targets: Targets) -> Update {
+		Update {
+			targets,
+			changes: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn merges_updates_per_category() {
+		let coalescer = Coalescer::default();
+		let guild = Id::new(3_520_000);
+		let (category, other) = (Id::new(3_520_005), Id::new(3_520_006));
+		let channel = Id::new(3_520_010);
+
+		assert!(coalescer.push(guild, category, channel, update(Some(vec![Id::new(2)]))));
+		assert!(!coalescer.push(
+			guild,
+			category,
+			channel,
+			update(Some(vec![Id::new(1), Id::new(2)]))
+		));
+		assert!(coalescer.push(guild, other, Id::new(3_520_011), update(None)));
+
+		let updates = coalescer.take(guild, category);
+		assert_eq!(updates.len(), 1);
+		assert_eq!(updates[0].1.targets, Some(vec![Id::new(1), Id::new(2)]));
+		assert!(coalescer.take(guild, category).is_empty());
+		// the next update starts a new buffer
+		assert!(coalescer.push(guild, category, channel, update(Some(Vec::new()))));
+	}
+
+	#[test]
+	fn every_user_affected_wins() {
+		let coalescer = Coalescer::default();
+		let (guild, category, channel) = (Id::new(1), Id::new(2), Id::new(3));
+
+		coalescer.push(guild, category, channel, update(Some(vec![Id::new(4)])));
+		coalescer.push(guild, category, channel, update(None));
+		coalescer.push(guild, category, channel, update(Some(vec![Id::new(5)])));
+
+		assert_eq!(coalescer.take(guild, category)[0].1.targets, None);
+	}
+}
@@ -1,112 +1,1124 @@
 //! Contains all commands.
 //!
-//! Commands are defined in submodules. Required options are  `fn define() -> Command` (registering)
-//! and `async fn run(ctx: &Interaction) -> Result` (execute incomming) interaction.
+//! Commands are defined in submodules implementing [`SlashCommand`], and are registered and
+//! dispatched through their entry in [`COMMANDS`].
 //!
 //! This module also contain shared helper code.
 
+mod audit;
+pub mod components;
+mod info;
 mod is_monitored;
+mod last_prune;
 mod list;
+mod lock;
+mod maintenance;
 mod prune;
+mod schedule_prune;
+mod settings;
+pub mod setup;
+mod stats;
+mod who;
 
+use std::{
+	collections::hash_map::RandomState,
+	hash::{BuildHasher, Hasher},
+	iter,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use futures_util::future::BoxFuture;
+use twilight_http::{
+	api_error::{ApiError, GeneralApiError},
+	error::ErrorType,
+};
 use twilight_model::{
 	application::{
-		command::Command,
-		interaction::{application_command::CommandData, Interaction, InteractionData},
+		command::{Command, CommandOptionChoice},
+		interaction::{
+			application_command::{CommandData, CommandDataOption, CommandOptionValue},
+			Interaction, InteractionData, InteractionType,
+		},
 	},
-	channel::message::MessageFlags,
+	channel::message::{component::Component, AllowedMentions, Embed, MessageFlags},
 	http::interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType},
-	id::Id,
+	id::{
+		marker::{ChannelMarker, GuildMarker, RoleMarker},
+		Id,
+	},
 };
 
-use crate::BOT;
+use crate::{clock, error::Error, BOT, MONITORED_CHANNEL_TYPES};
+
+type Result = std::result::Result<(), Error>;
+
+/// Discord error code of an interaction that has already been acknowledged.
+const ALREADY_ACKNOWLEDGED: u64 = 40060;
+
+/// Every command, in registration order.
+const COMMANDS: [Entry; 13] = [
+	Entry::new::<audit::Audit>(),
+	Entry::new::<info::Info>(),
+	Entry::new::<is_monitored::IsMonitored>(),
+	Entry::new::<last_prune::LastPrune>(),
+	Entry::new::<list::List>(),
+	Entry::new::<lock::Lock>(),
+	Entry::new::<prune::Prune>(),
+	Entry::new::<schedule_prune::SchedulePrune>(),
+	Entry::new::<settings::Settings>(),
+	Entry::new::<setup::Setup>(),
+	Entry::new::<stats::Stats>(),
+	Entry::new::<lock::Unlock>(),
+	Entry::new::<who::Who>(),
+];
+
+const _: () = assert!(unique_names(&COMMANDS), "command names must be unique");
 
-type Result = anyhow::Result<()>;
+/// Whether the commands' names are unique, and not that of the maintenance command.
+const fn unique_names(commands: &[Entry]) -> bool {
+	let mut i = 0;
+	while i < commands.len() {
+		if str_eq(commands[i].name, maintenance::NAME) {
+			return false;
+		}
+		let mut j = i + 1;
+		while j < commands.len() {
+			if str_eq(commands[i].name, commands[j].name) {
+				return false;
+			}
+			j += 1;
+		}
+		i += 1;
+	}
+	true
+}
+
+/// `str` equality usable in constants.
+const fn str_eq(a: &str, b: &str) -> bool {
+	let (a, b) = (a.as_bytes(), b.as_bytes());
+	if a.len() != b.len() {
+		return false;
+	}
+	let mut i = 0;
+	while i < a.len() {
+		if a[i] != b[i] {
+			return false;
+		}
+		i += 1;
+	}
+	true
+}
+
+/// Maximum number of autocomplete choices.
+const CHOICE_LIMIT: usize = 25;
+
+/// Maximum length of a message's content.
+const MESSAGE_LIMIT: usize = 2000;
+
+/// Reply when the bot cannot see any voice channel of the guild.
+const NO_VOICE_CHANNELS: &str = "I can't see any voice channels, check my role permissions";
+
+/// Reply when a channel option isn't a voice channel, e.g. because of a stale command definition.
+const NOT_VOICE_CHANNEL: &str = "that isn't a voice channel";
+
+/// Reply to interactions from outside of a cached guild.
+///
+/// Commands are only registered for guilds, so this happens when the application is user
+/// installed or invited without the `bot` scope.
+const NOT_IN_GUILD: &str = "I'm not a member of this server. \
+Invite me with both the `bot` and `applications.commands` scopes to use my commands.";
+
+/// Chat input command.
+trait SlashCommand {
+	/// Name of the command, must match the name of its definition.
+	const NAME: &'static str;
+
+	/// Minimum time between invocations by the same user.
+	const COOLDOWN: Option<Duration> = None;
+
+	/// Definition of the command, registered with Discord.
+	fn define() -> Command;
+
+	/// Execute an invocation of the command.
+	fn run(ctx: Context) -> BoxFuture<'static, Result>;
+
+	/// Suggestions for the partially typed value of an autocomplete option.
+	fn autocomplete(_ctx: &Context, _option: &str, _value: &str) -> Vec<CommandOptionChoice> {
+		Vec::new()
+	}
+}
+
+/// Type erased [`SlashCommand`].
+struct Entry {
+	name: &'static str,
+	cooldown: Option<Duration>,
+	define: fn() -> Command,
+	run: fn(Context) -> BoxFuture<'static, Result>,
+	autocomplete: fn(&Context, &str, &str) -> Vec<CommandOptionChoice>,
+}
+
+impl Entry {
+	const fn new<C: SlashCommand>() -> Self {
+		Self {
+			name: C::NAME,
+			cooldown: C::COOLDOWN,
+			define: C::define,
+			run: C::run,
+			autocomplete: C::autocomplete,
+		}
+	}
+}
+
+/// Options of a command or subcommand.
+#[derive(Clone, Copy)]
+struct Options<'a>(&'a [CommandDataOption]);
+
+impl<'a> Options<'a> {
+	/// Value of the option, or `None` if it was not provided.
+	fn get<T: OptionValue<'a>>(self, name: &str) -> Option<T> {
+		self.0
+			.iter()
+			.find(|option| option.name == name)
+			.map(|option| T::from_value(&option.value).unwrap_or_else(|| unreachable!("undefined")))
+	}
+
+	/// Channels selected through the options, in order and without duplicates. Values are
+	/// autocompleted strings, identified by `parse`.
+	fn channels(
+		self,
+		names: &[&'static str],
+		parse: impl Fn(&str) -> Option<Id<ChannelMarker>>,
+	) -> std::result::Result<Vec<Id<ChannelMarker>>, Error> {
+		let mut channels = Vec::new();
+		for &name in names {
+			let Some(value) = self.get::<&str>(name) else {
+				continue;
+			};
+			let channel = parse(value).ok_or_else(|| Error::InvalidOption {
+				name,
+				value: value.to_owned(),
+			})?;
+			if !channels.contains(&channel) {
+				channels.push(channel);
+			}
+		}
+		Ok(channels)
+	}
+
+	/// Value of the required option.
+	fn required<T: OptionValue<'a>>(self, name: &str) -> T {
+		self.get(name).expect("required")
+	}
+
+	/// Name and partially typed value of the focused option of an autocomplete interaction.
+	fn focused(self) -> Option<(&'a str, &'a str)> {
+		self.0.iter().find_map(|option| match &option.value {
+			CommandOptionValue::Focused(value, _) => Some((option.name.as_str(), value.as_str())),
+			CommandOptionValue::SubCommand(options)
+			| CommandOptionValue::SubCommandGroup(options) => Options(options).focused(),
+			_ => None,
+		})
+	}
+
+	/// Name and options of the invoked subcommand or subcommand group.
+	fn subcommand(self) -> (&'a str, Options<'a>) {
+		match self.0.first() {
+			Some(CommandDataOption {
+				name,
+				value:
+					CommandOptionValue::SubCommand(options)
+					| CommandOptionValue::SubCommandGroup(options),
+			}) => (name, Options(options)),
+			_ => unreachable!("undefined"),
+		}
+	}
+}
+
+/// Type of an option's value.
+trait OptionValue<'a>: Sized {
+	/// Returns `None` if the value is of another type.
+	fn from_value(value: &'a CommandOptionValue) -> Option<Self>;
+}
+
+impl OptionValue<'_> for bool {
+	fn from_value(value: &CommandOptionValue) -> Option<Self> {
+		match *value {
+			CommandOptionValue::Boolean(value) => Some(value),
+			_ => None,
+		}
+	}
+}
+
+impl OptionValue<'_> for i64 {
+	fn from_value(value: &CommandOptionValue) -> Option<Self> {
+		match *value {
+			CommandOptionValue::Integer(value) => Some(value),
+			_ => None,
+		}
+	}
+}
+
+impl<'a> OptionValue<'a> for &'a str {
+	fn from_value(value: &'a CommandOptionValue) -> Option<Self> {
+		match value {
+			CommandOptionValue::String(value) => Some(value),
+			_ => None,
+		}
+	}
+}
+
+impl OptionValue<'_> for Id<ChannelMarker> {
+	fn from_value(value: &CommandOptionValue) -> Option<Self> {
+		match *value {
+			CommandOptionValue::Channel(id) => Some(id),
+			_ => None,
+		}
+	}
+}
+
+impl OptionValue<'_> for Id<RoleMarker> {
+	fn from_value(value: &CommandOptionValue) -> Option<Self> {
+		match *value {
+			CommandOptionValue::Role(id) => Some(id),
+			_ => None,
+		}
+	}
+}
 
 pub struct Context {
+	/// Whether the interaction was responded to, e.g. acknowledged, shared with the dispatcher.
+	responded: Arc<AtomicBool>,
 	data: Box<CommandData>,
+	/// Cached guild the command was invoked in.
+	guild: Id<GuildMarker>,
 	interaction: Interaction,
 }
 
 impl Context {
+	/// Options of the command.
+	fn options(&self) -> Options<'_> {
+		Options(&self.data.options)
+	}
+
+	/// Respond to the interaction.
+	async fn respond(&self, response: Response) -> Result {
+		respond(&self.interaction, response).await?;
+		self.responded.store(true, Ordering::Relaxed);
+		Ok(())
+	}
+
 	/// Acknowledge the interaction and signal that a message will be provided later.
 	async fn ack(&self) -> Result {
-		BOT.http
-			.interaction(BOT.application_id)
-			.create_response(
-				self.interaction.id,
-				&self.interaction.token,
-				&InteractionResponse {
-					kind: InteractionResponseType::DeferredChannelMessageWithSource,
-					data: Some(InteractionResponseData {
-						flags: Some(MessageFlags::EPHEMERAL),
-						..InteractionResponseData::default()
-					}),
-				},
-			)
-			.await?;
-		Ok(())
+		self.respond(Response::Deferred).await
 	}
 
 	/// Respond to the interaction with a message.
 	async fn reply(&self, message: String) -> Result {
-		BOT.http
-			.interaction(BOT.application_id)
-			.create_response(
-				self.interaction.id,
-				&self.interaction.token,
-				&InteractionResponse {
-					kind: InteractionResponseType::ChannelMessageWithSource,
-					data: Some(InteractionResponseData {
-						content: Some(message),
-						flags: Some(MessageFlags::EPHEMERAL),
-						..InteractionResponseData::default()
-					}),
-				},
-			)
+		self.respond(Response::Message(message)).await
+	}
+
+	/// Respond to the interaction with an embed.
+	async fn reply_embed(&self, embed: Embed) -> Result {
+		self.respond(Response::Embed(Box::new(embed))).await
+	}
+
+	/// Respond to the interaction with the lines, split into pages if they exceed a message.
+	async fn reply_paged(&self, lines: impl IntoIterator<Item = String>) -> Result {
+		components::paginate(&self.interaction, &self.data.name, lines).await?;
+		self.responded.store(true, Ordering::Relaxed);
+		Ok(())
+	}
+
+	/// Send a public follow-up message.
+	async fn followup(&self, message: &str) -> Result {
+		BOT.interaction()
+			.create_followup(&self.interaction.token)
+			.allowed_mentions(Some(&AllowedMentions::default()))
+			.content(message)
 			.await?;
 		Ok(())
 	}
 
+	/// Respond to the autocomplete interaction with choices.
+	async fn suggest(&self, choices: Vec<CommandOptionChoice>) -> Result {
+		self.respond(Response::Choices(choices)).await
+	}
+
 	/// Update an existing response with a message.
 	async fn update_response(&self, message: &str) -> Result {
-		BOT.http
-			.interaction(BOT.application_id)
-			.update_response(&self.interaction.token)
-			.content(Some(message))
-			.await?;
-		Ok(())
+		update_response(&self.interaction, message).await
 	}
 }
 
-/// Match the interaction to a command and run it.
-#[tracing::instrument(fields(id = %interaction.id), skip(interaction))]
+/// Initial response to an interaction.
+enum Response {
+	/// Acknowledgement, the message is provided later through an update.
+	Deferred,
+	Message(String),
+	/// Message of an embed.
+	Embed(Box<Embed>),
+	/// Message with components, e.g. confirmation buttons.
+	Prompt(String, Vec<Component>),
+	/// Autocomplete suggestions.
+	Choices(Vec<CommandOptionChoice>),
+	/// Replacement of the message whose component was clicked, with new components.
+	Update(String, Vec<Component>),
+	/// Replacement of the message whose component was clicked by an embed, without components.
+	UpdateEmbed(Box<Embed>),
+}
+
+impl Response {
+	/// The response, ephemeral and never mentioning anyone.
+	fn build(self) -> InteractionResponse {
+		let (kind, data) = match self {
+			Self::Deferred => (
+				InteractionResponseType::DeferredChannelMessageWithSource,
+				InteractionResponseData::default(),
+			),
+			Self::Message(message) => (
+				InteractionResponseType::ChannelMessageWithSource,
+				InteractionResponseData {
+					content: Some(message),
+					..InteractionResponseData::default()
+				},
+			),
+			Self::Embed(embed) => (
+				InteractionResponseType::ChannelMessageWithSource,
+				InteractionResponseData {
+					embeds: Some(vec![*embed]),
+					..InteractionResponseData::default()
+				},
+			),
+			Self::Prompt(message, components) => (
+				InteractionResponseType::ChannelMessageWithSource,
+				InteractionResponseData {
+					components: Some(components),
+					content: Some(message),
+					..InteractionResponseData::default()
+				},
+			),
+			Self::Choices(choices) => {
+				return InteractionResponse {
+					kind: InteractionResponseType::ApplicationCommandAutocompleteResult,
+					data: Some(InteractionResponseData {
+						choices: Some(choices),
+						..InteractionResponseData::default()
+					}),
+				};
+			}
+			Self::Update(message, components) => {
+				return InteractionResponse {
+					kind: InteractionResponseType::UpdateMessage,
+					data: Some(InteractionResponseData {
+						allowed_mentions: Some(AllowedMentions::default()),
+						components: Some(components),
+						content: Some(message),
+						..InteractionResponseData::default()
+					}),
+				};
+			}
+			Self::UpdateEmbed(embed) => {
+				return InteractionResponse {
+					kind: InteractionResponseType::UpdateMessage,
+					data: Some(InteractionResponseData {
+						allowed_mentions: Some(AllowedMentions::default()),
+						components: Some(Vec::new()),
+						content: Some(String::new()),
+						embeds: Some(vec![*embed]),
+						..InteractionResponseData::default()
+					}),
+				};
+			}
+		};
+
+		InteractionResponse {
+			kind,
+			data: Some(InteractionResponseData {
+				allowed_mentions: Some(AllowedMentions::default()),
+				flags: Some(MessageFlags::EPHEMERAL),
+				..data
+			}),
+		}
+	}
+}
+
+/// Parses a duration of a positive number followed by a unit of `m`, `h` or `d`.
+fn parse_duration(value: &str) -> Option<Duration> {
+	let value = value.trim();
+	let unit = match value.chars().last()? {
+		'm' => 60,
+		'h' => 60 * 60,
+		'd' => 24 * 60 * 60,
+		_ => return None,
+	};
+	let amount: u64 = value[..value.len() - 1].parse().ok()?;
+	amount
+		.checked_mul(unit)
+		.filter(|_| amount != 0)
+		.map(Duration::from_secs)
+}
+
+/// Formats the duration in its largest whole unit.
+fn humanize(duration: Duration) -> String {
+	let (value, unit) = match duration.as_secs() {
+		secs @ 0..=59 => (secs, "second"),
+		secs @ 60..=3599 => (secs / 60, "minute"),
+		secs @ 3600..=86399 => (secs / 3600, "hour"),
+		secs => (secs / 86400, "day"),
+	};
+	let plural = if value == 1 { "" } else { "s" };
+	format!("{value} {unit}{plural}")
+}
+
+/// Join the lines into messages within the limit, truncating overlong lines.
+fn chunk(lines: impl IntoIterator<Item = String>, limit: usize) -> Vec<String> {
+	let mut chunks = vec![String::new()];
+	for mut line in lines {
+		if line.len() > limit {
+			let mut end = limit;
+			while !line.is_char_boundary(end) {
+				end -= 1;
+			}
+			line.truncate(end);
+		}
+		let chunk = chunks.last_mut().expect("not empty");
+		if chunk.is_empty() {
+			*chunk = line;
+		} else if chunk.len() + 1 + line.len() <= limit {
+			chunk.push('\n');
+			chunk.push_str(&line);
+		} else {
+			chunks.push(line);
+		}
+	}
+	chunks
+}
+
+/// Respond to the interaction.
+///
+/// Already acknowledged interactions are only warned about, their response came from an earlier
+/// delivery.
+async fn respond(interaction: &Interaction, response: Response) -> Result {
+	match BOT
+		.interaction()
+		.create_response(interaction.id, &interaction.token, &response.build())
+		.await
+	{
+		Err(e) if is_already_acknowledged(&e) => {
+			tracing::warn!(error = &e as &dyn std::error::Error, "already acknowledged");
+			Ok(())
+		}
+		result => result.map(drop).map_err(Into::into),
+	}
+}
+
+/// Whether the error is due to the interaction already being acknowledged.
+fn is_already_acknowledged(error: &twilight_http::Error) -> bool {
+	matches!(
+		error.kind(),
+		ErrorType::Response {
+			error: ApiError::General(GeneralApiError {
+				code: ALREADY_ACKNOWLEDGED,
+				..
+			}),
+			..
+		}
+	)
+}
+
+/// Update an existing response with a message, never mentioning anyone.
+async fn update_response(interaction: &Interaction, message: &str) -> Result {
+	BOT.interaction()
+		.update_response(&interaction.token)
+		.allowed_mentions(Some(&AllowedMentions::default()))
+		.content(Some(message))
+		.await?;
+	Ok(())
+}
+
+/// Match the interaction to a command and run it, or autocomplete its focused option.
+#[tracing::instrument(fields(id = %interaction.id, guild_id), skip(interaction))]
 pub async fn interaction(mut interaction: Interaction) {
 	let Some(InteractionData::ApplicationCommand(data)) = interaction.data.take() else {
 		return;
 	};
+	if !BOT.seen.insert(interaction.id) {
+		tracing::debug!("dropping duplicate delivery");
+		return;
+	}
 
-	tracing::debug!(user = interaction.author_id().map_or(0, Id::get));
+	tracing::debug!(user_id = interaction.author_id().map_or(0, Id::get));
+	if interaction.kind == InteractionType::ApplicationCommand {
+		BOT.totals.command();
+	}
 
-	let ctx = Context { data, interaction };
+	if data.name == maintenance::NAME {
+		let result = maintenance::run(&interaction, &data).await;
+		return finish(&interaction, false, result).await;
+	}
 
-	let res = match ctx.data.name.as_str() {
-		"is-monitored" => is_monitored::run(ctx).await,
-		"list" => list::run(ctx).await,
-		"prune" => prune::run(ctx).await,
-		_ => {
-			tracing::info!("unregistered");
+	let guild = match interaction.guild_id {
+		Some(guild) if BOT.cache.guild_channels(guild).is_some() => guild,
+		guild => {
+			match guild {
+				Some(guild) => {
+					tracing::info!(guild_id = %guild, "guild not cached, was the bot scope omitted?");
+				}
+				None => tracing::info!("not invoked in a guild"),
+			}
+			if interaction.kind == InteractionType::ApplicationCommand {
+				if let Err(e) =
+					respond(&interaction, Response::Message(NOT_IN_GUILD.to_owned())).await
+				{
+					tracing::error!(error = &e as &dyn std::error::Error);
+				}
+			}
 			return;
 		}
 	};
 
-	match res {
+	tracing::Span::current().record("guild_id", tracing::field::display(guild));
+
+	let responded = Arc::new(AtomicBool::new(false));
+	let ctx = Context {
+		responded: Arc::clone(&responded),
+		data,
+		guild,
+		interaction,
+	};
+
+	let Some(command) = COMMANDS
+		.iter()
+		.find(|command| command.name == ctx.data.name)
+	else {
+		tracing::info!("unregistered");
+		return;
+	};
+
+	let interaction = ctx.interaction.clone();
+	let result = if interaction.kind == InteractionType::ApplicationCommandAutocomplete {
+		let choices = ctx
+			.options()
+			.focused()
+			.map_or_else(Vec::new, |(option, value)| {
+				(command.autocomplete)(&ctx, option, value)
+			});
+		ctx.suggest(choices).await
+	} else if let Some(remaining) = cooldown(command, &ctx) {
+		ctx.reply(format!(
+			"slow down, try again in {}s",
+			remaining.as_secs_f64().ceil()
+		))
+		.await
+	} else {
+		(command.run)(ctx).await
+	};
+
+	finish(&interaction, responded.load(Ordering::Relaxed), result).await;
+}
+
+/// Handle a click of a message component, e.g. a button.
+#[tracing::instrument(fields(id = %interaction.id), skip(interaction))]
+pub async fn component(mut interaction: Interaction) {
+	let Some(InteractionData::MessageComponent(data)) = interaction.data.take() else {
+		return;
+	};
+	if !BOT.seen.insert(interaction.id) {
+		tracing::debug!("dropping duplicate delivery");
+		return;
+	}
+
+	let result = if data.custom_id.starts_with(components::PREFIX) {
+		components::click(&interaction, &data.custom_id).await
+	} else if data.custom_id.starts_with(setup::PREFIX) {
+		setup::component(&interaction, &data).await
+	} else if data.custom_id.starts_with(maintenance::COMPONENT_PREFIX) {
+		maintenance::component(&interaction, &data.custom_id).await
+	} else {
+		tracing::info!(custom_id = data.custom_id, "unknown component");
+		return;
+	};
+	finish(&interaction, false, result).await;
+}
+
+/// Log the result, showing errors to the user.
+async fn finish(interaction: &Interaction, responded: bool, result: Result) {
+	match result {
 		Ok(_) => tracing::debug!("successfully ran"),
-		Err(e) => tracing::error!(error = &*e),
+		Err(e) => {
+			if let Err(e) = report(interaction, responded, message(&e)).await {
+				tracing::error!(error = &e as &dyn std::error::Error);
+			}
+		}
+	}
+}
+
+/// Logs the error and returns the message shown to the user.
+fn message(e: &Error) -> String {
+	match e {
+		Error::Http(_) => {
+			BOT.totals.http_failure();
+			let reference = reference();
+			tracing::error!(%reference, error = e as &dyn std::error::Error);
+			format!("Something went wrong (reference: {reference})")
+		}
+		Error::CacheMiss { .. } => {
+			tracing::warn!(error = e as &dyn std::error::Error);
+			"I'm still catching up on this server, try again in a minute".to_owned()
+		}
+		Error::InvalidOption { name, value } => {
+			tracing::debug!(error = e as &dyn std::error::Error);
+			format!("\"{value}\" is not a valid {name}, pick one of the suggestions")
+		}
+		Error::Hierarchy { role } => {
+			tracing::debug!(error = e as &dyn std::error::Error);
+			format!("<@&{role}> is not below my highest role, so I can't remove its members")
+		}
 	}
 }
 
-/// Array with all command definitions.
-pub fn get() -> [Command; 3] {
-	[is_monitored::define(), list::define(), prune::define()]
+/// Remaining cooldown of the invoker, starting a new one if none is active.
+fn cooldown(command: &Entry, ctx: &Context) -> Option<Duration> {
+	let cooldown = command.cooldown?;
+	let user = ctx.interaction.author_id()?;
+	BOT.cooldowns
+		.acquire(ctx.guild, user, command.name, cooldown)
+		.err()
+}
+
+/// Whether the channel is a cached channel of the guild of a kind the bot may monitor.
+///
+/// Command definitions restrict channel options to these kinds, but a stale definition may still
+/// deliver others.
+fn is_voice_channel(guild: Id<GuildMarker>, channel: Id<ChannelMarker>) -> bool {
+	BOT.cache.channel(channel).is_some_and(|channel| {
+		channel.guild_id == Some(guild) && MONITORED_CHANNEL_TYPES.contains(&channel.kind)
+	})
+}
+
+/// Short random ID correlating an error message with its log line.
+fn reference() -> String {
+	let mut hasher = RandomState::new().build_hasher();
+	hasher.write_u128(clock::now().as_nanos());
+	format!("{:08x}", hasher.finish() as u32)
+}
+
+/// Show the message to the user, replying or updating the response if already responded to, e.g.
+/// acknowledged.
+async fn report(interaction: &Interaction, responded: bool, message: String) -> Result {
+	if responded {
+		update_response(interaction, &message).await
+	} else {
+		respond(interaction, Response::Message(message)).await
+	}
+}
+
+/// Register the commands with Discord, retrying transient errors, and returns their number.
+pub async fn register() -> std::result::Result<usize, twilight_http::Error> {
+	let commands = get();
+	let client = BOT.interaction();
+	let result = crate::retry::transient(|| client.set_global_commands(&commands)).await;
+	BOT.commands_registered
+		.store(result.is_ok(), Ordering::Relaxed);
+	result?;
+	tracing::info!(commands = commands.len(), "registered commands");
+	Ok(commands.len())
+}
+
+/// Register the commands with Discord, only logging errors.
+pub async fn try_register() {
+	if let Err(e) = register().await {
+		tracing::warn!(
+			error = &e as &dyn std::error::Error,
+			"unable to register commands"
+		);
+	}
+}
+
+/// All command definitions.
+///
+/// Every registration goes through this, so a definition whose name differs from its entry's,
+/// which would then never be dispatched, is caught in debug builds.
+pub fn get() -> Vec<Command> {
+	COMMANDS
+		.iter()
+		.map(|command| {
+			let definition = (command.define)();
+			debug_assert_eq!(
+				definition.name, command.name,
+				"name must match the definition"
+			);
+			definition
+		})
+		.chain(iter::once(maintenance::define()))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		sync::{
+			atomic::{AtomicBool, Ordering},
+			Arc,
+		},
+		time::Duration,
+	};
+
+	use twilight_http::Client;
+	use twilight_model::{
+		application::interaction::{
+			application_command::{CommandDataOption, CommandOptionValue},
+			Interaction, InteractionData,
+		},
+		channel::{
+			message::{AllowedMentions, MessageFlags},
+			ChannelType,
+		},
+		id::{marker::ChannelMarker, Id},
+	};
+	use twilight_util::builder::embed::EmbedBuilder;
+
+	use super::{finish, message, prune::CHANNEL_OPTIONS, Context, Error, Options, Response};
+	use crate::{test::Guild, BOT};
+
+	/// String options with the names and values.
+	fn options(options: &[(&str, &str)]) -> Vec<CommandDataOption> {
+		options
+			.iter()
+			.map(|&(name, value)| CommandDataOption {
+				name: name.to_owned(),
+				value: CommandOptionValue::String(value.to_owned()),
+			})
+			.collect()
+	}
+
+	fn channels(options: &[(&str, &str)]) -> Result<Vec<Id<ChannelMarker>>, Error> {
+		Options(&self::options(options)).channels(&CHANNEL_OPTIONS, |value| value.parse().ok())
+	}
+
+	#[test]
+	fn no_channels() {
+		assert!(channels(&[]).unwrap().is_empty());
+	}
+
+	#[test]
+	fn one_channel() {
+		assert_eq!(channels(&[("channel", "5")]).unwrap(), [Id::new(5)]);
+	}
+
+	#[test]
+	fn duplicate_channels() {
+		let channels = channels(&[("channel", "5"), ("channel2", "6"), ("channel3", "5")]);
+
+		assert_eq!(channels.unwrap(), [Id::new(5), Id::new(6)]);
+	}
+
+	#[test]
+	fn missing_channel() {
+		let channels = channels(&[("channel", "5"), ("channel3", "7")]);
+
+		assert_eq!(channels.unwrap(), [Id::new(5), Id::new(7)]);
+	}
+
+	#[test]
+	fn invalid_channel() {
+		let channels = channels(&[("channel", "5"), ("channel2", "general")]);
+
+		assert!(matches!(
+			channels,
+			Err(Error::InvalidOption { name: "channel2", value }) if value == "general"
+		));
+	}
+
+	#[tokio::test]
+	async fn http_error_message() {
+		crate::test::init();
+		// nothing listens on the discard port, so the request fails without leaving the host
+		let http = Client::builder()
+			.proxy("127.0.0.1:9".to_owned(), true)
+			.build();
+		let e = http.gateway().await.expect_err("connection refused");
+		let failures = BOT.totals.snapshot().http_failures;
+
+		let message = message(&Error::from(e));
+
+		assert!(message.starts_with("Something went wrong (reference: "));
+		assert!(BOT.totals.snapshot().http_failures > failures);
+	}
+
+	#[test]
+	fn cache_miss_message() {
+		let e = Error::CacheMiss {
+			resource: "guild",
+			id: 1,
+		};
+
+		assert_eq!(
+			message(&e),
+			"I'm still catching up on this server, try again in a minute"
+		);
+	}
+
+	#[test]
+	fn invalid_option_message() {
+		let e = Error::InvalidOption {
+			name: "channel",
+			value: "general".to_owned(),
+		};
+
+		assert_eq!(
+			message(&e),
+			"\"general\" is not a valid channel, pick one of the suggestions"
+		);
+	}
+
+	#[test]
+	fn hierarchy_message() {
+		let e = Error::Hierarchy { role: Id::new(5) };
+
+		assert_eq!(
+			message(&e),
+			"<@&5> is not below my highest role, so I can't remove its members"
+		);
+	}
+
+	#[test]
+	fn only_prune_has_cooldown() {
+		for command in &super::COMMANDS {
+			let expected = (command.name == "prune").then_some(Duration::from_secs(10));
+			assert_eq!(command.cooldown, expected, "{}", command.name);
+		}
+	}
+
+	#[test]
+	fn parses_durations() {
+		let parse = super::parse_duration;
+		assert_eq!(parse("30m"), Some(Duration::from_secs(30 * 60)));
+		assert_eq!(parse(" 2h "), Some(Duration::from_secs(2 * 60 * 60)));
+		assert_eq!(parse("1d"), Some(Duration::from_secs(24 * 60 * 60)));
+
+		for invalid in [
+			"",
+			"m",
+			"0m",
+			"5",
+			"5s",
+			"-1h",
+			"1.5h",
+			"99999999999999999d",
+		] {
+			assert_eq!(parse(invalid), None, "{invalid:?}");
+		}
+	}
+
+	#[test]
+	fn humanizes_durations() {
+		assert_eq!(super::humanize(Duration::from_secs(1)), "1 second");
+		assert_eq!(super::humanize(Duration::from_secs(90 * 60)), "1 hour");
+		assert_eq!(
+			super::humanize(Duration::from_secs(7 * 24 * 60 * 60)),
+			"7 days"
+		);
+	}
+
+	/// Command interaction in the guild, whose token is `token`.
+	fn interaction(guild: u64, token: &str) -> Interaction {
+		serde_json::from_value(serde_json::json!({
+			"application_id": crate::test::BOT_ID.to_string(),
+			"channel_id": (guild + 20).to_string(),
+			"data": {"id": "1", "name": "prune", "type": 1},
+			"entitlements": [],
+			"guild_id": guild.to_string(),
+			"id": guild.to_string(),
+			"locale": "en-US",
+			"token": token,
+			"type": 2,
+			"version": 1,
+		}))
+		.expect("valid interaction")
+	}
+
+	/// Finishes a command with an error after optionally acknowledging it.
+	async fn fail(guild: u64, token: &str, ack: bool) {
+		let guild = Guild::new(guild).create();
+		let mut interaction = interaction(guild.get(), token);
+		let Some(InteractionData::ApplicationCommand(data)) = interaction.data.take() else {
+			unreachable!("command interaction");
+		};
+		let responded = Arc::new(AtomicBool::new(false));
+		let ctx = Context {
+			responded: Arc::clone(&responded),
+			data,
+			guild,
+			interaction: interaction.clone(),
+		};
+		if ack {
+			ctx.ack().await.unwrap();
+		}
+
+		let error = Error::CacheMiss {
+			resource: "guild",
+			id: guild.get(),
+		};
+		finish(&interaction, responded.load(Ordering::Relaxed), Err(error)).await;
+	}
+
+	#[tokio::test]
+	async fn error_updates_acknowledged_response() {
+		let token = "error-updates-acknowledged-response";
+		fail(3_560_000, token, true).await;
+
+		let requests: Vec<_> = crate::test::requests(token)
+			.into_iter()
+			.map(|request| format!("{} {}", request.method, request.path))
+			.collect();
+		assert_eq!(
+			requests,
+			[
+				format!("POST /interactions/3560000/{token}/callback"),
+				format!("PATCH /webhooks/1/{token}/messages/@original"),
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn error_replies_without_response() {
+		let token = "error-replies-without-response";
+		fail(3_561_000, token, false).await;
+
+		let requests = crate::test::requests(token);
+		assert_eq!(requests.len(), 1);
+		assert_eq!(
+			requests[0].path,
+			format!("/interactions/3561000/{token}/callback")
+		);
+		assert_eq!(requests[0].body["type"], 4, "message");
+		assert_eq!(requests[0].body["data"]["flags"], 64, "ephemeral");
+	}
+
+	#[test]
+	fn responses_never_mention() {
+		let responses = [
+			Response::Deferred,
+			Response::Message("<@1> pruned".to_owned()),
+			Response::Embed(Box::new(EmbedBuilder::new().description("<@1>").build())),
+			Response::Prompt("<@1>?".to_owned(), Vec::new()),
+		];
+		for response in responses {
+			let data = response.build().data.expect("data");
+			assert_eq!(data.allowed_mentions, Some(AllowedMentions::default()));
+			assert_eq!(data.flags, Some(MessageFlags::EPHEMERAL));
+		}
+
+		// updates keep the flags of the replaced message
+		let updates = [
+			Response::Update("<@1> pruned".to_owned(), Vec::new()),
+			Response::UpdateEmbed(Box::new(EmbedBuilder::new().description("<@1>").build())),
+		];
+		for update in updates {
+			let data = update.build().data.expect("data");
+			assert_eq!(data.allowed_mentions, Some(AllowedMentions::default()));
+		}
+	}
+
+	#[tokio::test]
+	async fn updates_never_mention() {
+		let token = "updates-never-mention";
+		let interaction = interaction(3_580_000, token);
+
+		super::update_response(&interaction, "<@1> pruned")
+			.await
+			.unwrap();
+
+		let requests = crate::test::requests(token);
+		assert_eq!(
+			requests[0].body["allowed_mentions"]["parse"],
+			serde_json::json!([])
+		);
+	}
+
+	#[tokio::test]
+	async fn drops_duplicate_delivery() {
+		const GUILD: u64 = 3_810_000;
+		let token = "drops-duplicate-delivery";
+		Guild::new(GUILD).create();
+
+		super::interaction(interaction(GUILD, token)).await;
+		let responses = crate::test::requests(token).len();
+		assert_ne!(responses, 0);
+
+		super::interaction(interaction(GUILD, token)).await;
+		assert_eq!(crate::test::requests(token).len(), responses);
+	}
+
+	#[test]
+	fn validates_voice_channels() {
+		const GUILD: u64 = 3_850_000;
+		let guild = Guild::new(GUILD)
+			.voice(GUILD + 10, &[])
+			.channel(GUILD + 11, ChannelType::GuildStageVoice, None, &[])
+			.channel(GUILD + 12, ChannelType::GuildText, None, &[])
+			.create();
+		Guild::new(GUILD + 1000).voice(GUILD + 1010, &[]).create();
+		let is_voice_channel = |channel| super::is_voice_channel(guild, Id::new(channel));
+
+		assert!(is_voice_channel(GUILD + 10));
+		assert!(is_voice_channel(GUILD + 11), "stage");
+		assert!(!is_voice_channel(GUILD + 12), "text");
+		assert!(!is_voice_channel(GUILD + 13), "uncached");
+		assert!(!is_voice_channel(GUILD + 1010), "of another guild");
+	}
+
+	/// Command shadowing the maintenance command.
+	struct Shadow;
+
+	impl super::SlashCommand for Shadow {
+		const NAME: &'static str = super::maintenance::NAME;
+
+		fn define() -> twilight_model::application::command::Command {
+			unreachable!("never registered")
+		}
+
+		fn run(_ctx: Context) -> futures_util::future::BoxFuture<'static, super::Result> {
+			unreachable!("never dispatched")
+		}
+	}
+
+	#[test]
+	fn unique_names() {
+		use super::{who::Who, Entry};
+
+		assert!(super::unique_names(&super::COMMANDS));
+		assert!(!super::unique_names(&[
+			Entry::new::<Who>(),
+			Entry::new::<Who>()
+		]));
+		assert!(!super::unique_names(&[Entry::new::<Shadow>()]));
+
+		assert!(super::str_eq("who", "who"));
+		assert!(!super::str_eq("who", "whom"));
+		assert!(!super::str_eq("who", "why"));
+	}
+
+	#[test]
+	fn definitions_are_dispatched() {
+		let names: Vec<_> = super::get()
+			.into_iter()
+			.map(|definition| definition.name)
+			.collect();
+
+		assert_eq!(names.len(), super::COMMANDS.len() + 1);
+		let (maintenance, names) = names.split_last().unwrap();
+		assert_eq!(maintenance, super::maintenance::NAME);
+		for name in names {
+			assert!(
+				super::COMMANDS.iter().any(|command| command.name == name),
+				"{name} is dispatched"
+			);
+		}
+	}
 }
@@ -0,0 +1,167 @@
+use futures_util::{future::BoxFuture, FutureExt};
+use twilight_model::{
+	application::command::{Command, CommandType},
+	guild::Permissions,
+	id::{
+		marker::{ChannelMarker, GuildMarker, RoleMarker},
+		Id,
+	},
+};
+use twilight_util::builder::command::CommandBuilder;
+
+use crate::{prune::highest_position, BOT, MONITORED_CHANNEL_TYPES};
+
+pub struct Audit;
+
+impl super::SlashCommand for Audit {
+	const NAME: &'static str = "audit";
+
+	fn define() -> Command {
+		CommandBuilder::new(
+			Self::NAME,
+			"Report permission configurations causing surprising prunes",
+			CommandType::ChatInput,
+		)
+		.default_member_permissions(Permissions::MANAGE_GUILD)
+		.dm_permission(false)
+		.build()
+	}
+
+	fn run(ctx: super::Context) -> BoxFuture<'static, super::Result> {
+		run(ctx).boxed()
+	}
+}
+
+/// Channels the bot may move members from at the guild level but cannot view.
+fn hidden(guild: Id<GuildMarker>, channels: &[Id<ChannelMarker>]) -> Vec<String> {
+	let permissions = BOT.cache.permissions();
+	if !permissions
+		.root(BOT.id, guild)
+		.is_ok_and(|root| root.contains(Permissions::MOVE_MEMBERS))
+	{
+		return Vec::new();
+	}
+
+	channels
+		.iter()
+		.filter(|&&id| {
+			permissions
+				.in_channel(BOT.id, id)
+				.is_ok_and(|permissions| !permissions.contains(Permissions::VIEW_CHANNEL))
+		})
+		.map(|id| {
+			format!("• <#{id}> - allow me to view the channel, it is otherwise not monitored")
+		})
+		.collect()
+}
+
+/// Channels denying `@everyone` CONNECT with connected users that are not permitted.
+fn grandfathered(guild: Id<GuildMarker>, channels: &[Id<ChannelMarker>]) -> Vec<String> {
+	channels
+		.iter()
+		.filter(|&&id| {
+			BOT.cache.channel(id).is_some_and(|channel| {
+				channel
+					.permission_overwrites
+					.iter()
+					.flatten()
+					.any(|overwrite| {
+						overwrite.id == guild.cast()
+							&& overwrite.deny.contains(Permissions::CONNECT)
+					})
+			})
+		})
+		.filter_map(|&id| {
+			let users = BOT.cache.voice_channel_states(id)?.filter(|state| {
+				BOT.cache
+					.permissions()
+					.in_channel(state.user_id(), id)
+					.is_ok_and(|permissions| !permissions.contains(Permissions::CONNECT))
+			});
+			match users.count() {
+				0 => None,
+				n => Some(format!(
+					"• <#{id}> - {n} users connected without permission, run `/prune` to disconnect them"
+				)),
+			}
+		})
+		.collect()
+}
+
+/// Roles granting CONNECT whose members the bot, with its roles, cannot act on.
+fn unreachable(guild: Id<GuildMarker>, own_roles: &[Id<RoleMarker>]) -> Vec<String> {
+	let position = highest_position(own_roles);
+	let Some(roles) = BOT.cache.guild_roles(guild) else {
+		return Vec::new();
+	};
+	let mut roles: Vec<_> = roles
+		.iter()
+		.filter(|&&id| id != guild.cast() && !own_roles.contains(&id))
+		.filter_map(|&id| {
+			let role = BOT.cache.role(id)?;
+			(role.position >= position
+				&& role
+					.permissions
+					.intersects(Permissions::CONNECT | Permissions::ADMINISTRATOR))
+			.then_some((role.position, id))
+		})
+		.collect();
+	roles.sort_unstable_by(|a, b| b.cmp(a));
+
+	roles
+		.into_iter()
+		.map(|(_, id)| format!("• <@&{id}> - move my role above it to prune its members"))
+		.collect()
+}
+
+async fn run(ctx: super::Context) -> super::Result {
+	let guild = ctx.guild;
+	let settings = BOT.settings.get(guild);
+	let channels: Vec<_> = BOT
+		.cache
+		.guild_channels(guild)
+		.ok_or(super::Error::CacheMiss {
+			resource: "guild",
+			id: guild.get(),
+		})?
+		.iter()
+		.copied()
+		.filter(|&id| {
+			BOT.cache.channel(id).is_some_and(|channel| {
+				MONITORED_CHANNEL_TYPES.contains(&channel.kind)
+					&& BOT.monitors(&settings, channel.kind)
+			})
+		})
+		.collect();
+	let own_roles = BOT
+		.cache
+		.member(guild, BOT.id)
+		.ok_or(super::Error::CacheMiss {
+			resource: "member",
+			id: BOT.id.get(),
+		})?
+		.roles()
+		.to_vec();
+
+	let sections = [
+		("**Channels I cannot view**", hidden(guild, &channels)),
+		(
+			"**Channels with unpermitted users**",
+			grandfathered(guild, &channels),
+		),
+		("**Roles above mine**", unreachable(guild, &own_roles)),
+	];
+
+	let mut lines = Vec::new();
+	for (title, findings) in sections {
+		if !findings.is_empty() {
+			lines.push(title.to_owned());
+			lines.extend(findings);
+		}
+	}
+	if lines.is_empty() {
+		lines.push("No issues found".to_owned());
+	}
+
+	ctx.reply_paged(lines).await
+}
@@ -0,0 +1,316 @@
+//! Paginated replies, turned through buttons that only the invoker may click.
+//!
+//! Pages are kept in memory until their buttons expire after [`EXPIRY`], when they are disabled.
+
+use std::{
+	collections::HashMap,
+	sync::{Mutex, PoisonError},
+	time::Duration,
+};
+
+use twilight_model::{
+	application::interaction::Interaction,
+	channel::message::component::{ActionRow, Button, ButtonStyle, Component},
+	id::{
+		marker::{InteractionMarker, UserMarker},
+		Id,
+	},
+};
+
+use super::{chunk, respond, Response, MESSAGE_LIMIT};
+use crate::BOT;
+
+/// Prefix of the custom IDs of page buttons.
+pub const PREFIX: &str = "page:";
+
+/// Time after which the buttons are disabled.
+const EXPIRY: Duration = Duration::from_secs(5 * 60);
+
+/// Room left on each page for its footer.
+const FOOTER_LIMIT: usize = 32;
+
+/// Reply to clicks of expired buttons.
+const EXPIRED: &str = "These pages expired, run the command again";
+
+/// Reply to clicks by someone other than the invoker.
+const DENIED: &str = "Only the user who ran the command may turn its pages";
+
+/// Pages of a reply and the one shown.
+#[derive(Debug)]
+struct Reply {
+	pages: Vec<String>,
+	index: usize,
+}
+
+/// Pages of the replies with unexpired buttons, by the interaction they replied to.
+#[derive(Debug, Default)]
+pub struct Pages(Mutex<HashMap<Id<InteractionMarker>, Reply>>);
+
+impl Pages {
+	fn insert(&self, reply: Id<InteractionMarker>, pages: Vec<String>) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.insert(reply, Reply { pages, index: 0 });
+	}
+
+	/// Shows the page, returning it and the number of pages, `None` if expired or out of range.
+	fn turn(&self, reply: Id<InteractionMarker>, index: usize) -> Option<(String, usize)> {
+		let mut replies = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		let reply = replies.get_mut(&reply)?;
+		let page = reply.pages.get(index)?.clone();
+		reply.index = index;
+		Some((page, reply.pages.len()))
+	}
+
+	/// Drops the reply, returning the shown page's index and the number of pages.
+	fn expire(&self, reply: Id<InteractionMarker>) -> Option<(usize, usize)> {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&reply)
+			.map(|reply| (reply.index, reply.pages.len()))
+	}
+}
+
+/// Button turning to a page, encoded in its custom ID.
+#[derive(Debug, Eq, PartialEq)]
+struct Turn<'a> {
+	/// Name of the command that replied.
+	command: &'a str,
+	/// Interaction that was replied to.
+	reply: Id<InteractionMarker>,
+	/// Index of the page turned to.
+	index: usize,
+	/// Invoker of the command.
+	user: Id<UserMarker>,
+}
+
+impl<'a> Turn<'a> {
+	fn custom_id(&self) -> String {
+		format!(
+			"{PREFIX}{}:{}:{}:{}",
+			self.command, self.reply, self.index, self.user
+		)
+	}
+
+	fn parse(custom_id: &'a str) -> Option<Self> {
+		let mut parts = custom_id.strip_prefix(PREFIX)?.split(':');
+		let turn = Self {
+			command: parts.next()?,
+			reply: parts.next()?.parse().ok()?,
+			index: parts.next()?.parse().ok()?,
+			user: parts.next()?.parse().ok()?,
+		};
+		parts.next().is_none().then_some(turn)
+	}
+}
+
+/// Respond with the lines, split into pages turned through buttons if they exceed a message.
+pub async fn paginate(
+	interaction: &Interaction,
+	command: &str,
+	lines: impl IntoIterator<Item = String>,
+) -> super::Result {
+	let mut pages = chunk(lines, MESSAGE_LIMIT - FOOTER_LIMIT);
+	let Some(user) = interaction.author_id().filter(|_| pages.len() > 1) else {
+		return respond(interaction, Response::Message(pages.swap_remove(0))).await;
+	};
+
+	let count = pages.len();
+	let first = render(&pages[0], 0, count);
+	BOT.pages.insert(interaction.id, pages);
+	let turn = Turn {
+		command,
+		reply: interaction.id,
+		index: 0,
+		user,
+	};
+	respond(
+		interaction,
+		Response::Prompt(first, buttons(&turn, count, false)),
+	)
+	.await?;
+
+	let command = command.to_owned();
+	let reply = interaction.id;
+	let token = interaction.token.clone();
+	tokio::spawn(async move {
+		tokio::time::sleep(EXPIRY).await;
+		let Some((index, count)) = BOT.pages.expire(reply) else {
+			return;
+		};
+		let turn = Turn {
+			command: &command,
+			reply,
+			index,
+			user,
+		};
+		let components = buttons(&turn, count, true);
+		if let Err(e) = BOT
+			.interaction()
+			.update_response(&token)
+			.components(Some(&components))
+			.await
+		{
+			tracing::debug!(
+				error = &e as &dyn std::error::Error,
+				"unable to expire pages"
+			);
+		}
+	});
+	Ok(())
+}
+
+/// Handle a click of a page button, turning the page if clicked by the invoker.
+pub async fn click(interaction: &Interaction, custom_id: &str) -> super::Result {
+	let Some(turn) = Turn::parse(custom_id) else {
+		tracing::info!(custom_id, "unknown component");
+		return Ok(());
+	};
+	if !is_invoker(interaction, &turn) {
+		return respond(interaction, Response::Message(DENIED.to_owned())).await;
+	}
+
+	let Some((page, count)) = BOT.pages.turn(turn.reply, turn.index) else {
+		return respond(interaction, Response::Message(EXPIRED.to_owned())).await;
+	};
+	let page = render(&page, turn.index, count);
+	respond(
+		interaction,
+		Response::Update(page, buttons(&turn, count, false)),
+	)
+	.await
+}
+
+/// Whether the user clicking the button invoked the command.
+fn is_invoker(interaction: &Interaction, turn: &Turn) -> bool {
+	interaction.author_id() == Some(turn.user)
+}
+
+/// The page with its number.
+fn render(page: &str, index: usize, count: usize) -> String {
+	format!("{page}\n\nPage {}/{count}", index + 1)
+}
+
+/// Buttons turning from the page to its neighbours, disabled once expired.
+fn buttons(turn: &Turn, count: usize, expired: bool) -> Vec<Component> {
+	let button = |index: usize, label: &str, disabled: bool| {
+		let target = Turn { index, ..*turn };
+		Component::Button(Button {
+			custom_id: Some(target.custom_id()),
+			disabled: expired || disabled,
+			emoji: None,
+			label: Some(label.to_owned()),
+			style: ButtonStyle::Secondary,
+			url: None,
+		})
+	};
+	// distinct custom IDs, as the previous page of the first is only disabled
+	vec![Component::ActionRow(ActionRow {
+		components: vec![
+			button(turn.index.saturating_sub(1), "⟨ Previous", turn.index == 0),
+			button(turn.index + 1, "Next ⟩", turn.index + 1 == count),
+		],
+	})]
+}
+
+#[cfg(test)]
+mod tests {
+	use twilight_model::{application::interaction::Interaction, id::Id};
+
+	use super::Turn;
+	use crate::BOT;
+
+	#[test]
+	fn custom_id_round_trip() {
+		let turn = Turn {
+			command: "list",
+			reply: Id::new(3_950_001),
+			index: 2,
+			user: Id::new(3_950_100),
+		};
+		let custom_id = turn.custom_id();
+
+		assert_eq!(custom_id, "page:list:3950001:2:3950100");
+		assert_eq!(Turn::parse(&custom_id), Some(turn));
+		for invalid in [
+			"list:3950001:2:3950100",
+			"page:list:3950001:2",
+			"page:list:3950001:2:3950100:1",
+			"page:list:3950001:-1:3950100",
+			"page:list:0:2:3950100",
+		] {
+			assert_eq!(Turn::parse(invalid), None, "{invalid}");
+		}
+	}
+
+	/// Click of the page button by the user, whose token is `token`.
+	fn click(id: u64, token: &str, user: u64, custom_id: &str) -> Interaction {
+		crate::test::init();
+		serde_json::from_value(serde_json::json!({
+			"application_id": crate::test::BOT_ID.to_string(),
+			"channel_id": "3950020",
+			"data": {"component_type": 2, "custom_id": custom_id},
+			"entitlements": [],
+			"id": id.to_string(),
+			"locale": "en-US",
+			"token": token,
+			"type": 3,
+			"user": {
+				"avatar": null,
+				"discriminator": "0",
+				"id": user.to_string(),
+				"username": format!("user {user}"),
+			},
+			"version": 1,
+		}))
+		.expect("valid interaction")
+	}
+
+	#[tokio::test]
+	async fn only_invoker_turns_pages() {
+		let reply = Id::new(3_951_001);
+		BOT.pages
+			.insert(reply, vec!["first".to_owned(), "second".to_owned()]);
+		let custom_id = Turn {
+			command: "list",
+			reply,
+			index: 1,
+			user: Id::new(3_951_100),
+		}
+		.custom_id();
+
+		let token = "only-invoker-turns-pages-other";
+		let interaction = click(3_951_002, token, 3_951_101, &custom_id);
+		super::click(&interaction, &custom_id).await.unwrap();
+		let requests = crate::test::requests(token);
+		assert_eq!(requests[0].body["data"]["content"], super::DENIED);
+
+		let token = "only-invoker-turns-pages-invoker";
+		let interaction = click(3_951_003, token, 3_951_100, &custom_id);
+		super::click(&interaction, &custom_id).await.unwrap();
+		let requests = crate::test::requests(token);
+		assert_eq!(requests[0].body["type"], 7, "update");
+		assert_eq!(requests[0].body["data"]["content"], "second\n\nPage 2/2");
+	}
+
+	#[tokio::test]
+	async fn expired_pages() {
+		let reply = Id::new(3_952_001);
+		let custom_id = Turn {
+			command: "list",
+			reply,
+			index: 1,
+			user: Id::new(3_952_100),
+		}
+		.custom_id();
+
+		let token = "expired-pages";
+		let interaction = click(3_952_002, token, 3_952_100, &custom_id);
+		super::click(&interaction, &custom_id).await.unwrap();
+
+		let requests = crate::test::requests(token);
+		assert_eq!(requests[0].body["data"]["content"], super::EXPIRED);
+	}
+}
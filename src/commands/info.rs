@@ -0,0 +1,46 @@
+use futures_util::{future::BoxFuture, FutureExt};
+use twilight_model::application::command::{Command, CommandType};
+use twilight_util::builder::command::CommandBuilder;
+
+use crate::{status_file::Status, BOT};
+
+pub struct Info;
+
+impl super::SlashCommand for Info {
+	const NAME: &'static str = "info";
+
+	fn define() -> Command {
+		CommandBuilder::new(
+			Self::NAME,
+			"Information about this bot instance",
+			CommandType::ChatInput,
+		)
+		.dm_permission(false)
+		.build()
+	}
+
+	fn run(ctx: super::Context) -> BoxFuture<'static, super::Result> {
+		run(ctx).boxed()
+	}
+}
+
+async fn run(ctx: super::Context) -> super::Result {
+	let status = Status::collect();
+	let mut msg = format!(
+		"{} {}\nOperating in: {}\nUptime: {}s, shard {}, {} servers",
+		status.name, status.version, status.policy, status.uptime, status.shard, status.guild_count,
+	);
+	msg += &format!("\nSince start: {}", status.totals);
+	if let Some(proxy) = &BOT.proxy {
+		msg += &format!("\nProxy: {proxy}");
+	}
+	if let Some(missing) = BOT
+		.missing_permissions(ctx.guild)
+		.filter(|missing| !missing.is_empty())
+	{
+		let missing: Vec<_> = missing.iter_names().map(|(name, _)| name).collect();
+		msg += &format!("\nMissing permissions: {}", missing.join(", "));
+	}
+
+	ctx.reply(msg).await
+}
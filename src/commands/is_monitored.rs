@@ -1,30 +1,39 @@
-use twilight_model::application::{
-	command::{Command, CommandType},
-	interaction::application_command::CommandOptionValue,
-};
+use futures_util::{future::BoxFuture, FutureExt};
+use twilight_model::application::command::{Command, CommandType};
 use twilight_util::builder::command::{ChannelBuilder, CommandBuilder};
 
 use crate::{BOT, MONITORED_CHANNEL_TYPES};
 
-pub fn define() -> Command {
-	CommandBuilder::new(
-		"is-monitored",
-		"Checks if a voice channel is monitored",
-		CommandType::ChatInput,
-	)
-	.dm_permission(false)
-	.option(
-		ChannelBuilder::new("channel", "Whether the voice channel is monitored")
-			.channel_types(MONITORED_CHANNEL_TYPES)
-			.required(true),
-	)
-	.build()
+pub struct IsMonitored;
+
+impl super::SlashCommand for IsMonitored {
+	const NAME: &'static str = "is-monitored";
+
+	fn define() -> Command {
+		CommandBuilder::new(
+			Self::NAME,
+			"Checks if a voice channel is monitored",
+			CommandType::ChatInput,
+		)
+		.dm_permission(false)
+		.option(
+			ChannelBuilder::new("channel", "Whether the voice channel is monitored")
+				.channel_types(MONITORED_CHANNEL_TYPES)
+				.required(true),
+		)
+		.build()
+	}
+
+	fn run(ctx: super::Context) -> BoxFuture<'static, super::Result> {
+		run(ctx).boxed()
+	}
 }
 
-pub async fn run(ctx: super::Context) -> super::Result {
-	let CommandOptionValue::Channel(channel) = ctx.data.options[0].value else {
-		unreachable!("undefined");
-	};
+async fn run(ctx: super::Context) -> super::Result {
+	let channel = ctx.options().required("channel");
+	if !super::is_voice_channel(ctx.guild, channel) {
+		return ctx.reply(super::NOT_VOICE_CHANNEL.to_owned()).await;
+	}
 
 	ctx.reply(BOT.is_monitored(channel).to_string()).await
 }
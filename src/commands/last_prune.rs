@@ -0,0 +1,56 @@
+use futures_util::{future::BoxFuture, FutureExt};
+use twilight_model::{
+	application::command::{Command, CommandType},
+	id::{marker::ChannelMarker, Id},
+};
+use twilight_util::builder::command::{ChannelBuilder, CommandBuilder};
+
+use crate::{BOT, MONITORED_CHANNEL_TYPES};
+
+pub struct LastPrune;
+
+impl super::SlashCommand for LastPrune {
+	const NAME: &'static str = "last-prune";
+
+	fn define() -> Command {
+		CommandBuilder::new(
+			Self::NAME,
+			"When users were last pruned",
+			CommandType::ChatInput,
+		)
+		.dm_permission(false)
+		.option(
+			ChannelBuilder::new("channel", "Only from this voice channel")
+				.channel_types(MONITORED_CHANNEL_TYPES),
+		)
+		.build()
+	}
+
+	fn run(ctx: super::Context) -> BoxFuture<'static, super::Result> {
+		run(ctx).boxed()
+	}
+}
+
+async fn run(ctx: super::Context) -> super::Result {
+	let channel: Option<Id<ChannelMarker>> = ctx.options().get("channel");
+	if channel.is_some_and(|channel| !super::is_voice_channel(ctx.guild, channel)) {
+		return ctx.reply(super::NOT_VOICE_CHANNEL.to_owned()).await;
+	}
+
+	let prune = match channel {
+		Some(channel) => BOT.history.channel(ctx.guild, channel),
+		None => BOT.history.guild(ctx.guild),
+	};
+
+	let msg = match prune {
+		Some(prune) => format!(
+			"Last prune: {} ago, {}, {} users",
+			super::humanize(prune.at.elapsed()),
+			prune.trigger,
+			prune.users
+		),
+		None => "No recent prunes".to_owned(),
+	};
+
+	ctx.reply(msg).await
+}
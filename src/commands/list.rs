@@ -1,60 +1,190 @@
+use std::{collections::HashMap, iter};
+
+use futures_util::{future::BoxFuture, FutureExt};
 use twilight_model::{
-	application::{
-		command::{Command, CommandType},
-		interaction::application_command::CommandOptionValue,
-	},
+	application::command::{Command, CommandType},
 	id::{marker::ChannelMarker, Id},
 };
 use twilight_util::builder::command::{CommandBuilder, StringBuilder};
 
-use crate::{BOT, MONITORED_CHANNEL_TYPES};
-
-pub fn define() -> Command {
-	CommandBuilder::new(
-		"list",
-		"List visible voice channels",
-		CommandType::ChatInput,
-	)
-	.dm_permission(false)
-	.option(
-		StringBuilder::new("type", "Only monitored / unmonitored voice channels")
-			.choices([("Monitored", "monitored"), ("Unmonitored", "unmonitored")]),
-	)
-	.build()
+use crate::BOT;
+
+pub struct List;
+
+impl super::SlashCommand for List {
+	const NAME: &'static str = "list";
+
+	fn define() -> Command {
+		CommandBuilder::new(
+			Self::NAME,
+			"List visible voice channels",
+			CommandType::ChatInput,
+		)
+		.dm_permission(false)
+		.option(
+			StringBuilder::new("type", "Only monitored / unmonitored voice channels").choices([
+				("Monitored", "monitored"),
+				("Unmonitored", "unmonitored"),
+				("Offenders", "offenders"),
+			]),
+		)
+		.build()
+	}
+
+	fn run(ctx: super::Context) -> BoxFuture<'static, super::Result> {
+		run(ctx).boxed()
+	}
 }
 
-pub async fn run(ctx: super::Context) -> super::Result {
-	let guild = ctx.interaction.guild_id.expect("required");
+/// Monitored voice channels followed by their users a prune would currently remove.
+async fn offenders(ctx: super::Context) -> super::Result {
+	let prunes = crate::prune::offenders(ctx.guild).ok_or(super::Error::CacheMiss {
+		resource: "guild",
+		id: ctx.guild.get(),
+	})?;
+	if prunes.is_empty() {
+		return ctx.reply("No offenders found".to_owned()).await;
+	}
 
-	let channels = BOT.cache.guild_channels(guild).expect("cached");
+	let lines = prunes.into_iter().map(|prune| {
+		let users: Vec<_> = prune
+			.users
+			.iter()
+			.map(|user| format!("<@{user}>"))
+			.collect();
+		format!("• <#{}>: {}", prune.channel, users.join(" "))
+	});
+	ctx.reply_paged(lines).await
+}
+
+async fn run(ctx: super::Context) -> super::Result {
+	if !BOT.sees_voice_channels(ctx.guild) {
+		return ctx.reply(super::NO_VOICE_CHANNELS.to_owned()).await;
+	}
+
+	if ctx.options().get("type") == Some("offenders") {
+		return offenders(ctx).await;
+	}
+
+	let settings = BOT.settings.get(ctx.guild);
+	let channels = BOT
+		.cache
+		.guild_channels(ctx.guild)
+		.ok_or(super::Error::CacheMiss {
+			resource: "guild",
+			id: ctx.guild.get(),
+		})?;
 	let channels = channels
 		.iter()
 		.copied()
-		.filter(|&id| MONITORED_CHANNEL_TYPES.contains(&BOT.cache.channel(id).unwrap().kind));
-
-	let format = |id: Id<ChannelMarker>| format!("• <#{id}>\n");
-
-	let msg: String = match ctx.data.options.first().map(|data| &data.value) {
-		Some(CommandOptionValue::String(r#type)) => match r#type.as_str() {
-			"monitored" => channels
-				.filter(|&channel| BOT.is_monitored(channel))
-				.map(format)
-				.collect(),
-			"unmonitored" => channels
-				.filter(|&channel| !BOT.is_monitored(channel))
-				.map(format)
-				.collect(),
-			_ => unreachable!("undefined"),
-		},
+		.filter(|&id| BOT.monitors(&settings, BOT.cache.channel(id).unwrap().kind));
+
+	let channels: Vec<_> = match ctx.options().get("type") {
+		Some("monitored") => channels
+			.filter(|&channel| BOT.is_monitored_with(&settings, channel))
+			.collect(),
+		Some("unmonitored") => channels
+			.filter(|&channel| !BOT.is_monitored_with(&settings, channel))
+			.collect(),
 		Some(_) => unreachable!("undefined"),
-		None => channels.map(format).collect(),
+		None => channels.collect(),
 	};
 
-	let msg = if msg.is_empty() {
-		"none".to_owned()
-	} else {
-		msg
-	};
+	if channels.is_empty() {
+		return ctx.reply("none".to_owned()).await;
+	}
+
+	let lines = group(channels)
+		.into_iter()
+		.flat_map(|(category, channels)| {
+			let header = format!("**{}**", category.as_deref().unwrap_or("Uncategorized"));
+			iter::once(header).chain(channels.into_iter().map(|id| {
+				if BOT.is_afk(id) {
+					format!("\u{2003}• <#{id}> (AFK)")
+				} else {
+					format!("\u{2003}• <#{id}>")
+				}
+			}))
+		});
+	ctx.reply_paged(lines).await
+}
+
+/// Channel and its position.
+type Positioned = (i32, Id<ChannelMarker>);
+
+/// Groups the channels by their parent category's name, uncategorized channels last.
+///
+/// Groups and channels are in Discord's position order, and channels whose category is not cached
+/// count as uncategorized.
+fn group(
+	channels: impl IntoIterator<Item = Id<ChannelMarker>>,
+) -> Vec<(Option<String>, Vec<Id<ChannelMarker>>)> {
+	// Keyed by the category, `None` for uncategorized channels.
+	let mut groups: HashMap<Option<Positioned>, Vec<Positioned>> = HashMap::new();
+	for id in channels {
+		let Some(channel) = BOT.cache.channel(id) else {
+			continue;
+		};
+		let category = channel
+			.parent_id
+			.and_then(|parent| BOT.cache.channel(parent))
+			.map(|category| (category.position.unwrap_or_default(), category.id));
+		groups
+			.entry(category)
+			.or_default()
+			.push((channel.position.unwrap_or_default(), id));
+	}
+
+	let mut groups: Vec<_> = groups.into_iter().collect();
+	groups.sort_unstable_by_key(|&(category, _)| (category.is_none(), category));
+	groups
+		.into_iter()
+		.map(|(category, mut channels)| {
+			channels.sort_unstable();
+			let name = category.and_then(|(_, id)| BOT.cache.channel(id)?.name.clone());
+			(name, channels.into_iter().map(|(_, id)| id).collect())
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use twilight_model::{channel::ChannelType, id::Id};
+
+	use crate::test::Guild;
+
+	#[test]
+	fn groups_by_category() {
+		const GUILD: u64 = 3_590_000;
+		let mut guild = Guild::new(GUILD).category(GUILD + 5).category(GUILD + 3);
+		for (id, parent) in [
+			(GUILD + 20, Some(GUILD + 5)),
+			(GUILD + 12, Some(GUILD + 5)),
+			(GUILD + 15, Some(GUILD + 3)),
+			(GUILD + 31, None),
+			// the category isn't cached
+			(GUILD + 30, Some(GUILD + 4)),
+		] {
+			guild = guild.channel(id, ChannelType::GuildVoice, parent, &[]);
+		}
+		guild.create();
+
+		let channels = [20, 12, 15, 31, 30].map(|offset| Id::new(GUILD + offset));
+		let groups = super::group(channels);
 
-	ctx.reply(msg).await
+		let ids = |offsets: &[u64]| -> Vec<_> {
+			offsets
+				.iter()
+				.map(|offset| Id::new(GUILD + offset))
+				.collect()
+		};
+		assert_eq!(
+			groups,
+			[
+				(Some(format!("channel {}", GUILD + 3)), ids(&[15])),
+				(Some(format!("channel {}", GUILD + 5)), ids(&[12, 20])),
+				(None, ids(&[30, 31])),
+			]
+		);
+	}
 }
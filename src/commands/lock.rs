@@ -0,0 +1,349 @@
+//! `/lock` and `/unlock`, denying or allowing again `CONNECT` on a voice channel.
+//!
+//! `/lock` prunes the channel itself, so the auto prune of its overwrite change is skipped, see
+//! [`crate::dedup::Locks`].
+
+use std::time::Duration;
+
+use futures_util::{future::BoxFuture, FutureExt};
+use twilight_http::request::AuditLogReason;
+use twilight_model::{
+	application::command::{Command, CommandType},
+	channel::permission_overwrite::PermissionOverwriteType,
+	guild::Permissions,
+	http::permission_overwrite::{PermissionOverwrite, PermissionOverwriteType as OverwriteType},
+	id::{
+		marker::{ChannelMarker, RoleMarker},
+		Id,
+	},
+};
+use twilight_util::builder::command::{ChannelBuilder, CommandBuilder, RoleBuilder};
+
+use crate::{prune::Trigger, BOT, MONITORED_CHANNEL_TYPES};
+
+/// Permissions the bot requires in the channel to edit its overwrites.
+const REQUIRED_PERMISSIONS: Permissions =
+	Permissions::MANAGE_CHANNELS.union(Permissions::MANAGE_ROLES);
+
+/// Maximum time waited for the overwrite change to be received through the gateway.
+const PROPAGATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Interval the cache is checked at while waiting for the overwrite change.
+const PROPAGATION_POLL: Duration = Duration::from_millis(250);
+
+pub struct Lock;
+
+impl super::SlashCommand for Lock {
+	const NAME: &'static str = "lock";
+
+	fn define() -> Command {
+		CommandBuilder::new(
+			Self::NAME,
+			"Deny connecting to a voice channel and prune it",
+			CommandType::ChatInput,
+		)
+		.default_member_permissions(Permissions::MANAGE_CHANNELS | Permissions::MOVE_MEMBERS)
+		.dm_permission(false)
+		.option(
+			ChannelBuilder::new("channel", "Voice channel to lock")
+				.channel_types(MONITORED_CHANNEL_TYPES)
+				.required(true),
+		)
+		.option(RoleBuilder::new(
+			"role",
+			"Deny only this role, defaults to @everyone",
+		))
+		.build()
+	}
+
+	fn run(ctx: super::Context) -> BoxFuture<'static, super::Result> {
+		run(ctx, true).boxed()
+	}
+}
+
+pub struct Unlock;
+
+impl super::SlashCommand for Unlock {
+	const NAME: &'static str = "unlock";
+
+	fn define() -> Command {
+		CommandBuilder::new(
+			Self::NAME,
+			"Remove the connect denial of `/lock` from a voice channel",
+			CommandType::ChatInput,
+		)
+		.default_member_permissions(Permissions::MANAGE_CHANNELS | Permissions::MOVE_MEMBERS)
+		.dm_permission(false)
+		.option(
+			ChannelBuilder::new("channel", "Voice channel to unlock")
+				.channel_types(MONITORED_CHANNEL_TYPES)
+				.required(true),
+		)
+		.option(RoleBuilder::new(
+			"role",
+			"Allow only this role again, defaults to @everyone",
+		))
+		.build()
+	}
+
+	fn run(ctx: super::Context) -> BoxFuture<'static, super::Result> {
+		run(ctx, false).boxed()
+	}
+}
+
+/// Required permissions the bot lacks in the channel, `None` if not cached.
+fn missing_permissions(channel: Id<ChannelMarker>) -> Option<Permissions> {
+	BOT.cache
+		.permissions()
+		.in_channel(BOT.id, channel)
+		.ok()
+		.map(|permissions| REQUIRED_PERMISSIONS.difference(permissions))
+}
+
+/// Allowed and denied permissions of the role's overwrite in the cached channel.
+fn overwrite(channel: Id<ChannelMarker>, role: Id<RoleMarker>) -> (Permissions, Permissions) {
+	BOT.cache
+		.channel(channel)
+		.and_then(|channel| {
+			channel
+				.permission_overwrites
+				.as_ref()?
+				.iter()
+				.find(|overwrite| {
+					overwrite.kind == PermissionOverwriteType::Role && overwrite.id == role.cast()
+				})
+				.map(|overwrite| (overwrite.allow, overwrite.deny))
+		})
+		.unwrap_or((Permissions::empty(), Permissions::empty()))
+}
+
+/// Waits until the cached overwrite of the role denies `CONNECT`, returning whether it did before
+/// [`PROPAGATION_TIMEOUT`].
+async fn propagated(channel: Id<ChannelMarker>, role: Id<RoleMarker>) -> bool {
+	let wait = async {
+		while !overwrite(channel, role).1.contains(Permissions::CONNECT) {
+			tokio::time::sleep(PROPAGATION_POLL).await;
+		}
+	};
+	tokio::time::timeout(PROPAGATION_TIMEOUT, wait)
+		.await
+		.is_ok()
+}
+
+/// Denies `CONNECT` and prunes the channel if `lock`, otherwise removes the denial.
+async fn run(ctx: super::Context, lock: bool) -> super::Result {
+	let guild = ctx.guild;
+	let channel: Id<ChannelMarker> = ctx.options().required("channel");
+	if !super::is_voice_channel(guild, channel) {
+		return ctx.reply(super::NOT_VOICE_CHANNEL.to_owned()).await;
+	}
+	let role = ctx
+		.options()
+		.get::<Id<RoleMarker>>("role")
+		.unwrap_or_else(|| guild.cast());
+	let target = if role == guild.cast() {
+		"@everyone".to_owned()
+	} else {
+		format!("<@&{role}>")
+	};
+
+	match missing_permissions(channel) {
+		None => {
+			return Err(super::Error::CacheMiss {
+				resource: "channel",
+				id: channel.get(),
+			})
+		}
+		Some(missing) if !missing.is_empty() => {
+			let missing: Vec<_> = missing.iter_names().map(|(name, _)| name).collect();
+			return ctx
+				.reply(format!(
+					"I'm missing these permissions in <#{channel}>: {}",
+					missing.join(", ")
+				))
+				.await;
+		}
+		Some(_) => {}
+	}
+
+	let (allow, deny) = overwrite(channel, role);
+	if deny.contains(Permissions::CONNECT) == lock {
+		return ctx
+			.reply(format!(
+				"<#{channel}> is already {} for {target}",
+				if lock { "locked" } else { "unlocked" }
+			))
+			.await;
+	}
+
+	ctx.ack().await?;
+
+	let reason = ctx.interaction.author_id().map(|user| {
+		format!(
+			"{} by user {user}",
+			if lock { "Locked" } else { "Unlocked" }
+		)
+	});
+	let (allow, deny) = if lock {
+		(
+			allow.difference(Permissions::CONNECT),
+			deny.union(Permissions::CONNECT),
+		)
+	} else {
+		(allow, deny.difference(Permissions::CONNECT))
+	};
+	if lock {
+		BOT.locks.mark(channel);
+	}
+	let result = if allow.is_empty() && deny.is_empty() {
+		let request = BOT.http.delete_channel_permission(channel).role(role);
+		match &reason {
+			Some(reason) => request.reason(reason).await,
+			None => request.await,
+		}
+	} else {
+		let overwrite = PermissionOverwrite {
+			allow: Some(allow),
+			deny: Some(deny),
+			id: role.cast(),
+			kind: OverwriteType::Role,
+		};
+		let request = BOT.http.update_channel_permission(channel, &overwrite);
+		match &reason {
+			Some(reason) => request.reason(reason).await,
+			None => request.await,
+		}
+	};
+	if let Err(e) = result {
+		BOT.locks.unmark(channel);
+		tracing::warn!(channel_id = %channel, error = &e as &dyn std::error::Error, "unable to update overwrite");
+		return ctx
+			.update_response(&format!(
+				"Unable to update the permissions of <#{channel}>, nothing was changed"
+			))
+			.await;
+	}
+
+	if !lock {
+		return ctx
+			.update_response(&format!("Unlocked <#{channel}> for {target}"))
+			.await;
+	}
+
+	let message = if propagated(channel, role).await {
+		let summary = crate::prune::channel(channel, guild, Trigger::Command, |_| true, None).await;
+		let mut message = if summary.report {
+			format!(
+				"Locked <#{channel}> for {target}, report mode: {} users would be pruned",
+				summary.pruned()
+			)
+		} else {
+			format!(
+				"Locked <#{channel}> for {target}, {} users pruned",
+				summary.pruned()
+			)
+		};
+		if !summary.failed.is_empty() {
+			message += &format!(", {} failed", summary.failed.len());
+		}
+		message
+	} else {
+		format!(
+			"Locked <#{channel}> for {target}, but the change wasn't received in time so nobody was pruned, run `/prune` to prune it"
+		)
+	};
+	ctx.update_response(&message).await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test::{member_overwrite, role_overwrite, Guild};
+
+	#[test]
+	fn requires_managing_the_channel() {
+		const GUILD: u64 = 3_670_000;
+		Guild::new(GUILD)
+			.voice(GUILD + 10, &[])
+			.voice(
+				GUILD + 11,
+				&[member_overwrite(
+					crate::test::BOT_ID,
+					REQUIRED_PERMISSIONS,
+					Permissions::empty(),
+				)],
+			)
+			.voice(
+				GUILD + 12,
+				&[
+					member_overwrite(
+						crate::test::BOT_ID,
+						REQUIRED_PERMISSIONS,
+						Permissions::empty(),
+					),
+					role_overwrite(GUILD + 1, Permissions::empty(), Permissions::MANAGE_ROLES),
+				],
+			)
+			.create();
+
+		assert_eq!(
+			missing_permissions(Id::new(GUILD + 10)),
+			Some(REQUIRED_PERMISSIONS)
+		);
+		assert_eq!(
+			missing_permissions(Id::new(GUILD + 11)),
+			Some(Permissions::empty())
+		);
+		// member overwrites take precedence over role overwrites
+		assert_eq!(
+			missing_permissions(Id::new(GUILD + 12)),
+			Some(Permissions::empty())
+		);
+		assert_eq!(missing_permissions(Id::new(GUILD + 13)), None);
+	}
+
+	#[test]
+	fn administrators_manage_every_channel() {
+		const GUILD: u64 = 3_671_000;
+		Guild::new(GUILD)
+			.everyone(Permissions::ADMINISTRATOR)
+			.voice(
+				GUILD + 10,
+				&[role_overwrite(
+					GUILD,
+					Permissions::empty(),
+					REQUIRED_PERMISSIONS,
+				)],
+			)
+			.create();
+
+		assert_eq!(
+			missing_permissions(Id::new(GUILD + 10)),
+			Some(Permissions::empty())
+		);
+	}
+
+	#[test]
+	fn reads_role_overwrite() {
+		const GUILD: u64 = 3_672_000;
+		Guild::new(GUILD)
+			.voice(
+				GUILD + 10,
+				&[
+					role_overwrite(GUILD, Permissions::SPEAK, Permissions::CONNECT),
+					member_overwrite(GUILD + 2, Permissions::CONNECT, Permissions::empty()),
+				],
+			)
+			.create();
+
+		assert_eq!(
+			overwrite(Id::new(GUILD + 10), Id::new(GUILD)),
+			(Permissions::SPEAK, Permissions::CONNECT)
+		);
+		// member overwrites share the ID space, but aren't role overwrites
+		assert_eq!(
+			overwrite(Id::new(GUILD + 10), Id::new(GUILD + 2)),
+			(Permissions::empty(), Permissions::empty())
+		);
+	}
+}
@@ -0,0 +1,448 @@
+//! Operator commands, restricted to the bot's owners configured through the `OWNERS` environment
+//! variable.
+//!
+//! Unlike the other commands these are usable everywhere, including DMs, so they are dispatched
+//! before the guild lookup.
+
+use std::iter;
+
+use twilight_model::{
+	application::{
+		command::{Command, CommandOptionChoice, CommandOptionChoiceValue, CommandType},
+		interaction::{application_command::CommandData, Interaction, InteractionType},
+	},
+	channel::message::component::{ActionRow, Button, ButtonStyle, Component},
+	guild::Permissions,
+	id::{marker::GuildMarker, Id},
+};
+use twilight_util::builder::command::{
+	CommandBuilder, IntegerBuilder, StringBuilder, SubCommandBuilder,
+};
+
+use super::{components, respond, Options, Response};
+use crate::{error::Error, BOT};
+
+pub const NAME: &str = "maintenance";
+
+/// Prefix of the custom IDs of this command's components.
+pub const COMPONENT_PREFIX: &str = "maintenance:";
+
+/// Custom ID of the leave confirmation button, followed by the guild ID.
+const LEAVE: &str = "maintenance:leave:";
+
+/// Custom ID of the leave cancellation button.
+const CANCEL: &str = "maintenance:cancel";
+
+/// Reply to users who are not owners.
+const DENIED: &str = "Only the bot's owners may use this command";
+
+/// Number of guilds listed per page.
+const PAGE_SIZE: usize = 10;
+
+pub fn define() -> Command {
+	CommandBuilder::new(NAME, "Operate the bot", CommandType::ChatInput)
+		.default_member_permissions(Permissions::ADMINISTRATOR)
+		.dm_permission(true)
+		.option(
+			SubCommandBuilder::new(
+				"decisions",
+				"List a server's recent auto prune decisions of channel and role updates",
+			)
+			.option(
+				StringBuilder::new("guild", "ID of the server")
+					.autocomplete(true)
+					.required(true),
+			),
+		)
+		.option(
+			SubCommandBuilder::new("guilds", "List the servers the bot is in").option(
+				IntegerBuilder::new("page", "Page of the list, defaults to the first").min_value(1),
+			),
+		)
+		.option(
+			SubCommandBuilder::new("leave", "Make the bot leave a server").option(
+				StringBuilder::new("guild", "ID of the server")
+					.autocomplete(true)
+					.required(true),
+			),
+		)
+		.option(SubCommandBuilder::new(
+			"reload-commands",
+			"Register the bot's commands with Discord again",
+		))
+		.build()
+}
+
+/// Invoked subcommand with its options.
+#[derive(Debug, PartialEq, Eq)]
+enum Subcommand<'a> {
+	Decisions {
+		guild: &'a str,
+	},
+	/// `page` is out of range if not positive.
+	Guilds {
+		page: usize,
+	},
+	Leave {
+		guild: &'a str,
+	},
+	ReloadCommands,
+}
+
+impl<'a> Subcommand<'a> {
+	fn parse(options: Options<'a>) -> Self {
+		match options.subcommand() {
+			("decisions", options) => Self::Decisions {
+				guild: options.required("guild"),
+			},
+			("guilds", options) => Self::Guilds {
+				page: options.get::<i64>("page").map_or(1, |page| {
+					usize::try_from(page)
+						.ok()
+						.filter(|&page| page != 0)
+						.unwrap_or(usize::MAX)
+				}),
+			},
+			("leave", options) => Self::Leave {
+				guild: options.required("guild"),
+			},
+			("reload-commands", _) => Self::ReloadCommands,
+			_ => unreachable!("undefined"),
+		}
+	}
+}
+
+/// Whether the invoker is one of the bot's owners.
+fn is_owner(interaction: &Interaction) -> bool {
+	interaction
+		.author_id()
+		.is_some_and(|user| BOT.owners.contains(&user))
+}
+
+/// Run the invocation, or autocomplete its focused option.
+pub async fn run(interaction: &Interaction, data: &CommandData) -> super::Result {
+	let options = Options(&data.options);
+	if interaction.kind == InteractionType::ApplicationCommandAutocomplete {
+		let choices = match options.focused() {
+			Some(("guild", value)) if is_owner(interaction) => guild_choices(value),
+			_ => Vec::new(),
+		};
+		return respond(interaction, Response::Choices(choices)).await;
+	}
+
+	if !is_owner(interaction) {
+		tracing::info!("denied maintenance command");
+		return respond(interaction, Response::Message(DENIED.to_owned())).await;
+	}
+
+	let message = match Subcommand::parse(options) {
+		Subcommand::Decisions { guild } => return decisions(interaction, guild).await,
+		Subcommand::Guilds { page } => guilds(page),
+		Subcommand::Leave { guild } => return leave(interaction, guild).await,
+		Subcommand::ReloadCommands => {
+			format!("Registered {} commands", super::register().await?)
+		}
+	};
+	respond(interaction, Response::Message(message)).await
+}
+
+/// Handle a click of one of the leave confirmation's buttons.
+pub async fn component(interaction: &Interaction, custom_id: &str) -> super::Result {
+	if !is_owner(interaction) {
+		return respond(interaction, Response::Message(DENIED.to_owned())).await;
+	}
+
+	let message = if custom_id == CANCEL {
+		"Cancelled".to_owned()
+	} else if let Some(guild) = custom_id
+		.strip_prefix(LEAVE)
+		.and_then(|id| id.parse::<Id<GuildMarker>>().ok())
+	{
+		let name = guild_name(guild);
+		tracing::info!(guild_id = %guild, "leaving guild by maintenance command");
+		BOT.http.leave_guild(guild).await?;
+		format!("Left {name}")
+	} else {
+		tracing::info!(custom_id, "unknown component");
+		return Ok(());
+	};
+	respond(interaction, Response::Update(message, Vec::new())).await
+}
+
+/// The guild's recorded auto prune decisions, most recent first.
+async fn decisions(interaction: &Interaction, value: &str) -> super::Result {
+	let guild = parse_guild(value).ok_or_else(|| Error::InvalidOption {
+		name: "server",
+		value: value.to_owned(),
+	})?;
+	let decisions = BOT.decisions.guild(guild);
+	if decisions.is_empty() {
+		let message = format!("No decisions recorded for {}", guild_name(guild));
+		return respond(interaction, Response::Message(message)).await;
+	}
+
+	let header = format!(
+		"{} recent decisions of {}",
+		decisions.len(),
+		guild_name(guild)
+	);
+	let lines = decisions.into_iter().map(|decision| {
+		let at = decision.at;
+		let outcome = if decision.skipped {
+			"skipped"
+		} else {
+			"pruning"
+		};
+		format!(
+			"• <t:{at}:T> {:?} {}: {outcome}, {}",
+			decision.event, decision.target, decision.reason
+		)
+	});
+	components::paginate(interaction, NAME, iter::once(header).chain(lines)).await
+}
+
+/// One page of the bot's guilds with their member count and auto prune status.
+fn guilds(page: usize) -> String {
+	let mut guilds: Vec<_> = BOT
+		.cache
+		.iter()
+		.guilds()
+		.map(|guild| (guild.name().to_lowercase(), guild.id()))
+		.collect();
+	if guilds.is_empty() {
+		return "I'm not in any server".to_owned();
+	}
+	guilds.sort_unstable();
+
+	let pages = (guilds.len() + PAGE_SIZE - 1) / PAGE_SIZE;
+	if page > pages {
+		return format!("There are only {pages} pages");
+	}
+
+	let lines = guilds
+		.iter()
+		.skip((page - 1) * PAGE_SIZE)
+		.take(PAGE_SIZE)
+		.map(|&(_, guild)| {
+			let members = BOT
+				.cache
+				.guild(guild)
+				.and_then(|guild| guild.member_count())
+				.map_or_else(|| "?".to_owned(), |members| members.to_string());
+			let status = if BOT.is_paused(guild) {
+				"paused"
+			} else if BOT.auto_prune(guild) {
+				"enabled"
+			} else {
+				"disabled"
+			};
+			format!(
+				"• {} (`{guild}`): {members} members, auto prune {status}",
+				guild_name(guild)
+			)
+		});
+
+	let mut message = format!("**Page {page}/{pages}** of {} servers", guilds.len());
+	for line in lines {
+		message.push('\n');
+		message.push_str(&line);
+	}
+	message
+}
+
+/// Ask for confirmation before leaving the guild.
+async fn leave(interaction: &Interaction, value: &str) -> super::Result {
+	let guild = parse_guild(value).ok_or_else(|| Error::InvalidOption {
+		name: "server",
+		value: value.to_owned(),
+	})?;
+
+	let button = |custom_id: String, label: &str, style| {
+		Component::Button(Button {
+			custom_id: Some(custom_id),
+			disabled: false,
+			emoji: None,
+			label: Some(label.to_owned()),
+			style,
+			url: None,
+		})
+	};
+	let components = vec![Component::ActionRow(ActionRow {
+		components: vec![
+			button(format!("{LEAVE}{guild}"), "Leave", ButtonStyle::Danger),
+			button(CANCEL.to_owned(), "Cancel", ButtonStyle::Secondary),
+		],
+	})];
+	let message = format!("Leave {} (`{guild}`)?", guild_name(guild));
+	respond(interaction, Response::Prompt(message, components)).await
+}
+
+/// The cached guild of the `guild` option's value.
+fn parse_guild(value: &str) -> Option<Id<GuildMarker>> {
+	value
+		.trim()
+		.parse()
+		.ok()
+		.filter(|&guild| BOT.cache.guild(guild).is_some())
+}
+
+/// Bold name of the guild, or its ID if not cached.
+fn guild_name(guild: Id<GuildMarker>) -> String {
+	BOT.cache.guild(guild).map_or_else(
+		|| guild.to_string(),
+		|guild| format!("**{}**", escape(guild.name())),
+	)
+}
+
+/// The text with Discord's markdown characters escaped, so that it renders as is.
+fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		if matches!(
+			c,
+			'\\' | '*' | '_' | '~' | '`' | '|' | '>' | '#' | '-' | '[' | ']' | '(' | ')' | '<'
+		) {
+			escaped.push('\\');
+		}
+		escaped.push(c);
+	}
+	escaped
+}
+
+/// Guilds whose name or ID contains the value.
+fn guild_choices(value: &str) -> Vec<CommandOptionChoice> {
+	let value = value.to_lowercase();
+	BOT.cache
+		.iter()
+		.guilds()
+		.filter(|guild| {
+			guild.name().to_lowercase().contains(&value) || guild.id().to_string().contains(&value)
+		})
+		.take(super::CHOICE_LIMIT)
+		.map(|guild| CommandOptionChoice {
+			name: guild.name().chars().take(100).collect(),
+			name_localizations: None,
+			value: CommandOptionChoiceValue::String(guild.id().to_string()),
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use twilight_model::application::interaction::application_command::{
+		CommandDataOption, CommandOptionValue,
+	};
+
+	use super::*;
+
+	/// Options of the subcommand invocation.
+	fn invoke(name: &str, options: Vec<CommandDataOption>) -> Vec<CommandDataOption> {
+		vec![CommandDataOption {
+			name: name.to_owned(),
+			value: CommandOptionValue::SubCommand(options),
+		}]
+	}
+
+	fn option(name: &str, value: CommandOptionValue) -> CommandDataOption {
+		CommandDataOption {
+			name: name.to_owned(),
+			value,
+		}
+	}
+
+	#[test]
+	fn parses_subcommands() {
+		let guild = || vec![option("guild", CommandOptionValue::String("1".to_owned()))];
+		for (options, expected) in [
+			(
+				invoke("decisions", guild()),
+				Subcommand::Decisions { guild: "1" },
+			),
+			(invoke("guilds", Vec::new()), Subcommand::Guilds { page: 1 }),
+			(invoke("leave", guild()), Subcommand::Leave { guild: "1" }),
+			(
+				invoke("reload-commands", Vec::new()),
+				Subcommand::ReloadCommands,
+			),
+		] {
+			assert_eq!(Subcommand::parse(Options(&options)), expected);
+		}
+	}
+
+	#[test]
+	fn parses_every_defined_subcommand() {
+		for subcommand in define().options {
+			let options = subcommand
+				.options
+				.unwrap_or_default()
+				.into_iter()
+				.filter(|defined| defined.required == Some(true))
+				.map(|defined| option(&defined.name, CommandOptionValue::String("1".to_owned())))
+				.collect();
+			Subcommand::parse(Options(&invoke(&subcommand.name, options)));
+		}
+	}
+
+	#[test]
+	fn parses_pages() {
+		for (page, expected) in [
+			(1, 1),
+			(3, 3),
+			// out of range
+			(0, usize::MAX),
+			(-2, usize::MAX),
+		] {
+			let options = invoke(
+				"guilds",
+				vec![option("page", CommandOptionValue::Integer(page))],
+			);
+			assert_eq!(
+				Subcommand::parse(Options(&options)),
+				Subcommand::Guilds { page: expected },
+				"{page}"
+			);
+		}
+	}
+
+	#[test]
+	fn lists_pages() {
+		const GUILD: u64 = 3_600_000;
+		crate::test::Guild::new(GUILD).create();
+
+		// other tests cache guilds concurrently, so the number of pages may change
+		let first = guilds(1);
+		assert!(first.starts_with("**Page 1/"), "{first}");
+		assert!((2..=1 + PAGE_SIZE).contains(&first.lines().count()));
+		let out_of_range = guilds(usize::MAX);
+		assert!(
+			out_of_range.starts_with("There are only "),
+			"{out_of_range}"
+		);
+	}
+
+	#[test]
+	fn escapes_markdown() {
+		assert_eq!(escape("plain name"), "plain name");
+		assert_eq!(
+			escape("**bold** _it_ `code` ~~x~~ ||spoiler|| > quote"),
+			r"\*\*bold\*\* \_it\_ \`code\` \~\~x\~\~ \|\|spoiler\|\| \> quote"
+		);
+		assert_eq!(
+			escape("# [link](url) <@1> \\"),
+			r"\# \[link\]\(url\) \<@1\> \\"
+		);
+	}
+
+	#[test]
+	fn escapes_guild_names() {
+		const GUILD: u64 = 3_601_000;
+		crate::test::init();
+		let mut event = crate::test::Guild::new(GUILD).event();
+		if let twilight_model::gateway::event::Event::GuildCreate(guild) = &mut event {
+			guild.0.name = "*bold*".to_owned();
+		}
+		BOT.cache.update(&event);
+
+		assert_eq!(guild_name(Id::new(GUILD)), r"**\*bold\***");
+	}
+}
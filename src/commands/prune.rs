@@ -1,74 +1,957 @@
+use std::{
+	fmt::Write,
+	sync::atomic::{AtomicBool, Ordering},
+	time::{Duration, Instant},
+};
+
+use futures_util::{future::BoxFuture, FutureExt};
+use tokio::time;
+use twilight_cache_inmemory::model::CachedVoiceState;
 use twilight_model::{
-	application::{
-		command::{Command, CommandType},
-		interaction::application_command::CommandOptionValue,
+	application::command::{Command, CommandOptionChoice, CommandOptionChoiceValue, CommandType},
+	channel::ChannelType,
+	guild::{PartialMember, Permissions},
+	id::{
+		marker::{ChannelMarker, GuildMarker, RoleMarker, UserMarker},
+		Id,
 	},
-	guild::Permissions,
 };
-use twilight_util::builder::command::{ChannelBuilder, CommandBuilder, RoleBuilder};
+use twilight_util::builder::command::{
+	BooleanBuilder, ChannelBuilder, CommandBuilder, IntegerBuilder, RoleBuilder, StringBuilder,
+};
+
+use crate::{
+	prune::{Failure, Manual, SkipReason, Summary, Trigger},
+	settings::Settings,
+	voice::VoiceStateExt,
+	BOT, MONITORED_CHANNEL_TYPES,
+};
+
+/// Time after which the interaction token may have expired, Discord allows 15 minutes.
+const TOKEN_LIFETIME: Duration = Duration::from_secs(14 * 60);
+
+/// Interval between progress updates of long running prunes.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Options each selecting a voice channel to prune.
+pub(super) const CHANNEL_OPTIONS: [&str; 3] = ["channel", "channel2", "channel3"];
+
+pub struct Prune;
+
+impl super::SlashCommand for Prune {
+	const NAME: &'static str = "prune";
+	const COOLDOWN: Option<Duration> = Some(Duration::from_secs(10));
+
+	fn define() -> Command {
+		CommandBuilder::new(
+			Self::NAME,
+			"Prune users from voice channels",
+			CommandType::ChatInput,
+		)
+		.default_member_permissions(Permissions::MOVE_MEMBERS)
+		.dm_permission(false)
+		.option(StringBuilder::new("channel", "Only from this voice channel").autocomplete(true))
+		.option(StringBuilder::new("channel2", "Also from this voice channel").autocomplete(true))
+		.option(StringBuilder::new("channel3", "Also from this voice channel").autocomplete(true))
+		.option(
+			ChannelBuilder::new("category", "Only from voice channels in this category")
+				.channel_types([ChannelType::GuildCategory]),
+		)
+		.option(RoleBuilder::new("role", "Only users with this role"))
+		.option(BooleanBuilder::new(
+			"announce",
+			"Post a summary to this channel when done",
+		))
+		.option(BooleanBuilder::new(
+			"force",
+			"Prune even in report mode, requires the Manage Server permission",
+		))
+		.option(BooleanBuilder::new(
+			"include-bots",
+			"Also prune bots, even if the server ignores them",
+		))
+		.option(BooleanBuilder::new(
+			"include-self",
+			"Also prune yourself, otherwise you are skipped",
+		))
+		.option(BooleanBuilder::new(
+			"verbose",
+			"List how many users were checked and why the others were kept",
+		))
+		.option(BooleanBuilder::new(
+			"refresh",
+			"Fetch voice states from Discord first, only without a channel or category",
+		))
+		.option(
+			IntegerBuilder::new(
+				"idle",
+				"Instead prune users deafened for at least this many minutes",
+			)
+			.min_value(1),
+		)
+		.build()
+	}
 
-use crate::{BOT, MONITORED_CHANNEL_TYPES};
+	fn run(ctx: super::Context) -> BoxFuture<'static, super::Result> {
+		run(ctx).boxed()
+	}
 
-pub fn define() -> Command {
-	CommandBuilder::new(
-		"prune",
-		"Prune users from voice channels",
-		CommandType::ChatInput,
-	)
-	.default_member_permissions(Permissions::MOVE_MEMBERS)
-	.dm_permission(false)
-	.option(
-		ChannelBuilder::new("channel", "Only from this voice channel")
-			.channel_types(MONITORED_CHANNEL_TYPES),
-	)
-	.option(RoleBuilder::new("role", "Only users with this role"))
-	.build()
+	fn autocomplete(ctx: &super::Context, option: &str, value: &str) -> Vec<CommandOptionChoice> {
+		match option {
+			"channel" | "channel2" | "channel3" => channels(ctx.guild, value),
+			_ => unreachable!("undefined"),
+		}
+	}
 }
 
-pub async fn run(ctx: super::Context) -> super::Result {
-	let guild = ctx.interaction.guild_id.expect("required");
+/// Monitored voice channels in the guild whose names fuzzy match the query, best matches first.
+fn channels(guild: Id<GuildMarker>, query: &str) -> Vec<CommandOptionChoice> {
+	let query = query.to_lowercase();
+	let Some(channels) = BOT.cache.guild_channels(guild) else {
+		return Vec::new();
+	};
+
+	let mut matches: Vec<_> = channels
+		.iter()
+		.filter_map(|&id| {
+			let channel = BOT.cache.channel(id)?;
+			if !MONITORED_CHANNEL_TYPES.contains(&channel.kind) {
+				return None;
+			}
+			let name = channel.name.clone()?;
+			let rank = rank(&name.to_lowercase(), &query)?;
+			Some((rank, channel.position.unwrap_or_default(), id, name))
+		})
+		.filter(|&(.., id, _)| BOT.is_monitored(id))
+		.collect();
+	matches.sort_unstable_by_key(|&(rank, position, id, _)| (rank, position, id));
+
+	matches
+		.into_iter()
+		.take(super::CHOICE_LIMIT)
+		.map(|(.., id, name)| CommandOptionChoice {
+			name,
+			name_localizations: None,
+			value: CommandOptionChoiceValue::String(id.to_string()),
+		})
+		.collect()
+}
+
+/// How well the name matches the query, lower is better: prefix, then substring, then the query's
+/// characters in order.
+fn rank(name: &str, query: &str) -> Option<u8> {
+	if name.starts_with(query) {
+		Some(0)
+	} else if name.contains(query) {
+		Some(1)
+	} else {
+		let mut chars = name.chars();
+		query
+			.chars()
+			.all(|c| chars.any(|name| name == c))
+			.then_some(2)
+	}
+}
+
+/// Voice channel in the guild identified by an autocompleted option value.
+fn parse_channel(guild: Id<GuildMarker>, value: &str) -> Option<Id<ChannelMarker>> {
+	let id = value.parse().ok()?;
+	super::is_voice_channel(guild, id).then_some(id)
+}
+
+/// Time users must have been deafened for to be pruned as idle, `None` unless a positive number
+/// of minutes.
+fn idle_threshold(minutes: i64) -> Option<Duration> {
+	let minutes = u64::try_from(minutes)
+		.ok()
+		.filter(|&minutes| minutes != 0)?;
+	Some(Duration::from_secs(minutes * 60))
+}
+
+/// Whether the bot's highest role is above the role, `true` if the role is not cached.
+fn outranks(guild: Id<GuildMarker>, role: Id<RoleMarker>) -> bool {
+	BOT.cache.role(role).map_or(true, |role| {
+		let bot = BOT
+			.cache
+			.member(guild, BOT.id)
+			.map_or(0, |member| crate::prune::highest_position(member.roles()));
+		role.position < bot
+	})
+}
+
+/// Monitored voice channels in the category, in position order.
+fn category_channels(
+	guild: Id<GuildMarker>,
+	category: Id<ChannelMarker>,
+) -> Vec<Id<ChannelMarker>> {
+	let Some(channels) = BOT.cache.guild_channels(guild) else {
+		return Vec::new();
+	};
+	let children = channels.iter().copied().filter(|&id| {
+		BOT.cache
+			.channel(id)
+			.is_some_and(|channel| channel.parent_id == Some(category))
+	});
+	let mut channels: Vec<_> = crate::monitored(guild, children).into_iter().collect();
+	channels.sort_unstable_by_key(|&id| {
+		(
+			BOT.cache
+				.channel(id)
+				.and_then(|channel| channel.position)
+				.unwrap_or_default(),
+			id,
+		)
+	});
+	channels
+}
+
+/// Whether the invoking member has one of the guild's prune roles, if it has any.
+///
+/// Without prune roles, Discord only lets members with Move Members invoke `/prune`. Fails closed
+/// without member data.
+fn may_prune(settings: &Settings, member: Option<&PartialMember>) -> bool {
+	settings.prune_roles.is_empty()
+		|| member.is_some_and(|member| {
+			member
+				.roles
+				.iter()
+				.any(|role| settings.prune_roles.contains(role))
+		})
+}
+
+/// Whether the user is the `invoker`, recording in `skipped` if they would have been pruned.
+fn skips_invoker(
+	state: &CachedVoiceState,
+	invoker: Option<Id<UserMarker>>,
+	settings: &Settings,
+	skipped: &AtomicBool,
+) -> bool {
+	if Some(state.user_id()) != invoker {
+		return false;
+	}
+	// server prunes filter before checking permissions
+	if state.is_denied(&BOT.cache, settings) {
+		skipped.store(true, Ordering::Relaxed);
+	}
+	true
+}
+
+async fn run(ctx: super::Context) -> super::Result {
+	let guild = ctx.guild;
+	let start = Instant::now();
+
+	let settings = BOT.settings.get(guild);
+	if !may_prune(&settings, ctx.interaction.member.as_ref()) {
+		return ctx
+			.reply("Only members with one of this server's prune roles may use `/prune`".to_owned())
+			.await;
+	}
+
+	let channels = ctx
+		.options()
+		.channels(&CHANNEL_OPTIONS, |value| parse_channel(guild, value))?;
+	if let Some(&unmonitored) = channels.iter().find(|&&channel| !BOT.is_monitored(channel)) {
+		return ctx
+			.reply(format!("<#{unmonitored}> is not monitored"))
+			.await;
+	}
+	let category: Option<Id<ChannelMarker>> = ctx.options().get("category");
+	if !channels.is_empty() && category.is_some() {
+		return ctx
+			.reply("Pick either a channel or a category, not both".to_owned())
+			.await;
+	}
+	let role: Option<Id<RoleMarker>> = ctx.options().get("role");
+	if let Some(role) = role.filter(|&role| !outranks(guild, role)) {
+		return Err(super::Error::Hierarchy { role });
+	}
+	let refresh = ctx.options().get("refresh") == Some(true);
+	if refresh && (!channels.is_empty() || category.is_some()) {
+		return ctx
+			.reply("Voice states may only be refreshed when pruning the whole server".to_owned())
+			.await;
+	}
+
+	if !BOT.sees_voice_channels(guild) {
+		return ctx.reply(super::NO_VOICE_CHANNELS.to_owned()).await;
+	}
+
+	let trigger = if ctx.options().get("force") == Some(true) {
+		let permitted = ctx
+			.interaction
+			.member
+			.as_ref()
+			.and_then(|member| member.permissions)
+			.is_some_and(|permissions| permissions.contains(Permissions::MANAGE_GUILD));
+		if !permitted {
+			return ctx
+				.reply("Forcing a prune requires the Manage Server permission".to_owned())
+				.await;
+		}
+		Trigger::Forced
+	} else {
+		Trigger::Command
+	};
+
+	// the definition's minimum may be missing from a stale definition
+	let idle = match ctx.options().get::<i64>("idle") {
+		Some(minutes) => Some(idle_threshold(minutes).ok_or(super::Error::InvalidOption {
+			name: "idle",
+			value: minutes.to_string(),
+		})?),
+		None => None,
+	};
 
 	// await kicking all members before responding
 	ctx.ack().await?;
 
-	let mut channel = None;
-	let mut role = None;
-
-	for option in &ctx.data.options {
-		match option.name.as_str() {
-			"channel" => match option.value {
-				CommandOptionValue::Channel(id) => channel = Some(id),
-				_ => unreachable!("undefined"),
-			},
-			"role" => match option.value {
-				CommandOptionValue::Role(id) => role = Some(id),
-				_ => unreachable!("undefined"),
-			},
-			_ => unreachable!("undefined"),
+	let drift = if refresh {
+		Some(crate::refresh::voice_states(guild).await?)
+	} else {
+		None
+	};
+
+	let manual = Manual {
+		include_bots: ctx.options().get("include-bots") == Some(true),
+		..Manual::default()
+	};
+	// the invoker may have been moved into the channel without permission, so pruning them would
+	// lose the response
+	let invoker = ctx
+		.interaction
+		.author_id()
+		.filter(|_| ctx.options().get("include-self") != Some(true));
+	let skipped_self = AtomicBool::new(false);
+	let kick = |state: &CachedVoiceState| {
+		category.map_or(true, |category| state.in_category(&BOT.cache, category))
+			&& role.map_or(true, |role| state.member_has_role(&BOT.cache, role))
+			&& !skips_invoker(state, invoker, &settings, &skipped_self)
+	};
+	let prune = async {
+		Ok::<_, super::Error>(match (channels.as_slice(), idle) {
+			(channels, Some(threshold)) => {
+				let kick = |state: &CachedVoiceState| {
+					(channels.is_empty() || channels.contains(&state.channel_id())) && kick(state)
+				};
+				crate::prune::idle(guild, threshold, trigger, kick, Some(&manual)).await?
+			}
+			([], None) => crate::prune::guild(guild, trigger, None, kick, Some(&manual)).await?,
+			(channels, None) => {
+				crate::prune::channels(
+					channels,
+					guild,
+					trigger,
+					|_, state| kick(state),
+					Some(&manual),
+				)
+				.await
+			}
+		})
+	};
+	tokio::pin!(prune);
+
+	let mut interval = time::interval_at((start + PROGRESS_INTERVAL).into(), PROGRESS_INTERVAL);
+	let summary = loop {
+		tokio::select! {
+			summary = &mut prune => break summary?,
+			_ = interval.tick(), if start.elapsed() < TOKEN_LIFETIME => {
+				let (done, total) = manual.progress.get();
+				let message = format!("pruned {done}/{total} so far...");
+				if let Err(e) = ctx.update_response(&message).await {
+					tracing::debug!(error = &e as &dyn std::error::Error, "unable to report progress");
+				}
+			}
 		}
+	};
+
+	let mut message = outcome(&summary, &channels, category);
+	if skipped_self.load(Ordering::Relaxed) {
+		message += " (you were skipped — use include-self:true to prune yourself)";
+	}
+	if ctx.options().get("verbose") == Some(true) {
+		message += &format!("\n{}", breakdown(&summary));
 	}
+	if let Some(category) = category {
+		let scanned: Vec<_> = category_channels(guild, category)
+			.into_iter()
+			.map(|channel| format!("<#{channel}>"))
+			.collect();
+		message += &format!(
+			"\nscanned: {}",
+			if scanned.is_empty() {
+				"none".to_owned()
+			} else {
+				scanned.join(", ")
+			}
+		);
+	}
+	if channels.len() > 1 {
+		for &channel in &channels {
+			let pruned = summary
+				.channels
+				.iter()
+				.find(|prune| prune.channel == channel)
+				.map_or(0, |prune| prune.users.len());
+			message += &format!("\n<#{channel}>: {pruned}");
+		}
+	}
+	match drift {
+		Some(Some(drift)) => message += &format!("\nrefreshed voice states: {drift}"),
+		Some(None) => {
+			message += &format!(
+				"\nvoice states not refreshed: more than {} members",
+				crate::refresh::MEMBER_LIMIT
+			);
+		}
+		None => {}
+	}
+	if let Some(remaining) = BOT.settings.get(guild).paused_for() {
+		message += &format!(
+			"\nnote: auto pruning is paused for {}",
+			super::humanize(remaining)
+		);
+	}
+	if start.elapsed() >= TOKEN_LIFETIME {
+		tracing::info!("interaction token expired, sending summary elsewhere");
+		return fallback(&ctx, &message).await;
+	}
+	ctx.update_response(&message).await?;
 
-	let users = match (channel, role) {
-		(None, None) => crate::prune::guild(guild, |_| true).await,
-		(None, Some(role)) => {
-			crate::prune::guild(guild, |state| {
-				BOT.cache
-					.member(state.guild_id(), state.user_id())
-					.is_some_and(|member| member.roles().contains(&role))
-			})
-			.await
+	if ctx.options().get("announce") == Some(true) {
+		let of = match category {
+			Some(category) if channels.is_empty() => format!("<#{category}>"),
+			None if channels.is_empty() => "the server".to_owned(),
+			_ => mentions(&channels),
+		};
+		ctx.followup(&announcement(&ctx, &of, start, &summary))
+			.await?;
+	}
+
+	Ok(())
+}
+
+/// Response to the prune of the `channels`, or of the `category` or the whole server if none.
+fn outcome(
+	summary: &Summary,
+	channels: &[Id<ChannelMarker>],
+	category: Option<Id<ChannelMarker>>,
+) -> String {
+	let pruned_from = match (channels.len(), category) {
+		(0, Some(category)) => format!("{} channels in <#{category}>", summary.channels.len()),
+		(0, None) => format!("{} channels", summary.channels.len()),
+		_ => mentions(channels),
+	};
+	let mut message = if summary.report {
+		format!(
+			"report mode: {} users would be pruned from {pruned_from}",
+			summary.pruned()
+		)
+	} else {
+		format!("{} users pruned from {pruned_from}", summary.pruned())
+	};
+	if let Some(kinds) = crate::prune::describe_kinds(|kind| u32::from(summary.pruned_in(kind))) {
+		message += &format!(" ({kinds})");
+	}
+	let hierarchy = summary.skipped(SkipReason::Hierarchy);
+	if hierarchy != 0 {
+		message += &format!(", {hierarchy} skipped: role hierarchy");
+	}
+	if summary.resolved != 0 {
+		message += &format!(", {} skipped: moved or left", summary.resolved);
+	}
+	if summary.unnotified != 0 {
+		message += &format!(", {} not notified: DM limit reached", summary.unnotified);
+	}
+	if summary.verified != 0 {
+		message += &format!(
+			", {} verified: {} permitted and kept",
+			summary.verified, summary.spared
+		);
+	}
+	if summary.unverified != 0 {
+		message += &format!(", {} not verified: limit reached", summary.unverified);
+	}
+	for failure in [Failure::MissingPermissions, Failure::Busy, Failure::Other] {
+		let failed = summary
+			.failed
+			.iter()
+			.filter(|&&(_, kind)| kind == failure)
+			.count();
+		if failed != 0 {
+			message += &format!(", {failed} failed ({failure})");
 		}
-		(Some(channel), None) => crate::prune::channel(channel, guild, |_| true).await,
-		(Some(channel), Some(role)) => {
-			crate::prune::channel(channel, guild, |state| {
-				BOT.cache
-					.member(state.guild_id(), state.user_id())
-					.is_some_and(|member| member.roles().contains(&role))
-			})
-			.await
+	}
+	if let Some(removed) = summary.lost_permissions {
+		message += &format!(", aborted: lost permissions after {removed} kicks");
+	}
+	if summary.halted {
+		message += ", stopped by `/settings panic`";
+	}
+	message
+}
+
+/// Send the summary to the invoking channel if permitted, otherwise as a DM to the invoker.
+async fn fallback(ctx: &super::Context, message: &str) -> super::Result {
+	let channel = ctx.interaction.channel.as_ref().map(|channel| channel.id);
+	let channel = match channel {
+		Some(channel)
+			if BOT
+				.cache
+				.permissions()
+				.in_channel(BOT.id, channel)
+				.is_ok_and(|permissions| permissions.contains(Permissions::SEND_MESSAGES)) =>
+		{
+			channel
+		}
+		_ => {
+			if let Some(user) = ctx.interaction.author_id() {
+				BOT.dm(user, message).await;
+			}
+			return Ok(());
+		}
+	};
+
+	BOT.http.create_message(channel).content(message).await?;
+	Ok(())
+}
+
+/// Number of users the prune checked, pruned and kept per [`SkipReason`].
+fn breakdown(summary: &Summary) -> String {
+	let mut message = format!("{} checked: {} pruned", summary.checked, summary.pruned());
+	for reason in SkipReason::ALL {
+		let skipped = summary.skipped(reason);
+		if skipped != 0 {
+			_ = write!(message, ", {skipped} {reason}");
+		}
+	}
+	message
+}
+
+/// The channels' mentions, separated by commas.
+fn mentions(channels: &[Id<ChannelMarker>]) -> String {
+	let mentions: Vec<_> = channels
+		.iter()
+		.map(|channel| format!("<#{channel}>"))
+		.collect();
+	mentions.join(", ")
+}
+
+/// Public summary of the prune of what is described by `of`, listing channels until
+/// [`super::MESSAGE_LIMIT`] is reached.
+fn announcement(ctx: &super::Context, of: &str, start: Instant, summary: &Summary) -> String {
+	let mut message = String::from("Prune");
+	if let Some(user) = ctx.interaction.author_id() {
+		_ = write!(message, " by <@{user}>");
+	}
+	_ = write!(message, " of {of}");
+	_ = write!(
+		message,
+		" finished in {}s: {} users pruned",
+		start.elapsed().as_secs(),
+		summary.pruned()
+	);
+
+	for (i, prune) in summary.channels.iter().enumerate() {
+		let line = format!("\n<#{}>: {}", prune.channel, prune.users.len());
+		// leave room for the line noting the remaining channels
+		if message.len() + line.len() > super::MESSAGE_LIMIT - 32 {
+			_ = write!(
+				message,
+				"\n…and {} more channels",
+				summary.channels.len() - i
+			);
+			break;
 		}
+		message += &line;
+	}
+
+	message
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		sync::atomic::{AtomicBool, Ordering},
+		time::Duration,
 	};
 
-	ctx.update_response(&(format!("{users} users pruned")))
+	use twilight_model::{
+		channel::ChannelType,
+		guild::{PartialMember, Permissions},
+		id::{marker::GuildMarker, Id},
+	};
+
+	use crate::{
+		prune::{Failure, Manual, Summary, Trigger},
+		settings::{PruneOrder, Settings},
+		test::{role_overwrite, Guild},
+		voice::VoiceStateExt,
+		BOT,
+	};
+
+	const GUILD: u64 = 3250000;
+
+	#[test]
+	fn idle_threshold() {
+		assert_eq!(super::idle_threshold(-1), None);
+		assert_eq!(super::idle_threshold(0), None);
+		assert_eq!(super::idle_threshold(1), Some(Duration::from_secs(60)));
+		assert_eq!(
+			super::idle_threshold(90),
+			Some(Duration::from_secs(90 * 60))
+		);
+	}
+
+	#[test]
+	fn idle_minimum() {
+		use twilight_model::application::command::CommandOptionValue;
+
+		let command = <super::Prune as crate::commands::SlashCommand>::define();
+		let idle = command
+			.options
+			.iter()
+			.find(|option| option.name == "idle")
+			.expect("defined");
+		assert_eq!(idle.min_value, Some(CommandOptionValue::Integer(1)));
+	}
+
+	#[test]
+	fn outranks() {
+		// the bot's role is at position 10
+		let guild = Guild::new(GUILD)
+			.role(GUILD + 2, Permissions::empty(), 9)
+			.role(GUILD + 3, Permissions::empty(), 10)
+			.role(GUILD + 4, Permissions::empty(), 11)
+			.create();
+
+		assert!(super::outranks(guild, Id::new(GUILD + 2)));
+		assert!(!super::outranks(guild, Id::new(GUILD + 3)));
+		assert!(!super::outranks(guild, Id::new(GUILD + 4)));
+		// not cached
+		assert!(super::outranks(guild, Id::new(GUILD + 5)));
+	}
+
+	#[tokio::test]
+	async fn reports_hierarchy_skips() {
+		const GUILD: u64 = 3172000;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.role(GUILD + 2, Permissions::empty(), 10)
+			.voice(GUILD + 10, &[])
+			.member(GUILD + 100, &[GUILD + 2])
+			.member(GUILD + 101, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.connect(GUILD + 101, GUILD + 10)
+			.create();
+		let channel = Id::new(GUILD + 10);
+
+		let summary = crate::prune::channel(channel, guild, Trigger::Command, |_| true, None).await;
+
+		assert_eq!(
+			super::outcome(&summary, &[channel], None),
+			format!("1 users pruned from <#{channel}>, 1 skipped: role hierarchy")
+		);
+	}
+
+	#[test]
+	fn reports_moved_users() {
+		let channel = Id::new(3_541_010);
+		let mut summary = Summary::default();
+		summary.resolved = 2;
+
+		assert_eq!(
+			super::outcome(&summary, &[channel], None),
+			format!("0 users pruned from <#{channel}>, 2 skipped: moved or left")
+		);
+	}
+
+	/// Member with the roles.
+	fn member(roles: &[u64]) -> PartialMember {
+		let mut member = crate::test::member_payload(GUILD, GUILD + 100, roles, false);
+		member["permissions"] = serde_json::json!("0");
+		serde_json::from_value(member).expect("valid member")
+	}
+
+	#[test]
+	fn prune_roles() {
+		let mut settings = Settings::default();
+		assert!(super::may_prune(&settings, Some(&member(&[]))), "unset");
+
+		settings.prune_roles = vec![Id::new(GUILD + 2), Id::new(GUILD + 3)];
+		assert!(super::may_prune(
+			&settings,
+			Some(&member(&[GUILD + 4, GUILD + 3]))
+		));
+		assert!(!super::may_prune(&settings, Some(&member(&[GUILD + 4]))));
+		assert!(!super::may_prune(&settings, None), "fails closed");
+	}
+
+	/// Guild whose `@everyone` may not connect, with the voice channels `id + 13` and `id + 11` in
+	/// the category `id + 5`, `id + 12` hidden from the bot, and `id + 14` in the category `id + 6`,
+	/// each with a member.
+	fn categories(id: u64) -> Id<GuildMarker> {
+		let hidden = [role_overwrite(
+			id + 1,
+			Permissions::empty(),
+			Permissions::VIEW_CHANNEL,
+		)];
+		let mut guild = Guild::new(id)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.category(id + 5)
+			.category(id + 6);
+		for (channel, category, overwrites) in [
+			(id + 13, id + 5, &[][..]),
+			(id + 11, id + 5, &[]),
+			(id + 12, id + 5, &hidden),
+			(id + 14, id + 6, &[]),
+		] {
+			guild = guild
+				.channel(channel, ChannelType::GuildVoice, Some(category), overwrites)
+				.member(channel + 100, &[])
+				.connect(channel + 100, channel);
+		}
+		guild.create()
+	}
+
+	#[test]
+	fn category_channels() {
+		const GUILD: u64 = 3_650_000;
+		let guild = categories(GUILD);
+
+		assert_eq!(
+			super::category_channels(guild, Id::new(GUILD + 5)),
+			[Id::new(GUILD + 11), Id::new(GUILD + 13)]
+		);
+		assert_eq!(
+			super::category_channels(guild, Id::new(GUILD + 6)),
+			[Id::new(GUILD + 14)]
+		);
+	}
+
+	#[tokio::test]
+	async fn prunes_only_category() {
+		const GUILD: u64 = 3_651_000;
+		let guild = categories(GUILD);
+		let category = Id::new(GUILD + 5);
+
+		crate::prune::guild(
+			guild,
+			Trigger::Command,
+			None,
+			|state| state.in_category(&BOT.cache, category),
+			None,
+		)
+		.await
+		.unwrap();
+
+		let mut removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user.get())
+			.collect();
+		removed.sort_unstable();
+		assert_eq!(removed, [GUILD + 111, GUILD + 113]);
+	}
+
+	/// Guild whose `@everyone` may not connect, with the users `id + 100` and `id + 101` connected
+	/// to the voice channel `id + 10` and `id + 102` to `id + 11`.
+	fn offenders(id: u64) -> Id<GuildMarker> {
+		Guild::new(id)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(id + 10, &[])
+			.voice(id + 11, &[])
+			.member(id + 100, &[])
+			.member(id + 101, &[])
+			.member(id + 102, &[])
+			.connect(id + 100, id + 10)
+			.connect(id + 101, id + 10)
+			.connect(id + 102, id + 11)
+			.create()
+	}
+
+	/// Users removed in the guild, sorted.
+	fn removed(guild: Id<GuildMarker>) -> Vec<u64> {
+		let mut removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user.get())
+			.collect();
+		removed.sort_unstable();
+		removed
+	}
+
+	#[tokio::test]
+	async fn channel_prune_skips_invoker() {
+		const GUILD: u64 = 3_750_000;
+		let guild = offenders(GUILD);
+		let invoker = Some(Id::new(GUILD + 100));
+		let settings = Settings::default();
+		let skipped = AtomicBool::new(false);
+
+		crate::prune::channel(
+			Id::new(GUILD + 10),
+			guild,
+			Trigger::Command,
+			|state| !super::skips_invoker(state, invoker, &settings, &skipped),
+			None,
+		)
+		.await;
+
+		assert_eq!(removed(guild), [GUILD + 101]);
+		assert!(skipped.load(Ordering::Relaxed));
+	}
+
+	#[tokio::test]
+	async fn guild_prune_skips_invoker() {
+		const GUILD: u64 = 3_751_000;
+		let guild = offenders(GUILD);
+		let invoker = Some(Id::new(GUILD + 102));
+		let settings = Settings::default();
+		let skipped = AtomicBool::new(false);
+
+		crate::prune::guild(
+			guild,
+			Trigger::Command,
+			None,
+			|state| !super::skips_invoker(state, invoker, &settings, &skipped),
+			None,
+		)
 		.await
+		.unwrap();
+
+		assert_eq!(removed(guild), [GUILD + 100, GUILD + 101]);
+		assert!(skipped.load(Ordering::Relaxed));
+	}
+
+	#[tokio::test]
+	async fn permitted_invoker_is_not_reported() {
+		const GUILD: u64 = 3_752_000;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL | Permissions::CONNECT)
+			.voice(GUILD + 10, &[])
+			.member(GUILD + 100, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.create();
+		let settings = Settings::default();
+		let skipped = AtomicBool::new(false);
+
+		crate::prune::guild(
+			guild,
+			Trigger::Command,
+			None,
+			|state| !super::skips_invoker(state, Some(Id::new(GUILD + 100)), &settings, &skipped),
+			None,
+		)
+		.await
+		.unwrap();
+
+		assert!(!skipped.load(Ordering::Relaxed));
+	}
+
+	#[tokio::test]
+	async fn aborts_after_losing_permissions() {
+		const GUILD: u64 = 3_970_000;
+		let users = GUILD + 100..GUILD + 110;
+		let guild = users
+			.clone()
+			.fold(
+				Guild::new(GUILD).everyone(Permissions::VIEW_CHANNEL).voice(
+					GUILD + 10,
+					&[role_overwrite(
+						GUILD,
+						Permissions::empty(),
+						Permissions::CONNECT,
+					)],
+				),
+				|guild, user| guild.member(user, &[]).connect(user, GUILD + 10),
+			)
+			.create();
+		let channel = Id::new(GUILD + 10);
+		BOT.settings.update(guild, |settings| {
+			settings.prune_order = PruneOrder::RolePosition;
+		});
+		// the bot loses its permissions after removing two users
+		for user in users.clone().skip(2) {
+			crate::test::deny(guild, Id::new(user));
+		}
+
+		let summary = crate::prune::channel(channel, guild, Trigger::Command, |_| true, None).await;
+
+		let removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user.get())
+			.collect();
+		assert_eq!(removed, [GUILD + 100, GUILD + 101]);
+		assert_eq!(summary.lost_permissions, Some(2));
+		let requested = |user| {
+			!crate::test::requests(&crate::test::member_path(guild, Id::new(user))).is_empty()
+		};
+		assert!(requested(GUILD + 102));
+		assert!(!requested(users.end - 1), "aborted before the last user");
+		assert!(summary
+			.failed
+			.iter()
+			.all(|&(_, failure)| failure == Failure::MissingPermissions));
+		assert!(super::outcome(&summary, &[channel], None)
+			.ends_with(", aborted: lost permissions after 2 kicks"));
+	}
+
+	#[test]
+	fn mentions_pruned_channels() {
+		let (first, second) = (Id::new(4_040_010), Id::new(4_040_011));
+		let category = Id::new(4_040_005);
+		let mut summary = Summary::default();
+
+		assert_eq!(
+			super::outcome(&summary, &[first], None),
+			"0 users pruned from <#4040010>"
+		);
+		assert_eq!(
+			super::outcome(&summary, &[first, second], Some(category)),
+			"0 users pruned from <#4040010>, <#4040011>"
+		);
+		assert_eq!(
+			super::outcome(&summary, &[], Some(category)),
+			"0 users pruned from 0 channels in <#4040005>"
+		);
+		assert_eq!(
+			super::outcome(&summary, &[], None),
+			"0 users pruned from 0 channels"
+		);
+
+		summary.report = true;
+		assert_eq!(
+			super::outcome(&summary, &[first], None),
+			"report mode: 0 users would be pruned from <#4040010>"
+		);
+	}
+
+	#[tokio::test]
+	async fn channels_prune_once() {
+		const GUILD: u64 = 4_071_000;
+		let mut guild = Guild::new(GUILD).everyone(Permissions::VIEW_CHANNEL);
+		for channel in GUILD + 10..GUILD + 13 {
+			guild = guild
+				.voice(channel, &[])
+				.member(channel + 100, &[])
+				.connect(channel + 100, channel);
+		}
+		let guild = guild.create();
+		let channels = [GUILD + 10, GUILD + 11, GUILD + 12].map(Id::new);
+		let manual = Manual::default();
+
+		let summary = crate::prune::channels(
+			&channels,
+			guild,
+			Trigger::Command,
+			|_, _| true,
+			Some(&manual),
+		)
+		.await;
+
+		assert_eq!(summary.pruned(), 3);
+		assert_eq!(manual.progress.get(), (3, 3));
+		let days = BOT.stats.guild(guild);
+		assert_eq!(days.len(), 1);
+		assert_eq!(days[0].manual, 1);
+		assert_eq!(days[0].users, 3);
+	}
 }
@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use futures_util::{future::BoxFuture, FutureExt};
+use twilight_model::{
+	application::command::{Command, CommandType},
+	guild::Permissions,
+	id::{marker::ChannelMarker, Id},
+};
+use twilight_util::builder::command::{
+	ChannelBuilder, CommandBuilder, IntegerBuilder, StringBuilder, SubCommandBuilder,
+};
+
+use crate::{schedule, BOT, MONITORED_CHANNEL_TYPES};
+
+/// Maximum delay of a scheduled prune, one week.
+const MAX_DELAY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+pub struct SchedulePrune;
+
+impl super::SlashCommand for SchedulePrune {
+	const NAME: &'static str = "schedule-prune";
+
+	fn define() -> Command {
+		CommandBuilder::new(
+			Self::NAME,
+			"Prune a voice channel once at a later time",
+			CommandType::ChatInput,
+		)
+		.default_member_permissions(Permissions::MOVE_MEMBERS)
+		.dm_permission(false)
+		.option(
+			SubCommandBuilder::new("add", "Schedule a prune of a voice channel")
+				.option(
+					ChannelBuilder::new("channel", "Voice channel to prune")
+						.channel_types(MONITORED_CHANNEL_TYPES)
+						.required(true),
+				)
+				.option(
+					StringBuilder::new("in", "How long until the prune, e.g. 30m, 2h or 1d")
+						.required(true),
+				),
+		)
+		.option(
+			SubCommandBuilder::new("cancel", "Cancel a scheduled prune").option(
+				IntegerBuilder::new("id", "ID of the scheduled prune")
+					.min_value(1)
+					.required(true),
+			),
+		)
+		.option(SubCommandBuilder::new("list", "List the scheduled prunes"))
+		.build()
+	}
+
+	fn run(ctx: super::Context) -> BoxFuture<'static, super::Result> {
+		run(ctx).boxed()
+	}
+}
+
+async fn run(ctx: super::Context) -> super::Result {
+	match ctx.options().subcommand() {
+		("add", options) => {
+			let channel: Id<ChannelMarker> = options.required("channel");
+			if !super::is_voice_channel(ctx.guild, channel) {
+				return ctx.reply(super::NOT_VOICE_CHANNEL.to_owned()).await;
+			}
+			let value: &str = options.required("in");
+			let Some(delay) = super::parse_duration(value).filter(|&delay| delay <= MAX_DELAY)
+			else {
+				return ctx
+					.reply(format!(
+						"\"{value}\" is not a duration of at most 7 days, e.g. 30m, 2h or 1d"
+					))
+					.await;
+			};
+			let Some(job) = BOT.scheduler.schedule(ctx.guild, channel, delay) else {
+				return ctx
+					.reply(format!(
+						"This server already has {} scheduled prunes, cancel one first",
+						schedule::LIMIT
+					))
+					.await;
+			};
+			ctx.reply(format!(
+				"Scheduled prune {} of <#{channel}> at <t:{at}:t> (<t:{at}:R>). It's skipped if auto pruning is disabled or paused by then, and lost if the bot restarts",
+				job.id,
+				at = job.at
+			))
+			.await
+		}
+		("cancel", options) => {
+			let id: i64 = options.required("id");
+			let cancelled = BOT.scheduler.cancel(ctx.guild, id.unsigned_abs());
+			ctx.reply(if cancelled {
+				format!("Cancelled scheduled prune {id}")
+			} else {
+				format!("There is no scheduled prune {id}")
+			})
+			.await
+		}
+		("list", _) => {
+			let jobs = BOT.scheduler.jobs(ctx.guild);
+			if jobs.is_empty() {
+				return ctx.reply("No scheduled prunes".to_owned()).await;
+			}
+			let lines: Vec<_> = jobs
+				.iter()
+				.map(|job| {
+					format!(
+						"{}: <#{}> at <t:{at}:t> (<t:{at}:R>)",
+						job.id,
+						job.channel,
+						at = job.at
+					)
+				})
+				.collect();
+			ctx.reply(lines.join("\n")).await
+		}
+		_ => unreachable!("undefined"),
+	}
+}
@@ -0,0 +1,909 @@
+use std::time::Duration;
+
+use futures_util::{future::BoxFuture, FutureExt};
+use serde_json::Value;
+use twilight_model::{
+	application::command::{Command, CommandType},
+	channel::message::Embed,
+	guild::Permissions,
+	id::{
+		marker::{ChannelMarker, GenericMarker, GuildMarker, RoleMarker},
+		Id,
+	},
+};
+use twilight_util::builder::{
+	command::{
+		BooleanBuilder, ChannelBuilder, CommandBuilder, IntegerBuilder, RoleBuilder, StringBuilder,
+		SubCommandBuilder, SubCommandGroupBuilder,
+	},
+	embed::{EmbedBuilder, EmbedFieldBuilder, EmbedFooterBuilder},
+};
+
+use crate::{
+	cleanup::Cleanup,
+	reason,
+	settings::{AutoPruneScope, Export, Fields, Mode, PruneOrder, EXPORT_VERSION},
+	BOT, MONITORED_CHANNEL_TYPES,
+};
+
+/// Maximum idle timeout in minutes, one week.
+const MAX_IDLE_TIMEOUT: i64 = 7 * 24 * 60;
+
+/// Maximum minutes a prune status is shown for, one day.
+const MAX_PRUNE_STATUS: i64 = 24 * 60;
+
+/// Duration auto pruning is paused for by the `panic` subcommand.
+const PANIC_PAUSE: Duration = Duration::from_secs(60 * 60);
+
+/// Maximum duration auto pruning may be paused for, one week.
+const MAX_PAUSE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Name marker used if none is given.
+const DEFAULT_NAME_MARKER: &str = "🔓";
+
+/// Maximum length of a name marker.
+const MAX_NAME_MARKER: u16 = 32;
+
+pub struct Settings;
+
+impl super::SlashCommand for Settings {
+	const NAME: &'static str = "settings";
+
+	fn define() -> Command {
+		CommandBuilder::new(
+			Self::NAME,
+			"Configure the bot for this server",
+			CommandType::ChatInput,
+		)
+		.default_member_permissions(Permissions::MANAGE_GUILD)
+		.dm_permission(false)
+		.option(
+			SubCommandBuilder::new(
+				"attribute-changes",
+				"Log the moderator whose change caused an auto prune, requires View Audit Log",
+			)
+			.option(
+				BooleanBuilder::new("enabled", "Whether changes are attributed").required(true),
+			),
+		)
+		.option(
+			SubCommandGroupBuilder::new(
+				"auto-channels",
+				"Channels auto pruning is restricted to when its scope is `selected`",
+			)
+			.subcommands([
+				SubCommandBuilder::new("add", "Select the channel for auto pruning").option(
+					ChannelBuilder::new("channel", "Voice channel to select")
+						.channel_types(MONITORED_CHANNEL_TYPES)
+						.required(true),
+				),
+				SubCommandBuilder::new("remove", "Stop selecting the channel for auto pruning")
+					.option(
+						ChannelBuilder::new("channel", "Voice channel to stop selecting")
+							.channel_types(MONITORED_CHANNEL_TYPES)
+							.required(true),
+					),
+				SubCommandBuilder::new("list", "List the channels selected for auto pruning"),
+			]),
+		)
+		.option(
+			SubCommandBuilder::new("auto-prune-scope", "Channels auto pruning acts on").option(
+				StringBuilder::new("scope", "Auto prune scope")
+					.choices([
+						("All - every monitored channel", "all"),
+						("Selected - only the channels of `/settings auto-channels`", "selected"),
+					])
+					.required(true),
+			),
+		)
+		.option(
+			SubCommandBuilder::new(
+				"clear-stage-on-end",
+				"Prune users left in a stage channel when its stage ends",
+			)
+			.option(BooleanBuilder::new("enabled", "Whether stage channels are pruned").required(true))
+			.option(BooleanBuilder::new(
+				"audience",
+				"Also remove permitted users who aren't stage moderators, defaults to false",
+			)),
+		)
+		.option(
+			SubCommandBuilder::new("dm-on-prune", "Notify pruned users through a DM").option(
+				BooleanBuilder::new("enabled", "Whether pruned users are notified").required(true),
+			),
+		)
+		.option(
+			SubCommandBuilder::new(
+				"idle-timeout",
+				"Periodically prune users deafened for longer than a duration",
+			)
+			.option(
+				IntegerBuilder::new("minutes", "Minutes users may be deafened, 0 disables")
+					.min_value(0)
+					.max_value(MAX_IDLE_TIMEOUT)
+					.required(true),
+			),
+		)
+		.option(SubCommandBuilder::new(
+			"export",
+			"Export this server's settings as JSON, to import into another server",
+		))
+		.option(
+			SubCommandBuilder::new("ignore-bots", "Never prune bots, except with `/prune include-bots`")
+				.option(BooleanBuilder::new("enabled", "Whether bots are ignored").required(true)),
+		)
+		.option(
+			SubCommandBuilder::new("import", "Import settings exported from another server").option(
+				StringBuilder::new("settings", "The JSON of `/settings export`").required(true),
+			),
+		)
+		.option(
+			SubCommandBuilder::new("kick-reason", "Set the audit log reason of removals").option(
+				StringBuilder::new(
+					"template",
+					"Reason with {channel}, {trigger} and {user} placeholders, omit for none",
+				)
+				.max_length(u16::try_from(reason::LIMIT).expect("fits")),
+			),
+		)
+		.option(
+			SubCommandBuilder::new(
+				"max-auto-prune",
+				"Abort auto prunes that would remove more users than a limit",
+			)
+			.option(
+				IntegerBuilder::new("users", "Maximum number of users, 0 removes the limit")
+					.min_value(0)
+					.required(true),
+			),
+		)
+		.option(
+			SubCommandBuilder::new("mode", "Whether prunes remove users or only report them").option(
+				StringBuilder::new("mode", "Prune mode")
+					.choices([
+						("Enforce - remove users", "enforce"),
+						("Report - only log who would be removed", "report"),
+					])
+					.required(true),
+			),
+		)
+		.option(
+			SubCommandBuilder::new(
+				"monitor-stage",
+				"Monitor stage channels, overriding the bot's default",
+			)
+			.option(
+				BooleanBuilder::new("enabled", "Whether stage channels are monitored")
+					.required(true),
+			),
+		)
+		.option(
+			SubCommandBuilder::new(
+				"name-marker",
+				"Stop monitoring voice channels whose name ends with a marker",
+			)
+			.option(
+				BooleanBuilder::new("enabled", "Whether marked channels are unmonitored")
+					.required(true),
+			)
+			.option(
+				StringBuilder::new("marker", "Marker ending the channel names, defaults to 🔓")
+					.max_length(MAX_NAME_MARKER)
+					.min_length(1),
+			),
+		)
+		.option(SubCommandBuilder::new(
+			"panic",
+			"Stop every running and scheduled prune, and pause auto pruning for an hour",
+		))
+		.option(
+			SubCommandBuilder::new("pause", "Pause auto pruning for a while").option(
+				StringBuilder::new("duration", "How long to pause for, e.g. 30m, 2h or 1d")
+					.required(true),
+			),
+		)
+		.option(
+			SubCommandBuilder::new(
+				"prune-afk",
+				"Prune the AFK channel, which users may always be moved to",
+			)
+			.option(
+				BooleanBuilder::new("enabled", "Whether the AFK channel is pruned").required(true),
+			),
+		)
+		.option(
+			SubCommandBuilder::new("prune-order", "Order users are pruned in").option(
+				StringBuilder::new("order", "Prune order")
+					.choices([
+						("Role position - lowest ranked users first", "role-position"),
+						("Unordered", "unordered"),
+					])
+					.required(true),
+			),
+		)
+		.option(
+			SubCommandBuilder::new(
+				"prune-pending",
+				"Prune members who haven't completed membership screening, even if permitted",
+			)
+			.option(
+				BooleanBuilder::new("enabled", "Whether pending members are pruned").required(true),
+			),
+		)
+		.option(
+			SubCommandBuilder::new(
+				"prune-status",
+				"Note prunes in the status of the pruned voice channels",
+			)
+			.option(
+				IntegerBuilder::new("minutes", "Minutes the status is shown for, 0 disables")
+					.min_value(0)
+					.max_value(MAX_PRUNE_STATUS)
+					.required(true),
+			),
+		)
+		.option(
+			SubCommandGroupBuilder::new(
+				"prune-roles",
+				"Restrict `/prune` to members with one of these roles",
+			)
+			.subcommands([
+				SubCommandBuilder::new("add", "Allow members with the role to use `/prune`").option(
+					RoleBuilder::new("role", "Role to allow").required(true),
+				),
+				SubCommandBuilder::new("remove", "Stop allowing members with the role").option(
+					RoleBuilder::new("role", "Role to stop allowing").required(true),
+				),
+				SubCommandBuilder::new("list", "List the roles allowed to use `/prune`"),
+			]),
+		)
+		.option(SubCommandBuilder::new("resume", "Resume paused auto pruning"))
+		.option(
+			SubCommandBuilder::new(
+				"soft-prune",
+				"Move users to a permitted voice channel instead of disconnecting them on auto prune",
+			)
+			.option(BooleanBuilder::new("enabled", "Whether soft pruning is enabled").required(true)),
+		)
+		.option(
+			SubCommandBuilder::new(
+				"verify-before-kick",
+				"Re-fetch users' roles before disconnecting them, guarding against a stale cache",
+			)
+			.option(
+				BooleanBuilder::new("enabled", "Whether users are verified").required(true),
+			),
+		)
+		.option(SubCommandBuilder::new("show", "Show this server's settings"))
+		.build()
+	}
+
+	fn run(ctx: super::Context) -> BoxFuture<'static, super::Result> {
+		run(ctx).boxed()
+	}
+}
+
+async fn run(ctx: super::Context) -> super::Result {
+	match ctx.options().subcommand() {
+		("attribute-changes", options) => {
+			let enabled = options.required("enabled");
+			BOT.settings
+				.update(ctx.guild, |settings| settings.attribute_changes = enabled);
+			ctx.reply(format!(
+				"change attribution {}",
+				if enabled { "enabled" } else { "disabled" }
+			))
+			.await
+		}
+		("auto-channels", options) => auto_channels(&ctx, options).await,
+		("auto-prune-scope", options) => {
+			let scope = match options.required("scope") {
+				"all" => AutoPruneScope::All,
+				"selected" => AutoPruneScope::Selected,
+				_ => unreachable!("undefined"),
+			};
+			BOT.settings
+				.update(ctx.guild, |settings| settings.auto_prune_scope = scope);
+			let mut message = format!("auto prune scope set to {scope}");
+			if scope == AutoPruneScope::Selected
+				&& BOT.settings.get(ctx.guild).auto_channels.is_empty()
+			{
+				message += ", no channels are selected so nothing is auto pruned";
+			}
+			ctx.reply(message).await
+		}
+		("clear-stage-on-end", options) => {
+			let enabled = options.required("enabled");
+			let audience = enabled && options.get("audience").unwrap_or_default();
+			BOT.settings.update(ctx.guild, |settings| {
+				settings.clear_stage_on_end = enabled;
+				settings.clear_stage_audience = audience;
+			});
+			ctx.reply(
+				match (enabled, audience) {
+					(false, _) => "stage channels are not pruned when their stage ends",
+					(true, false) => "stage channels are pruned when their stage ends",
+					(true, true) => "stage channels are cleared of everyone but stage moderators when their stage ends",
+				}
+				.to_owned(),
+			)
+			.await
+		}
+		("dm-on-prune", options) => {
+			let enabled = options.required("enabled");
+			BOT.settings
+				.update(ctx.guild, |settings| settings.dm_on_prune = enabled);
+			ctx.reply(format!(
+				"DMs on prune {}",
+				if enabled { "enabled" } else { "disabled" }
+			))
+			.await
+		}
+		("export", _) => {
+			let export = Export::new(&BOT.settings.get(ctx.guild));
+			let json = serde_json::to_string_pretty(&export).expect("serializable");
+			ctx.reply(format!("```json\n{json}\n```")).await
+		}
+		("idle-timeout", options) => {
+			let minutes: i64 = options.required("minutes");
+			let timeout = u32::try_from(minutes).ok().filter(|&minutes| minutes != 0);
+			BOT.settings
+				.update(ctx.guild, |settings| settings.idle_timeout = timeout);
+			ctx.reply(match timeout {
+				Some(minutes) => format!("users deafened for {minutes} minutes are pruned"),
+				None => "idle pruning disabled".to_owned(),
+			})
+			.await
+		}
+		("ignore-bots", options) => {
+			let enabled = options.required("enabled");
+			BOT.settings
+				.update(ctx.guild, |settings| settings.ignore_bots = enabled);
+			ctx.reply(format!(
+				"bots {}",
+				if enabled { "ignored" } else { "pruned" }
+			))
+			.await
+		}
+		("import", options) => {
+			let value: &str = options.required("settings");
+			let json = value.trim().trim_start_matches("```json").trim_matches('`');
+			let mut export: Export = match serde_json::from_str(json) {
+				Ok(export) => export,
+				Err(e) => return ctx.reply(format!("invalid settings: {e}")).await,
+			};
+			if export.version != EXPORT_VERSION {
+				return ctx
+					.reply(format!(
+						"unsupported settings version {}, expected {EXPORT_VERSION}",
+						export.version
+					))
+					.await;
+			}
+			let (roles, unknown): (Vec<_>, Vec<_>) = export
+				.settings
+				.prune_roles
+				.iter()
+				.partition(|&&role| is_guild_role(ctx.guild, role));
+			export.settings.prune_roles = roles;
+			let invalid_reason = export
+				.settings
+				.kick_reason
+				.as_deref()
+				.and_then(|template| reason::validate(template).err());
+			if invalid_reason.is_some() {
+				export.settings.kick_reason = None;
+			}
+			BOT.settings
+				.update(ctx.guild, |settings| export.apply(settings));
+			let mut message = "settings imported".to_owned();
+			if !unknown.is_empty() {
+				let unknown: Vec<_> = unknown.iter().map(ToString::to_string).collect();
+				message += &format!(", dropped unknown prune roles: {}", unknown.join(", "));
+			}
+			if let Some(e) = invalid_reason {
+				message += &format!(", dropped the kick reason: {e}");
+			}
+			ctx.reply(message).await
+		}
+		("kick-reason", options) => {
+			let template: Option<&str> = options.get("template");
+			if let Some(Err(e)) = template.map(reason::validate) {
+				return ctx.reply(format!("invalid kick reason: {e}")).await;
+			}
+			BOT.settings.update(ctx.guild, |settings| {
+				settings.kick_reason = template.map(ToOwned::to_owned);
+			});
+			ctx.reply(match template {
+				Some(template) => format!("kick reason set to \"{template}\""),
+				None => "removals have no audit log reason".to_owned(),
+			})
+			.await
+		}
+		("max-auto-prune", options) => {
+			let users: i64 = options.required("users");
+			let limit = u32::try_from(users).ok().filter(|&users| users != 0);
+			BOT.settings
+				.update(ctx.guild, |settings| settings.max_auto_prune = limit);
+			ctx.reply(match limit {
+				Some(users) => format!("auto prunes removing more than {users} users are aborted"),
+				None => "auto prunes are unlimited".to_owned(),
+			})
+			.await
+		}
+		("mode", options) => {
+			let mode = match options.required("mode") {
+				"enforce" => Mode::Enforce,
+				"report" => Mode::Report,
+				_ => unreachable!("undefined"),
+			};
+			BOT.settings
+				.update(ctx.guild, |settings| settings.mode = mode);
+			ctx.reply(format!("mode set to {mode}")).await
+		}
+		("monitor-stage", options) => {
+			let enabled = options.required("enabled");
+			BOT.settings
+				.update(ctx.guild, |settings| settings.monitor_stage = Some(enabled));
+			ctx.reply(format!(
+				"stage channels {}",
+				if enabled { "monitored" } else { "unmonitored" }
+			))
+			.await
+		}
+		("name-marker", options) => {
+			let enabled: bool = options.required("enabled");
+			let marker = options
+				.get::<&str>("marker")
+				.map_or(DEFAULT_NAME_MARKER, str::trim);
+			if marker.is_empty() {
+				return ctx.reply("the marker must not be blank".to_owned()).await;
+			}
+			let marker = enabled.then(|| marker.to_owned());
+			let message = match &marker {
+				Some(marker) => {
+					format!("voice channels whose name ends with \"{marker}\" are unmonitored")
+				}
+				None => "voice channels are monitored regardless of their name".to_owned(),
+			};
+			BOT.settings
+				.update(ctx.guild, |settings| settings.name_marker = marker);
+			ctx.reply(message).await
+		}
+		("panic", _) => {
+			let scheduled = panic(ctx.guild);
+			ctx.reply(format!(
+				"running prunes and queued removals stopped, {scheduled} scheduled prunes cancelled and auto pruning paused for {}, use `/settings resume` to resume it",
+				super::humanize(PANIC_PAUSE)
+			))
+			.await
+		}
+		("pause", options) => {
+			let value: &str = options.required("duration");
+			let Some(duration) =
+				super::parse_duration(value).filter(|&duration| duration <= MAX_PAUSE)
+			else {
+				return ctx
+					.reply(format!(
+						"\"{value}\" is not a valid duration, use e.g. 30m, 2h or 1d, at most 7 days"
+					))
+					.await;
+			};
+			BOT.settings
+				.update(ctx.guild, |settings| settings.pause(duration));
+			ctx.reply(format!(
+				"auto pruning paused for {}, `/prune` still works",
+				super::humanize(duration)
+			))
+			.await
+		}
+		("prune-afk", options) => {
+			let enabled = options.required("enabled");
+			BOT.settings
+				.update(ctx.guild, |settings| settings.prune_afk = enabled);
+			ctx.reply(format!(
+				"AFK channel {}",
+				if enabled { "pruned" } else { "not pruned" }
+			))
+			.await
+		}
+		("prune-order", options) => {
+			let order = match options.required("order") {
+				"role-position" => PruneOrder::RolePosition,
+				"unordered" => PruneOrder::Unordered,
+				_ => unreachable!("undefined"),
+			};
+			BOT.settings
+				.update(ctx.guild, |settings| settings.prune_order = order);
+			ctx.reply(format!("prune order set to {order}")).await
+		}
+		("prune-pending", options) => {
+			let enabled = options.required("enabled");
+			BOT.settings
+				.update(ctx.guild, |settings| settings.prune_pending = enabled);
+			ctx.reply(format!(
+				"pending members {}",
+				if enabled { "pruned" } else { "not pruned" }
+			))
+			.await
+		}
+		("prune-roles", options) => prune_roles(&ctx, options).await,
+		("prune-status", options) => {
+			let minutes: i64 = options.required("minutes");
+			let minutes = u32::try_from(minutes).ok().filter(|&minutes| minutes != 0);
+			BOT.settings
+				.update(ctx.guild, |settings| settings.prune_status = minutes);
+			ctx.reply(match minutes {
+				Some(minutes) => format!(
+					"pruned voice channels show it in their status for {minutes} minutes, requires the Manage Channels permission"
+				),
+				None => "prune statuses disabled".to_owned(),
+			})
+			.await
+		}
+		("resume", _) => {
+			BOT.settings
+				.update(ctx.guild, |settings| settings.paused_until = None);
+			ctx.reply("auto pruning resumed".to_owned()).await
+		}
+		("soft-prune", options) => {
+			let enabled = options.required("enabled");
+			BOT.settings
+				.update(ctx.guild, |settings| settings.soft_prune = enabled);
+			ctx.reply(format!(
+				"soft pruning {}",
+				if enabled { "enabled" } else { "disabled" }
+			))
+			.await
+		}
+		("verify-before-kick", options) => {
+			let enabled = options.required("enabled");
+			BOT.settings
+				.update(ctx.guild, |settings| settings.verify_before_kick = enabled);
+			ctx.reply(format!(
+				"verification before kicking {}",
+				if enabled { "enabled" } else { "disabled" }
+			))
+			.await
+		}
+		("show", _) => show(&ctx).await,
+		_ => unreachable!("undefined"),
+	}
+}
+
+/// Stops every prune of the guild and pauses auto pruning for [`PANIC_PAUSE`], returning how
+/// many scheduled prunes were cancelled.
+fn panic(guild: Id<GuildMarker>) -> usize {
+	BOT.halts.halt(guild);
+	let scheduled = BOT.scheduler.cancel_all(guild);
+	Cleanup::guild(&BOT.coalescer, guild);
+	BOT.settings
+		.update(guild, |settings| settings.pause(PANIC_PAUSE));
+	scheduled
+}
+
+/// Maximum number of fields of an embed.
+const EMBED_FIELDS: usize = 25;
+
+/// Maximum length of an embed field's value.
+const FIELD_LIMIT: usize = 1024;
+
+/// Run the `show` subcommand.
+async fn show(ctx: &super::Context) -> super::Result {
+	ctx.reply_embed(summary(ctx.guild)).await
+}
+
+/// Embed with a field per setting of the guild.
+pub fn summary(guild: Id<GuildMarker>) -> Embed {
+	let fields = BOT.settings.get(guild).fields();
+	let mut embed = EmbedBuilder::new().title("Settings");
+	let mut deleted = false;
+	for field in fields.iter().take(EMBED_FIELDS) {
+		let (value, stale) = render(guild, &field.name, &field.value);
+		deleted |= stale;
+		let origin = if field.default { "default" } else { "set" };
+		let mut value = truncate(value, FIELD_LIMIT - origin.len() - 3);
+		value.push_str(&format!("\n*{origin}*"));
+		embed = embed.field(EmbedFieldBuilder::new(field.name.clone(), value).inline());
+	}
+
+	let mut notes = Vec::new();
+	if fields.len() > EMBED_FIELDS {
+		notes.push(format!("{} more not shown", fields.len() - EMBED_FIELDS));
+	}
+	if deleted {
+		notes.push("remove deleted channels and roles through their subcommands".to_owned());
+	}
+	if !notes.is_empty() {
+		embed = embed.footer(EmbedFooterBuilder::new(notes.join(", ")));
+	}
+	embed.build()
+}
+
+/// Renders the setting's value, resolving IDs to mentions, and whether it refers to something
+/// deleted.
+fn render(guild: Id<GuildMarker>, name: &str, value: &Value) -> (String, bool) {
+	match value {
+		Value::Null => ("none".to_owned(), false),
+		Value::Bool(true) => ("enabled".to_owned(), false),
+		Value::Bool(false) => ("disabled".to_owned(), false),
+		Value::Number(timestamp) if name == "paused-until" => (format!("<t:{timestamp}:R>"), false),
+		Value::Number(number) => (number.to_string(), false),
+		Value::String(string) => (format!("`{string}`"), false),
+		Value::Array(values) if values.is_empty() => ("none".to_owned(), false),
+		Value::Array(values) => {
+			let mut deleted = false;
+			let values: Vec<_> = values
+				.iter()
+				.map(|value| {
+					let (value, stale) = match value.as_str().and_then(|id| id.parse().ok()) {
+						Some(id) => mention(guild, id),
+						None => render(guild, name, value),
+					};
+					deleted |= stale;
+					value
+				})
+				.collect();
+			(values.join(", "), deleted)
+		}
+		Value::Object(_) => (format!("`{value}`"), false),
+	}
+}
+
+/// Mention of the guild's channel or role, the raw ID marked deleted if neither is cached.
+fn mention(guild: Id<GuildMarker>, id: Id<GenericMarker>) -> (String, bool) {
+	if BOT
+		.cache
+		.channel(id.cast())
+		.is_some_and(|channel| channel.guild_id == Some(guild))
+	{
+		(format!("<#{id}>"), false)
+	} else if is_guild_role(guild, id.cast()) {
+		(format!("<@&{id}>"), false)
+	} else {
+		(format!("`{id}` (deleted)"), true)
+	}
+}
+
+/// The text cut to at most `limit` bytes, ending with an ellipsis if cut.
+fn truncate(mut text: String, limit: usize) -> String {
+	if text.len() > limit {
+		let mut end = limit - '…'.len_utf8();
+		while !text.is_char_boundary(end) {
+			end -= 1;
+		}
+		text.truncate(end);
+		text.push('…');
+	}
+	text
+}
+
+/// Whether the role is a cached role of the guild.
+fn is_guild_role(guild: Id<GuildMarker>, role: Id<RoleMarker>) -> bool {
+	BOT.cache
+		.role(role)
+		.is_some_and(|role| role.guild_id() == guild)
+}
+
+/// Run the `auto-channels` subcommand group.
+async fn auto_channels(ctx: &super::Context, options: super::Options<'_>) -> super::Result {
+	match options.subcommand() {
+		("add", options) => {
+			let channel: Id<ChannelMarker> = options.required("channel");
+			if !super::is_voice_channel(ctx.guild, channel) {
+				return ctx.reply(super::NOT_VOICE_CHANNEL.to_owned()).await;
+			}
+			BOT.settings.update(ctx.guild, |settings| {
+				if !settings.auto_channels.contains(&channel) {
+					settings.auto_channels.push(channel);
+				}
+			});
+			ctx.reply(format!("<#{channel}> selected for auto pruning"))
+				.await
+		}
+		("remove", options) => {
+			let channel: Id<ChannelMarker> = options.required("channel");
+			BOT.settings.update(ctx.guild, |settings| {
+				settings.auto_channels.retain(|&id| id != channel)
+			});
+			ctx.reply(format!("<#{channel}> no longer selected for auto pruning"))
+				.await
+		}
+		("list", _) => {
+			let channels = BOT.settings.get(ctx.guild).auto_channels;
+			if channels.is_empty() {
+				return ctx
+					.reply("no channels selected for auto pruning".to_owned())
+					.await;
+			}
+			let channels: Vec<_> = channels
+				.iter()
+				.map(|channel| format!("<#{channel}>"))
+				.collect();
+			ctx.reply(format!("auto prune channels: {}", channels.join(", ")))
+				.await
+		}
+		_ => unreachable!("undefined"),
+	}
+}
+
+/// Run the `prune-roles` subcommand group.
+async fn prune_roles(ctx: &super::Context, options: super::Options<'_>) -> super::Result {
+	match options.subcommand() {
+		("add", options) => {
+			let role: Id<RoleMarker> = options.required("role");
+			BOT.settings.update(ctx.guild, |settings| {
+				if !settings.prune_roles.contains(&role) {
+					settings.prune_roles.push(role);
+				}
+			});
+			ctx.reply(format!("<@&{role}> may use `/prune`")).await
+		}
+		("remove", options) => {
+			let role: Id<RoleMarker> = options.required("role");
+			BOT.settings.update(ctx.guild, |settings| {
+				settings.prune_roles.retain(|&id| id != role)
+			});
+			ctx.reply(format!("<@&{role}> removed from the prune roles"))
+				.await
+		}
+		("list", _) => {
+			let roles = BOT.settings.get(ctx.guild).prune_roles;
+			if roles.is_empty() {
+				return ctx
+					.reply(
+						"no prune roles, anyone permitted by the server may use `/prune`"
+							.to_owned(),
+					)
+					.await;
+			}
+			let roles: Vec<_> = roles.iter().map(|role| format!("<@&{role}>")).collect();
+			ctx.reply(format!("prune roles: {}", roles.join(", ")))
+				.await
+		}
+		_ => unreachable!("undefined"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use twilight_model::{
+		channel::message::Embed,
+		guild::Permissions,
+		id::{marker::GuildMarker, Id},
+	};
+
+	use crate::{
+		prune::Trigger,
+		settings::{Mode, PruneOrder},
+		test::{role_overwrite, Guild},
+		BOT,
+	};
+
+	const GUILD: u64 = 4_000_000;
+
+	/// Value of the embed's field.
+	fn field<'a>(embed: &'a Embed, name: &str) -> &'a str {
+		&embed
+			.fields
+			.iter()
+			.find(|field| field.name == name)
+			.expect("listed")
+			.value
+	}
+
+	/// Guild with the voice channel `id + 10` and role `id + 2`.
+	fn guild(id: u64) -> Id<GuildMarker> {
+		Guild::new(id)
+			.role(id + 2, Permissions::empty(), 1)
+			.voice(id + 10, &[])
+			.create()
+	}
+
+	#[test]
+	fn default_summary() {
+		let guild = guild(GUILD);
+		let embed = super::summary(guild);
+
+		assert_eq!(embed.title.as_deref(), Some("Settings"));
+		assert!(embed.footer.is_none());
+		assert!(embed
+			.fields
+			.iter()
+			.all(|field| field.value.ends_with("\n*default*")));
+		assert_eq!(field(&embed, "auto-channels"), "none\n*default*");
+		assert_eq!(field(&embed, "mode"), "`enforce`\n*default*");
+		assert_eq!(field(&embed, "dm-on-prune"), "disabled\n*default*");
+		assert_eq!(field(&embed, "kick-reason"), "none\n*default*");
+	}
+
+	#[test]
+	fn customized_summary() {
+		const GUILD: u64 = 4_001_000;
+		let guild = guild(GUILD);
+		BOT.settings.update(guild, |settings| {
+			settings.auto_channels = vec![Id::new(GUILD + 10), Id::new(GUILD + 11)];
+			settings.prune_roles = vec![Id::new(GUILD + 2)];
+			settings.mode = Mode::Report;
+			settings.dm_on_prune = true;
+			settings.idle_timeout = Some(30);
+			settings.paused_until = Some(1_700_000_000);
+		});
+		let embed = super::summary(guild);
+
+		assert_eq!(
+			field(&embed, "auto-channels"),
+			format!("<#{}>, `{}` (deleted)\n*set*", GUILD + 10, GUILD + 11)
+		);
+		assert_eq!(
+			field(&embed, "prune-roles"),
+			format!("<@&{}>\n*set*", GUILD + 2)
+		);
+		assert_eq!(field(&embed, "mode"), "`report`\n*set*");
+		assert_eq!(field(&embed, "dm-on-prune"), "enabled\n*set*");
+		assert_eq!(field(&embed, "idle-timeout"), "30\n*set*");
+		assert_eq!(field(&embed, "paused-until"), "<t:1700000000:R>\n*set*");
+		assert_eq!(field(&embed, "soft-prune"), "disabled\n*default*");
+		assert_eq!(
+			embed.footer.map(|footer| footer.text).as_deref(),
+			Some("remove deleted channels and roles through their subcommands")
+		);
+	}
+
+	#[test]
+	fn truncate() {
+		assert_eq!(super::truncate("short".to_owned(), 5), "short");
+		assert_eq!(super::truncate("longer".to_owned(), 5), "lo…");
+		assert_eq!(super::truncate("ééé".to_owned(), 5), "é…");
+	}
+
+	#[tokio::test]
+	async fn panic_stops_prunes() {
+		const GUILD: u64 = 4_050_000;
+		let users = GUILD + 100..GUILD + 110;
+		let guild = users
+			.clone()
+			.fold(
+				Guild::new(GUILD).everyone(Permissions::VIEW_CHANNEL).voice(
+					GUILD + 10,
+					&[role_overwrite(
+						GUILD,
+						Permissions::empty(),
+						Permissions::CONNECT,
+					)],
+				),
+				|guild, user| guild.member(user, &[]).connect(user, GUILD + 10),
+			)
+			.create();
+		let channel = Id::new(GUILD + 10);
+		BOT.settings.update(guild, |settings| {
+			settings.prune_order = PruneOrder::RolePosition;
+		});
+		let scheduled = BOT
+			.scheduler
+			.schedule(guild, channel, Duration::from_secs(60 * 60));
+		assert!(scheduled.is_some());
+		// the panic arrives while the second user is removed
+		crate::test::on_removal(guild, Id::new(GUILD + 101), |guild| {
+			super::panic(guild);
+		});
+
+		let summary = crate::prune::channel(channel, guild, Trigger::Command, |_| true, None).await;
+
+		let removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user.get())
+			.collect();
+		assert_eq!(removed, [GUILD + 100, GUILD + 101]);
+		assert!(summary.halted);
+		assert!(summary.failed.is_empty(), "dropped removals didn't fail");
+		assert!(BOT.scheduler.jobs(guild).is_empty());
+		assert!(BOT.settings.get(guild).paused_for().is_some());
+
+		// later prunes run again, of everyone as removals don't update the cache
+		crate::prune::channel(channel, guild, Trigger::Command, |_| true, None).await;
+		assert_eq!(crate::test::removed(guild).len(), 2 + users.count());
+	}
+}
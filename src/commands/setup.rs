@@ -0,0 +1,662 @@
+//! `/setup`, walking through the key settings with message components.
+//!
+//! The choices are kept in memory, keyed by the interaction that started the wizard, until they are
+//! saved, cancelled or expire after [`EXPIRY`], when the message is deleted.
+
+use std::{
+	collections::HashMap,
+	sync::{Mutex, PoisonError},
+	time::Duration,
+};
+
+use futures_util::{future::BoxFuture, FutureExt};
+use twilight_model::{
+	application::{
+		command::{Command, CommandType},
+		interaction::{message_component::MessageComponentInteractionData, Interaction},
+	},
+	channel::message::component::{
+		ActionRow, Button, ButtonStyle, Component, SelectDefaultValue, SelectMenu, SelectMenuType,
+	},
+	guild::Permissions,
+	id::{
+		marker::{ChannelMarker, GuildMarker, InteractionMarker, RoleMarker, UserMarker},
+		Id,
+	},
+};
+use twilight_util::builder::command::CommandBuilder;
+
+use super::{respond, Response};
+use crate::{
+	settings::{AutoPruneScope, Mode, Settings},
+	BOT, MONITORED_CHANNEL_TYPES,
+};
+
+/// Prefix of the custom IDs of the wizard's components.
+pub const PREFIX: &str = "setup:";
+
+/// Time after which an unfinished wizard is dropped and its message deleted.
+const EXPIRY: Duration = Duration::from_secs(10 * 60);
+
+/// Maximum number of values of a select menu.
+const SELECT_LIMIT: u8 = 25;
+
+/// Reply to clicks of an expired wizard.
+const EXPIRED: &str = "This setup expired, run `/setup` again";
+
+/// Reply to clicks by someone other than the invoker.
+const DENIED: &str = "Only the user who ran `/setup` may use it";
+
+pub struct Setup;
+
+impl super::SlashCommand for Setup {
+	const NAME: &'static str = "setup";
+
+	fn define() -> Command {
+		CommandBuilder::new(
+			Self::NAME,
+			"Walk through the key settings of this server",
+			CommandType::ChatInput,
+		)
+		.default_member_permissions(Permissions::MANAGE_GUILD)
+		.dm_permission(false)
+		.build()
+	}
+
+	fn run(ctx: super::Context) -> BoxFuture<'static, super::Result> {
+		run(ctx).boxed()
+	}
+}
+
+/// Step of a wizard, each asking for one choice.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Step {
+	Mode,
+	Scope,
+	/// Channels to auto prune, only asked if the scope is [`AutoPruneScope::Selected`].
+	Channels,
+	Roles,
+	Confirm,
+}
+
+/// Choice made through a component.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Choice {
+	Mode(Mode),
+	Scope(AutoPruneScope),
+	Channels(Vec<Id<ChannelMarker>>),
+	/// Keep the current channels.
+	KeepChannels,
+	Roles(Vec<Id<RoleMarker>>),
+	Save,
+	Cancel,
+}
+
+impl Choice {
+	/// Parses the action of a custom ID and the selected values.
+	fn parse(action: &str, values: &[String]) -> Option<Self> {
+		Some(match action {
+			"enforce" => Self::Mode(Mode::Enforce),
+			"report" => Self::Mode(Mode::Report),
+			"all" => Self::Scope(AutoPruneScope::All),
+			"selected" => Self::Scope(AutoPruneScope::Selected),
+			"channels" => Self::Channels(parse_ids(values)?),
+			"keep-channels" => Self::KeepChannels,
+			"roles" => Self::Roles(parse_ids(values)?),
+			"anyone" => Self::Roles(Vec::new()),
+			"save" => Self::Save,
+			"cancel" => Self::Cancel,
+			_ => return None,
+		})
+	}
+}
+
+/// Component of a wizard, encoded in its custom ID.
+#[derive(Debug, Eq, PartialEq)]
+struct Target<'a> {
+	/// Interaction that started the wizard.
+	reply: Id<InteractionMarker>,
+	/// Invoker of the command.
+	user: Id<UserMarker>,
+	/// Action of the component, see [`Choice::parse`].
+	action: &'a str,
+}
+
+impl<'a> Target<'a> {
+	fn custom_id(&self) -> String {
+		format!("{PREFIX}{}:{}:{}", self.reply, self.user, self.action)
+	}
+
+	fn parse(custom_id: &'a str) -> Option<Self> {
+		let mut parts = custom_id.strip_prefix(PREFIX)?.splitn(3, ':');
+		Some(Self {
+			reply: parts.next()?.parse().ok()?,
+			user: parts.next()?.parse().ok()?,
+			action: parts.next()?,
+		})
+	}
+}
+
+/// Parses selected IDs, `None` if any is invalid.
+fn parse_ids<T>(values: &[String]) -> Option<Vec<Id<T>>> {
+	values.iter().map(|value| value.parse().ok()).collect()
+}
+
+/// Choices of a wizard, initialized from the guild's settings.
+#[derive(Clone, Debug)]
+struct Wizard {
+	guild: Id<GuildMarker>,
+	/// Invoker of the command.
+	user: Id<UserMarker>,
+	step: Step,
+	mode: Mode,
+	scope: AutoPruneScope,
+	channels: Vec<Id<ChannelMarker>>,
+	roles: Vec<Id<RoleMarker>>,
+}
+
+impl Wizard {
+	fn new(guild: Id<GuildMarker>, user: Id<UserMarker>, settings: &Settings) -> Self {
+		Self {
+			guild,
+			user,
+			step: Step::Mode,
+			mode: settings.mode,
+			scope: settings.auto_prune_scope,
+			channels: settings.auto_channels.clone(),
+			roles: settings.prune_roles.clone(),
+		}
+	}
+
+	/// Applies the choice to the current step and moves to the next, `false` if the choice isn't
+	/// one of the step's.
+	fn advance(&mut self, choice: Choice) -> bool {
+		self.step = match (self.step, choice) {
+			(Step::Mode, Choice::Mode(mode)) => {
+				self.mode = mode;
+				Step::Scope
+			}
+			(Step::Scope, Choice::Scope(scope)) => {
+				self.scope = scope;
+				match scope {
+					AutoPruneScope::All => Step::Roles,
+					AutoPruneScope::Selected => Step::Channels,
+				}
+			}
+			(Step::Channels, Choice::Channels(channels)) => {
+				self.channels = channels;
+				Step::Roles
+			}
+			(Step::Channels, Choice::KeepChannels) => Step::Roles,
+			(Step::Roles, Choice::Roles(roles)) => {
+				self.roles = roles;
+				Step::Confirm
+			}
+			_ => return false,
+		};
+		true
+	}
+
+	/// Writes the choices to the guild's settings.
+	fn save(&self) {
+		BOT.settings.update(self.guild, |settings| {
+			settings.mode = self.mode;
+			settings.auto_prune_scope = self.scope;
+			settings.auto_channels = self.channels.clone();
+			settings.prune_roles = self.roles.clone();
+		});
+	}
+
+	/// The current step's message and components.
+	fn prompt(&self, reply: Id<InteractionMarker>) -> (String, Vec<Component>) {
+		let custom_id = |action: &str| {
+			Target {
+				reply,
+				user: self.user,
+				action,
+			}
+			.custom_id()
+		};
+		let button = |action: &str, label: &str, style: ButtonStyle| {
+			Component::Button(Button {
+				custom_id: Some(custom_id(action)),
+				disabled: false,
+				emoji: None,
+				label: Some(label.to_owned()),
+				style,
+				url: None,
+			})
+		};
+		let select = |action: &str, kind, defaults: Vec<SelectDefaultValue>, placeholder: &str| {
+			Component::SelectMenu(SelectMenu {
+				channel_types: (kind == SelectMenuType::Channel)
+					.then(|| MONITORED_CHANNEL_TYPES.to_vec()),
+				custom_id: custom_id(action),
+				default_values: Some(defaults).filter(|defaults| !defaults.is_empty()),
+				disabled: false,
+				kind,
+				max_values: Some(SELECT_LIMIT),
+				min_values: Some(1),
+				options: None,
+				placeholder: Some(placeholder.to_owned()),
+			})
+		};
+		let row = |components| Component::ActionRow(ActionRow { components });
+
+		match self.step {
+			Step::Mode => (
+				"**Mode** (1/4): should prunes remove users, or only report who would be removed?"
+					.to_owned(),
+				vec![row(vec![
+					button("enforce", "Remove users", ButtonStyle::Primary),
+					button("report", "Only report", ButtonStyle::Secondary),
+				])],
+			),
+			Step::Scope => (
+				"**Auto prune scope** (2/4): which voice channels are pruned when permissions change?"
+					.to_owned(),
+				vec![row(vec![
+					button("all", "All channels", ButtonStyle::Primary),
+					button("selected", "Selected channels", ButtonStyle::Secondary),
+				])],
+			),
+			Step::Channels => {
+				let defaults = self
+					.channels
+					.iter()
+					.take(SELECT_LIMIT.into())
+					.map(|&channel| SelectDefaultValue::Channel(channel))
+					.collect();
+				(
+					"**Auto prune channels** (2/4): select the voice channels to auto prune"
+						.to_owned(),
+					vec![
+						row(vec![select(
+							"channels",
+							SelectMenuType::Channel,
+							defaults,
+							"Voice channels",
+						)]),
+						row(vec![button(
+							"keep-channels",
+							"Keep current",
+							ButtonStyle::Secondary,
+						)]),
+					],
+				)
+			}
+			Step::Roles => {
+				let defaults = self
+					.roles
+					.iter()
+					.take(SELECT_LIMIT.into())
+					.map(|&role| SelectDefaultValue::Role(role))
+					.collect();
+				(
+					"**Prune roles** (3/4): select the roles whose members may use `/prune`".to_owned(),
+					vec![
+						row(vec![select(
+							"roles",
+							SelectMenuType::Role,
+							defaults,
+							"Roles",
+						)]),
+						row(vec![button(
+							"anyone",
+							"Anyone permitted by Discord",
+							ButtonStyle::Secondary,
+						)]),
+					],
+				)
+			}
+			Step::Confirm => (
+				format!("**Confirm** (4/4): save these settings?\n{}", self.summary()),
+				vec![row(vec![
+					button("save", "Save", ButtonStyle::Success),
+					button("cancel", "Cancel", ButtonStyle::Danger),
+				])],
+			),
+		}
+	}
+
+	/// Lines listing the choices.
+	fn summary(&self) -> String {
+		let mentions = |ids: Vec<String>| {
+			if ids.is_empty() {
+				"none".to_owned()
+			} else {
+				ids.join(", ")
+			}
+		};
+		let scope = match self.scope {
+			AutoPruneScope::All => self.scope.to_string(),
+			AutoPruneScope::Selected => format!(
+				"{} ({})",
+				self.scope,
+				mentions(self.channels.iter().map(|id| format!("<#{id}>")).collect())
+			),
+		};
+		let roles = if self.roles.is_empty() {
+			"anyone permitted by Discord".to_owned()
+		} else {
+			mentions(self.roles.iter().map(|id| format!("<@&{id}>")).collect())
+		};
+		format!(
+			"mode: {}\nauto-prune-scope: {scope}\nprune-roles: {roles}",
+			self.mode
+		)
+	}
+}
+
+/// Unfinished wizards, by the interaction that started them.
+#[derive(Debug, Default)]
+pub struct Wizards(Mutex<HashMap<Id<InteractionMarker>, Wizard>>);
+
+impl Wizards {
+	fn insert(&self, reply: Id<InteractionMarker>, wizard: Wizard) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.insert(reply, wizard);
+	}
+
+	fn remove(&self, reply: Id<InteractionMarker>) -> Option<Wizard> {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&reply)
+	}
+
+	/// Applies the choice, returning the wizard after it, `None` if expired or not a choice of the
+	/// current step. Saving and cancelling finish the wizard, only once confirming.
+	fn choose(&self, reply: Id<InteractionMarker>, choice: Choice) -> Option<Wizard> {
+		let mut wizards = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		match choice {
+			Choice::Save | Choice::Cancel => {
+				if wizards.get(&reply)?.step != Step::Confirm {
+					return None;
+				}
+				wizards.remove(&reply)
+			}
+			choice => {
+				let wizard = wizards.get_mut(&reply)?;
+				wizard.advance(choice).then(|| wizard.clone())
+			}
+		}
+	}
+}
+
+async fn run(ctx: super::Context) -> super::Result {
+	let Some(user) = ctx.interaction.author_id() else {
+		return Ok(());
+	};
+	let wizard = Wizard::new(ctx.guild, user, &BOT.settings.get(ctx.guild));
+	let (message, components) = wizard.prompt(ctx.interaction.id);
+	BOT.wizards.insert(ctx.interaction.id, wizard);
+	ctx.respond(Response::Prompt(message, components)).await?;
+
+	tokio::spawn(expire(ctx.interaction.id, ctx.interaction.token.clone()));
+	Ok(())
+}
+
+/// Drops the wizard after [`EXPIRY`] if unfinished, deleting its message.
+async fn expire(reply: Id<InteractionMarker>, token: String) {
+	tokio::time::sleep(EXPIRY).await;
+	if BOT.wizards.remove(reply).is_none() {
+		return;
+	}
+	if let Err(e) = BOT.interaction().delete_response(&token).await {
+		tracing::debug!(
+			error = &e as &dyn std::error::Error,
+			"unable to delete expired setup"
+		);
+	}
+}
+
+/// Handle a click or selection of a wizard component, by the invoker only.
+pub async fn component(
+	interaction: &Interaction,
+	data: &MessageComponentInteractionData,
+) -> super::Result {
+	let Some((target, choice)) = Target::parse(&data.custom_id).and_then(|target| {
+		let choice = Choice::parse(target.action, &data.values)?;
+		Some((target, choice))
+	}) else {
+		tracing::info!(custom_id = data.custom_id, "unknown component");
+		return Ok(());
+	};
+	let Target { reply, user, .. } = target;
+	if interaction.author_id() != Some(user) {
+		return respond(interaction, Response::Message(DENIED.to_owned())).await;
+	}
+
+	let Some(wizard) = BOT.wizards.choose(reply, choice.clone()) else {
+		return respond(interaction, Response::Message(EXPIRED.to_owned())).await;
+	};
+	match choice {
+		Choice::Save => {
+			wizard.save();
+			tracing::info!(guild_id = %wizard.guild, user_id = %user, "settings saved by setup");
+			let embed = super::settings::summary(wizard.guild);
+			respond(interaction, Response::UpdateEmbed(Box::new(embed))).await
+		}
+		Choice::Cancel => {
+			let message = "Setup cancelled, no settings were changed".to_owned();
+			respond(interaction, Response::Update(message, Vec::new())).await
+		}
+		_ => {
+			let (message, components) = wizard.prompt(reply);
+			respond(interaction, Response::Update(message, components)).await
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use twilight_model::{
+		application::interaction::Interaction,
+		id::{
+			marker::{GuildMarker, InteractionMarker},
+			Id,
+		},
+	};
+
+	use super::{Choice, Step, Target, Wizard, EXPIRY};
+	use crate::{
+		settings::{AutoPruneScope, Mode, Settings},
+		BOT,
+	};
+
+	const GUILD: u64 = 4_020_000;
+
+	/// Wizard of `GUILD + 100` started from default settings.
+	fn wizard(guild: Id<GuildMarker>) -> Wizard {
+		Wizard::new(guild, Id::new(GUILD + 100), &Settings::default())
+	}
+
+	#[test]
+	fn custom_id_round_trip() {
+		let target = Target {
+			reply: Id::new(GUILD),
+			user: Id::new(GUILD + 100),
+			action: "keep-channels",
+		};
+
+		assert_eq!(Target::parse(&target.custom_id()), Some(target));
+		assert_eq!(Target::parse("page:1:2:3"), None);
+		assert_eq!(Target::parse("setup:1:2"), None);
+	}
+
+	#[test]
+	fn parses_choices() {
+		let values = ["10".to_owned(), "11".to_owned()];
+
+		assert_eq!(
+			Choice::parse("report", &[]),
+			Some(Choice::Mode(Mode::Report))
+		);
+		assert_eq!(
+			Choice::parse("channels", &values),
+			Some(Choice::Channels(vec![Id::new(10), Id::new(11)]))
+		);
+		assert_eq!(
+			Choice::parse("anyone", &[]),
+			Some(Choice::Roles(Vec::new()))
+		);
+		assert_eq!(Choice::parse("roles", &["role".to_owned()]), None);
+		assert_eq!(Choice::parse("unknown", &[]), None);
+	}
+
+	#[test]
+	fn transitions() {
+		let mut wizard = wizard(Id::new(GUILD));
+		assert_eq!(wizard.step, Step::Mode);
+
+		assert!(
+			!wizard.advance(Choice::Scope(AutoPruneScope::All)),
+			"not this step's"
+		);
+		assert!(wizard.advance(Choice::Mode(Mode::Report)));
+		assert_eq!(wizard.step, Step::Scope);
+		assert!(wizard.advance(Choice::Scope(AutoPruneScope::Selected)));
+		assert_eq!(wizard.step, Step::Channels);
+		assert!(wizard.advance(Choice::Channels(vec![Id::new(GUILD + 10)])));
+		assert_eq!(wizard.step, Step::Roles);
+		assert!(wizard.advance(Choice::Roles(vec![Id::new(GUILD + 2)])));
+		assert_eq!(wizard.step, Step::Confirm);
+		assert!(!wizard.advance(Choice::Mode(Mode::Enforce)));
+		assert_eq!(wizard.mode, Mode::Report);
+
+		// channels are only asked for if selected
+		let mut wizard = self::wizard(Id::new(GUILD));
+		wizard.advance(Choice::Mode(Mode::Enforce));
+		wizard.advance(Choice::Scope(AutoPruneScope::All));
+		assert_eq!(wizard.step, Step::Roles);
+	}
+
+	#[test]
+	fn saves_when_confirmed() {
+		const GUILD: u64 = 4_021_000;
+		let guild = crate::test::Guild::new(GUILD).create();
+		let reply = Id::new(GUILD);
+		BOT.wizards.insert(reply, wizard(guild));
+
+		for choice in [
+			Choice::Mode(Mode::Report),
+			Choice::Scope(AutoPruneScope::Selected),
+			Choice::KeepChannels,
+		] {
+			assert!(BOT.wizards.choose(reply, choice).is_some());
+		}
+		assert!(
+			BOT.wizards.choose(reply, Choice::Save).is_none(),
+			"not this step's"
+		);
+		let wizard = BOT
+			.wizards
+			.choose(reply, Choice::Roles(vec![Id::new(GUILD + 2)]))
+			.expect("unfinished");
+		assert_eq!(BOT.settings.get(guild).mode, Mode::Enforce, "not yet saved");
+
+		BOT.wizards
+			.choose(reply, Choice::Save)
+			.expect("unfinished")
+			.save();
+		let settings = BOT.settings.get(guild);
+		assert_eq!(settings.mode, Mode::Report);
+		assert_eq!(settings.auto_prune_scope, AutoPruneScope::Selected);
+		assert_eq!(settings.prune_roles, wizard.roles);
+		assert!(
+			BOT.wizards.choose(reply, Choice::Save).is_none(),
+			"finished"
+		);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn expires() {
+		const GUILD: u64 = 4_022_000;
+		crate::test::init();
+		let token = "setup-expires";
+		let expired = Id::new(GUILD);
+		let finished = Id::new(GUILD + 1);
+		BOT.wizards.insert(expired, wizard(Id::new(GUILD)));
+		BOT.wizards.insert(finished, wizard(Id::new(GUILD)));
+
+		let expiries = [
+			tokio::spawn(super::expire(expired, token.to_owned())),
+			tokio::spawn(super::expire(finished, "setup-finished".to_owned())),
+		];
+		BOT.wizards.remove(finished);
+		// both sleeping
+		tokio::task::yield_now().await;
+		tokio::time::advance(EXPIRY).await;
+		// the message is deleted in real time
+		tokio::time::resume();
+		for expiry in expiries {
+			expiry.await.unwrap();
+		}
+
+		assert!(BOT
+			.wizards
+			.choose(expired, Choice::Mode(Mode::Report))
+			.is_none());
+		let requests = crate::test::requests(token);
+		assert_eq!(requests.len(), 1);
+		assert_eq!(requests[0].method, "DELETE");
+		assert!(crate::test::requests("setup-finished").is_empty());
+	}
+
+	#[tokio::test]
+	async fn only_invoker_chooses() {
+		crate::test::init();
+		let reply: Id<InteractionMarker> = Id::new(4_023_000);
+		BOT.wizards.insert(reply, wizard(Id::new(4_023_000)));
+		let custom_id = Target {
+			reply,
+			user: Id::new(GUILD + 100),
+			action: "report",
+		}
+		.custom_id();
+		let click = |id: u64, token: &str, user: u64| {
+			let interaction: Interaction = serde_json::from_value(serde_json::json!({
+				"application_id": crate::test::BOT_ID.to_string(),
+				"channel_id": "4023020",
+				"data": {"component_type": 2, "custom_id": custom_id},
+				"entitlements": [],
+				"id": id.to_string(),
+				"locale": "en-US",
+				"token": token,
+				"type": 3,
+				"user": {
+					"avatar": null,
+					"discriminator": "0",
+					"id": user.to_string(),
+					"username": format!("user {user}"),
+				},
+				"version": 1,
+			}))
+			.expect("valid interaction");
+			interaction
+		};
+		let choose = |interaction: Interaction| async move {
+			let Some(twilight_model::application::interaction::InteractionData::MessageComponent(
+				data,
+			)) = &interaction.data
+			else {
+				unreachable!("component interaction");
+			};
+			super::component(&interaction, data).await.unwrap();
+		};
+
+		choose(click(4_023_001, "setup-other", GUILD + 101)).await;
+		let response = &crate::test::requests("setup-other")[0].body;
+		assert_eq!(response["data"]["content"], super::DENIED);
+		assert_eq!(
+			BOT.wizards
+				.choose(reply, Choice::Mode(Mode::Enforce))
+				.map(|wizard| wizard.step),
+			Some(Step::Scope),
+			"the mode is still asked for"
+		);
+	}
+}
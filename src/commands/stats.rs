@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use futures_util::{future::BoxFuture, FutureExt};
+use twilight_model::application::command::{Command, CommandType};
+use twilight_util::builder::command::CommandBuilder;
+
+use crate::{
+	stats::{self, RETENTION},
+	BOT,
+};
+
+/// Characters of the sparkline, from lowest to highest.
+const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Number of days in the sparkline.
+const SPARKLINE_DAYS: u64 = 7;
+
+/// Number of top channels listed.
+const TOP_CHANNELS: usize = 3;
+
+pub struct Stats;
+
+impl super::SlashCommand for Stats {
+	const NAME: &'static str = "stats";
+
+	fn define() -> Command {
+		CommandBuilder::new(
+			Self::NAME,
+			"Prune statistics of this server",
+			CommandType::ChatInput,
+		)
+		.dm_permission(false)
+		.build()
+	}
+
+	fn run(ctx: super::Context) -> BoxFuture<'static, super::Result> {
+		run(ctx).boxed()
+	}
+}
+
+/// Users pruned per day of the week up to today, oldest first.
+fn sparkline(days: &[stats::Day], today: u64) -> String {
+	let users: Vec<_> = (0..SPARKLINE_DAYS)
+		.rev()
+		.map(|ago| {
+			days.iter()
+				.find(|day| day.day + ago == today)
+				.map_or(0, |day| day.users)
+		})
+		.collect();
+	let max = users.iter().copied().max().unwrap_or_default().max(1);
+
+	users
+		.into_iter()
+		.map(|users| {
+			// round up so that any prune is visible
+			let scaled = (users as usize * (BLOCKS.len() - 1) + max as usize - 1) / max as usize;
+			BLOCKS[scaled]
+		})
+		.collect()
+}
+
+async fn run(ctx: super::Context) -> super::Result {
+	let days = BOT.stats.guild(ctx.guild);
+
+	let auto: u32 = days.iter().map(|day| day.auto).sum();
+	let manual: u32 = days.iter().map(|day| day.manual).sum();
+	let users: u32 = days.iter().map(|day| day.users).sum();
+	let kinds = crate::prune::describe_kinds(|kind| {
+		days.iter().filter_map(|day| day.kinds.get(&kind)).sum()
+	})
+	.map_or_else(String::new, |kinds| format!(" ({kinds})"));
+	let mut msg = format!(
+		"Last {RETENTION} days: {auto} auto prunes, {manual} manual prunes, {users} users pruned{kinds}\n\
+		Last {SPARKLINE_DAYS} days: {}",
+		sparkline(&days, stats::today())
+	);
+
+	let mut channels = HashMap::<_, u32>::new();
+	for day in &days {
+		for (&channel, &users) in &day.channels {
+			*channels.entry(channel).or_default() += users;
+		}
+	}
+	let mut channels: Vec<_> = channels.into_iter().collect();
+	channels.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+	if !channels.is_empty() {
+		let top: Vec<_> = channels
+			.iter()
+			.take(TOP_CHANNELS)
+			.map(|(channel, users)| format!("<#{channel}> ({users})"))
+			.collect();
+		msg += &format!("\nTop channels: {}", top.join(", "));
+	}
+
+	if !BOT.stats.is_persisted() {
+		msg += "\n-# Statistics are lost when the bot restarts";
+	}
+
+	ctx.reply(msg).await
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::stats::Day;
+
+	const TODAY: u64 = 20_000;
+
+	fn day(day: u64, users: u32) -> Day {
+		Day {
+			day,
+			users,
+			..Day::default()
+		}
+	}
+
+	#[test]
+	fn sparkline() {
+		let days = [
+			day(TODAY - 10, 100),
+			day(TODAY - 6, 1),
+			day(TODAY - 1, 4),
+			day(TODAY, 8),
+		];
+
+		// days before the week are left out, and any prune is visible
+		assert_eq!(super::sparkline(&days, TODAY), "▂▁▁▁▁▅█");
+		assert_eq!(super::sparkline(&[], TODAY), "▁▁▁▁▁▁▁");
+	}
+}
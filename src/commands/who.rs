@@ -0,0 +1,156 @@
+use std::iter;
+
+use futures_util::{future::BoxFuture, FutureExt};
+use twilight_cache_inmemory::model::CachedVoiceState;
+use twilight_model::{
+	application::command::{Command, CommandType},
+	guild::Permissions,
+	id::{
+		marker::{ChannelMarker, UserMarker},
+		Id,
+	},
+};
+use twilight_util::builder::command::{ChannelBuilder, CommandBuilder};
+
+use crate::{voice::VoiceStateExt, BOT, MONITORED_CHANNEL_TYPES};
+
+pub struct Who;
+
+impl super::SlashCommand for Who {
+	const NAME: &'static str = "who";
+
+	fn define() -> Command {
+		CommandBuilder::new(
+			Self::NAME,
+			"List the users connected to a voice channel",
+			CommandType::ChatInput,
+		)
+		.default_member_permissions(Permissions::MOVE_MEMBERS)
+		.dm_permission(false)
+		.option(
+			ChannelBuilder::new("channel", "Voice channel to list")
+				.channel_types(MONITORED_CHANNEL_TYPES)
+				.required(true),
+		)
+		.build()
+	}
+
+	fn run(ctx: super::Context) -> BoxFuture<'static, super::Result> {
+		run(ctx).boxed()
+	}
+}
+
+async fn run(ctx: super::Context) -> super::Result {
+	let channel: Id<ChannelMarker> = ctx.options().required("channel");
+	if !super::is_voice_channel(ctx.guild, channel) {
+		return ctx.reply(super::NOT_VOICE_CHANNEL.to_owned()).await;
+	}
+
+	let offenders = crate::prune::channel_offenders(channel);
+	let mut users: Vec<_> =
+		BOT.cache
+			.voice_channel_states(channel)
+			.map_or_else(Vec::new, |states| {
+				states
+					.map(|state| {
+						let flags = flags(&state, offenders.contains(&state.user_id()));
+						(state.display_name(&BOT.cache), state.user_id(), flags)
+					})
+					.collect()
+			});
+	if users.is_empty() {
+		return ctx
+			.reply(format!("Nobody is connected to <#{channel}>"))
+			.await;
+	}
+	users.sort_unstable_by(|(a, a_id, _), (b, b_id, _)| {
+		a.to_lowercase().cmp(&b.to_lowercase()).then(a_id.cmp(b_id))
+	});
+
+	let header = format!("<#{channel}>: {} connected", users.len());
+	let lines = users
+		.into_iter()
+		.map(|(name, user, flags)| line(user, &name, &flags));
+	ctx.reply_paged(iter::once(header).chain(lines)).await
+}
+
+/// Line listing the connected user with their flags.
+fn line(user: Id<UserMarker>, name: &str, flags: &[&str]) -> String {
+	if flags.is_empty() {
+		format!("• <@{user}> ({name})")
+	} else {
+		format!("• <@{user}> ({name}): {}", flags.join(", "))
+	}
+}
+
+/// Flags of the connected user, e.g. whether a prune would remove them.
+fn flags(state: &CachedVoiceState, pruned: bool) -> Vec<&'static str> {
+	[
+		(pruned, "would be pruned"),
+		(state.mute(), "server muted"),
+		(state.deaf(), "server deafened"),
+		(state.is_streaming(), "streaming"),
+		(state.is_suppressed(), "suppressed"),
+	]
+	.into_iter()
+	.filter_map(|(set, flag)| set.then_some(flag))
+	.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use twilight_model::{guild::Permissions, id::Id};
+
+	use crate::{
+		test::{member_overwrite, Guild},
+		BOT,
+	};
+
+	#[test]
+	fn flags() {
+		const GUILD: u64 = 3_880_000;
+		let guild = Guild::new(GUILD)
+			.voice(
+				GUILD + 10,
+				&[member_overwrite(
+					GUILD + 100,
+					Permissions::empty(),
+					Permissions::CONNECT,
+				)],
+			)
+			.member(GUILD + 100, &[])
+			.member(GUILD + 101, &[])
+			.member(GUILD + 102, &[])
+			.member(GUILD + 103, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.muted()
+			.connect(GUILD + 101, GUILD + 10)
+			.deafened()
+			.streaming()
+			.connect(GUILD + 102, GUILD + 10)
+			.suppressed()
+			.connect(GUILD + 103, GUILD + 10)
+			.create();
+		let offenders = crate::prune::channel_offenders(Id::new(GUILD + 10));
+		let flags = |user| {
+			let state = BOT.cache.voice_state(Id::new(user), guild).expect("cached");
+			super::flags(&state, offenders.contains(&state.user_id()))
+		};
+
+		assert_eq!(flags(GUILD + 100), ["would be pruned", "server muted"]);
+		assert_eq!(flags(GUILD + 101), ["server deafened", "streaming"]);
+		assert_eq!(flags(GUILD + 102), ["suppressed"]);
+		assert!(flags(GUILD + 103).is_empty());
+	}
+
+	#[test]
+	fn line() {
+		let user = Id::new(3_881_100);
+
+		assert_eq!(super::line(user, "Nick", &[]), "• <@3881100> (Nick)");
+		assert_eq!(
+			super::line(user, "Nick", &["would be pruned", "streaming"]),
+			"• <@3881100> (Nick): would be pruned, streaming"
+		);
+	}
+}
@@ -0,0 +1,282 @@
+//! Configuration file given through `--config`, an alternative to the environment variables.
+//!
+//! The file is a flat TOML document whose keys are the lowercase environment variable names, e.g.
+//! `removal_queue = 512`. Values are exported as their environment variable unless it's already
+//! set, so the environment takes precedence over the file, which takes precedence over the
+//! defaults.
+
+use std::{collections::HashSet, env, fs, path::Path};
+
+use anyhow::Context;
+
+/// Type of a key's value.
+#[derive(Clone, Copy, Debug)]
+enum Kind {
+	/// Exported as is.
+	String,
+	/// Exported in decimal.
+	Integer,
+	/// Exported as the first string if true, otherwise as the second.
+	Boolean(&'static str, &'static str),
+	/// Array of IDs, exported comma-separated.
+	Ids,
+}
+
+/// Keys of the file, with their environment variable and type.
+const KEYS: [(&str, &str, Kind); 18] = [
+	("auto_prune_limit", "AUTO_PRUNE_LIMIT", Kind::Integer),
+	("auto_prune_window", "AUTO_PRUNE_WINDOW", Kind::Integer),
+	("control_socket", "CONTROL_SOCKET", Kind::String),
+	("discord_proxy_url", "DISCORD_PROXY_URL", Kind::String),
+	("guild_allowlist", "GUILD_ALLOWLIST", Kind::Ids),
+	("guild_denylist", "GUILD_DENYLIST", Kind::Ids),
+	("log_format", "LOG_FORMAT", Kind::String),
+	("log_guild", "LOG_GUILD", Kind::Integer),
+	(
+		"monitor_stage",
+		"MONITOR_STAGE",
+		Kind::Boolean("true", "false"),
+	),
+	("owners", "OWNERS", Kind::Ids),
+	("presence", "PRESENCE", Kind::Boolean("1", "0")),
+	("presence_template", "PRESENCE_TEMPLATE", Kind::String),
+	("removal_queue", "REMOVAL_QUEUE", Kind::Integer),
+	("removal_workers", "REMOVAL_WORKERS", Kind::Integer),
+	("rust_log", "RUST_LOG", Kind::String),
+	("status_file", "STATUS_FILE", Kind::String),
+	("token_file", "TOKEN_FILE", Kind::String),
+	("verify_limit", "VERIFY_LIMIT", Kind::Integer),
+];
+
+/// Parsed value of a key.
+#[derive(Debug)]
+enum Value {
+	String(String),
+	Integer(i64),
+	Boolean(bool),
+	Array(Vec<Value>),
+}
+
+/// Reads the file and exports its values, returning how many weren't overridden by the
+/// environment.
+///
+/// Must be called before anything reads the environment.
+pub fn load(path: &Path) -> Result<usize, anyhow::Error> {
+	let contents =
+		fs::read_to_string(path).with_context(|| format!("unable to read {}", path.display()))?;
+	let values = parse(&contents).with_context(|| format!("invalid config {}", path.display()))?;
+
+	let exports = unset(values, |var| env::var_os(var).is_some());
+	let exported = exports.len();
+	for (var, value) in exports {
+		env::set_var(var, value);
+	}
+	Ok(exported)
+}
+
+/// The values whose variable `is_set` returns `false` for, as the environment takes precedence.
+fn unset(
+	values: Vec<(&'static str, String)>,
+	is_set: impl Fn(&str) -> bool,
+) -> Vec<(&'static str, String)> {
+	values
+		.into_iter()
+		.filter(|&(var, _)| !is_set(var))
+		.collect()
+}
+
+/// Parses the document into environment variables and their values.
+fn parse(contents: &str) -> Result<Vec<(&'static str, String)>, anyhow::Error> {
+	let mut seen = HashSet::new();
+	let mut values = Vec::new();
+	for (i, line) in contents.lines().enumerate() {
+		let line_number = i + 1;
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		anyhow::ensure!(
+			!line.starts_with('['),
+			"line {line_number}: tables are not supported"
+		);
+
+		let (key, value) = line
+			.split_once('=')
+			.with_context(|| format!("line {line_number}: expected \"key = value\""))?;
+		let key = key.trim();
+		let &(_, var, kind) = KEYS
+			.iter()
+			.find(|&&(name, ..)| name == key)
+			.with_context(|| format!("line {line_number}: unknown key \"{key}\""))?;
+		anyhow::ensure!(
+			seen.insert(key),
+			"line {line_number}: \"{key}\" is set more than once"
+		);
+
+		let value = Value::parse(value)
+			.and_then(|value| value.export(kind))
+			.with_context(|| format!("line {line_number}: invalid value of \"{key}\""))?;
+		values.push((var, value));
+	}
+	Ok(values)
+}
+
+impl Value {
+	/// Parses a value, followed by an optional comment.
+	fn parse(s: &str) -> Result<Self, anyhow::Error> {
+		let (value, rest) = Self::parse_prefix(s.trim_start())?;
+		let rest = rest.trim_start();
+		anyhow::ensure!(
+			rest.is_empty() || rest.starts_with('#'),
+			"unexpected \"{rest}\" after the value"
+		);
+		Ok(value)
+	}
+
+	/// Parses the value at the start of the string, returning it and the remainder.
+	fn parse_prefix(s: &str) -> Result<(Self, &str), anyhow::Error> {
+		if let Some(s) = s.strip_prefix('"') {
+			let mut string = String::new();
+			let mut chars = s.char_indices();
+			while let Some((i, c)) = chars.next() {
+				match c {
+					'"' => return Ok((Self::String(string), &s[i + 1..])),
+					'\\' => string.push(match chars.next().map(|(_, c)| c) {
+						Some('"') => '"',
+						Some('\\') => '\\',
+						Some('n') => '\n',
+						Some('t') => '\t',
+						_ => anyhow::bail!("unsupported escape sequence"),
+					}),
+					c => string.push(c),
+				}
+			}
+			anyhow::bail!("unterminated string");
+		}
+
+		if let Some(mut s) = s.strip_prefix('[') {
+			let mut values = Vec::new();
+			loop {
+				s = s.trim_start();
+				if let Some(rest) = s.strip_prefix(']') {
+					return Ok((Self::Array(values), rest));
+				}
+				let (value, rest) = Self::parse_prefix(s)?;
+				values.push(value);
+				s = rest.trim_start();
+				if let Some(rest) = s.strip_prefix(',') {
+					s = rest;
+				} else {
+					anyhow::ensure!(s.starts_with(']'), "expected \",\" or \"]\" in the array");
+				}
+			}
+		}
+
+		let end = s
+			.find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#')
+			.unwrap_or(s.len());
+		let (token, rest) = s.split_at(end);
+		let value = match token {
+			"true" => Self::Boolean(true),
+			"false" => Self::Boolean(false),
+			_ => Self::Integer(
+				token
+					.replace('_', "")
+					.parse()
+					.with_context(|| format!("\"{token}\" is not a string, integer or boolean"))?,
+			),
+		};
+		Ok((value, rest))
+	}
+
+	/// Formats the value as the environment variable of a key of the kind.
+	fn export(self, kind: Kind) -> Result<String, anyhow::Error> {
+		match (kind, self) {
+			(Kind::String, Self::String(s)) => Ok(s),
+			(Kind::Integer, Self::Integer(n)) => Ok(n.to_string()),
+			(Kind::Boolean(yes, no), Self::Boolean(b)) => Ok(if b { yes } else { no }.to_owned()),
+			(Kind::Ids, Self::Array(values)) => values
+				.into_iter()
+				.map(|value| match value {
+					Self::Integer(n) => Ok(n.to_string()),
+					Self::String(s) => Ok(s),
+					_ => anyhow::bail!("expected an array of IDs"),
+				})
+				.collect::<Result<Vec<_>, _>>()
+				.map(|ids| ids.join(",")),
+			(Kind::String, _) => anyhow::bail!("expected a string"),
+			(Kind::Integer, _) => anyhow::bail!("expected an integer"),
+			(Kind::Boolean(..), _) => anyhow::bail!("expected a boolean"),
+			(Kind::Ids, _) => anyhow::bail!("expected an array of IDs"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use super::*;
+
+	fn fixture() -> PathBuf {
+		Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/config.toml")
+	}
+
+	#[test]
+	fn parses_every_key() {
+		let contents = fs::read_to_string(fixture()).unwrap();
+		let values: Vec<_> = parse(&contents).unwrap();
+
+		let vars: HashSet<_> = values.iter().map(|&(var, _)| var).collect();
+		assert_eq!(vars.len(), KEYS.len(), "every key is set");
+		let value = |var| &values.iter().find(|&&(name, _)| name == var).unwrap().1;
+		assert_eq!(
+			value("TOKEN_FILE"),
+			"/run/credentials/voice-pruner.service/token"
+		);
+		assert_eq!(
+			value("GUILD_ALLOWLIST"),
+			"123456789012345678,234567890123456789"
+		);
+		assert_eq!(value("GUILD_DENYLIST"), "");
+		assert_eq!(value("REMOVAL_QUEUE"), "1024");
+		assert_eq!(value("MONITOR_STAGE"), "false");
+		assert_eq!(value("PRESENCE"), "1");
+		assert_eq!(value("PRESENCE_TEMPLATE"), "{channels} \"voice\" channels");
+	}
+
+	#[test]
+	fn errors_name_line_and_key() {
+		let error = |contents| format!("{:#}", parse(contents).unwrap_err());
+
+		assert_eq!(
+			error("# comment\nremoval_queue = \"many\""),
+			"line 2: invalid value of \"removal_queue\": expected an integer"
+		);
+		assert_eq!(error("token = \"secret\""), "line 1: unknown key \"token\"");
+		assert_eq!(
+			error("presence = true\npresence = false"),
+			"line 2: \"presence\" is set more than once"
+		);
+		assert_eq!(error("[bot]"), "line 1: tables are not supported");
+		assert_eq!(
+			error("owners = [1, true]"),
+			"line 1: invalid value of \"owners\": expected an array of IDs"
+		);
+		assert_eq!(
+			error("rust_log = \"info\" debug"),
+			"line 1: invalid value of \"rust_log\": unexpected \"debug\" after the value"
+		);
+	}
+
+	#[test]
+	fn environment_takes_precedence() {
+		let values =
+			parse("presence_template = \"from the file\"\nstatus_file = \"/from/the/file\"")
+				.unwrap();
+
+		let exports = unset(values, |var| var == "PRESENCE_TEMPLATE");
+
+		assert_eq!(exports, [("STATUS_FILE", "/from/the/file".to_owned())]);
+	}
+}
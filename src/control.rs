@@ -0,0 +1,335 @@
+//! Optional control socket for external systems, enabled by setting `CONTROL_SOCKET` to a path.
+//!
+//! Clients write newline-delimited JSON commands to the Unix domain socket and receive a JSON reply
+//! line per command. Commands run as auto prunes, so they respect the guild's auto prune settings.
+
+use std::{
+	env, fs, os::unix::fs::PermissionsExt, path::PathBuf, sync::atomic::Ordering, time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::{
+	io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+	net::{UnixListener, UnixStream},
+};
+use twilight_model::id::{
+	marker::{ChannelMarker, GuildMarker},
+	Id,
+};
+
+use crate::{prune::Trigger, BOT, SHUTDOWN};
+
+/// Interval [`SHUTDOWN`] is checked at.
+const SHUTDOWN_POLL: Duration = Duration::from_secs(1);
+
+/// Command of a client.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "op")]
+enum Command {
+	PruneChannel {
+		channel_id: Id<ChannelMarker>,
+		guild_id: Id<GuildMarker>,
+	},
+	PruneGuild {
+		guild_id: Id<GuildMarker>,
+	},
+}
+
+/// Reply to a command.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+enum Reply {
+	/// The prune finished.
+	Pruned {
+		users: u16,
+		failed: usize,
+		/// Whether users were only reported, see [`crate::settings::Mode::Report`].
+		report: bool,
+	},
+	/// The guild is not fully cached, the prune runs once it is.
+	Deferred,
+	Error {
+		error: String,
+	},
+}
+
+impl Reply {
+	fn error(error: impl ToString) -> Self {
+		Self::Error {
+			error: error.to_string(),
+		}
+	}
+}
+
+/// Socket listening for commands.
+#[derive(Debug)]
+pub struct Control {
+	listener: UnixListener,
+	path: PathBuf,
+}
+
+impl Control {
+	/// Binds the socket from the environment, `None` if disabled.
+	///
+	/// A stale socket at the path is replaced, and only the bot's user may connect.
+	pub fn bind() -> Result<Option<Self>, anyhow::Error> {
+		let Some(path) = env::var_os("CONTROL_SOCKET").map(PathBuf::from) else {
+			return Ok(None);
+		};
+
+		match fs::remove_file(&path) {
+			Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+				return Err(e).with_context(|| format!("unable to remove {}", path.display()));
+			}
+			_ => {}
+		}
+		let listener = UnixListener::bind(&path)
+			.with_context(|| format!("unable to bind control socket {}", path.display()))?;
+		fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+			.with_context(|| format!("unable to restrict {}", path.display()))?;
+		tracing::info!(path = %path.display(), "listening on control socket");
+
+		Ok(Some(Self { listener, path }))
+	}
+}
+
+/// Accepts clients until shutdown, removing the socket afterwards.
+pub async fn run(control: Option<Control>) {
+	let Some(control) = control else {
+		return;
+	};
+
+	let mut interval = tokio::time::interval(SHUTDOWN_POLL);
+	loop {
+		tokio::select! {
+			res = control.listener.accept() => match res {
+				Ok((stream, _)) => {
+					tokio::spawn(serve(stream));
+				}
+				Err(e) => tracing::warn!(error = &e as &dyn std::error::Error, "unable to accept"),
+			},
+			_ = interval.tick() => if SHUTDOWN.load(Ordering::Relaxed) {
+				break;
+			},
+		}
+	}
+
+	_ = fs::remove_file(&control.path);
+}
+
+/// Replies to the client's commands until it disconnects.
+async fn serve(stream: UnixStream) {
+	let (reader, mut writer) = stream.into_split();
+	let mut lines = BufReader::new(reader).lines();
+	loop {
+		let line = match lines.next_line().await {
+			Ok(Some(line)) => line,
+			Ok(None) => return,
+			Err(e) => {
+				tracing::debug!(error = &e as &dyn std::error::Error, "control client");
+				return;
+			}
+		};
+		if line.trim().is_empty() {
+			continue;
+		}
+
+		let reply = match serde_json::from_str(&line) {
+			Ok(command) => execute(command).await,
+			Err(e) => Reply::error(format_args!("invalid command: {e}")),
+		};
+		let mut reply = serde_json::to_vec(&reply).expect("serializable");
+		reply.push(b'\n');
+		if let Err(e) = writer.write_all(&reply).await {
+			tracing::debug!(error = &e as &dyn std::error::Error, "control client");
+			return;
+		}
+	}
+}
+
+/// Runs the command's prune.
+async fn execute(command: Command) -> Reply {
+	tracing::info!(?command, "control command");
+	let guild = match command {
+		Command::PruneChannel { guild_id, .. } | Command::PruneGuild { guild_id } => guild_id,
+	};
+	if !BOT.policy.allows(guild) || BOT.cache.guild(guild).is_none() {
+		return Reply::error("unknown guild");
+	}
+	if !BOT.auto_prune(guild) {
+		return Reply::error("auto prune is disabled in this guild");
+	}
+	if BOT.readiness.defer(guild, Trigger::Control) {
+		return Reply::Deferred;
+	}
+
+	let summary = match command {
+		Command::PruneChannel { channel_id, .. } => {
+			let in_guild = BOT
+				.cache
+				.channel(channel_id)
+				.is_some_and(|channel| channel.guild_id == Some(guild));
+			if !in_guild {
+				return Reply::error("unknown channel");
+			}
+			crate::prune::channel(channel_id, guild, Trigger::Control, |_| true, None).await
+		}
+		Command::PruneGuild { .. } => {
+			match crate::prune::guild(guild, Trigger::Control, None, |_| true, None).await {
+				Ok(summary) => summary,
+				Err(e) => return Reply::error(e),
+			}
+		}
+	};
+
+	if summary.aborted {
+		return Reply::error("aborted, the prune exceeds the guild's max-auto-prune");
+	}
+	Reply::Pruned {
+		users: summary.pruned(),
+		failed: summary.failed.len(),
+		report: summary.report,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::{json, Value};
+	use twilight_model::guild::Permissions;
+
+	use super::*;
+	use crate::test::{removed, Guild};
+
+	#[test]
+	fn parses_commands() {
+		let command = serde_json::from_str(r#"{"op":"prune_guild","guild_id":"1"}"#);
+		assert!(matches!(command, Ok(Command::PruneGuild { guild_id }) if guild_id == Id::new(1)));
+
+		let command =
+			serde_json::from_str(r#"{"op":"prune_channel","channel_id":"2","guild_id":"1"}"#);
+		assert!(matches!(
+			command,
+			Ok(Command::PruneChannel { channel_id, guild_id })
+				if channel_id == Id::new(2) && guild_id == Id::new(1)
+		));
+	}
+
+	#[test]
+	fn rejects_invalid_commands() {
+		for line in [
+			r#"{"op":"prune_everything"}"#,
+			r#"{"guild_id":"1"}"#,
+			r#"{"op":"prune_channel","guild_id":"1"}"#,
+			r#"{"op":"prune_guild","guild_id":"0"}"#,
+			r#"{"op":"prune_guild","guild_id":"guild"}"#,
+			"prune_guild 1",
+		] {
+			assert!(serde_json::from_str::<Command>(line).is_err(), "{line}");
+		}
+	}
+
+	#[test]
+	fn serializes_replies() {
+		let reply = Reply::Pruned {
+			users: 2,
+			failed: 1,
+			report: false,
+		};
+		assert_eq!(
+			serde_json::to_value(reply).unwrap(),
+			json!({"status": "pruned", "users": 2, "failed": 1, "report": false}),
+		);
+		assert_eq!(
+			serde_json::to_value(Reply::Deferred).unwrap(),
+			json!({"status": "deferred"}),
+		);
+		assert_eq!(
+			serde_json::to_value(Reply::error("unknown guild")).unwrap(),
+			json!({"status": "error", "error": "unknown guild"}),
+		);
+	}
+
+	/// Writes the command and reads the reply.
+	async fn request(client: &mut BufReader<UnixStream>, command: String) -> Value {
+		client.write_all(command.as_bytes()).await.unwrap();
+		// blank lines are skipped
+		client.write_all(b"\n\n").await.unwrap();
+		let mut line = String::new();
+		client.read_line(&mut line).await.unwrap();
+		serde_json::from_str(&line).unwrap()
+	}
+
+	#[tokio::test]
+	async fn smoke() {
+		const GUILD: u64 = 3_470_000;
+		const COLD: u64 = 3_471_000;
+		const USER: u64 = GUILD + 100;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(GUILD + 10, &[])
+			.voice(GUILD + 11, &[])
+			.member(USER, &[])
+			.connect(USER, GUILD + 10)
+			.create();
+		let cold = Guild::new(COLD).create();
+		BOT.readiness.cool([cold]);
+
+		let (client, server) = UnixStream::pair().unwrap();
+		tokio::spawn(serve(server));
+		let mut client = BufReader::new(client);
+
+		assert_eq!(
+			request(&mut client, String::from("{")).await["status"],
+			"error",
+			"invalid JSON"
+		);
+		assert_eq!(
+			request(
+				&mut client,
+				format!(r#"{{"op":"prune_guild","guild_id":"{}"}}"#, GUILD + 999)
+			)
+			.await,
+			json!({"status": "error", "error": "unknown guild"}),
+		);
+		assert_eq!(
+			request(
+				&mut client,
+				format!(
+					r#"{{"op":"prune_channel","channel_id":"{}","guild_id":"{GUILD}"}}"#,
+					COLD + 10
+				)
+			)
+			.await,
+			json!({"status": "error", "error": "unknown channel"}),
+		);
+		assert_eq!(
+			request(
+				&mut client,
+				format!(r#"{{"op":"prune_guild","guild_id":"{COLD}"}}"#)
+			)
+			.await,
+			json!({"status": "deferred"}),
+		);
+		assert_eq!(
+			request(
+				&mut client,
+				format!(
+					r#"{{"op":"prune_channel","channel_id":"{}","guild_id":"{GUILD}"}}"#,
+					GUILD + 11
+				)
+			)
+			.await,
+			json!({"status": "pruned", "users": 0, "failed": 0, "report": false}),
+		);
+		assert_eq!(
+			request(
+				&mut client,
+				format!(r#"{{"op":"prune_guild","guild_id":"{GUILD}"}}"#)
+			)
+			.await,
+			json!({"status": "pruned", "users": 1, "failed": 0, "report": false}),
+		);
+		assert_eq!(removed(guild), [(guild, Id::new(USER), None)]);
+	}
+}
@@ -0,0 +1,165 @@
+//! Per-invoker command cooldowns, lost on restart.
+
+use std::{
+	collections::HashMap,
+	sync::{Mutex, PoisonError},
+	time::{Duration, Instant},
+};
+
+use twilight_model::id::{
+	marker::{GuildMarker, UserMarker},
+	Id,
+};
+
+use crate::cleanup::Cleanup;
+
+/// Number of entries above which expired entries are evicted.
+const EVICT_AT: usize = 256;
+
+/// Invoker and command name.
+type Key = (Id<GuildMarker>, Id<UserMarker>, &'static str);
+
+/// End of every invoker's command cooldowns.
+#[derive(Debug, Default)]
+pub struct Cooldowns(Mutex<HashMap<Key, Instant>>);
+
+impl Cooldowns {
+	/// Starts the cooldown of the user's command, or returns the remaining cooldown if still
+	/// active.
+	pub fn acquire(
+		&self,
+		guild: Id<GuildMarker>,
+		user: Id<UserMarker>,
+		command: &'static str,
+		cooldown: Duration,
+	) -> Result<(), Duration> {
+		self.acquire_at((guild, user, command), cooldown, Instant::now())
+	}
+
+	fn acquire_at(&self, key: Key, cooldown: Duration, now: Instant) -> Result<(), Duration> {
+		let mut cooldowns = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		if cooldowns.len() >= EVICT_AT {
+			cooldowns.retain(|_, &mut until| until > now);
+		}
+
+		match cooldowns.get(&key) {
+			Some(&until) if until > now => Err(until - now),
+			_ => {
+				cooldowns.insert(key, now + cooldown);
+				Ok(())
+			}
+		}
+	}
+}
+
+impl Cleanup for Cooldowns {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.retain(|&(id, ..), _| id != guild);
+	}
+
+	fn member(&self, guild: Id<GuildMarker>, user: Id<UserMarker>) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.retain(|&(id, member, _), _| (id, member) != (guild, user));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const COOLDOWN: Duration = Duration::from_secs(10);
+
+	fn key(user: u64, command: &'static str) -> Key {
+		(Id::new(3_310_000), Id::new(user), command)
+	}
+
+	#[test]
+	fn expires_exactly_after_cooldown() {
+		let cooldowns = Cooldowns::default();
+		let start = Instant::now();
+
+		assert_eq!(
+			cooldowns.acquire_at(key(1, "prune"), COOLDOWN, start),
+			Ok(())
+		);
+		assert_eq!(
+			cooldowns.acquire_at(key(1, "prune"), COOLDOWN, start + Duration::from_secs(4)),
+			Err(Duration::from_secs(6))
+		);
+		assert_eq!(
+			cooldowns.acquire_at(
+				key(1, "prune"),
+				COOLDOWN,
+				start + COOLDOWN - Duration::from_nanos(1)
+			),
+			Err(Duration::from_nanos(1))
+		);
+		assert_eq!(
+			cooldowns.acquire_at(key(1, "prune"), COOLDOWN, start + COOLDOWN),
+			Ok(())
+		);
+		// acquiring restarted the cooldown
+		assert!(cooldowns
+			.acquire_at(key(1, "prune"), COOLDOWN, start + COOLDOWN)
+			.is_err());
+	}
+
+	#[test]
+	fn separate_per_user_and_command() {
+		let cooldowns = Cooldowns::default();
+		let now = Instant::now();
+		cooldowns
+			.acquire_at(key(1, "prune"), COOLDOWN, now)
+			.unwrap();
+
+		assert_eq!(cooldowns.acquire_at(key(2, "prune"), COOLDOWN, now), Ok(()));
+		assert_eq!(cooldowns.acquire_at(key(1, "lock"), COOLDOWN, now), Ok(()));
+	}
+
+	#[test]
+	fn evicts_expired_entries() {
+		let cooldowns = Cooldowns::default();
+		let start = Instant::now();
+		for user in 1..=EVICT_AT as u64 {
+			cooldowns
+				.acquire_at(key(user, "prune"), COOLDOWN, start)
+				.unwrap();
+		}
+		let late = EVICT_AT as u64 + 1;
+		cooldowns
+			.acquire_at(key(late, "prune"), COOLDOWN * 2, start)
+			.unwrap();
+
+		cooldowns
+			.acquire_at(key(late + 1, "prune"), COOLDOWN, start + COOLDOWN)
+			.unwrap();
+
+		let len = cooldowns.0.lock().unwrap().len();
+		assert_eq!(len, 2, "only the unexpired and new entries are kept");
+	}
+
+	#[test]
+	fn cleanup_forgets_member() {
+		let cooldowns = Cooldowns::default();
+		let now = Instant::now();
+		let (guild, ..) = key(1, "prune");
+		cooldowns
+			.acquire_at(key(1, "prune"), COOLDOWN, now)
+			.unwrap();
+		cooldowns
+			.acquire_at(key(2, "prune"), COOLDOWN, now)
+			.unwrap();
+
+		Cleanup::member(&cooldowns, guild, Id::new(1));
+
+		assert_eq!(cooldowns.acquire_at(key(1, "prune"), COOLDOWN, now), Ok(()));
+		assert!(cooldowns
+			.acquire_at(key(2, "prune"), COOLDOWN, now)
+			.is_err());
+	}
+}
@@ -0,0 +1,120 @@
+//! Record of the most recent auto prune decisions of channel and role updates, lost on restart.
+//!
+//! Shown by `/maintenance decisions` to debug why an update did or didn't cause an auto prune.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	fmt::{self, Display, Formatter},
+	sync::{Mutex, PoisonError},
+};
+
+use twilight_model::{
+	gateway::event::EventType,
+	id::{
+		marker::{ChannelMarker, GuildMarker, RoleMarker},
+		Id,
+	},
+};
+
+use crate::{cleanup::Cleanup, clock};
+
+/// Number of decisions recorded per guild.
+const LIMIT: usize = 50;
+
+/// Channel or role an update decision is about.
+#[derive(Clone, Copy, Debug)]
+pub enum Target {
+	Channel(Id<ChannelMarker>),
+	Role(Id<RoleMarker>),
+}
+
+impl Display for Target {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Channel(channel) => write!(f, "<#{channel}>"),
+			Self::Role(role) => write!(f, "<@&{role}>"),
+		}
+	}
+}
+
+/// Record of a decision.
+#[derive(Clone, Copy, Debug)]
+pub struct Decision {
+	/// Unix timestamp in seconds.
+	pub at: u64,
+	pub event: EventType,
+	pub target: Target,
+	/// Whether the auto prune was skipped.
+	pub skipped: bool,
+	/// Condition the decision was made on.
+	pub reason: &'static str,
+}
+
+/// Last decisions of every guild, most recent first.
+#[derive(Debug, Default)]
+pub struct Decisions(Mutex<HashMap<Id<GuildMarker>, VecDeque<Decision>>>);
+
+impl Decisions {
+	/// Records the decision, dropping the guild's oldest one past [`LIMIT`].
+	pub fn record(
+		&self,
+		guild: Id<GuildMarker>,
+		event: EventType,
+		target: Target,
+		skipped: bool,
+		reason: &'static str,
+	) {
+		tracing::trace!(guild_id = %guild, ?event, %target, skipped, reason, "auto prune decision");
+		let mut guilds = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		let decisions = guilds.entry(guild).or_default();
+		decisions.push_front(Decision {
+			at: clock::now().as_secs(),
+			event,
+			target,
+			skipped,
+			reason,
+		});
+		decisions.truncate(LIMIT);
+	}
+
+	/// Last decisions of the guild, most recent first.
+	pub fn guild(&self, guild: Id<GuildMarker>) -> Vec<Decision> {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&guild)
+			.map_or_else(Vec::new, |decisions| decisions.iter().copied().collect())
+	}
+}
+
+impl Cleanup for Decisions {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&guild);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use twilight_model::{gateway::event::EventType, id::Id};
+
+	use super::{Decisions, Target, LIMIT};
+
+	#[test]
+	fn keeps_most_recent() {
+		let decisions = Decisions::default();
+		let guild = Id::new(3_901_000);
+		for role in 1..=LIMIT as u64 + 1 {
+			let target = Target::Role(Id::new(role));
+			decisions.record(guild, EventType::RoleUpdate, target, false, "reason");
+		}
+
+		let recorded = decisions.guild(guild);
+		assert_eq!(recorded.len(), LIMIT);
+		assert!(matches!(recorded[0].target, Target::Role(role) if role.get() == LIMIT as u64 + 1));
+		assert!(matches!(recorded[LIMIT - 1].target, Target::Role(role) if role.get() == 2));
+		assert!(decisions.guild(Id::new(3_902_000)).is_empty());
+	}
+}
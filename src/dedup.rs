@@ -0,0 +1,134 @@
+//! Recently seen interactions, guarding against the gateway delivering one more than once, e.g.
+//! when replaying events after a resume, and channel changes of `/lock`, which prunes the channel
+//! itself instead of the change's auto prune.
+
+use std::{
+	collections::{HashMap, HashSet, VecDeque},
+	sync::{Mutex, PoisonError},
+	time::{Duration, Instant},
+};
+
+use twilight_model::id::{
+	marker::{ChannelMarker, InteractionMarker},
+	Id,
+};
+
+/// Duration an interaction is remembered for, exceeding its 15 minute token lifetime.
+const TTL: Duration = Duration::from_secs(20 * 60);
+
+/// Maximum number of remembered interactions, the oldest are forgotten first.
+const LIMIT: usize = 1024;
+
+/// Remembered interactions, oldest first.
+#[derive(Debug, Default)]
+struct Inner {
+	ids: HashSet<Id<InteractionMarker>>,
+	order: VecDeque<(Instant, Id<InteractionMarker>)>,
+}
+
+/// Interactions received within the last [`TTL`].
+#[derive(Debug, Default)]
+pub struct Seen(Mutex<Inner>);
+
+impl Seen {
+	/// Remembers the interaction, returning whether it wasn't already seen.
+	pub fn insert(&self, id: Id<InteractionMarker>) -> bool {
+		self.insert_at(id, Instant::now())
+	}
+
+	/// Remembers the interaction at `now`, see [`Self::insert`].
+	fn insert_at(&self, id: Id<InteractionMarker>, now: Instant) -> bool {
+		let mut inner = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		while let Some(&(at, oldest)) = inner.order.front() {
+			if now.duration_since(at) < TTL && inner.order.len() < LIMIT {
+				break;
+			}
+			inner.order.pop_front();
+			inner.ids.remove(&oldest);
+		}
+
+		if !inner.ids.insert(id) {
+			return false;
+		}
+		inner.order.push_back((now, id));
+		true
+	}
+}
+
+/// Duration a channel change of `/lock` is expected for, exceeding the time it waits for it.
+const LOCK_TTL: Duration = Duration::from_secs(30);
+
+/// Channels whose next overwrite change is made by `/lock`, with when they were marked.
+#[derive(Debug, Default)]
+pub struct Locks(Mutex<HashMap<Id<ChannelMarker>, Instant>>);
+
+impl Locks {
+	/// Marks the channel's next overwrite change as made by `/lock`.
+	pub fn mark(&self, channel: Id<ChannelMarker>) {
+		let now = Instant::now();
+		let mut channels = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		channels.retain(|_, at| now.duration_since(*at) < LOCK_TTL);
+		channels.insert(channel, now);
+	}
+
+	/// Forgets the mark of the channel, e.g. as the change failed.
+	pub fn unmark(&self, channel: Id<ChannelMarker>) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&channel);
+	}
+
+	/// Removes the mark of the channel, returning whether the change was made by `/lock`.
+	pub fn take(&self, channel: Id<ChannelMarker>) -> bool {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&channel)
+			.is_some_and(|at| at.elapsed() < LOCK_TTL)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use twilight_model::id::Id;
+
+	use super::{Seen, LIMIT, TTL};
+
+	#[test]
+	fn rejects_duplicates() {
+		let seen = Seen::default();
+
+		assert!(seen.insert(Id::new(1)));
+		assert!(!seen.insert(Id::new(1)));
+		assert!(seen.insert(Id::new(2)));
+	}
+
+	#[test]
+	fn forgets_after_ttl() {
+		let seen = Seen::default();
+		let start = Instant::now();
+
+		assert!(seen.insert_at(Id::new(1), start));
+		assert!(!seen.insert_at(Id::new(1), start + TTL - Duration::from_secs(1)));
+		// only evicted by a later insertion
+		assert!(seen.insert_at(Id::new(2), start + TTL));
+		assert!(seen.insert_at(Id::new(1), start + TTL));
+	}
+
+	#[test]
+	fn forgets_oldest_over_limit() {
+		let seen = Seen::default();
+		let now = Instant::now();
+		for id in 1..=LIMIT as u64 {
+			assert!(seen.insert_at(Id::new(id), now));
+		}
+
+		assert!(seen.insert_at(Id::new(LIMIT as u64 + 1), now));
+		assert!(seen.insert_at(Id::new(1), now), "oldest forgotten");
+		assert!(!seen.insert_at(Id::new(LIMIT as u64), now));
+		assert!(seen.0.lock().unwrap().order.len() <= LIMIT);
+	}
+}
@@ -0,0 +1,46 @@
+//! Error type of commands and prunes.
+
+use std::fmt;
+
+use twilight_model::id::{marker::RoleMarker, Id};
+
+/// Error of a command or prune.
+#[derive(Debug)]
+pub enum Error {
+	/// A request to Discord failed.
+	Http(Box<twilight_http::Error>),
+	/// A resource is not cached, e.g. because the guild is still being received.
+	CacheMiss { resource: &'static str, id: u64 },
+	/// An option's value is invalid.
+	InvalidOption { name: &'static str, value: String },
+	/// The bot does not outrank members with the role, so it may not remove them.
+	Hierarchy { role: Id<RoleMarker> },
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Http(_) => f.write_str("request to Discord failed"),
+			Self::CacheMiss { resource, id } => write!(f, "{resource} {id} is not cached"),
+			Self::InvalidOption { name, value } => {
+				write!(f, "\"{value}\" is not a valid value for \"{name}\"")
+			}
+			Self::Hierarchy { role } => write!(f, "role {role} is not below the bot's roles"),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Http(e) => Some(e.as_ref()),
+			Self::CacheMiss { .. } | Self::InvalidOption { .. } | Self::Hierarchy { .. } => None,
+		}
+	}
+}
+
+impl From<twilight_http::Error> for Error {
+	fn from(e: twilight_http::Error) -> Self {
+		Self::Http(Box::new(e))
+	}
+}
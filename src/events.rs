@@ -0,0 +1,1607 @@
+//! Handling of gateway events.
+//!
+//! Events that may require an auto prune are decided by a function per event type, updating the
+//! cache and returning an [`Action`] that [`handle`] then executes.
+
+use std::{
+	collections::{HashMap, HashSet},
+	sync::atomic::Ordering,
+	time::Instant,
+};
+
+use tracing::Instrument;
+
+use twilight_cache_inmemory::model::CachedVoiceState;
+use twilight_model::{
+	application::interaction::{Interaction, InteractionType},
+	channel::Channel,
+	gateway::{
+		event::{Event, EventType},
+		payload::incoming::{
+			ChannelUpdate, GuildUpdate, MemberUpdate, Ready, RoleDelete, RoleUpdate,
+			StageInstanceDelete,
+		},
+	},
+	guild::Permissions,
+	id::{
+		marker::{ChannelMarker, GenericMarker, GuildMarker, RoleMarker, UserMarker},
+		Id,
+	},
+};
+
+use crate::{
+	cause::{self, Cause},
+	chunk, cleanup, coalesce,
+	decision::Target,
+	onboarding,
+	prune::Trigger,
+	throttle,
+	voice::VoiceStateExt,
+	BOT,
+};
+
+/// Auto prune decided from an event.
+#[derive(Debug)]
+enum Action {
+	None,
+	/// Prune the channel's users that are targets of the changed overwrites, or every user if
+	/// `None`.
+	PruneChannel {
+		guild: Id<GuildMarker>,
+		channel: Id<ChannelMarker>,
+		update: coalesce::Update,
+	},
+	/// Prune every user of the former AFK channel, no longer exempt.
+	PruneFormerAfk {
+		guild: Id<GuildMarker>,
+		channel: Id<ChannelMarker>,
+	},
+	/// Prune every user of the channels the bot started monitoring.
+	PruneChannels {
+		guild: Id<GuildMarker>,
+		channels: Vec<Id<ChannelMarker>>,
+	},
+	/// Prune the guild, logging the role change that caused it if known.
+	PruneGuild {
+		guild: Id<GuildMarker>,
+		cause: Option<Cause>,
+	},
+	/// Prune the users, who had the deleted role.
+	PruneRoleMembers {
+		guild: Id<GuildMarker>,
+		users: HashSet<Id<UserMarker>>,
+	},
+	/// Prune the stage channel of the ended stage, including its audience if set.
+	PruneStage {
+		guild: Id<GuildMarker>,
+		channel: Id<ChannelMarker>,
+		audience: bool,
+	},
+	/// Prune the member, logging their role changes if known.
+	PruneUser {
+		guild: Id<GuildMarker>,
+		user: Id<UserMarker>,
+		cause: Option<Cause>,
+	},
+}
+
+impl Action {
+	/// Guild and trigger of the auto prune, `None` if there is none.
+	const fn auto_prune(&self) -> Option<(Id<GuildMarker>, Trigger)> {
+		match *self {
+			Self::None => None,
+			Self::PruneChannel { guild, .. } => Some((guild, Trigger::Channel)),
+			Self::PruneFormerAfk { guild, .. } => Some((guild, Trigger::Guild)),
+			Self::PruneChannels { guild, .. } | Self::PruneUser { guild, .. } => {
+				Some((guild, Trigger::Member))
+			}
+			Self::PruneGuild { guild, .. } | Self::PruneRoleMembers { guild, .. } => {
+				Some((guild, Trigger::Role))
+			}
+			Self::PruneStage { guild, .. } => Some((guild, Trigger::Stage)),
+		}
+	}
+
+	/// Runs the auto prune, unless the guild is cold or has auto pruning disabled.
+	async fn execute(self) {
+		let Some((guild, trigger)) = self.auto_prune() else {
+			return;
+		};
+		// the change causing the prune is in the audit log fetched after this
+		let since = Instant::now();
+		if BOT.readiness.defer(guild, trigger) || !BOT.auto_prune(guild) {
+			return;
+		}
+
+		match self {
+			Self::None => {}
+			Self::PruneChannel {
+				guild,
+				channel,
+				update,
+			} => {
+				let category = BOT
+					.cache
+					.channel(channel)
+					.and_then(|channel| channel.parent_id);
+				let Some(category) = category else {
+					prune_channels(guild, vec![(channel, update)], since).await;
+					return;
+				};
+				if !BOT.coalescer.push(guild, category, channel, update) {
+					return;
+				}
+				// buffering must not hold up the handling of the guild's following events, which
+				// are the updates to coalesce, so the prune is queued behind them once buffered
+				tokio::spawn(async move {
+					tokio::time::sleep(coalesce::WINDOW).await;
+					BOT.serial.run(guild, async move {
+						let channels = BOT.coalescer.take(guild, category);
+						tracing::debug!(guild_id = %guild, category_id = %category, channels = channels.len(), "coalesced channel updates");
+						prune_channels(guild, channels, since).await;
+					});
+				});
+			}
+			Self::PruneFormerAfk { guild, channel } => {
+				tracing::info!(guild_id = %guild, channel_id = %channel, "AFK channel changed");
+				throttle::auto_prune(guild, trigger, async {
+					crate::prune::channel(channel, guild, trigger, |_| true, None).await;
+				})
+				.await;
+			}
+			Self::PruneChannels { guild, channels } => {
+				tracing::info!(guild_id = %guild, channels = channels.len(), "started monitoring");
+				throttle::auto_prune(guild, trigger, async {
+					crate::prune::channels(&channels, guild, trigger, |_, _| true, None).await;
+				})
+				.await;
+			}
+			Self::PruneGuild { guild, cause } => {
+				let settings = BOT.settings.get(guild);
+				let channels = settings.auto_channels();
+				throttle::auto_prune(guild, trigger, async {
+					match crate::prune::guild(guild, trigger, channels, |_| true, None).await {
+						Ok(summary) => {
+							if let Some(cause) = cause {
+								cause.log(guild, &summary, since).await;
+							}
+						}
+						Err(e) => {
+							tracing::warn!(guild_id = %guild, error = &e as &dyn std::error::Error);
+						}
+					}
+				})
+				.await;
+			}
+			Self::PruneRoleMembers { guild, users } => {
+				let settings = BOT.settings.get(guild);
+				let channels = settings.auto_channels();
+				let kick = |state: &CachedVoiceState| users.contains(&state.user_id());
+				throttle::auto_prune(guild, trigger, async {
+					if let Err(e) = crate::prune::guild(guild, trigger, channels, kick, None).await
+					{
+						tracing::warn!(guild_id = %guild, error = &e as &dyn std::error::Error);
+					}
+				})
+				.await;
+			}
+			Self::PruneStage {
+				guild,
+				channel,
+				audience,
+			} => {
+				tracing::info!(guild_id = %guild, channel_id = %channel, audience, "stage ended");
+				throttle::auto_prune(guild, trigger, async {
+					crate::prune::stage(channel, guild, audience).await;
+				})
+				.await;
+			}
+			Self::PruneUser { guild, user, cause } => {
+				throttle::auto_prune(guild, trigger, async {
+					let summary = crate::prune::user(guild, user).await;
+					if let Some(cause) = cause {
+						cause.log(guild, &summary, since).await;
+					}
+				})
+				.await;
+			}
+		}
+	}
+}
+
+/// Prunes the still cached channels after their updates, logging the changed overwrites.
+async fn prune_channels(
+	guild: Id<GuildMarker>,
+	updates: Vec<(Id<ChannelMarker>, coalesce::Update)>,
+	since: Instant,
+) {
+	let trigger = Trigger::Channel;
+	let updates = updates
+		.into_iter()
+		.filter(|&(channel, _)| BOT.cache.channel(channel).is_some());
+	let mut ids = Vec::new();
+	let mut targets = HashMap::new();
+	let mut changes = HashMap::new();
+	for (channel, update) in updates {
+		ids.push(channel);
+		targets.insert(channel, update.targets);
+		changes.insert(channel, update.changes);
+	}
+	let kick = |channel, state: &CachedVoiceState| {
+		targets[&channel]
+			.as_ref()
+			.map_or(true, |targets| state.is_target(&BOT.cache, targets))
+	};
+	throttle::auto_prune(guild, trigger, async {
+		let summary = crate::prune::channels(&ids, guild, trigger, kick, None).await;
+		Cause::Overwrites(changes).log(guild, &summary, since).await;
+	})
+	.await;
+}
+
+/// Handle a gateway [`Event`].
+///
+/// A guild's events are handled one at a time in the order they are received, see
+/// [`serial`](crate::serial). Each updates the cache, decides its auto prune and executes it in
+/// the same step, so that the prune sees the state left by the preceding events and none of the
+/// following. Events of no guild update the cache before returning.
+pub fn handle(event: Event) {
+	BOT.totals.event();
+	let span = tracing::info_span!("handle", event = ?event.kind());
+	match event.guild_id() {
+		Some(guild) => BOT.serial.run(
+			guild,
+			async move {
+				let action = decide(&event);
+				process(event, action).await;
+			}
+			.instrument(span),
+		),
+		None => {
+			let action = span.in_scope(|| decide(&event));
+			tokio::spawn(process(event, action).instrument(span));
+		}
+	}
+}
+
+/// Updates the cache with the event and decides its auto prune, `None` if the event's guild is
+/// disallowed.
+fn decide(event: &Event) -> Option<Action> {
+	if event
+		.guild_id()
+		.is_some_and(|guild| !BOT.policy.allows(guild))
+	{
+		return None;
+	}
+
+	Some(match event {
+		Event::ChannelUpdate(c) => channel_update(c),
+		Event::GuildUpdate(g) => guild_update(g),
+		Event::MemberUpdate(m) => member_update(m),
+		Event::RoleDelete(r) => role_delete(r),
+		Event::RoleUpdate(r) => role_update(r),
+		Event::StageInstanceDelete(s) => stage_instance_delete(s),
+		_ => {
+			BOT.cache.update(event);
+			Action::None
+		}
+	})
+}
+
+/// Executes the decided auto prune and the event's remaining handling.
+async fn process(event: Event, action: Option<Action>) {
+	let Some(action) = action else {
+		if let Event::GuildCreate(g) = event {
+			tracing::info!(guild_id = %g.id, "leaving disallowed guild");
+			if let Err(e) = BOT.http.leave_guild(g.id).await {
+				tracing::warn!(guild_id = %g.id, error = &e as &dyn std::error::Error);
+			}
+		}
+		return;
+	};
+	action.execute().await;
+
+	match event {
+		Event::ChannelDelete(c) => {
+			if let Some(guild) = c.guild_id {
+				cleanup::channel(guild, c.id);
+			}
+		}
+		Event::GuildDelete(g) if !g.unavailable => cleanup::guild(g.id),
+		Event::MemberRemove(m) => cleanup::member(m.guild_id, m.user.id),
+		Event::GuildDelete(g) => BOT.readiness.cool([g.id]),
+		Event::GuildCreate(g) if g.unavailable => {
+			tracing::info!(guild_id = %g.id, "unavailable");
+			BOT.readiness.cool([g.id]);
+		}
+		Event::GuildCreate(g) => {
+			for state in &g.voice_states {
+				BOT.deafened.update(g.id, state);
+			}
+			if let Some(missing) = BOT
+				.missing_permissions(g.id)
+				.filter(|missing| !missing.is_empty())
+			{
+				let missing: Vec<_> = missing.iter_names().map(|(name, _)| name).collect();
+				tracing::warn!(guild_id = %g.id, ?missing, "missing required permissions");
+			}
+			onboarding::greet(g.id).await;
+			let users: Vec<_> = g.voice_states.iter().map(|state| state.user_id).collect();
+			if g.member_count.is_some_and(|count| count > chunk::THRESHOLD)
+				&& BOT.chunks.request(g.id, &users)
+			{
+				let guild = g.id;
+				tokio::spawn(async move {
+					tokio::time::sleep(chunk::TIMEOUT).await;
+					if BOT.chunks.expire(guild) {
+						tracing::warn!(guild_id = %guild, "requested members not received in time");
+						warm(guild).await;
+					}
+				});
+			} else {
+				warm(g.id).await;
+			}
+		}
+		Event::MemberChunk(c) => {
+			if let Some(guild) = BOT.chunks.receive(&c) {
+				tracing::debug!(guild_id = %guild, "received requested members");
+				warm(guild).await;
+			}
+		}
+		Event::VoiceStateUpdate(v) => {
+			if let Some(guild) = v.guild_id {
+				BOT.deafened.update(guild, &v);
+			}
+		}
+		Event::InteractionCreate(interaction) => interaction_create(interaction.0).await,
+		Event::Ready(r) => ready(&r).await,
+		_ => {}
+	}
+}
+
+/// Marks the guild as warm, replaying its deferred auto prune.
+async fn warm(guild: Id<GuildMarker>) {
+	let Some(trigger) = BOT.readiness.warm(guild).filter(|_| BOT.auto_prune(guild)) else {
+		return;
+	};
+	tracing::debug!(guild_id = %guild, %trigger, "replaying deferred auto prune");
+	let settings = BOT.settings.get(guild);
+	let channels = settings.auto_channels();
+	throttle::auto_prune(guild, trigger, async {
+		if let Err(e) = crate::prune::guild(guild, trigger, channels, |_| true, None).await {
+			tracing::warn!(guild_id = %guild, error = &e as &dyn std::error::Error);
+		}
+	})
+	.await;
+}
+
+/// Prune the channel if its permission overwrites changed, its name marker was removed or the bot
+/// started monitoring it.
+///
+/// Channels not previously cached are conservatively pruned in full.
+fn channel_update(c: &ChannelUpdate) -> Action {
+	let Some(guild) = c.guild_id else {
+		BOT.cache.update(c);
+		return Action::None;
+	};
+
+	let cached = BOT.cache.channel(c.id).map(|cached| cached.clone());
+	let was_monitored = crate::monitored(guild, [c.id]).contains(&c.id);
+	BOT.cache.update(c);
+	let record = |skipped, reason| {
+		BOT.decisions.record(
+			guild,
+			EventType::ChannelUpdate,
+			Target::Channel(c.id),
+			skipped,
+			reason,
+		);
+	};
+
+	let settings = BOT.settings.get(guild);
+	if !settings.is_auto_channel(c.id) {
+		record(true, "not selected for auto pruning");
+		return Action::None;
+	}
+
+	let is_marked = |channel: &Channel| {
+		channel
+			.name
+			.as_deref()
+			.is_some_and(|name| settings.is_marked(name))
+	};
+	let marker = cached
+		.as_ref()
+		.map(|cached| (is_marked(cached), is_marked(c)));
+	if marker == Some((false, true)) {
+		record(true, "name marker added");
+		return Action::None;
+	}
+	let marker_removed = marker == Some((true, false));
+
+	if !marker_removed
+		&& cached
+			.as_ref()
+			.is_some_and(|cached| !channel_update_requires_prune(cached, c))
+	{
+		record(true, "permission overwrites unchanged");
+		return Action::None;
+	}
+
+	if BOT.locks.take(c.id) {
+		record(true, "changed by /lock, which prunes the channel itself");
+		return Action::None;
+	}
+
+	let started_monitoring = !was_monitored && crate::monitored(guild, [c.id]).contains(&c.id);
+	record(
+		false,
+		match cached {
+			None => "not previously cached",
+			Some(_) if marker_removed => "name marker removed",
+			Some(_) if started_monitoring => "permission overwrites changed, started monitoring",
+			Some(_) => "permission overwrites changed",
+		},
+	);
+	let changes = cached
+		.as_ref()
+		.map_or_else(Vec::new, |cached| cause::changes(cached, c));
+	// every user of newly monitored channels must be checked
+	let targets = cached
+		.filter(|_| !started_monitoring)
+		.and_then(|cached| changed_overwrites(&cached, c));
+	Action::PruneChannel {
+		guild,
+		channel: c.id,
+		update: coalesce::Update { targets, changes },
+	}
+}
+
+/// Prune the former AFK channel if it changed.
+///
+/// The guild's owner is read from the cache, so the update also keeps the role hierarchy guard
+/// current.
+fn guild_update(g: &GuildUpdate) -> Action {
+	let afk = BOT
+		.cache
+		.guild(g.id)
+		.and_then(|guild| guild.afk_channel_id());
+	BOT.cache.update(g);
+
+	match afk.filter(|&channel| g.afk_channel_id != Some(channel)) {
+		Some(channel) => Action::PruneFormerAfk {
+			guild: g.id,
+			channel,
+		},
+		None => Action::None,
+	}
+}
+
+/// Prune the member, or the channels the bot started monitoring if the member is the bot.
+fn member_update(m: &MemberUpdate) -> Action {
+	if m.user.id != BOT.id {
+		let cause = BOT.cache.member(m.guild_id, m.user.id).map(|cached| {
+			let changed = |from: &[Id<RoleMarker>], to: &[Id<RoleMarker>]| {
+				from.iter()
+					.filter(|role| !to.contains(role))
+					.copied()
+					.collect()
+			};
+			Cause::Roles {
+				added: changed(&m.roles, cached.roles()),
+				removed: changed(cached.roles(), &m.roles),
+			}
+		});
+		BOT.cache.update(m);
+		return Action::PruneUser {
+			guild: m.guild_id,
+			user: m.user.id,
+			cause,
+		};
+	}
+
+	let channels = |guild| {
+		BOT.cache
+			.guild_channels(guild)
+			.map_or_else(HashSet::new, |channels| {
+				crate::monitored(guild, channels.iter().copied())
+			})
+	};
+	let before = channels(m.guild_id);
+	BOT.cache.update(m);
+	let settings = BOT.settings.get(m.guild_id);
+	let channels: Vec<_> = channels(m.guild_id)
+		.difference(&before)
+		.copied()
+		.filter(|&channel| settings.is_auto_channel(channel))
+		.collect();
+
+	if channels.is_empty() {
+		Action::None
+	} else {
+		Action::PruneChannels {
+			guild: m.guild_id,
+			channels,
+		}
+	}
+}
+
+/// Prune the role's members in voice channels, as they may have lost permissions.
+fn role_delete(r: &RoleDelete) -> Action {
+	// the cache no longer knows the role's members once updated
+	let connected: Vec<_> = BOT
+		.cache
+		.guild_voice_states(r.guild_id)
+		.map_or_else(Vec::new, |users| users.iter().copied().collect());
+	let users: HashSet<_> = connected
+		.into_iter()
+		.filter(|&user| {
+			BOT.cache
+				.member(r.guild_id, user)
+				.is_some_and(|member| member.roles().contains(&r.role_id))
+		})
+		.collect();
+	crate::cache::remove_role(&BOT.cache, r.guild_id, r.role_id);
+	BOT.cache.update(r);
+
+	if users.is_empty() {
+		Action::None
+	} else {
+		Action::PruneRoleMembers {
+			guild: r.guild_id,
+			users,
+		}
+	}
+}
+
+/// Permissions whose change may alter who is permitted to connect.
+const CONNECT_PERMISSIONS: Permissions = Permissions::ADMINISTRATOR
+	.union(Permissions::CONNECT)
+	.union(Permissions::VIEW_CHANNEL);
+
+/// Prune the guild if the role's permissions changed.
+///
+/// Roles not previously cached are conservatively pruned. Changes to a role of the bot that only
+/// remove Move Members are skipped, as they leave everyone's access unchanged and the bot could
+/// only act in channels it already monitored.
+fn role_update(r: &RoleUpdate) -> Action {
+	let old = BOT.cache.role(r.role.id).map(|cached| cached.permissions);
+	BOT.cache.update(r);
+	let record = |skipped, reason| {
+		BOT.decisions.record(
+			r.guild_id,
+			EventType::RoleUpdate,
+			Target::Role(r.role.id),
+			skipped,
+			reason,
+		);
+	};
+
+	if old == Some(r.role.permissions) {
+		record(true, "permissions unchanged");
+		Action::None
+	} else if old.is_some_and(|old| only_lost_move_members(r, old)) {
+		tracing::debug!(guild_id = %r.guild_id, role_id = %r.role.id, "bot's role lost move members");
+		record(true, "bot's role lost move members");
+		Action::None
+	} else {
+		record(
+			false,
+			match old {
+				Some(_) => "permissions changed",
+				None => "not previously cached",
+			},
+		);
+		Action::PruneGuild {
+			guild: r.guild_id,
+			cause: old.map(|old| Cause::Role {
+				role: r.role.id,
+				old,
+				new: r.role.permissions,
+			}),
+		}
+	}
+}
+
+/// Whether the role is held by the bot and its update removed Move Members without changing
+/// permissions to connect.
+fn only_lost_move_members(r: &RoleUpdate, old: Permissions) -> bool {
+	let changed = old ^ r.role.permissions;
+	changed.contains(Permissions::MOVE_MEMBERS)
+		&& !r.role.permissions.contains(Permissions::MOVE_MEMBERS)
+		&& !changed.intersects(CONNECT_PERMISSIONS)
+		&& BOT
+			.cache
+			.member(r.guild_id, BOT.id)
+			.is_some_and(|member| member.roles().contains(&r.role.id))
+}
+
+/// Prune the stage channel if the guild clears stage channels when their stage ends.
+fn stage_instance_delete(s: &StageInstanceDelete) -> Action {
+	BOT.cache.update(s);
+
+	let settings = BOT.settings.get(s.guild_id);
+	if settings.clear_stage_on_end {
+		Action::PruneStage {
+			guild: s.guild_id,
+			channel: s.channel_id,
+			audience: settings.clear_stage_audience,
+		}
+	} else {
+		Action::None
+	}
+}
+
+/// Runs the interaction's command.
+async fn interaction_create(interaction: Interaction) {
+	match interaction.kind {
+		InteractionType::ApplicationCommand | InteractionType::ApplicationCommandAutocomplete => {
+			crate::commands::interaction(interaction).await;
+		}
+		InteractionType::MessageComponent => crate::commands::component(interaction).await,
+		_ => tracing::info!(?interaction, "unhandled"),
+	}
+}
+
+/// Marks the guilds as cold and seen, and retries registering the commands if it failed.
+async fn ready(r: &Ready) {
+	tracing::debug!(guilds = %r.guilds.len(), user = %r.user.name);
+	BOT.readiness.cool(r.guilds.iter().map(|guild| guild.id));
+	BOT.onboarding.seen(r.guilds.iter().map(|guild| guild.id));
+	if !BOT.commands_registered.load(Ordering::Relaxed) {
+		crate::commands::try_register().await;
+	}
+}
+
+/// Whether the channel update may change who is permitted to connect.
+///
+/// Only permission overwrites matter, so e.g. RTC region or bitrate updates are ignored.
+fn channel_update_requires_prune(cached: &Channel, new: &Channel) -> bool {
+	cached.permission_overwrites != new.permission_overwrites
+}
+
+/// Targets of the permission overwrites that differ between the cached and new channel, or `None`
+/// if every user may be affected, i.e. the `@everyone` overwrite changed.
+fn changed_overwrites(cached: &Channel, new: &Channel) -> Option<Vec<Id<GenericMarker>>> {
+	let guild = new.guild_id?;
+	let cached = cached.permission_overwrites.as_deref().unwrap_or_default();
+	let new = new.permission_overwrites.as_deref().unwrap_or_default();
+
+	let mut targets: Vec<_> = cached
+		.iter()
+		.filter(|overwrite| !new.contains(overwrite))
+		.chain(new.iter().filter(|overwrite| !cached.contains(overwrite)))
+		.map(|overwrite| overwrite.id)
+		.collect();
+	targets.sort_unstable();
+	targets.dedup();
+
+	(!targets.contains(&guild.cast())).then_some(targets)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{fs, path::Path, time::Duration};
+
+	use serde_json::json;
+	use twilight_model::channel::ChannelType;
+
+	use super::*;
+	use crate::test::{channel_payload, member_overwrite, role_overwrite, Guild};
+
+	/// Name of the action's variant.
+	fn name(action: &Action) -> &'static str {
+		match action {
+			Action::None => "None",
+			Action::PruneChannel { .. } => "PruneChannel",
+			Action::PruneFormerAfk { .. } => "PruneFormerAfk",
+			Action::PruneChannels { .. } => "PruneChannels",
+			Action::PruneGuild { .. } => "PruneGuild",
+			Action::PruneRoleMembers { .. } => "PruneRoleMembers",
+			Action::PruneStage { .. } => "PruneStage",
+			Action::PruneUser { .. } => "PruneUser",
+		}
+	}
+
+	/// Replays the recorded gateway payloads of the fixture through [`decide`], asserting the
+	/// actions decided for each and the users they removed.
+	///
+	/// Fixtures are JSON files in `fixtures/replay` listing the `events`, the names of their
+	/// expected `actions` and the IDs of the `removed` users.
+	async fn replay(fixture: &str) {
+		crate::test::init();
+		let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+			.join("fixtures/replay")
+			.join(fixture)
+			.with_extension("json");
+		let contents = fs::read_to_string(path).expect("fixture exists");
+		let fixture: serde_json::Value = serde_json::from_str(&contents).expect("valid JSON");
+
+		let strings = |key: &str| -> Vec<String> {
+			fixture[key]
+				.as_array()
+				.expect("array")
+				.iter()
+				.map(|value| value.as_str().expect("string").to_owned())
+				.collect()
+		};
+
+		let mut guild = None;
+		let mut actions = Vec::new();
+		for payload in fixture["events"].as_array().expect("events array") {
+			let event = crate::test::parse(&payload.to_string());
+			guild = guild.or(event.guild_id());
+			let Some(action) = decide(&event) else {
+				actions.push("Disallowed".to_owned());
+				continue;
+			};
+			actions.push(name(&action).to_owned());
+			action.execute().await;
+		}
+		assert_eq!(actions, strings("actions"));
+
+		let removed: Vec<_> = crate::test::removed(guild.expect("guild event"))
+			.into_iter()
+			.map(|(_, user, _)| user.to_string())
+			.collect();
+		assert_eq!(removed, strings("removed"));
+	}
+
+	/// Voice channel of a guild with the overwrites.
+	fn channel(overwrites: &[serde_json::Value]) -> Channel {
+		serde_json::from_value(crate::test::channel_payload(
+			3230000,
+			3230010,
+			ChannelType::GuildVoice,
+			None,
+			overwrites,
+		))
+		.expect("valid channel")
+	}
+
+	#[test]
+	fn unchanged_overwrite_does_not_require_prune() {
+		let overwrites = [crate::test::role_overwrite(
+			3230002,
+			Permissions::empty(),
+			Permissions::CONNECT,
+		)];
+		let cached = channel(&overwrites);
+		let mut new = channel(&overwrites);
+		new.bitrate = Some(96000);
+		new.rtc_region = Some("rotterdam".to_owned());
+
+		assert!(!channel_update_requires_prune(&cached, &new));
+	}
+
+	#[test]
+	fn changed_member_overwrite_requires_prune() {
+		let cached = channel(&[crate::test::member_overwrite(
+			3230100,
+			Permissions::CONNECT,
+			Permissions::empty(),
+		)]);
+		let new = channel(&[crate::test::member_overwrite(
+			3230100,
+			Permissions::empty(),
+			Permissions::CONNECT,
+		)]);
+
+		assert!(channel_update_requires_prune(&cached, &new));
+		assert_eq!(
+			changed_overwrites(&cached, &new),
+			Some(vec![Id::new(3230100)])
+		);
+	}
+
+	#[test]
+	fn changed_everyone_overwrite_requires_prune() {
+		let cached = channel(&[]);
+		let new = channel(&[crate::test::role_overwrite(
+			3230000,
+			Permissions::empty(),
+			Permissions::CONNECT,
+		)]);
+
+		assert!(channel_update_requires_prune(&cached, &new));
+		// every user may be affected
+		assert_eq!(changed_overwrites(&cached, &new), None);
+	}
+
+	#[tokio::test]
+	async fn role_loses_connect() {
+		replay("role_loses_connect").await;
+	}
+
+	#[tokio::test]
+	async fn channel_overwrite_added() {
+		replay("channel_overwrite_added").await;
+	}
+
+	#[tokio::test]
+	async fn member_role_removed() {
+		replay("member_role_removed").await;
+	}
+
+	#[tokio::test]
+	async fn bitrate_changed() {
+		replay("bitrate_changed").await;
+	}
+
+	#[tokio::test]
+	async fn lock_change_skips_auto_prune() {
+		const GUILD: u64 = 3_673_000;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL | Permissions::CONNECT)
+			.voice(GUILD + 10, &[])
+			.member(GUILD + 100, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.create();
+		let update = |deny| {
+			let channel = channel_payload(
+				GUILD,
+				GUILD + 10,
+				ChannelType::GuildVoice,
+				None,
+				&[role_overwrite(GUILD, Permissions::empty(), deny)],
+			);
+			crate::test::parse(
+				&json!({"op": 0, "s": 1, "t": "CHANNEL_UPDATE", "d": channel}).to_string(),
+			)
+		};
+
+		BOT.locks.mark(Id::new(GUILD + 10));
+		let action = decide(&update(Permissions::CONNECT)).unwrap();
+		assert_eq!(name(&action), "None");
+		assert_eq!(
+			BOT.decisions.guild(guild)[0].reason,
+			"changed by /lock, which prunes the channel itself"
+		);
+
+		// only the marked change is skipped
+		let action = decide(&update(Permissions::CONNECT | Permissions::SPEAK)).unwrap();
+		assert_eq!(name(&action), "PruneChannel");
+	}
+
+	/// Update of the voice channel `guild + 10` cycling through its overwrites: `@everyone` denied
+	/// connecting, unchanged, none, and `guild + 100` allowed connecting.
+	fn cycled(guild: Id<GuildMarker>, step: usize) -> Event {
+		let id = guild.get();
+		let overwrites = match step % 4 {
+			0 | 1 => vec![role_overwrite(
+				id,
+				Permissions::empty(),
+				Permissions::CONNECT,
+			)],
+			2 => vec![],
+			_ => vec![member_overwrite(
+				id + 100,
+				Permissions::CONNECT,
+				Permissions::empty(),
+			)],
+		};
+		let channel = channel_payload(id, id + 10, ChannelType::GuildVoice, None, &overwrites);
+		crate::test::parse(
+			&json!({"op": 0, "s": 1, "t": "CHANNEL_UPDATE", "d": channel}).to_string(),
+		)
+	}
+
+	/// Waits for the handling of the guilds' events to finish.
+	async fn idle(guilds: &[Id<GuildMarker>]) {
+		for _ in 0..5000 {
+			if guilds.iter().all(|&guild| BOT.serial.is_idle(guild)) {
+				return;
+			}
+			tokio::time::sleep(Duration::from_millis(1)).await;
+		}
+		panic!("events still being handled");
+	}
+
+	#[tokio::test]
+	async fn interleaved_events_match_serial_handling() {
+		// within the throttle's limit of auto prunes
+		const STEPS: usize = 6;
+		let [a, b, serial] = [3760000, 3761000, 3762000].map(|id| {
+			Guild::new(id)
+				.voice(id + 10, &[])
+				.member(id + 100, &[])
+				.connect(id + 100, id + 10)
+				.create()
+		});
+
+		// as delivered by the gateway
+		for step in 0..STEPS {
+			handle(cycled(a, step));
+			handle(cycled(b, step));
+		}
+		for step in 0..STEPS {
+			handle(cycled(serial, step));
+			idle(&[serial]).await;
+		}
+		idle(&[a, b]).await;
+
+		let decisions = |guild| -> Vec<_> {
+			BOT.decisions
+				.guild(guild)
+				.iter()
+				.map(|decision| (decision.event, decision.skipped, decision.reason))
+				.collect()
+		};
+		assert_eq!(decisions(serial).len(), STEPS);
+		assert_eq!(decisions(a), decisions(serial));
+		assert_eq!(decisions(b), decisions(serial));
+		// denied at the first and fifth step
+		let removed = |guild: Id<GuildMarker>| -> Vec<_> {
+			crate::test::removed(guild)
+				.into_iter()
+				.map(|(_, user, target)| (user.get() - guild.get(), target))
+				.collect()
+		};
+		assert_eq!(removed(serial), [(100, None), (100, None)]);
+		assert_eq!(removed(a), removed(serial));
+		assert_eq!(removed(b), removed(serial));
+	}
+
+	#[tokio::test]
+	async fn replays_deferred_prune_once_warm() {
+		const GUILD: u64 = 3_281_000;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL | Permissions::CONNECT)
+			.voice(
+				GUILD + 10,
+				&[role_overwrite(
+					GUILD,
+					Permissions::empty(),
+					Permissions::CONNECT,
+				)],
+			)
+			.member(GUILD + 100, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.create();
+		BOT.readiness.cool([guild]);
+
+		Action::PruneGuild { guild, cause: None }.execute().await;
+		assert!(
+			crate::test::removed(guild).is_empty(),
+			"deferred while cold"
+		);
+
+		warm(guild).await;
+		let removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user)
+			.collect();
+		assert_eq!(removed, [Id::new(GUILD + 100)]);
+	}
+
+	#[test]
+	fn changed_role_overwrite_requires_prune() {
+		let cached = channel(&[crate::test::role_overwrite(
+			3230001,
+			Permissions::CONNECT,
+			Permissions::empty(),
+		)]);
+		let new = channel(&[]);
+
+		assert!(channel_update_requires_prune(&cached, &new));
+		assert_eq!(
+			changed_overwrites(&cached, &new),
+			Some(vec![Id::new(3230001)])
+		);
+	}
+
+	/// Removes the users connected to the voice channel `guild + 10` after changing its overwrites
+	/// from `cached` to `new`.
+	///
+	/// Everyone but role `guild + 2` and member `guild + 100` is denied connecting. Member `guild +
+	/// 100` and `guild + 101`, who has the role, are allowed, while `guild + 102` is connected
+	/// regardless, so is only removed if every connected user is checked.
+	async fn prune_overwrites(
+		guild: u64,
+		cached: &[serde_json::Value],
+		new: &[serde_json::Value],
+	) -> Vec<u64> {
+		let id = Guild::new(guild)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.role(guild + 2, Permissions::empty(), 1)
+			.voice(guild + 10, cached)
+			.member(guild + 100, &[])
+			.member(guild + 101, &[guild + 2])
+			.member(guild + 102, &[])
+			.connect(guild + 100, guild + 10)
+			.connect(guild + 101, guild + 10)
+			.connect(guild + 102, guild + 10)
+			.create();
+		let channel = channel_payload(guild, guild + 10, ChannelType::GuildVoice, None, new);
+		let event = crate::test::parse(
+			&json!({"op": 0, "s": 1, "t": "CHANNEL_UPDATE", "d": channel}).to_string(),
+		);
+
+		decide(&event).unwrap().execute().await;
+
+		let mut removed: Vec<_> = crate::test::removed(id)
+			.into_iter()
+			.map(|(_, user, _)| user.get())
+			.collect();
+		removed.sort_unstable();
+		removed
+	}
+
+	/// Overwrites of [`prune_overwrites`]'s channel, allowing the role and member to connect.
+	fn allowed(guild: u64, role: bool, member: bool) -> Vec<serde_json::Value> {
+		let mut overwrites = vec![role_overwrite(
+			guild,
+			Permissions::empty(),
+			Permissions::CONNECT,
+		)];
+		if role {
+			overwrites.push(role_overwrite(
+				guild + 2,
+				Permissions::CONNECT,
+				Permissions::empty(),
+			));
+		}
+		if member {
+			overwrites.push(member_overwrite(
+				guild + 100,
+				Permissions::CONNECT,
+				Permissions::empty(),
+			));
+		}
+		overwrites
+	}
+
+	#[tokio::test]
+	async fn member_overwrite_prunes_only_member() {
+		const GUILD: u64 = 3_370_000;
+		let removed = prune_overwrites(
+			GUILD,
+			&allowed(GUILD, true, true),
+			&allowed(GUILD, true, false),
+		)
+		.await;
+
+		assert_eq!(removed, [GUILD + 100]);
+	}
+
+	#[tokio::test]
+	async fn role_overwrite_prunes_only_role_members() {
+		const GUILD: u64 = 3_371_000;
+		let removed = prune_overwrites(
+			GUILD,
+			&allowed(GUILD, true, true),
+			&allowed(GUILD, false, true),
+		)
+		.await;
+
+		assert_eq!(removed, [GUILD + 101]);
+	}
+
+	#[tokio::test]
+	async fn everyone_overwrite_prunes_every_user() {
+		const GUILD: u64 = 3_372_000;
+		let mut new = allowed(GUILD, false, false);
+		new[0] = role_overwrite(
+			GUILD,
+			Permissions::empty(),
+			Permissions::CONNECT | Permissions::SPEAK,
+		);
+		let removed = prune_overwrites(GUILD, &allowed(GUILD, true, true), &new).await;
+
+		assert_eq!(removed, [GUILD + 100, GUILD + 101, GUILD + 102]);
+	}
+
+	/// Guild whose voice channel `guild + 10` is only monitored if the bot has role `guild + 2`, to
+	/// which the connected member `guild + 100` is denied connecting.
+	fn monitoring(guild: u64, role: bool) -> Id<GuildMarker> {
+		let id = Guild::new(guild)
+			.role(guild + 2, Permissions::empty(), 5)
+			.voice(guild + 10, &monitoring_overwrites(guild, &[]))
+			.member(guild + 100, &[])
+			.connect(guild + 100, guild + 10)
+			.create();
+		if role {
+			BOT.cache.update(&bot_roles(guild, role));
+		}
+		id
+	}
+
+	/// Overwrites of [`monitoring`]'s channel, followed by the extra ones.
+	fn monitoring_overwrites(guild: u64, extra: &[serde_json::Value]) -> Vec<serde_json::Value> {
+		let mut overwrites = vec![
+			role_overwrite(guild + 1, Permissions::empty(), Permissions::MOVE_MEMBERS),
+			role_overwrite(guild + 2, Permissions::MOVE_MEMBERS, Permissions::empty()),
+			member_overwrite(guild + 100, Permissions::empty(), Permissions::CONNECT),
+		];
+		overwrites.extend_from_slice(extra);
+		overwrites
+	}
+
+	/// Member update of the bot, with role `guild + 2` if `role`.
+	fn bot_roles(guild: u64, role: bool) -> Event {
+		let roles: &[u64] = if role {
+			&[guild + 1, guild + 2]
+		} else {
+			&[guild + 1]
+		};
+		let member = crate::test::member_payload(guild, crate::test::BOT_ID, roles, true);
+		crate::test::parse(
+			&json!({"op": 0, "s": 1, "t": "GUILD_MEMBER_UPDATE", "d": member}).to_string(),
+		)
+	}
+
+	/// Channel update of [`monitoring`]'s channel with the extra overwrites.
+	fn monitoring_update(guild: u64, extra: &[serde_json::Value]) -> Event {
+		let channel = channel_payload(
+			guild,
+			guild + 10,
+			ChannelType::GuildVoice,
+			None,
+			&monitoring_overwrites(guild, extra),
+		);
+		crate::test::parse(
+			&json!({"op": 0, "s": 1, "t": "CHANNEL_UPDATE", "d": channel}).to_string(),
+		)
+	}
+
+	#[tokio::test]
+	async fn bot_gaining_role_prunes_newly_monitored_channel() {
+		const GUILD: u64 = 3_400_000;
+		let guild = monitoring(GUILD, false);
+
+		let action = decide(&bot_roles(GUILD, true)).unwrap();
+		assert!(
+			matches!(&action, Action::PruneChannels { channels, .. } if channels == &[Id::new(GUILD + 10)])
+		);
+		action.execute().await;
+
+		let removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user)
+			.collect();
+		assert_eq!(removed, [Id::new(GUILD + 100)]);
+	}
+
+	#[tokio::test]
+	async fn bot_losing_role_prunes_nothing() {
+		const GUILD: u64 = 3_401_000;
+		let guild = monitoring(GUILD, true);
+
+		let action = decide(&bot_roles(GUILD, false)).unwrap();
+		assert_eq!(name(&action), "None");
+		action.execute().await;
+
+		assert!(crate::test::removed(guild).is_empty());
+	}
+
+	#[tokio::test]
+	async fn bot_gaining_overwrite_prunes_every_user() {
+		const GUILD: u64 = 3_402_000;
+		let guild = monitoring(GUILD, false);
+		let allow = member_overwrite(
+			crate::test::BOT_ID,
+			Permissions::MOVE_MEMBERS,
+			Permissions::empty(),
+		);
+
+		let action = decide(&monitoring_update(GUILD, &[allow])).unwrap();
+		assert_eq!(
+			BOT.decisions.guild(guild)[0].reason,
+			"permission overwrites changed, started monitoring"
+		);
+		assert!(matches!(
+			&action,
+			Action::PruneChannel { update, .. } if update.targets.is_none()
+		));
+		action.execute().await;
+
+		let removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user)
+			.collect();
+		assert_eq!(removed, [Id::new(GUILD + 100)]);
+	}
+
+	#[tokio::test]
+	async fn bot_losing_overwrite_prunes_nothing() {
+		const GUILD: u64 = 3_403_000;
+		let guild = monitoring(GUILD, true);
+		let deny = member_overwrite(
+			crate::test::BOT_ID,
+			Permissions::empty(),
+			Permissions::MOVE_MEMBERS,
+		);
+
+		let action = decide(&monitoring_update(GUILD, &[deny])).unwrap();
+		assert_eq!(
+			BOT.decisions.guild(guild)[0].reason,
+			"permission overwrites changed"
+		);
+		action.execute().await;
+
+		assert!(crate::test::removed(guild).is_empty());
+	}
+
+	#[tokio::test]
+	async fn afk_switch_prunes_former_afk_channel() {
+		const GUILD: u64 = 3_500_000;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(GUILD + 10, &[])
+			.voice(GUILD + 11, &[])
+			.afk(GUILD + 10)
+			.member(GUILD + 100, &[])
+			.member(GUILD + 101, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.connect(GUILD + 101, GUILD + 10)
+			.create();
+		// the update also transfers ownership to `GUILD + 101`
+		let mut update = crate::test::guild_payload(GUILD, GUILD + 101);
+		update["afk_channel_id"] = json!((GUILD + 11).to_string());
+		update["premium_tier"] = json!(0);
+		let event = crate::test::parse(
+			&json!({"op": 0, "s": 1, "t": "GUILD_UPDATE", "d": update}).to_string(),
+		);
+
+		let action = decide(&event).unwrap();
+		assert!(matches!(
+			action,
+			Action::PruneFormerAfk { channel, .. } if channel == Id::new(GUILD + 10)
+		));
+		action.execute().await;
+
+		let removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user)
+			.collect();
+		assert_eq!(removed, [Id::new(GUILD + 100)], "owners are never pruned");
+
+		// unchanged AFK channel
+		let action = decide(&event).unwrap();
+		assert_eq!(name(&action), "None");
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn coalesces_category_updates() {
+		const GUILD: u64 = 3_520_000;
+		let mut guild = Guild::new(GUILD).category(GUILD + 5);
+		for i in 0..3 {
+			guild = guild
+				.channel(
+					GUILD + 10 + i,
+					ChannelType::GuildVoice,
+					Some(GUILD + 5),
+					&[],
+				)
+				.member(GUILD + 100 + i, &[])
+				.connect(GUILD + 100 + i, GUILD + 10 + i);
+		}
+		let guild = guild.create();
+
+		// the category's edit denies `@everyone` to connect to its synced channels
+		let denied = [role_overwrite(
+			GUILD,
+			Permissions::empty(),
+			Permissions::CONNECT,
+		)];
+		for i in 0..3 {
+			let channel = channel_payload(
+				GUILD,
+				GUILD + 10 + i,
+				ChannelType::GuildVoice,
+				Some(GUILD + 5),
+				&denied,
+			);
+			let event = crate::test::parse(
+				&json!({"op": 0, "s": 1, "t": "CHANNEL_UPDATE", "d": channel}).to_string(),
+			);
+			decide(&event).unwrap().execute().await;
+		}
+		assert!(crate::test::removed(guild).is_empty(), "buffered");
+		// handling of a following event still running once the window closes
+		BOT.serial
+			.run(guild, tokio::time::sleep(coalesce::WINDOW * 2));
+
+		tokio::time::sleep(coalesce::WINDOW + Duration::from_millis(100)).await;
+		assert!(
+			crate::test::removed(guild).is_empty(),
+			"queued behind the guild's earlier events"
+		);
+		tokio::time::sleep(coalesce::WINDOW).await;
+		while BOT.history.guild(guild).is_none() {
+			tokio::time::sleep(Duration::from_millis(10)).await;
+		}
+
+		let mut removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user.get())
+			.collect();
+		removed.sort_unstable();
+		assert_eq!(removed, [GUILD + 100, GUILD + 101, GUILD + 102]);
+		let last = BOT.history.guild(guild).expect("pruned");
+		assert_eq!(last.users, 3, "pruned in one pass");
+	}
+
+	/// Dispatch payload of the event, parsed.
+	fn dispatch(kind: &str, data: serde_json::Value) -> Event {
+		crate::test::parse(&json!({"op": 0, "s": 1, "t": kind, "d": data}).to_string())
+	}
+
+	/// Decides and processes the event, like [`handle`] without the serialization.
+	async fn handled(event: Event) {
+		let action = decide(&event);
+		process(event, action).await;
+	}
+
+	#[tokio::test]
+	async fn member_remove_drops_member_state() {
+		const GUILD: u64 = 3_680_000;
+		let guild = Guild::new(GUILD)
+			.voice(GUILD + 10, &[])
+			.member(GUILD + 100, &[])
+			.member(GUILD + 101, &[])
+			.create();
+		for user in [GUILD + 100, GUILD + 101] {
+			let state = crate::test::voice_state_payload(GUILD, user, GUILD + 10, true);
+			let event = dispatch("VOICE_STATE_UPDATE", state);
+			handled(event).await;
+			BOT.cooldowns
+				.acquire(guild, Id::new(user), "prune", Duration::from_secs(60))
+				.unwrap();
+		}
+
+		let member = crate::test::member_payload(GUILD, GUILD + 100, &[], false);
+		let event = dispatch(
+			"GUILD_MEMBER_REMOVE",
+			json!({"guild_id": GUILD.to_string(), "user": member["user"]}),
+		);
+		handled(event).await;
+
+		let cooldown = |user| {
+			BOT.cooldowns
+				.acquire(guild, Id::new(user), "prune", Duration::from_secs(60))
+				.is_err()
+		};
+		assert_eq!(BOT.deafened.duration(guild, Id::new(GUILD + 100)), None);
+		assert!(!cooldown(GUILD + 100));
+		// other members are kept
+		assert!(BOT.deafened.duration(guild, Id::new(GUILD + 101)).is_some());
+		assert!(cooldown(GUILD + 101));
+	}
+
+	#[tokio::test]
+	async fn leaving_voice_drops_deafened_state() {
+		const GUILD: u64 = 3_681_000;
+		let guild = Guild::new(GUILD)
+			.voice(GUILD + 10, &[])
+			.member(GUILD + 100, &[])
+			.create();
+		let mut state = crate::test::voice_state_payload(GUILD, GUILD + 100, GUILD + 10, true);
+		let event = dispatch("VOICE_STATE_UPDATE", state.clone());
+		handled(event).await;
+		assert!(BOT.deafened.duration(guild, Id::new(GUILD + 100)).is_some());
+
+		state["channel_id"] = serde_json::Value::Null;
+		let event = dispatch("VOICE_STATE_UPDATE", state);
+		handled(event).await;
+
+		assert_eq!(BOT.deafened.duration(guild, Id::new(GUILD + 100)), None);
+	}
+
+	#[tokio::test]
+	async fn selected_scope_prunes_only_selected_channels() {
+		const GUILD: u64 = 3_740_000;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.role(GUILD + 2, Permissions::CONNECT, 1)
+			.voice(GUILD + 10, &[])
+			.voice(GUILD + 11, &[])
+			.member(GUILD + 100, &[GUILD + 2])
+			.member(GUILD + 101, &[GUILD + 2])
+			.connect(GUILD + 100, GUILD + 10)
+			.connect(GUILD + 101, GUILD + 11)
+			.create();
+		BOT.settings.update(guild, |settings| {
+			settings.auto_prune_scope = crate::settings::AutoPruneScope::Selected;
+			settings.auto_channels = vec![Id::new(GUILD + 10)];
+		});
+
+		let role = crate::test::role_payload(GUILD + 2, "Speaker", Permissions::empty(), 1);
+		let event = dispatch(
+			"GUILD_ROLE_UPDATE",
+			json!({"guild_id": GUILD.to_string(), "role": role}),
+		);
+		decide(&event).unwrap().execute().await;
+
+		let removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user)
+			.collect();
+		// `GUILD + 101`, connected to the other channel, is kept
+		assert_eq!(removed, [Id::new(GUILD + 100)]);
+
+		// updates of other channels are ignored
+		let channel = channel_payload(
+			GUILD,
+			GUILD + 11,
+			ChannelType::GuildVoice,
+			None,
+			&[role_overwrite(
+				GUILD,
+				Permissions::empty(),
+				Permissions::SPEAK,
+			)],
+		);
+		let action = decide(&dispatch("CHANNEL_UPDATE", channel)).unwrap();
+		assert_eq!(name(&action), "None");
+	}
+
+	#[tokio::test]
+	async fn role_delete_prunes_only_role_members() {
+		const GUILD: u64 = 3_800_000;
+		// `GUILD + 102` may not connect either, but didn't have the deleted role
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.role(GUILD + 2, Permissions::CONNECT, 1)
+			.role(GUILD + 3, Permissions::CONNECT, 2)
+			.voice(GUILD + 10, &[])
+			.member(GUILD + 100, &[GUILD + 2])
+			.member(GUILD + 101, &[GUILD + 2, GUILD + 3])
+			.member(GUILD + 102, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.connect(GUILD + 101, GUILD + 10)
+			.connect(GUILD + 102, GUILD + 10)
+			.create();
+		let event = dispatch(
+			"GUILD_ROLE_DELETE",
+			json!({"guild_id": GUILD.to_string(), "role_id": (GUILD + 2).to_string()}),
+		);
+
+		let action = decide(&event).unwrap();
+		assert!(matches!(
+			&action,
+			Action::PruneRoleMembers { users, .. }
+				if users == &HashSet::from([Id::new(GUILD + 100), Id::new(GUILD + 101)])
+		));
+		action.execute().await;
+
+		let removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user)
+			.collect();
+		assert_eq!(removed, [Id::new(GUILD + 100)]);
+	}
+
+	#[test]
+	fn records_channel_update_decisions() {
+		const GUILD: u64 = 3_900_000;
+		let guild = Guild::new(GUILD).voice(GUILD + 10, &[]).create();
+		let update = |channel, overwrites: &[serde_json::Value]| {
+			let mut channel =
+				channel_payload(GUILD, channel, ChannelType::GuildVoice, None, overwrites);
+			channel["bitrate"] = json!(96000);
+			dispatch("CHANNEL_UPDATE", channel)
+		};
+		let denied = [role_overwrite(
+			GUILD,
+			Permissions::empty(),
+			Permissions::CONNECT,
+		)];
+
+		decide(&update(GUILD + 10, &[]));
+		decide(&update(GUILD + 10, &denied));
+		decide(&update(GUILD + 11, &denied));
+
+		let decisions: Vec<_> = BOT
+			.decisions
+			.guild(guild)
+			.into_iter()
+			.map(|decision| {
+				assert_eq!(decision.event, EventType::ChannelUpdate);
+				(
+					decision.target.to_string(),
+					decision.skipped,
+					decision.reason,
+				)
+			})
+			.collect();
+		assert_eq!(
+			decisions,
+			[
+				(format!("<#{}>", GUILD + 11), false, "not previously cached"),
+				(
+					format!("<#{}>", GUILD + 10),
+					false,
+					"permission overwrites changed"
+				),
+				(
+					format!("<#{}>", GUILD + 10),
+					true,
+					"permission overwrites unchanged"
+				),
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn name_marker_transitions() {
+		const GUILD: u64 = 3_940_000;
+		let denied = [role_overwrite(
+			GUILD,
+			Permissions::empty(),
+			Permissions::CONNECT,
+		)];
+		let guild = Guild::new(GUILD)
+			.voice(GUILD + 10, &denied)
+			.member(GUILD + 100, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.create();
+		BOT.settings.update(guild, |settings| {
+			settings.name_marker = Some("🔓".to_owned());
+		});
+		let renamed = |name: &str| {
+			let mut channel =
+				channel_payload(GUILD, GUILD + 10, ChannelType::GuildVoice, None, &denied);
+			channel["name"] = json!(name);
+			dispatch("CHANNEL_UPDATE", channel)
+		};
+		let monitored =
+			|| crate::monitored(guild, [Id::new(GUILD + 10)]).contains(&Id::new(GUILD + 10));
+		assert!(monitored());
+
+		let action = decide(&renamed("staff 🔓")).unwrap();
+		assert_eq!(name(&action), "None");
+		assert_eq!(BOT.decisions.guild(guild)[0].reason, "name marker added");
+		assert!(!monitored());
+
+		let action = decide(&renamed("staff")).unwrap();
+		assert_eq!(BOT.decisions.guild(guild)[0].reason, "name marker removed");
+		assert!(monitored());
+		action.execute().await;
+
+		let removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user)
+			.collect();
+		assert_eq!(removed, [Id::new(GUILD + 100)]);
+	}
+
+	#[test]
+	fn bot_role_losing_move_members_skips_prune() {
+		const GUILD: u64 = 4_010_000;
+		let guild = Guild::new(GUILD)
+			.role(
+				GUILD + 2,
+				Permissions::CONNECT | Permissions::MOVE_MEMBERS,
+				1,
+			)
+			.create();
+		let update = |role, name, permissions| {
+			let role = crate::test::role_payload(role, name, permissions, 1);
+			decide(&dispatch(
+				"GUILD_ROLE_UPDATE",
+				json!({"guild_id": GUILD.to_string(), "role": role}),
+			))
+			.unwrap()
+		};
+		let reason = || BOT.decisions.guild(guild)[0].reason;
+
+		// the bot's role
+		let action = update(
+			GUILD + 1,
+			"bot",
+			Permissions::VIEW_CHANNEL | Permissions::CONNECT,
+		);
+		assert_eq!(name(&action), "None");
+		assert_eq!(reason(), "bot's role lost move members");
+		// also losing permissions to connect
+		let action = update(GUILD + 1, "bot", Permissions::VIEW_CHANNEL);
+		assert_eq!(name(&action), "PruneGuild");
+		assert_eq!(reason(), "permissions changed");
+
+		// a role the bot doesn't have
+		let action = update(GUILD + 2, "role 2", Permissions::CONNECT);
+		assert_eq!(name(&action), "PruneGuild");
+		assert_eq!(reason(), "permissions changed");
+	}
+}
@@ -0,0 +1,70 @@
+//! Emergency stops of a guild's prunes through `/settings panic`.
+//!
+//! Prunes take a [`Token`] of their guild when they start, passed on to their queued removals, and
+//! stop once it's cancelled by halting the guild, which cancels every token taken before.
+
+use std::{
+	collections::HashMap,
+	sync::{Mutex, PoisonError},
+};
+
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::{cleanup::Cleanup, BOT};
+
+/// Number of times each guild was halted.
+#[derive(Debug, Default)]
+pub struct Halts(Mutex<HashMap<Id<GuildMarker>, u64>>);
+
+impl Halts {
+	/// Token of the guild's work starting now.
+	pub fn token(&self, guild: Id<GuildMarker>) -> Token {
+		Token {
+			guild,
+			generation: self.generation(guild),
+		}
+	}
+
+	/// Cancels every token of the guild taken before.
+	pub fn halt(&self, guild: Id<GuildMarker>) {
+		tracing::info!(guild_id = %guild, "halting prunes");
+		*self
+			.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.entry(guild)
+			.or_default() += 1;
+	}
+
+	fn generation(&self, guild: Id<GuildMarker>) -> u64 {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&guild)
+			.copied()
+			.unwrap_or_default()
+	}
+}
+
+impl Cleanup for Halts {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&guild);
+	}
+}
+
+/// Cancellation token of a guild's work.
+#[derive(Clone, Copy, Debug)]
+pub struct Token {
+	guild: Id<GuildMarker>,
+	generation: u64,
+}
+
+impl Token {
+	/// Whether the guild was halted since the token was taken.
+	pub fn is_cancelled(self) -> bool {
+		BOT.halts.generation(self.guild) != self.generation
+	}
+}
@@ -0,0 +1,112 @@
+//! Record of the most recent prunes, lost on restart.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	sync::{Mutex, PoisonError},
+	time::Instant,
+};
+
+use twilight_model::id::{
+	marker::{ChannelMarker, GuildMarker},
+	Id,
+};
+
+use crate::{
+	cleanup::Cleanup,
+	prune::{Summary, Trigger},
+};
+
+/// Number of channels whose last prune is recorded per guild.
+const CHANNELS: usize = 16;
+
+/// Record of a prune.
+#[derive(Clone, Copy, Debug)]
+pub struct Prune {
+	/// When the prune finished.
+	pub at: Instant,
+	pub trigger: Trigger,
+	/// Number of users pruned.
+	pub users: u16,
+}
+
+/// Last prunes of a guild.
+#[derive(Debug)]
+struct Guild {
+	last: Prune,
+	/// Last prunes of the most recently pruned channels, most recent first.
+	channels: VecDeque<(Id<ChannelMarker>, Prune)>,
+}
+
+/// Last prunes of every guild.
+#[derive(Debug, Default)]
+pub struct History(Mutex<HashMap<Id<GuildMarker>, Guild>>);
+
+impl History {
+	/// Records the prune if it pruned any users.
+	pub fn record(&self, guild: Id<GuildMarker>, trigger: Trigger, summary: &Summary) {
+		if summary.channels.is_empty() {
+			return;
+		}
+
+		let at = Instant::now();
+		let last = Prune {
+			at,
+			trigger,
+			users: summary.pruned(),
+		};
+		let mut guilds = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		let record = guilds.entry(guild).or_insert_with(|| Guild {
+			last,
+			channels: VecDeque::new(),
+		});
+		record.last = last;
+		for prune in &summary.channels {
+			record.channels.retain(|&(id, _)| id != prune.channel);
+			let users = prune.users.len() as u16;
+			record
+				.channels
+				.push_front((prune.channel, Prune { at, trigger, users }));
+		}
+		record.channels.truncate(CHANNELS);
+	}
+
+	/// Last prune of the guild.
+	pub fn guild(&self, guild: Id<GuildMarker>) -> Option<Prune> {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&guild)
+			.map(|record| record.last)
+	}
+
+	/// Last prune of the channel, if among the guild's most recently pruned channels.
+	pub fn channel(&self, guild: Id<GuildMarker>, channel: Id<ChannelMarker>) -> Option<Prune> {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&guild)?
+			.channels
+			.iter()
+			.find_map(|&(id, prune)| (id == channel).then_some(prune))
+	}
+}
+
+impl Cleanup for History {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&guild);
+	}
+
+	fn channel(&self, guild: Id<GuildMarker>, channel: Id<ChannelMarker>) {
+		if let Some(record) = self
+			.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get_mut(&guild)
+		{
+			record.channels.retain(|&(id, _)| id != channel);
+		}
+	}
+}
@@ -0,0 +1,86 @@
+//! Tracking of how long users have been deafened, and periodic pruning of idle users.
+
+use std::{
+	collections::HashMap,
+	sync::{Mutex, PoisonError},
+	time::{Duration, Instant},
+};
+
+use twilight_model::{
+	id::{
+		marker::{GuildMarker, UserMarker},
+		Id,
+	},
+	voice::VoiceState,
+};
+
+use crate::{cleanup::Cleanup, prune::Trigger, BOT};
+
+/// Interval between checks for idle users.
+const INTERVAL: Duration = Duration::from_secs(60);
+
+/// Guild and user of a voice state.
+type Key = (Id<GuildMarker>, Id<UserMarker>);
+
+/// When connected users became deafened, by the server or themselves.
+#[derive(Debug, Default)]
+pub struct Deafened(Mutex<HashMap<Key, Instant>>);
+
+impl Deafened {
+	/// Updates the user's deafened state.
+	pub fn update(&self, guild: Id<GuildMarker>, state: &VoiceState) {
+		let mut users = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		let key = (guild, state.user_id);
+		if state.channel_id.is_some() && (state.deaf || state.self_deaf) {
+			users.entry(key).or_insert_with(Instant::now);
+		} else {
+			users.remove(&key);
+		}
+	}
+
+	/// How long the user has continuously been deafened.
+	pub fn duration(&self, guild: Id<GuildMarker>, user: Id<UserMarker>) -> Option<Duration> {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&(guild, user))
+			.map(Instant::elapsed)
+	}
+}
+
+impl Cleanup for Deafened {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.retain(|&(id, _), _| id != guild);
+	}
+
+	fn member(&self, guild: Id<GuildMarker>, user: Id<UserMarker>) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&(guild, user));
+	}
+}
+
+/// Periodically prunes users deafened for longer than their guild's idle timeout.
+pub async fn run() {
+	let mut interval = tokio::time::interval(INTERVAL);
+	loop {
+		interval.tick().await;
+		for (guild, settings) in BOT.settings.guilds() {
+			let Some(minutes) = settings.idle_timeout else {
+				continue;
+			};
+			if BOT.auto_prune(guild) {
+				let threshold = Duration::from_secs(u64::from(minutes) * 60);
+				if let Err(e) =
+					crate::prune::idle(guild, threshold, Trigger::Idle, |_| true, None).await
+				{
+					tracing::warn!(guild_id = %guild, error = &e as &dyn std::error::Error);
+				}
+			}
+		}
+	}
+}
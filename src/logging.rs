@@ -0,0 +1,225 @@
+//! Tracing subscriber configured through the environment.
+//!
+//! - `RUST_LOG`: [`Targets`] filter, defaulting to `info`.
+//! - `LOG_FORMAT`: `text` (default) or `json`, one object per line.
+//! - `LOG_GUILD`: guild ID whose events are additionally emitted at the `debug` level.
+
+use std::{env, fmt, str::FromStr};
+
+use anyhow::Context as _;
+use serde_json::{Map, Value};
+use tracing::{
+	field::{Field, Visit},
+	span, Event, Level, Metadata, Subscriber,
+};
+use tracing_subscriber::{
+	filter::{LevelFilter, Targets},
+	fmt::{
+		format::{FormatEvent, FormatFields, Writer},
+		time::{FormatTime, SystemTime},
+		FmtContext, FormattedFields,
+	},
+	layer::{self, Filter, SubscriberExt},
+	registry::LookupSpan,
+	util::SubscriberInitExt,
+	Layer,
+};
+use twilight_model::id::{marker::GuildMarker, Id};
+
+/// Installs the global tracing subscriber.
+pub fn init() -> Result<(), anyhow::Error> {
+	let targets = match env::var("RUST_LOG") {
+		Ok(var) => Targets::from_str(&var).context("unable to parse \"RUST_LOG\"")?,
+		Err(_) => Targets::new().with_default(LevelFilter::INFO),
+	};
+	let guild = env::var("LOG_GUILD")
+		.ok()
+		.map(|var| var.parse())
+		.transpose()
+		.context("\"LOG_GUILD\" must be a guild ID")?;
+	let filter = GuildFilter { guild, targets };
+
+	let layer = match env::var("LOG_FORMAT").as_deref() {
+		Ok("json") => tracing_subscriber::fmt::layer()
+			.fmt_fields(JsonFields)
+			.event_format(Json)
+			.with_filter(filter)
+			.boxed(),
+		Ok("text") | Err(_) => tracing_subscriber::fmt::layer().with_filter(filter).boxed(),
+		Ok(_) => anyhow::bail!("\"LOG_FORMAT\" must be either \"json\" or \"text\""),
+	};
+
+	tracing_subscriber::registry().with(layer).init();
+	Ok(())
+}
+
+/// Guild ID of a span, stored in its extensions.
+struct SpanGuild(Id<GuildMarker>);
+
+/// Records the `guild_id` field.
+struct GuildVisitor(Option<Id<GuildMarker>>);
+
+impl Visit for GuildVisitor {
+	fn record_u64(&mut self, field: &Field, value: u64) {
+		if field.name() == "guild_id" {
+			self.0 = Id::new_checked(value);
+		}
+	}
+
+	fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+		if field.name() == "guild_id" {
+			self.0 = format!("{value:?}").parse().ok();
+		}
+	}
+}
+
+/// [`Targets`] filter that also enables `debug` events of a guild, identified by their or their
+/// spans' `guild_id` field.
+struct GuildFilter {
+	guild: Option<Id<GuildMarker>>,
+	targets: Targets,
+}
+
+impl GuildFilter {
+	/// Whether the guild, if any, is enabled at the level.
+	fn guild_enabled(&self, level: &Level) -> bool {
+		self.guild.is_some() && *level <= Level::DEBUG
+	}
+
+	fn record_guild<S: Subscriber + for<'a> LookupSpan<'a>>(
+		id: &span::Id,
+		guild: Option<Id<GuildMarker>>,
+		cx: &layer::Context<'_, S>,
+	) {
+		if let (Some(guild), Some(span)) = (guild, cx.span(id)) {
+			span.extensions_mut().replace(SpanGuild(guild));
+		}
+	}
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Filter<S> for GuildFilter {
+	fn enabled(&self, meta: &Metadata<'_>, _: &layer::Context<'_, S>) -> bool {
+		self.targets.would_enable(meta.target(), meta.level()) || self.guild_enabled(meta.level())
+	}
+
+	fn event_enabled(&self, event: &Event<'_>, cx: &layer::Context<'_, S>) -> bool {
+		let meta = event.metadata();
+		if self.targets.would_enable(meta.target(), meta.level()) {
+			return true;
+		}
+
+		let mut visitor = GuildVisitor(None);
+		event.record(&mut visitor);
+		self.guild.is_some()
+			&& visitor.0.or_else(|| {
+				cx.event_scope(event)?
+					.find_map(|span| span.extensions().get::<SpanGuild>().map(|guild| guild.0))
+			}) == self.guild
+	}
+
+	fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, cx: layer::Context<'_, S>) {
+		let mut visitor = GuildVisitor(None);
+		attrs.record(&mut visitor);
+		Self::record_guild(id, visitor.0, &cx);
+	}
+
+	fn on_record(&self, id: &span::Id, values: &span::Record<'_>, cx: layer::Context<'_, S>) {
+		let mut visitor = GuildVisitor(None);
+		values.record(&mut visitor);
+		Self::record_guild(id, visitor.0, &cx);
+	}
+}
+
+/// Records fields into a JSON object.
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+	fn record_f64(&mut self, field: &Field, value: f64) {
+		self.0.insert(field.name().to_owned(), value.into());
+	}
+
+	fn record_i64(&mut self, field: &Field, value: i64) {
+		self.0.insert(field.name().to_owned(), value.into());
+	}
+
+	fn record_u64(&mut self, field: &Field, value: u64) {
+		self.0.insert(field.name().to_owned(), value.into());
+	}
+
+	fn record_bool(&mut self, field: &Field, value: bool) {
+		self.0.insert(field.name().to_owned(), value.into());
+	}
+
+	fn record_str(&mut self, field: &Field, value: &str) {
+		self.0.insert(field.name().to_owned(), value.into());
+	}
+
+	fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+		self.0
+			.insert(field.name().to_owned(), format!("{value:?}").into());
+	}
+}
+
+/// Formats span fields as a JSON object, read back by [`Json`].
+struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+	fn format_fields<R: tracing_subscriber::field::RecordFields>(
+		&self,
+		mut writer: Writer<'writer>,
+		fields: R,
+	) -> fmt::Result {
+		let mut object = Map::new();
+		fields.record(&mut JsonVisitor(&mut object));
+		write!(writer, "{}", Value::Object(object))
+	}
+
+	fn add_fields(
+		&self,
+		current: &'writer mut FormattedFields<Self>,
+		fields: &span::Record<'_>,
+	) -> fmt::Result {
+		let mut object: Map<_, _> = serde_json::from_str(&current.fields).unwrap_or_default();
+		fields.record(&mut JsonVisitor(&mut object));
+		current.fields = Value::Object(object).to_string();
+		Ok(())
+	}
+}
+
+/// Formats events as JSON objects, flattening in the fields of their spans.
+struct Json;
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> FormatEvent<S, JsonFields> for Json {
+	fn format_event(
+		&self,
+		ctx: &FmtContext<'_, S, JsonFields>,
+		mut writer: Writer<'_>,
+		event: &Event<'_>,
+	) -> fmt::Result {
+		let meta = event.metadata();
+		let mut object = Map::new();
+		object.insert("level".to_owned(), meta.level().as_str().into());
+		object.insert("target".to_owned(), meta.target().into());
+
+		if let Some(scope) = ctx.event_scope() {
+			let mut spans = Vec::new();
+			for span in scope.from_root() {
+				spans.push(Value::from(span.name()));
+				if let Some(fields) = span.extensions().get::<FormattedFields<JsonFields>>() {
+					if let Ok(Value::Object(fields)) = serde_json::from_str(fields) {
+						object.extend(fields);
+					}
+				}
+			}
+			object.insert("spans".to_owned(), spans.into());
+		}
+
+		event.record(&mut JsonVisitor(&mut object));
+
+		// the timestamp is only writable to `writer` and contains nothing to escape
+		write!(writer, "{{\"timestamp\":\"")?;
+		SystemTime.format_time(&mut writer)?;
+		let object = Value::Object(object).to_string();
+		writeln!(writer, "\",{}", &object[1..])
+	}
+}
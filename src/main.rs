@@ -1,34 +1,76 @@
 //! Bot that on channel, member & role updates goes through the relevant voice channels
 //! in the guild and removes members lacking connection permission.
 
+mod attribution;
+mod cache;
+mod cause;
+mod chunk;
+mod cleanup;
+mod cli;
+mod clock;
+mod coalesce;
 mod commands;
+mod config;
+#[cfg(target_family = "unix")]
+mod control;
+mod cooldown;
+mod decision;
+mod dedup;
+mod error;
+mod events;
+mod halt;
+mod history;
+mod idle;
+mod logging;
+mod onboarding;
+mod policy;
+mod presence;
 mod prune;
+mod queue;
+mod readiness;
+mod reason;
+mod refresh;
+mod retry;
+mod schedule;
+mod serial;
+mod settings;
+mod state;
+mod stats;
+mod status;
+mod status_file;
+#[cfg(all(feature = "systemd", target_family = "unix"))]
+mod systemd;
+#[cfg(test)]
+mod test;
+mod throttle;
+mod totals;
+mod voice;
 
 use std::{
+	collections::HashSet,
 	env,
+	ffi::OsString,
 	ops::Deref,
+	path::PathBuf,
 	sync::{
 		atomic::{AtomicBool, Ordering},
-		OnceLock,
+		Mutex, OnceLock, PoisonError,
 	},
+	time::Instant,
 };
 
 use anyhow::Context;
-use futures_util::stream::{self, StreamExt};
+use futures_util::{stream::FuturesOrdered, StreamExt};
 use tokio::signal;
-use twilight_cache_inmemory::{InMemoryCache, ResourceType};
+use twilight_cache_inmemory::ResourceType;
 use twilight_gateway::{
-	error::ReceiveMessageErrorType, EventTypeFlags, Shard, ShardId, StreamExt as _,
+	error::ReceiveMessageErrorType, ConfigBuilder, EventTypeFlags, MessageSender, Shard, ShardId,
+	ShardState, StreamExt as _,
 };
-use twilight_http::Client;
+use twilight_http::{client::InteractionClient, Client};
 use twilight_model::{
-	application::interaction::InteractionType,
 	channel::ChannelType,
-	gateway::{
-		event::Event,
-		payload::incoming::{RoleDelete, RoleUpdate},
-		CloseFrame, Intents,
-	},
+	gateway::{event::Event, CloseFrame, Intents},
 	guild::Permissions,
 	id::{
 		marker::{ApplicationMarker, ChannelMarker, GuildMarker, UserMarker},
@@ -68,10 +110,12 @@ const EVENT_TYPES: EventTypeFlags = EventTypeFlags::CHANNEL_CREATE
 	.union(EventTypeFlags::GUILD_UPDATE)
 	.union(EventTypeFlags::GUILD_VOICE_STATES)
 	.union(EventTypeFlags::INTERACTION_CREATE)
+	.union(EventTypeFlags::MEMBER_CHUNK)
 	.union(EventTypeFlags::READY)
 	.union(EventTypeFlags::ROLE_CREATE)
 	.union(EventTypeFlags::ROLE_DELETE)
-	.union(EventTypeFlags::ROLE_UPDATE);
+	.union(EventTypeFlags::ROLE_UPDATE)
+	.union(EventTypeFlags::STAGE_INSTANCE_DELETE);
 
 /// [`Intents`] the bot requires.
 const INTENTS: Intents = Intents::GUILDS
@@ -83,9 +127,12 @@ const INTENTS: Intents = Intents::GUILDS
 /// - `/list` requires `CHANNEL`.
 /// - `BOT.is_monitored` requires `CHANNEL`, `MEMBER` & `ROLE`.
 /// - pruning requires `VOICE_STATE`
+/// - notifying pruned users requires `GUILD`
 const RESOURCES: ResourceType = ResourceType::CHANNEL
+	.union(ResourceType::GUILD)
 	.union(ResourceType::MEMBER)
 	.union(ResourceType::ROLE)
+	.union(ResourceType::USER)
 	.union(ResourceType::VOICE_STATE);
 
 /// Flag indicating bot should shut down.
@@ -93,7 +140,7 @@ const RESOURCES: ResourceType = ResourceType::CHANNEL
 /// Used by the shard, not by event handler tasks.
 static SHUTDOWN: AtomicBool = AtomicBool::new(false);
 
-/// [`ChannelType`]s the bot operates on.
+/// [`ChannelType`]s the bot may operate on, see [`BotRef::monitors`].
 ///
 /// Must only be voice channels.
 const MONITORED_CHANNEL_TYPES: [ChannelType; 2] =
@@ -101,43 +148,105 @@ const MONITORED_CHANNEL_TYPES: [ChannelType; 2] =
 
 #[tracing::instrument(name = "retrieve bot token")]
 fn get_token() -> Result<String, anyhow::Error> {
+	token_from(|var| env::var_os(var))
+}
+
+/// Retrieves the bot token from the environment variables looked up through `var`.
+fn token_from(var: impl Fn(&str) -> Option<OsString>) -> Result<String, anyhow::Error> {
 	// https://systemd.io/CREDENTIALS/
-	#[cfg(target_os = "linux")]
-	if let Some(mut path) = env::var_os("CREDENTIALS_DIRECTORY") {
+	#[cfg(all(target_os = "linux", feature = "systemd"))]
+	if let Some(mut path) = var("CREDENTIALS_DIRECTORY") {
 		tracing::debug!("using systemd credentials");
 		path.push("/token");
-		return std::fs::read_to_string(path)
-			.map(|mut token| {
-				if token.ends_with('\n') {
-					token.truncate(token.len() - 1)
-				}
-				token
-			})
+		return read_token(path.as_ref())
 			.context("unable to retrieve bot token from the \"token\" systemd credential");
 	}
 
+	if var("TOKEN").is_none() {
+		if let Some(path) = var("TOKEN_FILE") {
+			tracing::debug!("using token file");
+			return read_token(path.as_ref())
+				.context("unable to retrieve bot token from the \"TOKEN_FILE\" file");
+		}
+	}
+
 	tracing::debug!("using environment variable");
-	#[cfg(target_os = "linux")]
+	#[cfg(all(target_os = "linux", feature = "systemd"))]
 	tracing::info!("prefer systemd credentials for improved security");
-	env::var("TOKEN")
+	var("TOKEN")
+		.and_then(|token| token.into_string().ok())
 		.context("unable to retrieve bot token from the \"TOKEN\" environment variable")
 }
 
+/// Reads the token from the file, without a trailing newline.
+fn read_token(path: &std::path::Path) -> std::io::Result<String> {
+	std::fs::read_to_string(path).map(|mut token| {
+		if token.ends_with('\n') {
+			token.truncate(token.len() - 1)
+		}
+		token
+	})
+}
+
+/// Host, with port, of the proxy URL.
+fn proxy_host(url: &str) -> &str {
+	let url = url
+		.strip_prefix("http://")
+		.or_else(|| url.strip_prefix("https://"))
+		.unwrap_or(url);
+	url.split('/').next().unwrap_or(url)
+}
+
+/// HTTP client routing requests through the proxy, if any, leaving ratelimiting to it.
+fn http_client(token: String, proxy: Option<&str>) -> Result<Client, anyhow::Error> {
+	let Some(url) = proxy else {
+		return Ok(Client::new(token));
+	};
+
+	let use_http = match url.split_once("://") {
+		Some(("http", _)) => true,
+		Some(("https", _)) | None => false,
+		Some(_) => anyhow::bail!("\"DISCORD_PROXY_URL\" must be an http or https URL"),
+	};
+	tracing::info!(host = proxy_host(url), "routing requests through proxy");
+
+	Ok(Client::builder()
+		.token(token)
+		.proxy(proxy_host(url).to_owned(), use_http)
+		.ratelimiter(None)
+		.build())
+}
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), anyhow::Error> {
-	tracing_subscriber::fmt::init();
+	let args = cli::Args::parse()?;
+	let exported = args.config.as_deref().map(config::load).transpose()?;
+	logging::init()?;
+	if let (Some(path), Some(exported)) = (&args.config, exported) {
+		tracing::info!(path = %path.display(), exported, "loaded config");
+	}
 
 	let token = get_token()?;
 
 	let mut shard = init(token).await.context("unable to initialize bot")?;
 	let sender = shard.sender();
 
+	#[cfg(target_family = "unix")]
+	tokio::spawn(control::run(control::Control::bind()?));
+	tokio::spawn(idle::run());
+	tokio::spawn(presence::run(sender.clone()));
+	tokio::spawn(status_file::run());
+	#[cfg(all(feature = "systemd", target_family = "unix"))]
+	systemd::notify(systemd::READY);
+
 	let handle = tokio::spawn(async move {
 		while let Some(res) = shard.next_event(EVENT_TYPES).await {
+			*BOT.shard.lock().unwrap_or_else(PoisonError::into_inner) = shard.state();
 			match res {
 				Ok(Event::GatewayClose(_)) if SHUTDOWN.load(Ordering::Relaxed) => break,
 				Ok(event) => {
-					tokio::spawn(handle(event));
+					// updates the cache in the order events are received
+					events::handle(event);
 				}
 				Err(error)
 					if matches!(error.kind(), ReceiveMessageErrorType::WebSocket)
@@ -161,10 +270,24 @@ async fn main() -> Result<(), anyhow::Error> {
 			signal(SignalKind::interrupt()).context("unable to register SIGINT handler")?;
 		let mut sigterm =
 			signal(SignalKind::terminate()).context("unable to register SIGTERM handler")?;
+		let mut sighup =
+			signal(SignalKind::hangup()).context("unable to register SIGHUP handler")?;
 
-		tokio::select! {
-				_ = sigint.recv() => tracing::trace!("received SIGINT"),
-				_ = sigterm.recv() => tracing::trace!("received SIGTERM"),
+		loop {
+			tokio::select! {
+				_ = sigint.recv() => {
+					tracing::trace!("received SIGINT");
+					break;
+				}
+				_ = sigterm.recv() => {
+					tracing::trace!("received SIGTERM");
+					break;
+				}
+				_ = sighup.recv() => {
+					tracing::info!("received SIGHUP, registering commands");
+					tokio::spawn(commands::try_register());
+				}
+			}
 		}
 	}
 
@@ -174,119 +297,438 @@ async fn main() -> Result<(), anyhow::Error> {
 		.context("unable to register Ctrl+C handler")?;
 
 	tracing::debug!("shutting down");
+	#[cfg(all(feature = "systemd", target_family = "unix"))]
+	systemd::notify(systemd::STOPPING);
 
 	SHUTDOWN.store(true, Ordering::Relaxed);
 	_ = sender.close(CloseFrame::NORMAL);
 
 	handle.await?;
+	status_file::write();
+	let totals = BOT.totals.snapshot();
+	tracing::info!(
+		events = totals.events,
+		prunes = totals.prunes,
+		users = totals.users,
+		commands = totals.commands,
+		http_failures = totals.http_failures,
+		"totals since start"
+	);
 	Ok(())
 }
 
-/// Handle a gateway [`Event`].
-async fn handle(event: Event) {
-	let skip = matches!(&event, Event::ChannelUpdate(c)
-			if BOT
-				.cache
-				.channel(c.id)
-				.is_some_and(|cached| cached.permission_overwrites == c.permission_overwrites))
-		|| matches!(&event, Event::RoleUpdate(r)
-				if BOT
-					.cache
-					.role(r.role.id)
-					.is_some_and(|cached| cached.permissions == r.role.permissions));
-
-	BOT.cache.update(&event);
-
-	if skip {
-		return;
+/// The channels the bot monitors, empty if the bot's member is not cached.
+pub fn monitored(
+	guild: Id<GuildMarker>,
+	channels: impl IntoIterator<Item = Id<ChannelMarker>>,
+) -> HashSet<Id<ChannelMarker>> {
+	if BOT.cache.member(guild, BOT.id).is_none() {
+		return HashSet::new();
 	}
 
-	match event {
-		Event::ChannelUpdate(c) if BOT.auto_prune(c.guild_id.unwrap()) => {
-			crate::prune::channel(c.id, c.guild_id.unwrap(), |_| true).await;
-		}
-		Event::MemberUpdate(m) if BOT.auto_prune(m.guild_id) => {
-			crate::prune::user(m.guild_id, m.user.id).await;
-		}
-		Event::RoleDelete(RoleDelete { guild_id, .. })
-		| Event::RoleUpdate(RoleUpdate { guild_id, .. })
-			if BOT.auto_prune(guild_id) =>
-		{
-			crate::prune::guild(guild_id, |_| true).await;
-		}
-		Event::InteractionCreate(interaction) => match interaction.kind {
-			InteractionType::ApplicationCommand => {
-				crate::commands::interaction(interaction.0).await;
-			}
-			_ => tracing::info!(?interaction, "unhandled"),
-		},
-		Event::Ready(r) => {
-			tracing::debug!(guilds = %r.guilds.len(), user = %r.user.name);
-		}
-		_ => {}
-	}
+	channels
+		.into_iter()
+		.filter(|&channel| BOT.cache.channel(channel).is_some() && BOT.is_monitored(channel))
+		.collect()
 }
 
-/// "Real" [`BOT`] struct.
-///
-/// Contains required modules: a HTTP client, and cache and state: bot user ID,
-/// and bot application ID.
+/// "Real" [`BOT`] struct, holding the bot's clients, cache and state.
 #[derive(Debug)]
 struct BotRef {
+	/// Application ID of the bot.
 	application_id: Id<ApplicationMarker>,
-	cache: InMemoryCache,
+	/// Audit log fetches attributing permission changes.
+	attributions: attribution::Attributions,
+	cache: cache::Cache,
+	/// Members requested from the gateway.
+	chunks: chunk::Chunks,
+	/// Channel updates buffered per category.
+	coalescer: coalesce::Coalescer,
+	/// Whether the commands were registered since starting, otherwise retried on reconnect.
+	commands_registered: AtomicBool,
+	/// Command cooldowns per guild.
+	cooldowns: cooldown::Cooldowns,
+	/// Recent auto prune decisions.
+	decisions: decision::Decisions,
+	/// Since when users are deafened.
+	deafened: idle::Deafened,
+	/// Emergency stops of guilds' prunes.
+	halts: halt::Halts,
+	/// Most recent prunes.
+	history: history::History,
 	http: Client,
-	/// User ID of the bot
+	/// User ID of the bot.
 	id: Id<UserMarker>,
+	/// Channels being locked through `/lock`.
+	locks: dedup::Locks,
+	/// Whether stage channels are monitored by default.
+	monitor_stage: bool,
+	/// Guilds introduced to on joining.
+	onboarding: onboarding::Onboarding,
+	/// Users permitted to run maintenance commands.
+	owners: HashSet<Id<UserMarker>>,
+	/// Pages of paginated replies.
+	pages: commands::components::Pages,
+	/// Guilds the bot may stay in.
+	policy: policy::GuildPolicy,
+	/// Presence showing the number of monitored channels, if enabled.
+	presence: Option<presence::Presence>,
+	/// Host of the HTTP proxy requests are routed through.
+	proxy: Option<String>,
+	/// Queue every removal goes through.
+	queue: queue::Queue,
+	/// Guilds whose cache is not yet warm.
+	readiness: readiness::Readiness,
+	/// Scheduled prunes.
+	scheduler: schedule::Scheduler,
+	/// Interactions received recently.
+	seen: dedup::Seen,
+	/// Handling of events in order per guild.
+	serial: serial::Serial,
+	/// Sender of gateway commands to the shard.
+	sender: MessageSender,
+	/// Settings per guild.
+	settings: settings::Store,
+	/// State of the shard as of its last received event.
+	shard: Mutex<ShardState>,
+	/// When the bot started.
+	started: Instant,
+	/// Prune statistics per guild.
+	stats: stats::Stats,
+	/// File the bot's status is written to.
+	status_file: Option<PathBuf>,
+	/// Voice channel statuses set after prunes.
+	statuses: status::Statuses,
+	/// Auto prune throttling per guild.
+	throttle: throttle::Throttle,
+	/// Totals since starting.
+	totals: totals::Totals,
+	/// Maximum number of users verified per prune.
+	verify_limit: u16,
+	/// Unfinished `/setup` wizards.
+	wizards: commands::setup::Wizards,
 }
 
 impl BotRef {
-	/// Whether the guild has auto prune enabled.
+	/// Guild-level permissions the bot requires to operate.
+	const REQUIRED_PERMISSIONS: Permissions =
+		Permissions::MOVE_MEMBERS.union(Permissions::VIEW_CHANNEL);
+
+	/// State dropped when its guild or channel is deleted.
+	fn cleanups(&self) -> [&dyn cleanup::Cleanup; 14] {
+		[
+			&self.attributions,
+			&self.chunks,
+			&self.coalescer,
+			&self.cooldowns,
+			&self.decisions,
+			&self.deafened,
+			&self.halts,
+			&self.history,
+			&self.readiness,
+			&self.scheduler,
+			&self.settings,
+			&self.stats,
+			&self.statuses,
+			&self.throttle,
+		]
+	}
+
+	/// Client of the bot's interactions.
+	fn interaction(&self) -> InteractionClient<'_> {
+		self.http.interaction(self.application_id)
+	}
+
+	/// Whether the guild has auto prune enabled and not paused.
 	fn auto_prune(&self, guild: Id<GuildMarker>) -> bool {
 		// event order isn't guarenteed, so this might not be cached yet
 		self.cache.member(guild, self.id).is_some_and(|member| {
-			!member
-				.roles()
-				.iter()
-				.any(|&role| self.cache.role(role).unwrap().name == "no-auto-prune")
+			!member.roles().iter().any(|&role| {
+				// uncached roles are skipped
+				self.cache
+					.role(role)
+					.is_some_and(|role| role.name == "no-auto-prune")
+			})
+		}) && !self.is_paused(guild)
+	}
+
+	/// Whether the guild's auto pruning is paused, clearing an expired pause.
+	fn is_paused(&self, guild: Id<GuildMarker>) -> bool {
+		let settings = self.settings.get(guild);
+		if settings.paused_for().is_some() {
+			return true;
+		}
+		if settings.paused_until.is_some() {
+			tracing::info!(guild_id = %guild, "auto prune pause expired");
+			self.settings
+				.update(guild, |settings| settings.paused_until = None);
+		}
+		false
+	}
+
+	/// Required permissions the bot's roles do not grant in the guild, `None` if not cached.
+	fn missing_permissions(&self, guild: Id<GuildMarker>) -> Option<Permissions> {
+		let permissions = self.cache.permissions().root(self.id, guild).ok()?;
+		Some(Self::REQUIRED_PERMISSIONS.difference(permissions))
+	}
+
+	/// Whether the voice channel is its guild's AFK channel.
+	fn is_afk(&self, channel: Id<ChannelMarker>) -> bool {
+		self.cache
+			.channel(channel)
+			.and_then(|channel| channel.guild_id)
+			.and_then(|guild| self.cache.guild(guild))
+			.is_some_and(|guild| guild.afk_channel_id() == Some(channel))
+	}
+
+	/// Whether the bot may view any voice channel of the guild.
+	///
+	/// Uncached permissions are assumed to be visible.
+	fn sees_voice_channels(&self, guild: Id<GuildMarker>) -> bool {
+		self.cache.guild_channels(guild).is_some_and(|channels| {
+			channels.iter().any(|&id| {
+				self.cache
+					.channel(id)
+					.is_some_and(|channel| MONITORED_CHANNEL_TYPES.contains(&channel.kind))
+					&& self
+						.cache
+						.permissions()
+						.in_channel(self.id, id)
+						.map_or(true, |permissions| {
+							permissions.contains(Permissions::VIEW_CHANNEL)
+						})
+			})
 		})
 	}
 
-	/// Whether the voice channel is monitored.
+	/// Whether the voice channel is monitored, `false` if its name ends with the guild's name marker
+	/// or its permissions can't be calculated from the cache, e.g. shortly after reconnecting.
 	fn is_monitored(&self, channel: Id<ChannelMarker>) -> bool {
-		self.cache
-			.permissions()
-			.in_channel(self.id, channel)
-			.expect("resources are available")
-			.contains(Permissions::MOVE_MEMBERS)
+		let settings = self
+			.cache
+			.channel(channel)
+			.and_then(|channel| channel.guild_id)
+			.map(|guild| self.settings.get(guild))
+			.unwrap_or_default();
+		self.is_monitored_with(&settings, channel)
+	}
+
+	/// [`Self::is_monitored`] with the settings of the channel's guild, for callers checking many
+	/// channels.
+	fn is_monitored_with(&self, settings: &settings::Settings, channel: Id<ChannelMarker>) -> bool {
+		let Some((kind, name)) = self
+			.cache
+			.channel(channel)
+			.filter(|channel| channel.guild_id.is_some())
+			.map(|channel| (channel.kind, channel.name.clone()))
+		else {
+			tracing::debug!(channel_id = %channel, "channel not cached, assuming unmonitored");
+			return false;
+		};
+		if name.is_some_and(|name| settings.is_marked(&name)) {
+			return false;
+		}
+
+		self.monitors(settings, kind)
+			&& match self.cache.permissions().in_channel(self.id, channel) {
+				Ok(permissions) => permissions.contains(Permissions::MOVE_MEMBERS),
+				Err(e) => {
+					tracing::debug!(channel_id = %channel, kind = ?e.kind(), "unable to calculate permissions, assuming unmonitored");
+					false
+				}
+			}
+	}
+
+	/// Whether channels of the type are monitored with the guild's settings.
+	fn monitors(&self, settings: &settings::Settings, kind: ChannelType) -> bool {
+		match kind {
+			ChannelType::GuildStageVoice => settings.monitor_stage.unwrap_or(self.monitor_stage),
+			kind => MONITORED_CHANNEL_TYPES.contains(&kind),
+		}
+	}
+
+	/// Moves the user from their voice channel to another, or removes them if `None`, through the
+	/// removal queue, logging on error. The removal is dropped once the run is halted, and skipped if
+	/// the run already submitted the user.
+	///
+	/// The guild's kick reason, if any, is rendered as the audit log reason.
+	async fn move_member(
+		&self,
+		guild: Id<GuildMarker>,
+		user: Id<UserMarker>,
+		from: Id<ChannelMarker>,
+		channel: Option<Id<ChannelMarker>>,
+		trigger: prune::Trigger,
+		run: queue::Run,
+	) -> Result<(), queue::Error> {
+		match channel {
+			Some(target) => tracing::debug!(user_id = %user, target_id = %target, "moving"),
+			None => tracing::debug!(user_id = %user, "kicking"),
+		}
+		let reason = self.settings.get(guild).kick_reason.map(|template| {
+			let channel = self
+				.cache
+				.channel(from)
+				.and_then(|channel| channel.name.clone())
+				.unwrap_or_else(|| from.to_string());
+			let name = self
+				.cache
+				.user(user)
+				.map_or_else(|| user.to_string(), |user| user.name.clone());
+			reason::render(&template, &channel, trigger, &name)
+		});
+		match self
+			.queue
+			.remove(guild, user, channel, trigger, reason, run)
+			.await
+		{
+			Ok(()) => Ok(()),
+			Err(queue::Error::Duplicate) => {
+				tracing::debug!(user_id = %user, "already removed by this prune");
+				Err(queue::Error::Duplicate)
+			}
+			Err(e) => {
+				tracing::warn!(user_id = %user, error = &e as &dyn std::error::Error);
+				if let queue::Error::Http(_) = e {
+					self.totals.http_failure();
+				}
+				Err(e)
+			}
+		}
+	}
+
+	/// Notifies the user through a DM that they were removed from the channel.
+	///
+	/// Errors, e.g. from the user's privacy settings, are only logged.
+	async fn notify_removed(
+		&self,
+		guild: Id<GuildMarker>,
+		user: Id<UserMarker>,
+		channel: Id<ChannelMarker>,
+	) {
+		let message = format!(
+			"You were disconnected from #{} in {} because you no longer have permission to connect",
+			self.cache
+				.channel(channel)
+				.and_then(|channel| channel.name.clone())
+				.unwrap_or_else(|| String::from("a voice channel")),
+			self.cache
+				.guild(guild)
+				.map_or_else(|| String::from("a server"), |guild| guild.name().to_owned()),
+		);
+
+		self.dm(user, &message).await;
+	}
+
+	/// Sends the user a DM.
+	///
+	/// Errors, e.g. from the user's privacy settings, are only logged.
+	async fn dm(&self, user: Id<UserMarker>, message: &str) {
+		let result = async {
+			let channel = self
+				.http
+				.create_private_channel(user)
+				.await?
+				.model()
+				.await?;
+			self.http
+				.create_message(channel.id)
+				.content(message)
+				.await?;
+			Ok::<_, anyhow::Error>(())
+		};
+		if let Err(e) = result.await {
+			tracing::debug!(user_id = %user, error = &*e, "unable to send DM");
+		}
 	}
 
-	/// Removes users, logging on error.
+	/// Removes users from the channel, logging on error, and notifies them if enabled.
 	///
-	/// Returns the number of users removed.
+	/// Users that moved or left the channel are skipped, and the rest are first verified against
+	/// freshly fetched data if enabled. Up to one removal per queue worker is in flight at a time.
+	/// Stops once the bot appears to have lost its permissions, see [`prune::Summary::denied`],
+	/// though removals already in flight are still awaited.
 	async fn remove(
 		&self,
 		guild: Id<GuildMarker>,
+		channel: Id<ChannelMarker>,
 		users: impl IntoIterator<Item = Id<UserMarker>>,
-	) -> u16 {
-		stream::iter(users)
-			.map(|user| async move {
-				tracing::debug!(user.id = %user, "kicking");
-				match self
-					.http
-					.update_guild_member(guild, user)
-					.channel_id(None)
-					.await
-				{
-					Ok(_) => 1,
-					Err(e) => {
-						tracing::warn!(error = &e as &dyn std::error::Error);
-						0
-					}
+		trigger: prune::Trigger,
+		summary: &mut prune::Summary,
+	) -> prune::Removal {
+		let settings = self.settings.get(guild);
+		let mut removal = prune::Removal::default();
+		let mut in_flight = FuturesOrdered::new();
+		for user in users {
+			if summary.is_halted() {
+				break;
+			}
+			if !prune::is_still_connected(guild, user, channel, summary) {
+				continue;
+			}
+			if settings.verify_before_kick
+				&& summary.take_verification(self.verify_limit)
+				&& prune::verify(guild, user, channel).await
+			{
+				tracing::info!(user_id = %user, "permitted according to fresh data, keeping");
+				summary.spared += 1;
+				continue;
+			}
+			let run = summary.run(guild);
+			in_flight.push_back(async move {
+				let result = self
+					.move_member(guild, user, channel, None, trigger, run)
+					.await;
+				(user, result)
+			});
+			if in_flight.len() < self.queue.workers() {
+				continue;
+			}
+			let result = in_flight.next().await.expect("not empty");
+			if self
+				.removed(guild, channel, result, &settings, &mut removal, summary)
+				.await
+			{
+				break;
+			}
+		}
+		while let Some(result) = in_flight.next().await {
+			self.removed(guild, channel, result, &settings, &mut removal, summary)
+				.await;
+		}
+
+		removal
+	}
+
+	/// Records the result of removing the user, returning whether the bot appears to have lost
+	/// its permissions.
+	async fn removed(
+		&self,
+		guild: Id<GuildMarker>,
+		channel: Id<ChannelMarker>,
+		(user, result): (Id<UserMarker>, Result<(), queue::Error>),
+		settings: &settings::Settings,
+		removal: &mut prune::Removal,
+		summary: &mut prune::Summary,
+	) -> bool {
+		match result {
+			Ok(()) => {
+				summary.reset_denials();
+				removal.removed.push(user);
+				if settings.dm_on_prune && summary.take_dm() {
+					self.notify_removed(guild, user, channel).await;
 				}
-			})
-			.fold(0, |a, b| async move { a + b.await })
-			.await
+				false
+			}
+			Err(e) => {
+				let failure = prune::Failure::classify(&e);
+				removal
+					.failed
+					.extend(failure.map(|failure| (user, failure)));
+				failure == Some(prune::Failure::MissingPermissions)
+					&& summary.denied(removal.removed.len())
+			}
+		}
 	}
 }
 
@@ -297,29 +739,244 @@ impl BotRef {
 /// Panics if called multiple times.
 #[tracing::instrument(skip_all)]
 async fn init(token: String) -> Result<Shard, anyhow::Error> {
-	let http = Client::new(token.clone());
+	let policy = policy::GuildPolicy::from_env()?;
+	let owners = policy::parse("OWNERS")?.unwrap_or_default();
+	let monitor_stage = match env::var("MONITOR_STAGE").as_deref() {
+		Ok("true") | Err(_) => true,
+		Ok("false") => false,
+		Ok(_) => anyhow::bail!("\"MONITOR_STAGE\" must be either \"true\" or \"false\""),
+	};
+	let verify_limit = env::var("VERIFY_LIMIT")
+		.map_or(Ok(10), |var| var.parse())
+		.context("\"VERIFY_LIMIT\" must be a non-negative integer")?;
+	let settings = settings::Store::load()?;
+	let stats = stats::Stats::load()?;
+	let throttle = throttle::Throttle::from_env()?;
+	let (queue, workers) = queue::Queue::from_env()?;
+	let presence = presence::Presence::from_env()?;
+	let proxy = env::var("DISCORD_PROXY_URL").ok();
+	let status_file = env::var_os("STATUS_FILE").map(PathBuf::from);
+	let http = http_client(token.clone(), proxy.as_deref())?;
+	let proxy = proxy.map(|url| proxy_host(&url).to_owned());
 
 	let (application_id, id) = tokio::try_join!(
 		async {
-			let application_id = http.current_user_application().await?.model().await?.id;
-			http.interaction(application_id)
-				.set_global_commands(&commands::get())
-				.await?;
-			Ok::<_, anyhow::Error>(application_id)
+			let application = retry::transient(|| http.current_user_application()).await?;
+			Ok::<_, anyhow::Error>(application.model().await?.id)
 		},
-		async { Ok(http.current_user().await?.model().await?.id) }
+		async {
+			let user = retry::transient(|| http.current_user()).await?;
+			Ok(user.model().await?.id)
+		}
 	)?;
 
+	let commands = commands::get();
+	let client = http.interaction(application_id);
+	let commands_registered = match retry::transient(|| client.set_global_commands(&commands)).await
+	{
+		Ok(_) => true,
+		Err(e) if retry::is_transient(&e) => {
+			tracing::warn!(
+				error = &e as &dyn std::error::Error,
+				"unable to register commands, previously registered commands remain available until retried on reconnect or SIGHUP"
+			);
+			false
+		}
+		Err(e) => return Err(e).context("unable to register commands"),
+	};
+
 	tracing::debug!(%application_id, user_id = %id);
 
+	let mut config = ConfigBuilder::new(token, INTENTS);
+	if let Some(presence) = &presence {
+		config = config.presence(presence.payload());
+	}
+	let shard = Shard::with_config(ShardId::ONE, config.build());
+
 	BOT.0
 		.set(BotRef {
 			application_id,
-			cache: InMemoryCache::builder().resource_types(RESOURCES).build(),
+			attributions: attribution::Attributions::default(),
+			cache: cache::Cache::builder().resource_types(RESOURCES).build(),
+			chunks: chunk::Chunks::default(),
+			coalescer: coalesce::Coalescer::default(),
+			commands_registered: AtomicBool::new(commands_registered),
+			cooldowns: cooldown::Cooldowns::default(),
+			decisions: decision::Decisions::default(),
+			deafened: idle::Deafened::default(),
+			halts: halt::Halts::default(),
+			history: history::History::default(),
 			http,
 			id,
+			locks: dedup::Locks::default(),
+			monitor_stage,
+			onboarding: onboarding::Onboarding::default(),
+			owners,
+			pages: commands::components::Pages::default(),
+			policy,
+			presence,
+			proxy,
+			queue,
+			readiness: readiness::Readiness::default(),
+			scheduler: schedule::Scheduler::default(),
+			seen: dedup::Seen::default(),
+			serial: serial::Serial::default(),
+			sender: shard.sender(),
+			settings,
+			shard: Mutex::new(ShardState::Identifying),
+			started: Instant::now(),
+			stats,
+			status_file,
+			statuses: status::Statuses::default(),
+			throttle,
+			totals: totals::Totals::default(),
+			verify_limit,
+			wizards: commands::setup::Wizards::default(),
 		})
 		.expect("only called once");
+	tokio::spawn(queue::run(workers));
+
+	Ok(shard)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use twilight_model::{
+		channel::ChannelType,
+		gateway::payload::incoming::RoleUpdate,
+		guild::Permissions,
+		id::{marker::GuildMarker, Id},
+	};
+
+	use crate::{
+		clock,
+		test::{role_overwrite, Guild},
+		BOT,
+	};
+
+	/// Guild with voice channels `id + 10` and `id + 11`, hidden from the bot if `hidden`, and the
+	/// text channel `id + 12`.
+	fn voice_channels(id: u64, hidden: [bool; 2]) -> Id<GuildMarker> {
+		let overwrites = |hidden| {
+			if hidden {
+				vec![role_overwrite(
+					id + 1,
+					Permissions::empty(),
+					Permissions::VIEW_CHANNEL,
+				)]
+			} else {
+				Vec::new()
+			}
+		};
+		Guild::new(id)
+			.voice(id + 10, &overwrites(hidden[0]))
+			.voice(id + 11, &overwrites(hidden[1]))
+			.channel(id + 12, ChannelType::GuildText, None, &[])
+			.create()
+	}
 
-	Ok(Shard::new(ShardId::ONE, token, INTENTS))
+	#[test]
+	fn sees_voice_channels() {
+		assert!(!BOT.sees_voice_channels(voice_channels(3_430_000, [true, true])));
+		assert!(BOT.sees_voice_channels(voice_channels(3_431_000, [true, false])));
+		assert!(BOT.sees_voice_channels(voice_channels(3_432_000, [false, false])));
+	}
+
+	/// Sets the permissions of the bot's role in the cached guild.
+	fn bot_role(guild: Id<GuildMarker>, permissions: Permissions) {
+		let role = crate::test::role_payload(guild.get() + 1, "bot", permissions, 10);
+		BOT.cache.update(&RoleUpdate {
+			guild_id: guild,
+			role: serde_json::from_value(role).expect("valid role"),
+		});
+	}
+
+	#[test]
+	fn missing_permissions() {
+		let guild = Guild::new(3_530_000).create();
+		assert_eq!(BOT.missing_permissions(guild), Some(Permissions::empty()));
+
+		bot_role(guild, Permissions::CONNECT);
+		assert_eq!(
+			BOT.missing_permissions(guild),
+			Some(Permissions::MOVE_MEMBERS),
+			"`@everyone` grants viewing channels"
+		);
+
+		bot_role(guild, Permissions::ADMINISTRATOR);
+		assert_eq!(BOT.missing_permissions(guild), Some(Permissions::empty()));
+
+		assert_eq!(
+			BOT.missing_permissions(Id::new(3_531_000)),
+			None,
+			"uncached"
+		);
+	}
+
+	#[test]
+	fn pause_disables_auto_prune() {
+		let guild = Guild::new(3_490_000).create();
+		assert!(BOT.auto_prune(guild));
+
+		BOT.settings
+			.update(guild, |settings| settings.pause(Duration::from_secs(60)));
+		assert!(!BOT.auto_prune(guild));
+
+		// expired pauses are cleared
+		BOT.settings.update(guild, |settings| {
+			settings.paused_until = Some(clock::now().as_secs() - 1);
+		});
+		assert!(BOT.auto_prune(guild));
+		assert_eq!(BOT.settings.get(guild).paused_until, None);
+	}
+
+	#[test]
+	fn ignores_text_channels() {
+		let guild = Guild::new(3_433_000)
+			.channel(3_433_012, ChannelType::GuildText, None, &[])
+			.create();
+
+		assert!(!BOT.sees_voice_channels(guild));
+	}
+
+	#[test]
+	fn credentials_require_systemd_feature() {
+		let dir =
+			std::env::temp_dir().join(format!("voice-pruner-{}-credentials", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(dir.join("token"), "from credentials\n").unwrap();
+		let var = |var: &str| match var {
+			"CREDENTIALS_DIRECTORY" => Some(dir.clone().into_os_string()),
+			"TOKEN" => Some("from environment".into()),
+			_ => None,
+		};
+
+		let token = super::token_from(var).unwrap();
+
+		std::fs::remove_dir_all(&dir).unwrap();
+		#[cfg(all(target_os = "linux", feature = "systemd"))]
+		assert_eq!(token, "from credentials");
+		#[cfg(not(all(target_os = "linux", feature = "systemd")))]
+		assert_eq!(token, "from environment");
+	}
+
+	#[test]
+	fn token_file_yields_to_token() {
+		let file = std::env::temp_dir().join(format!("voice-pruner-{}-token", std::process::id()));
+		std::fs::write(&file, "from file\n").unwrap();
+		let token = |token: Option<&str>| {
+			super::token_from(|var| match var {
+				"TOKEN" => token.map(Into::into),
+				"TOKEN_FILE" => Some(file.clone().into_os_string()),
+				_ => None,
+			})
+		};
+
+		assert_eq!(token(None).unwrap(), "from file");
+		assert_eq!(token(Some("from environment")).unwrap(), "from environment");
+		std::fs::remove_file(&file).unwrap();
+		assert!(token(None).is_err());
+	}
 }
@@ -0,0 +1,103 @@
+//! Introduction sent once the bot joins a new guild.
+//!
+//! Guilds of the ready event are not new, so only guilds joined while running are introduced to,
+//! at most once per process lifetime.
+
+use std::{
+	collections::HashSet,
+	sync::{Mutex, PoisonError},
+};
+
+use twilight_model::{
+	channel::ChannelType,
+	guild::Permissions,
+	id::{
+		marker::{ChannelMarker, GuildMarker},
+		Id,
+	},
+};
+
+use crate::BOT;
+
+const INTRODUCTION: &str = "Hi! I monitor voice channels and disconnect users that no longer have \
+permission to connect to them.
+• I need the Move Members permission to disconnect users
+• Give me a role named \"no-auto-prune\" to disable auto pruning, or configure me with `/settings`
+• `/audit` reports permission configurations that may cause surprising prunes";
+
+/// Guilds already seen by the bot.
+#[derive(Debug, Default)]
+pub struct Onboarding(Mutex<HashSet<Id<GuildMarker>>>);
+
+impl Onboarding {
+	/// Marks the guilds as seen.
+	pub fn seen(&self, guilds: impl IntoIterator<Item = Id<GuildMarker>>) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.extend(guilds);
+	}
+
+	/// Marks the guild as seen, returning whether it was new.
+	fn is_new(&self, guild: Id<GuildMarker>) -> bool {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.insert(guild)
+	}
+}
+
+/// Whether the bot may send messages in the channel.
+fn can_send(channel: Id<ChannelMarker>) -> bool {
+	BOT.cache
+		.permissions()
+		.in_channel(BOT.id, channel)
+		.is_ok_and(|permissions| {
+			permissions.contains(Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES)
+		})
+}
+
+/// The guild's system channel, or else its first text channel, that the bot may send messages in.
+fn channel(guild: Id<GuildMarker>) -> Option<Id<ChannelMarker>> {
+	if let Some(channel) = BOT
+		.cache
+		.guild(guild)?
+		.system_channel_id()
+		.filter(|&channel| can_send(channel))
+	{
+		return Some(channel);
+	}
+
+	let mut channels: Vec<_> = BOT
+		.cache
+		.guild_channels(guild)?
+		.iter()
+		.filter_map(|&id| {
+			let channel = BOT.cache.channel(id)?;
+			(channel.kind == ChannelType::GuildText)
+				.then(|| (channel.position.unwrap_or_default(), id))
+		})
+		.collect();
+	channels.sort_unstable();
+	channels
+		.into_iter()
+		.map(|(_, id)| id)
+		.find(|&channel| can_send(channel))
+}
+
+/// Introduces the bot to the guild if it is new.
+pub async fn greet(guild: Id<GuildMarker>) {
+	if !BOT.onboarding.is_new(guild) {
+		return;
+	}
+
+	let Some(channel) = channel(guild) else {
+		tracing::info!(guild_id = %guild, "no channel to introduce the bot in");
+		return;
+	};
+
+	tracing::info!(guild_id = %guild, channel_id = %channel, "introducing bot");
+	if let Err(e) = BOT.http.create_message(channel).content(INTRODUCTION).await {
+		tracing::warn!(guild_id = %guild, error = &e as &dyn std::error::Error);
+	}
+}
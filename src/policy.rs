@@ -0,0 +1,69 @@
+//! Guilds the bot operates in, configured through the `GUILD_ALLOWLIST` or `GUILD_DENYLIST`
+//! environment variables.
+
+use std::{collections::HashSet, env, fmt};
+
+use anyhow::Context;
+use twilight_model::id::{marker::GuildMarker, Id};
+
+/// Policy deciding which guilds the bot operates in.
+#[derive(Debug)]
+pub enum GuildPolicy {
+	/// Operate in every guild.
+	All,
+	/// Operate only in these guilds.
+	Allow(HashSet<Id<GuildMarker>>),
+	/// Operate in every guild except these.
+	Deny(HashSet<Id<GuildMarker>>),
+}
+
+impl GuildPolicy {
+	/// Parses the policy from the environment.
+	pub fn from_env() -> Result<Self, anyhow::Error> {
+		match (parse("GUILD_ALLOWLIST")?, parse("GUILD_DENYLIST")?) {
+			(Some(_), Some(_)) => {
+				anyhow::bail!("\"GUILD_ALLOWLIST\" and \"GUILD_DENYLIST\" are mutually exclusive")
+			}
+			(Some(guilds), None) => Ok(Self::Allow(guilds)),
+			(None, Some(guilds)) => Ok(Self::Deny(guilds)),
+			(None, None) => Ok(Self::All),
+		}
+	}
+
+	/// Whether the bot operates in the guild.
+	pub fn allows(&self, guild: Id<GuildMarker>) -> bool {
+		match self {
+			Self::All => true,
+			Self::Allow(guilds) => guilds.contains(&guild),
+			Self::Deny(guilds) => !guilds.contains(&guild),
+		}
+	}
+}
+
+impl fmt::Display for GuildPolicy {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::All => f.write_str("all servers"),
+			Self::Allow(guilds) => write!(f, "{} allowlisted servers", guilds.len()),
+			Self::Deny(guilds) => write!(f, "all but {} denylisted servers", guilds.len()),
+		}
+	}
+}
+
+/// Parses a comma-separated list of IDs from the environment variable.
+pub fn parse<T>(var: &str) -> Result<Option<HashSet<Id<T>>>, anyhow::Error> {
+	let Ok(value) = env::var(var) else {
+		return Ok(None);
+	};
+
+	value
+		.split(',')
+		.map(str::trim)
+		.filter(|id| !id.is_empty())
+		.map(|id| {
+			id.parse()
+				.with_context(|| format!("\"{var}\" contains an invalid ID: \"{id}\""))
+		})
+		.collect::<Result<_, _>>()
+		.map(Some)
+}
@@ -0,0 +1,104 @@
+//! Opt-in presence showing the number of monitored channels, enabled by setting `PRESENCE` to `1`.
+//!
+//! The text is configured through `PRESENCE_TEMPLATE`, replacing `{channels}` and `{guilds}`.
+
+use std::{env, sync::atomic::Ordering, time::Duration};
+
+use twilight_gateway::MessageSender;
+use twilight_model::gateway::{
+	payload::outgoing::{update_presence::UpdatePresencePayload, UpdatePresence},
+	presence::{ActivityType, MinimalActivity, Status},
+	OpCode,
+};
+
+use crate::{BOT, SHUTDOWN};
+
+/// Interval between presence refreshes, well above the gateway's limit of one per 20 seconds.
+const INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Delay of the first refresh, once guilds are likely cached.
+const FIRST_REFRESH: Duration = Duration::from_secs(60);
+
+/// Default [`Presence::template`].
+const TEMPLATE: &str = "{channels} voice channels";
+
+/// Presence configuration.
+#[derive(Debug)]
+pub struct Presence {
+	/// Text of the activity.
+	template: String,
+}
+
+impl Presence {
+	/// Parses the configuration from the environment, `None` if disabled.
+	pub fn from_env() -> Result<Option<Self>, anyhow::Error> {
+		match env::var("PRESENCE").as_deref() {
+			Ok("1") => {}
+			Ok("0") | Err(_) => return Ok(None),
+			Ok(_) => anyhow::bail!("\"PRESENCE\" must be either \"0\" or \"1\""),
+		}
+
+		Ok(Some(Self {
+			template: env::var("PRESENCE_TEMPLATE").unwrap_or_else(|_| TEMPLATE.to_owned()),
+		}))
+	}
+
+	/// Presence with the template filled in from the cache.
+	pub fn payload(&self) -> UpdatePresencePayload {
+		let guilds: Vec<_> = BOT.cache.iter().guilds().map(|guild| guild.id()).collect();
+		let channels: usize = guilds
+			.iter()
+			.filter_map(|&guild| {
+				let channels = BOT.cache.guild_channels(guild)?;
+				Some(crate::monitored(guild, channels.iter().copied()).len())
+			})
+			.sum();
+		let name = self
+			.template
+			.replace("{channels}", &channels.to_string())
+			.replace("{guilds}", &guilds.len().to_string());
+
+		let activity = MinimalActivity {
+			kind: ActivityType::Watching,
+			name,
+			url: None,
+		};
+		UpdatePresencePayload::new([activity.into()], false, None, Status::Online)
+			.expect("one activity")
+	}
+}
+
+/// Periodically refresh the presence until shutdown, if enabled.
+pub async fn run(sender: MessageSender) {
+	let Some(presence) = &BOT.presence else {
+		return;
+	};
+
+	// the presence set by identify is from before any guild was cached
+	let start = tokio::time::Instant::now() + FIRST_REFRESH;
+	let mut interval = tokio::time::interval_at(start, INTERVAL);
+	let mut last = None;
+	loop {
+		interval.tick().await;
+		if SHUTDOWN.load(Ordering::Relaxed) {
+			return;
+		}
+
+		let payload = presence.payload();
+		if last.as_ref() == Some(&payload) {
+			continue;
+		}
+		let command = UpdatePresence {
+			d: payload.clone(),
+			op: OpCode::PresenceUpdate,
+		};
+		if let Err(e) = sender.command(&command) {
+			tracing::warn!(
+				error = &e as &dyn std::error::Error,
+				"unable to update presence"
+			);
+			return;
+		}
+		last = Some(payload);
+	}
+}
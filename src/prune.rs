@@ -1,64 +1,2430 @@
 //! Search through resources for users who should be pruned.
 
-use futures_util::{stream, StreamExt};
+use std::{
+	collections::{HashMap, HashSet},
+	fmt, iter,
+	sync::atomic::{AtomicU16, Ordering},
+	time::Duration,
+};
+
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use tracing::Instrument;
 use twilight_cache_inmemory::model::CachedVoiceState;
+use twilight_http::{
+	api_error::{ApiError, GeneralApiError},
+	error::ErrorType,
+};
 use twilight_model::{
+	channel::{
+		permission_overwrite::{PermissionOverwrite, PermissionOverwriteType},
+		ChannelType,
+	},
 	guild::Permissions,
 	id::{
-		marker::{ChannelMarker, GuildMarker, UserMarker},
+		marker::{ChannelMarker, GuildMarker, RoleMarker, UserMarker},
 		Id,
 	},
 };
+use twilight_util::permission_calculator::PermissionCalculator;
+
+use crate::{
+	clock,
+	error::Error,
+	queue,
+	settings::{Mode, PruneOrder, Settings},
+	voice::{self, PermissionCheck, VoiceStateExt},
+	BOT,
+};
+
+/// Maximum number of users notified through DMs per prune.
+const DM_CAP: u16 = 20;
+
+/// Number of consecutive Missing Permissions failures after which a prune stops, as the bot likely
+/// lost its permissions.
+const DENIAL_LIMIT: u16 = 3;
+
+/// Discord error code of a member that is not in the guild.
+const UNKNOWN_MEMBER: u64 = 10007;
+
+/// Discord error code of missing permissions.
+const MISSING_PERMISSIONS: u64 = 50013;
+
+/// Reason a user could not be removed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Failure {
+	MissingPermissions,
+	/// The removal queue was full or stopped.
+	Busy,
+	/// Any other HTTP error.
+	Other,
+}
+
+impl Failure {
+	/// Classifies the error, or `None` if the user already left the guild, the removal was
+	/// halted or the prune already removed them.
+	pub fn classify(error: &queue::Error) -> Option<Self> {
+		let error = match error {
+			queue::Error::Full | queue::Error::Stopped => return Some(Self::Busy),
+			queue::Error::Halted | queue::Error::Duplicate => return None,
+			queue::Error::Http(e) => e,
+		};
+		match error.kind() {
+			ErrorType::Response {
+				error: ApiError::General(GeneralApiError { code, .. }),
+				..
+			} => match *code {
+				UNKNOWN_MEMBER => None,
+				MISSING_PERMISSIONS => Some(Self::MissingPermissions),
+				_ => Some(Self::Other),
+			},
+			_ => Some(Self::Other),
+		}
+	}
+}
+
+impl fmt::Display for Failure {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::MissingPermissions => "missing permissions",
+			Self::Busy => "removal queue full",
+			Self::Other => "other errors",
+		})
+	}
+}
+
+/// Outcome of removing users from a channel.
+#[derive(Debug, Default)]
+pub struct Removal {
+	pub removed: Vec<Id<UserMarker>>,
+	/// Users that could not be removed, excluding those that already left the guild.
+	pub failed: Vec<(Id<UserMarker>, Failure)>,
+}
+
+/// Users of a channel to prune, or that were pruned.
+#[derive(Clone, Debug)]
+pub struct ChannelPrune {
+	pub channel: Id<ChannelMarker>,
+	/// Kind of the channel when it was searched.
+	pub kind: ChannelKind,
+	pub users: Vec<Id<UserMarker>>,
+}
+
+/// Kind of a pruned channel, as moderation expectations differ between them.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChannelKind {
+	Voice,
+	Stage,
+	/// The channel was not cached, e.g. because it was deleted.
+	Unknown,
+}
+
+impl ChannelKind {
+	/// Every kind, in the order they are listed.
+	pub const ALL: [Self; 3] = [Self::Voice, Self::Stage, Self::Unknown];
+
+	/// Kind of the cached channel.
+	fn of(channel: Id<ChannelMarker>) -> Self {
+		match BOT.cache.channel(channel).map(|channel| channel.kind) {
+			Some(ChannelType::GuildStageVoice) => Self::Stage,
+			Some(_) => Self::Voice,
+			None => Self::Unknown,
+		}
+	}
+}
+
+impl fmt::Display for ChannelKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Voice => "voice",
+			Self::Stage => "stage",
+			Self::Unknown => "unknown",
+		})
+	}
+}
+
+/// Counts by channel kind, e.g. "5 voice, 2 stage", `None` unless there are several kinds.
+pub fn describe_kinds(counts: impl Fn(ChannelKind) -> u32) -> Option<String> {
+	let counts: Vec<_> = ChannelKind::ALL
+		.into_iter()
+		.map(|kind| (kind, counts(kind)))
+		.filter(|&(_, count)| count != 0)
+		.map(|(kind, count)| format!("{count} {kind}"))
+		.collect();
+	(counts.len() > 1).then(|| counts.join(", "))
+}
+
+/// Reason a user found by a prune was kept.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SkipReason {
+	/// Permitted to connect to their channel.
+	Permitted,
+	/// Not matched by the prune, e.g. not idle long enough or without the `/prune` role.
+	Unmatched,
+	/// A bot of a guild ignoring bots.
+	Bot,
+	/// Not outranked by the bot.
+	Hierarchy,
+	/// In the AFK channel of a guild not pruning it.
+	Afk,
+	/// In a channel with voice states without cached members.
+	Stale,
+}
+
+impl SkipReason {
+	pub const ALL: [Self; 6] = [
+		Self::Permitted,
+		Self::Unmatched,
+		Self::Bot,
+		Self::Hierarchy,
+		Self::Afk,
+		Self::Stale,
+	];
+}
+
+impl fmt::Display for SkipReason {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Permitted => "permitted",
+			Self::Unmatched => "not matched",
+			Self::Bot => "bot",
+			Self::Hierarchy => "role hierarchy",
+			Self::Afk => "AFK channel",
+			Self::Stale => "stale cache",
+		})
+	}
+}
+
+/// Outcome of a prune.
+#[derive(Clone, Debug, Default)]
+pub struct Summary {
+	/// Users pruned per channel, omitting channels without any.
+	pub channels: Vec<ChannelPrune>,
+	/// Number of users notified through DMs.
+	notified: u16,
+	/// Number of users in the searched channels.
+	pub checked: u16,
+	/// Number of users kept, indexed by [`SkipReason`].
+	skips: [u16; SkipReason::ALL.len()],
+	/// Number of users not notified because of [`DM_CAP`].
+	pub unnotified: u16,
+	/// Number of users whose permissions were verified against freshly fetched data.
+	pub verified: u16,
+	/// Number of verified users that were permitted after all, and kept.
+	pub spared: u16,
+	/// Number of users not verified because of the bot's verification limit.
+	pub unverified: u16,
+	/// Number of users that moved or left the channel before being removed.
+	pub resolved: u16,
+	/// Whether nobody was removed because the auto prune exceeded the guild's limit.
+	pub aborted: bool,
+	/// Number of consecutive Missing Permissions failures.
+	denials: u16,
+	/// Number of users removed before the prune stopped because of [`DENIAL_LIMIT`].
+	pub lost_permissions: Option<u16>,
+	/// Run started along with the prune, `None` if it never removes users.
+	run: Option<queue::Run>,
+	/// Whether the prune stopped because the guild was halted.
+	pub halted: bool,
+	/// Whether users were only reported, not removed, because of [`Mode::Report`].
+	pub report: bool,
+	/// Users that could not be removed.
+	pub failed: Vec<(Id<UserMarker>, Failure)>,
+}
+
+/// Progress of a running prune, observable while it removes users.
+#[derive(Debug, Default)]
+pub struct Progress {
+	/// Number of users handled so far.
+	done: AtomicU16,
+	/// Number of users to prune, known once searching finished.
+	total: AtomicU16,
+}
+
+impl Progress {
+	/// Number of users handled so far and in total.
+	pub fn get(&self) -> (u16, u16) {
+		(
+			self.done.load(Ordering::Relaxed),
+			self.total.load(Ordering::Relaxed),
+		)
+	}
+
+	/// Adds the users to prune to the total, once per prune sharing the progress.
+	fn start(progress: Option<&Self>, prunes: &[ChannelPrune]) {
+		if let Some(progress) = progress {
+			let total = prunes.iter().map(|prune| prune.users.len() as u16).sum();
+			progress.total.fetch_add(total, Ordering::Relaxed);
+		}
+	}
+
+	/// Counts the channel's users as handled.
+	fn advance(progress: Option<&Self>, users: u16) {
+		if let Some(progress) = progress {
+			progress.done.fetch_add(users, Ordering::Relaxed);
+		}
+	}
+}
+
+/// Options of a `/prune` command.
+#[derive(Debug, Default)]
+pub struct Manual {
+	pub progress: Progress,
+	/// Whether bots are pruned regardless of the guild's settings.
+	pub include_bots: bool,
+}
+
+/// Whether a prune of the guild with the settings considers bots.
+fn includes_bots(settings: &Settings, manual: Option<&Manual>) -> bool {
+	manual.is_some_and(|manual| manual.include_bots) || !settings.ignore_bots
+}
+
+/// Whether the user is a bot.
+fn is_bot(user: Id<UserMarker>) -> bool {
+	BOT.cache.user(user).is_some_and(|user| user.bot)
+}
+
+impl Summary {
+	/// Summary of a prune of the guild starting now.
+	fn new(guild: Id<GuildMarker>) -> Self {
+		Self {
+			run: Some(queue::Run::new(BOT.halts.token(guild))),
+			..Self::default()
+		}
+	}
+
+	/// Summary of a part of the prune run concurrently with others, to [`merge`](Self::merge) once
+	/// done.
+	fn part(&self) -> Self {
+		Self {
+			run: self.run,
+			..Self::default()
+		}
+	}
+
+	/// Combines the summaries of prunes run concurrently, e.g. of several channels.
+	pub fn merge(mut self, other: Self) -> Self {
+		self.channels.extend(other.channels);
+		self.notified += other.notified;
+		self.checked += other.checked;
+		for (skips, other) in self.skips.iter_mut().zip(other.skips) {
+			*skips += other;
+		}
+		self.unnotified += other.unnotified;
+		self.verified += other.verified;
+		self.spared += other.spared;
+		self.unverified += other.unverified;
+		self.resolved += other.resolved;
+		self.aborted |= other.aborted;
+		self.denials = self.denials.max(other.denials);
+		self.lost_permissions = match (self.lost_permissions, other.lost_permissions) {
+			(Some(removed), Some(other)) => Some(removed + other),
+			(removed, other) => removed.or(other),
+		};
+		self.halted |= other.halted;
+		self.report |= other.report;
+		self.failed.extend(other.failed);
+		self
+	}
+
+	/// Run started along with the prune, or now if it never removes users.
+	pub fn run(&self, guild: Id<GuildMarker>) -> queue::Run {
+		self.run
+			.unwrap_or_else(|| queue::Run::new(BOT.halts.token(guild)))
+	}
+
+	/// Whether the guild was halted since the prune started, after which it must stop.
+	pub fn is_halted(&mut self) -> bool {
+		if !self.halted && self.run.is_some_and(queue::Run::is_halted) {
+			tracing::warn!(removed = self.pruned(), "guild halted, stopping the prune");
+			self.halted = true;
+		}
+		self.halted
+	}
+
+	/// Number of users pruned.
+	pub fn pruned(&self) -> u16 {
+		self.channels
+			.iter()
+			.map(|prune| prune.users.len() as u16)
+			.sum()
+	}
+
+	/// Number of users kept for the reason.
+	pub fn skipped(&self, reason: SkipReason) -> u16 {
+		self.skips[reason as usize]
+	}
+
+	/// Counts the users as checked and kept for the reason.
+	fn skip(&mut self, reason: SkipReason, users: u16) {
+		self.checked += users;
+		self.skips[reason as usize] += users;
+	}
+
+	/// Number of users pruned from channels of the kind.
+	pub fn pruned_in(&self, kind: ChannelKind) -> u16 {
+		self.channels
+			.iter()
+			.filter(|prune| prune.kind == kind)
+			.map(|prune| prune.users.len() as u16)
+			.sum()
+	}
 
-use crate::BOT;
+	/// Resets the consecutive Missing Permissions failures after a removal succeeded.
+	pub fn reset_denials(&mut self) {
+		self.denials = 0;
+	}
+
+	/// Counts a Missing Permissions failure, returning whether the prune must stop after
+	/// [`DENIAL_LIMIT`] consecutive ones. `removed` is the number of users removed from the
+	/// current channel so far.
+	pub fn denied(&mut self, removed: usize) -> bool {
+		self.denials += 1;
+		if self.denials < DENIAL_LIMIT {
+			return false;
+		}
+		let removed = self.pruned() + removed as u16;
+		tracing::error!(removed, "lost permissions, aborting the remaining removals");
+		self.lost_permissions = Some(removed);
+		true
+	}
 
-fn is_permitted(state: &CachedVoiceState) -> bool {
+	/// Whether to notify another user, counting them towards [`DM_CAP`].
+	pub fn take_dm(&mut self) -> bool {
+		if self.notified < DM_CAP {
+			self.notified += 1;
+			true
+		} else {
+			if self.unnotified == 0 {
+				tracing::info!("DM cap reached, skipping the remaining DMs");
+			}
+			self.unnotified += 1;
+			false
+		}
+	}
+
+	/// Whether to verify another user, counting them towards the `limit`.
+	pub fn take_verification(&mut self, limit: u16) -> bool {
+		if self.verified < limit {
+			self.verified += 1;
+			true
+		} else {
+			if self.unverified == 0 {
+				tracing::info!("verification limit reached, trusting the cache for the rest");
+			}
+			self.unverified += 1;
+			false
+		}
+	}
+}
+
+/// What caused a prune.
+#[derive(Clone, Copy, Debug)]
+pub enum Trigger {
+	/// A channel's permission overwrites changed.
+	Channel,
+	/// A member's roles changed.
+	Member,
+	/// A role's permissions changed or it was deleted.
+	Role,
+	/// The guild's AFK channel changed.
+	Guild,
+	/// A stage ended.
+	Stage,
+	/// The periodic check for idle users.
+	Idle,
+	/// A command of the control socket.
+	Control,
+	/// A prune scheduled through `/schedule-prune`.
+	Schedule,
+	/// The `/prune` command.
+	Command,
+	/// The `/prune` command, ignoring [`Mode::Report`].
+	Forced,
+}
+
+impl Trigger {
+	/// Whether the prune was triggered by an event, i.e. is an auto prune.
+	pub const fn is_auto(self) -> bool {
+		!matches!(self, Self::Command | Self::Forced)
+	}
+}
+
+impl fmt::Display for Trigger {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Channel => "channel update",
+			Self::Member => "member update",
+			Self::Role => "role update",
+			Self::Guild => "guild update",
+			Self::Stage => "stage end",
+			Self::Idle => "idle",
+			Self::Control => "control socket",
+			Self::Schedule => "scheduled",
+			Self::Command => "command",
+			Self::Forced => "forced command",
+		})
+	}
+}
+
+/// Whether the user is permitted to connect to the channel, see [`voice::can_connect`].
+fn can_connect(user: Id<UserMarker>, channel: Id<ChannelMarker>) -> PermissionCheck {
+	let settings = guild_of(channel)
+		.map(|guild| BOT.settings.get(guild))
+		.unwrap_or_default();
+	voice::can_connect(&BOT.cache, &settings, user, channel)
+}
+
+/// Sorted roles of a member.
+type RoleSet = Box<[Id<RoleMarker>]>;
+
+/// Permission calculations shared between users with the same roles during a prune.
+///
+/// Users that are the guild owner, timed out, have a member overwrite in the channel or roles
+/// missing from the cache fall back to [`voice::can_connect`], so results are identical to it. Users
+/// whose permissions can't be calculated are assumed to be permitted.
+struct Memo {
+	/// Settings of the guild, read once per prune.
+	settings: Settings,
+	/// Guild-level permissions of a role set.
+	roots: HashMap<RoleSet, Option<Permissions>>,
+	/// Permissions of a role set in a channel.
+	channels: HashMap<(Id<ChannelMarker>, RoleSet), Permissions>,
+	/// Highest role position of the bot.
+	bot_position: Option<i64>,
+}
+
+impl Memo {
+	fn new(settings: Settings) -> Self {
+		Self {
+			settings,
+			roots: HashMap::new(),
+			channels: HashMap::new(),
+			bot_position: None,
+		}
+	}
+
+	/// See [`voice::can_connect`].
+	fn fallback(&self, user: Id<UserMarker>, channel: Id<ChannelMarker>) -> bool {
+		!voice::can_connect(&BOT.cache, &self.settings, user, channel).is_denied()
+	}
+
+	/// Whether the user is permitted to connect to the channel, or it's [`PermissionCheck::Unknown`].
+	fn can_connect(&mut self, user: Id<UserMarker>, channel: Id<ChannelMarker>) -> bool {
+		let Some((guild, kind, overwrites)) = BOT.cache.channel(channel).and_then(|channel| {
+			Some((
+				channel.guild_id?,
+				channel.kind,
+				channel.permission_overwrites.clone().unwrap_or_default(),
+			))
+		}) else {
+			return self.fallback(user, channel);
+		};
+		let Some((mut roles, timed_out, pending)) = BOT.cache.member(guild, user).map(|member| {
+			(
+				RoleSet::from(member.roles()),
+				member.communication_disabled_until().is_some(),
+				member.pending(),
+			)
+		}) else {
+			return self.fallback(user, channel);
+		};
+		if pending && self.settings.prune_pending {
+			return false;
+		}
+		let has_overwrite = overwrites.iter().any(|overwrite| {
+			overwrite.kind == PermissionOverwriteType::Member && overwrite.id == user.cast()
+		});
+		if voice::is_owner(&BOT.cache, guild, user) || timed_out || has_overwrite {
+			return self.fallback(user, channel);
+		}
+
+		// the calculation is independent of role order
+		roles.sort_unstable();
+		let Some(root) = *self
+			.roots
+			.entry(roles.clone())
+			.or_insert_with_key(|roles| root(guild, user, roles))
+		else {
+			return self.fallback(user, channel);
+		};
+		if root.contains(Permissions::ADMINISTRATOR) {
+			return true;
+		}
+		self.channels
+			.entry((channel, roles))
+			.or_insert_with_key(|(_, roles)| {
+				in_channel(guild, user, root, roles, kind, &overwrites)
+			})
+			.contains(Permissions::CONNECT)
+	}
+
+	/// Whether the user may be pruned from the channel, checking the cheaper `matched` filter of
+	/// the prune before their permissions, or why they are kept.
+	fn check(
+		&mut self,
+		user: Id<UserMarker>,
+		channel: Id<ChannelMarker>,
+		matched: impl FnOnce() -> bool,
+	) -> Result<(), SkipReason> {
+		if !matched() {
+			Err(SkipReason::Unmatched)
+		} else if self.can_connect(user, channel) {
+			Err(SkipReason::Permitted)
+		} else {
+			Ok(())
+		}
+	}
+
+	/// Whether the bot outranks the user, i.e. the user is not an administrator and their
+	/// highest role is below the bot's. `false` if the user's roles are not cached.
+	fn outranks(&mut self, guild: Id<GuildMarker>, user: Id<UserMarker>) -> bool {
+		if voice::is_owner(&BOT.cache, guild, user) {
+			return false;
+		}
+
+		let Some(mut roles) = BOT
+			.cache
+			.member(guild, user)
+			.map(|member| RoleSet::from(member.roles()))
+		else {
+			return false;
+		};
+		roles.sort_unstable();
+		let position = highest_position(&roles);
+		let Some(root) = *self
+			.roots
+			.entry(roles)
+			.or_insert_with_key(|roles| root(guild, user, roles))
+		else {
+			return false;
+		};
+		if root.contains(Permissions::ADMINISTRATOR) {
+			return false;
+		}
+
+		position
+			< *self.bot_position.get_or_insert_with(|| {
+				BOT.cache
+					.member(guild, BOT.id)
+					.map_or(0, |member| highest_position(member.roles()))
+			})
+	}
+}
+
+/// Position of the highest cached role, `0` for none (the `@everyone` role).
+pub fn highest_position(roles: &[Id<RoleMarker>]) -> i64 {
+	roles
+		.iter()
+		.filter_map(|&role| Some(BOT.cache.role(role)?.position))
+		.max()
+		.unwrap_or_default()
+}
+
+/// Guild-level permissions of a member with the roles, `None` if the roles or the guild's
+/// `@everyone` role are not cached.
+fn root(
+	guild: Id<GuildMarker>,
+	user: Id<UserMarker>,
+	roles: &[Id<RoleMarker>],
+) -> Option<Permissions> {
+	let permissions = |role| Some(BOT.cache.role(role)?.permissions);
+	let assigned = roles
+		.iter()
+		.map(|&id| Some((id, permissions(id)?)))
+		.collect::<Option<Vec<_>>>()?;
+	Some(PermissionCalculator::new(guild, user, permissions(guild.cast())?, &assigned).root())
+}
+
+/// Permissions in a channel of a member with the roles and guild-level permissions.
+fn in_channel(
+	guild: Id<GuildMarker>,
+	user: Id<UserMarker>,
+	root: Permissions,
+	roles: &[Id<RoleMarker>],
+	kind: ChannelType,
+	overwrites: &[PermissionOverwrite],
+) -> Permissions {
+	// `root` already includes the roles' permissions
+	let assigned: Vec<_> = roles.iter().map(|&id| (id, Permissions::empty())).collect();
+	PermissionCalculator::new(guild, user, root, &assigned).in_channel(kind, overwrites)
+}
+
+/// Whether the user is permitted to connect to the channel according to their freshly fetched
+/// member, calculated like [`Memo::can_connect`].
+///
+/// Users with roles missing from the cache, a sign of it being stale, are conservatively permitted.
+/// Fetch errors are logged and trust the cache, i.e. the user is not permitted.
+pub async fn verify(
+	guild: Id<GuildMarker>,
+	user: Id<UserMarker>,
+	channel: Id<ChannelMarker>,
+) -> bool {
+	let member = match BOT.http.guild_member(guild, user).await {
+		Ok(response) => match response.model().await {
+			Ok(member) => member,
+			Err(e) => {
+				tracing::warn!(user_id = %user, error = &e as &dyn std::error::Error, "unable to verify");
+				return false;
+			}
+		},
+		Err(e) => {
+			tracing::warn!(user_id = %user, error = &e as &dyn std::error::Error, "unable to verify");
+			return false;
+		}
+	};
+	if voice::is_owner(&BOT.cache, guild, user) {
+		return true;
+	}
+	if let Some(role) = iter::once(guild.cast())
+		.chain(member.roles.iter().copied())
+		.find(|&role| BOT.cache.role(role).is_none())
+	{
+		tracing::info!(user_id = %user, role_id = %role, "role missing from the cache, keeping user");
+		return true;
+	}
+	let Some((kind, overwrites)) = BOT.cache.channel(channel).map(|channel| {
+		(
+			channel.kind,
+			channel.permission_overwrites.clone().unwrap_or_default(),
+		)
+	}) else {
+		return false;
+	};
+
+	let Some(root) = root(guild, user, &member.roles) else {
+		return true;
+	};
+	let now = clock::now().as_micros();
+	let timed_out = member
+		.communication_disabled_until
+		.is_some_and(|until| i128::from(until.as_micros()) > now as i128);
+	if root.contains(Permissions::ADMINISTRATOR) {
+		return true;
+	}
+	if timed_out {
+		return false;
+	}
+
+	in_channel(guild, user, root, &member.roles, kind, &overwrites).contains(Permissions::CONNECT)
+}
+
+/// Closest voice channel in the same category as `channel` that the user is permitted to connect
+/// to and that has room for them.
+///
+/// `moved` returns the number of users moved to a channel that are not yet reflected in the cache.
+pub fn soft_target(
+	channel: Id<ChannelMarker>,
+	user: Id<UserMarker>,
+	moved: impl Fn(Id<ChannelMarker>) -> usize,
+) -> Option<Id<ChannelMarker>> {
+	let (guild, parent, position) = {
+		let source = BOT.cache.channel(channel)?;
+		(
+			source.guild_id?,
+			source.parent_id,
+			source.position.unwrap_or_default(),
+		)
+	};
+
+	let settings = BOT.settings.get(guild);
+	let mut siblings: Vec<_> = BOT
+		.cache
+		.guild_channels(guild)?
+		.iter()
+		.filter_map(|&id| {
+			let sibling = BOT.cache.channel(id)?;
+			(id != channel && sibling.parent_id == parent && BOT.monitors(&settings, sibling.kind))
+				.then(|| (id, sibling.position.unwrap_or_default(), sibling.user_limit))
+		})
+		.collect();
+	siblings.sort_unstable_by_key(|&(id, sibling, _)| (sibling.abs_diff(position), id));
+
+	siblings.into_iter().find_map(|(id, _, user_limit)| {
+		// a limit of zero means unlimited
+		let has_room = user_limit
+			.filter(|&limit| limit != 0)
+			.map_or(true, |limit| {
+				let connected = BOT
+					.cache
+					.voice_channel_states(id)
+					.map_or(0, Iterator::count);
+				connected + moved(id) < limit as usize
+			});
+		(has_room && can_connect(user, id) == PermissionCheck::Permitted).then_some(id)
+	})
+}
+
+/// Whether the user is still connected to the channel, counting them as resolved otherwise.
+///
+/// Users may move between being found and removed, and removing them would disconnect them from
+/// their new channel.
+pub fn is_still_connected(
+	guild: Id<GuildMarker>,
+	user: Id<UserMarker>,
+	channel: Id<ChannelMarker>,
+	summary: &mut Summary,
+) -> bool {
+	let connected = BOT
+		.cache
+		.voice_state(user, guild)
+		.is_some_and(|state| state.channel_id() == channel);
+	if !connected {
+		tracing::debug!(user_id = %user, "skipping: moved or left");
+		summary.resolved += 1;
+	}
+	connected
+}
+
+/// Moves users to the closest permitted sibling voice channel, removing users without one.
+///
+/// Returns the users moved or removed.
+async fn relocate(
+	guild: Id<GuildMarker>,
+	channel: Id<ChannelMarker>,
+	users: Vec<Id<UserMarker>>,
+	trigger: Trigger,
+	summary: &mut Summary,
+) -> Removal {
+	let mut moved = HashMap::<_, usize>::new();
+	let mut removal = Removal::default();
+	for user in users {
+		if summary.is_halted() {
+			break;
+		}
+		if !is_still_connected(guild, user, channel, summary) {
+			continue;
+		}
+		let target = soft_target(channel, user, |id| {
+			moved.get(&id).copied().unwrap_or_default()
+		});
+		let run = summary.run(guild);
+		match BOT
+			.move_member(guild, user, channel, target, trigger, run)
+			.await
+		{
+			Ok(()) => {
+				summary.reset_denials();
+				removal.removed.push(user);
+				if let Some(target) = target {
+					*moved.entry(target).or_default() += 1;
+				}
+			}
+			Err(e) => {
+				let failure = Failure::classify(&e);
+				removal
+					.failed
+					.extend(failure.map(|failure| (user, failure)));
+				if failure == Some(Failure::MissingPermissions)
+					&& summary.denied(removal.removed.len())
+				{
+					break;
+				}
+			}
+		}
+	}
+
+	removal
+}
+
+/// Removes the users from their channel, relocating them instead on soft auto prunes.
+///
+/// Adds the pruned users to the summary.
+async fn remove(
+	guild: Id<GuildMarker>,
+	trigger: Trigger,
+	ChannelPrune {
+		channel,
+		kind,
+		users,
+	}: ChannelPrune,
+	summary: &mut Summary,
+	progress: Option<&Progress>,
+) {
+	let handled = users.len() as u16;
+	let span = tracing::info_span!(
+		"remove",
+		guild_id = %guild,
+		channel_id = %channel,
+		trigger = %trigger,
+		removed = tracing::field::Empty,
+		failed = tracing::field::Empty,
+	);
+	if summary.lost_permissions.is_some() || summary.is_halted() {
+		Progress::advance(progress, handled);
+		return;
+	}
+	if !matches!(trigger, Trigger::Forced) && BOT.settings.get(guild).mode == Mode::Report {
+		summary.report = true;
+		summary.channels.push(ChannelPrune {
+			channel,
+			kind,
+			users,
+		});
+		Progress::advance(progress, handled);
+		return;
+	}
+
+	// idle users are disconnected rather than relocated
+	let relocates = trigger.is_auto() && !matches!(trigger, Trigger::Idle);
+	let Removal { removed, failed } = if relocates && BOT.settings.get(guild).soft_prune {
+		relocate(guild, channel, users, trigger, summary)
+			.instrument(span.clone())
+			.await
+	} else {
+		BOT.remove(guild, channel, users, trigger, summary)
+			.instrument(span.clone())
+			.await
+	};
+	Progress::advance(progress, handled);
+	span.record("removed", removed.len());
+	span.record("failed", failed.len());
+	summary.failed.extend(failed);
+	if !removed.is_empty() {
+		summary.channels.push(ChannelPrune {
+			channel,
+			kind,
+			users: removed,
+		});
+	}
+}
+
+/// Guild of the cached channel.
+fn guild_of(channel: Id<ChannelMarker>) -> Option<Id<GuildMarker>> {
+	BOT.cache.channel(channel)?.guild_id
+}
+
+/// Whether every user in the channel has a cached member, i.e. the channel's voice states may be
+/// acted upon.
+fn is_consistent(channel: Id<ChannelMarker>) -> bool {
 	BOT.cache
-		.permissions()
-		.in_channel(state.user_id(), state.channel_id())
-		.expect("resources are available")
-		.contains(Permissions::CONNECT)
+		.voice_channel_states(channel)
+		.map_or(true, |mut states| {
+			states.all(|state| {
+				BOT.cache
+					.member(state.guild_id(), state.user_id())
+					.is_some()
+			})
+		})
+}
+
+/// Number of users in the channel.
+fn voice_users(channel: Id<ChannelMarker>) -> u16 {
+	BOT.cache
+		.voice_channel_states(channel)
+		.map_or(0, |states| states.count() as u16)
+}
+
+/// Users in the monitored channel where the `prunable` closure returns `Ok`, skipping bots unless
+/// `bots` is set.
+///
+/// Users the bot does not outrank are skipped, and every kept user is counted in the summary by
+/// their [`SkipReason`]. Channels with stale voice states are skipped entirely.
+fn search<F>(
+	channel: Id<ChannelMarker>,
+	memo: &mut Memo,
+	summary: &mut Summary,
+	bots: bool,
+	mut prunable: F,
+) -> ChannelPrune
+where
+	F: FnMut(&mut Memo, &CachedVoiceState) -> Result<(), SkipReason>,
+{
+	if !BOT.is_monitored_with(&memo.settings, channel) {
+		return ChannelPrune {
+			channel,
+			kind: ChannelKind::of(channel),
+			users: Vec::new(),
+		};
+	}
+
+	if BOT.is_afk(channel) && !memo.settings.prune_afk {
+		tracing::debug!(channel_id = %channel, "skipping: AFK channel");
+		summary.skip(SkipReason::Afk, voice_users(channel));
+		return ChannelPrune {
+			channel,
+			kind: ChannelKind::of(channel),
+			users: Vec::new(),
+		};
+	}
+
+	if !is_consistent(channel) {
+		tracing::warn!(channel_id = %channel, "skipping: voice states without cached members");
+		summary.skip(SkipReason::Stale, voice_users(channel));
+		return ChannelPrune {
+			channel,
+			kind: ChannelKind::of(channel),
+			users: Vec::new(),
+		};
+	}
+
+	let mut users: Vec<_> = BOT
+		.cache
+		.voice_channel_states(channel)
+		.map_or(Vec::new(), |states| {
+			states
+				.filter_map(|state| {
+					let reason = if !bots && is_bot(state.user_id()) {
+						tracing::debug!(user_id = %state.user_id(), "skipping: bot");
+						SkipReason::Bot
+					} else if let Err(reason) = prunable(memo, &state) {
+						reason
+					} else if memo.outranks(state.guild_id(), state.user_id()) {
+						summary.checked += 1;
+						return Some(state.user_id());
+					} else {
+						tracing::debug!(user_id = %state.user_id(), "skipping: role hierarchy");
+						SkipReason::Hierarchy
+					};
+					summary.skip(reason, 1);
+					None
+				})
+				.collect()
+		});
+	if memo.settings.prune_order == PruneOrder::RolePosition {
+		if let Some(guild) = guild_of(channel) {
+			order(guild, &mut users);
+		}
+	}
+
+	ChannelPrune {
+		channel,
+		kind: ChannelKind::of(channel),
+		users,
+	}
+}
+
+/// Sorts the users by their highest role position, lowest first, then by their ID.
+fn order(guild: Id<GuildMarker>, users: &mut [Id<UserMarker>]) {
+	users.sort_by_cached_key(|&user| {
+		let position = BOT
+			.cache
+			.member(guild, user)
+			.map_or(0, |member| highest_position(member.roles()));
+		(position, user)
+	});
+}
+
+/// Whether the auto prune would remove more users than the guild's limit, and must be aborted.
+fn aborts(
+	guild: Id<GuildMarker>,
+	trigger: Trigger,
+	prunes: &[ChannelPrune],
+	summary: &mut Summary,
+) -> bool {
+	let Some(limit) = BOT
+		.settings
+		.get(guild)
+		.max_auto_prune
+		.filter(|_| trigger.is_auto())
+	else {
+		return false;
+	};
+	let users: usize = prunes.iter().map(|prune| prune.users.len()).sum();
+	if users <= limit as usize {
+		return false;
+	}
+
+	tracing::warn!(guild_id = %guild, %trigger, users, limit, "aborting auto prune exceeding the limit, run /prune to proceed");
+	summary.aborted = true;
+	true
+}
+
+/// Records the finished prune, logging the outcome of auto prunes and recording its counts on the
+/// prune's span.
+fn finish(guild: Id<GuildMarker>, trigger: Trigger, summary: &Summary) {
+	let span = tracing::Span::current();
+	span.record("pruned", summary.pruned());
+	span.record("failed", summary.failed.len());
+	BOT.totals
+		.prune(if summary.report { 0 } else { summary.pruned() });
+	if summary.report {
+		for prune in &summary.channels {
+			tracing::info!(
+				guild_id = %guild,
+				channel_id = %prune.channel,
+				%trigger,
+				users = ?prune.users,
+				"report mode, would have pruned"
+			);
+		}
+		return;
+	}
+
+	if trigger.is_auto() {
+		for prune in &summary.channels {
+			tracing::info!(
+				guild_id = %guild,
+				channel_id = %prune.channel,
+				kind = %prune.kind,
+				%trigger,
+				users = prune.users.len(),
+				"pruned"
+			);
+		}
+		for reason in [SkipReason::Bot, SkipReason::Hierarchy, SkipReason::Stale] {
+			let skipped = summary.skipped(reason);
+			if skipped != 0 {
+				tracing::info!(guild_id = %guild, %trigger, skipped, %reason, "kept");
+			}
+		}
+		if summary.spared != 0 {
+			tracing::info!(guild_id = %guild, %trigger, spared = summary.spared, "permitted according to fresh data");
+		}
+		if !summary.failed.is_empty() {
+			tracing::info!(guild_id = %guild, %trigger, failed = summary.failed.len(), "unable to remove");
+		}
+	}
+	BOT.history.record(guild, trigger, summary);
+	BOT.stats.record(guild, trigger, summary);
+	crate::status::update(guild, summary);
 }
 
 /// Prune users in the channel that are not permitted and where the `kick` closure returns `true`.
-pub async fn channel<F>(channel: Id<ChannelMarker>, guild: Id<GuildMarker>, kick: F) -> u16
+#[tracing::instrument(skip_all, fields(guild_id = %guild, channel_id = %channel, %trigger, pruned, failed))]
+pub async fn channel<F>(
+	channel: Id<ChannelMarker>,
+	guild: Id<GuildMarker>,
+	trigger: Trigger,
+	kick: F,
+	manual: Option<&Manual>,
+) -> Summary
 where
 	F: Fn(&CachedVoiceState) -> bool,
 {
-	let users = BOT
-		.is_monitored(channel)
-		.then(|| {
-			BOT.cache
-				.voice_channel_states(channel)
-				.map_or(Vec::new(), |states| {
-					states
-						.into_iter()
-						.filter_map(|state| {
-							(!is_permitted(&state) && kick(&state)).then(|| state.user_id())
-						})
-						.collect()
+	let mut memo = Memo::new(BOT.settings.get(guild));
+	let mut summary = Summary::new(guild);
+	let bots = includes_bots(&memo.settings, manual);
+	let prune = search(channel, &mut memo, &mut summary, bots, |memo, state| {
+		memo.check(state.user_id(), channel, || kick(state))
+	});
+	if aborts(guild, trigger, std::slice::from_ref(&prune), &mut summary) {
+		return summary;
+	}
+	let progress = manual.map(|manual| &manual.progress);
+	Progress::start(progress, std::slice::from_ref(&prune));
+	remove(guild, trigger, prune, &mut summary, progress).await;
+	finish(guild, trigger, &summary);
+	summary
+}
+
+/// Prune users in the stage channel that are not permitted, or if `audience` is set, that aren't
+/// stage moderators.
+#[tracing::instrument(skip_all, fields(guild_id = %guild, channel_id = %channel, audience, pruned, failed))]
+pub async fn stage(channel: Id<ChannelMarker>, guild: Id<GuildMarker>, audience: bool) -> Summary {
+	let trigger = Trigger::Stage;
+	let mut memo = Memo::new(BOT.settings.get(guild));
+	let mut summary = Summary::new(guild);
+	let bots = includes_bots(&memo.settings, None);
+	let prune = search(channel, &mut memo, &mut summary, bots, |memo, state| {
+		if memo.can_connect(state.user_id(), channel) && (!audience || is_stage_moderator(state)) {
+			Err(SkipReason::Permitted)
+		} else {
+			Ok(())
+		}
+	});
+	if aborts(guild, trigger, std::slice::from_ref(&prune), &mut summary) {
+		return summary;
+	}
+	remove(guild, trigger, prune, &mut summary, None).await;
+	finish(guild, trigger, &summary);
+	summary
+}
+
+/// Whether the user moderates their stage channel, assuming so if unknown.
+fn is_stage_moderator(state: &CachedVoiceState) -> bool {
+	const MODERATOR: Permissions = Permissions::MANAGE_CHANNELS
+		.union(Permissions::MOVE_MEMBERS)
+		.union(Permissions::MUTE_MEMBERS);
+
+	!state.is_permitted(&BOT.cache, MODERATOR).is_denied()
+}
+
+/// Prune users in the channels that are not permitted and where the `kick` closure returns `true`
+/// for their channel, as one prune removing from the channels concurrently.
+#[tracing::instrument(skip_all, fields(guild_id = %guild, channels = channels.len(), %trigger, pruned, failed))]
+pub async fn channels<F>(
+	channels: &[Id<ChannelMarker>],
+	guild: Id<GuildMarker>,
+	trigger: Trigger,
+	kick: F,
+	manual: Option<&Manual>,
+) -> Summary
+where
+	F: Fn(Id<ChannelMarker>, &CachedVoiceState) -> bool,
+{
+	let mut memo = Memo::new(BOT.settings.get(guild));
+	let mut summary = Summary::new(guild);
+	let bots = includes_bots(&memo.settings, manual);
+	let prunes = distinct(channels.iter().map(|&channel| {
+		search(channel, &mut memo, &mut summary, bots, |memo, state| {
+			memo.check(state.user_id(), channel, || kick(channel, state))
+		})
+	}));
+	if aborts(guild, trigger, &prunes, &mut summary) {
+		return summary;
+	}
+	let progress = manual.map(|manual| &manual.progress);
+	Progress::start(progress, &prunes);
+	let parts = join_all(prunes.into_iter().map(|prune| {
+		let mut part = summary.part();
+		async move {
+			remove(guild, trigger, prune, &mut part, progress).await;
+			part
+		}
+	}))
+	.await;
+	let summary = parts.into_iter().fold(summary, Summary::merge);
+	finish(guild, trigger, &summary);
+	summary
+}
+
+/// Users in the guild that are not permitted and where the `kick` closure returns `true`, or `None`
+/// if the guild is not cached.
+fn unpermitted<F>(
+	guild: Id<GuildMarker>,
+	channels: Option<&[Id<ChannelMarker>]>,
+	summary: &mut Summary,
+	manual: Option<&Manual>,
+	kick: F,
+) -> Option<Vec<ChannelPrune>>
+where
+	F: Fn(&CachedVoiceState) -> bool + Copy,
+{
+	let mut memo = Memo::new(BOT.settings.get(guild));
+	let bots = includes_bots(&memo.settings, manual);
+	Some(distinct(
+		BOT.cache
+			.guild_channels(guild)?
+			.iter()
+			.filter(|id| channels.map_or(true, |channels| channels.contains(id)))
+			.map(|&id| {
+				search(id, &mut memo, summary, bots, |memo, state| {
+					memo.check(state.user_id(), id, || kick(state))
 				})
+			}),
+	))
+}
+
+/// The channel prunes without empty ones, keeping users only in the first channel they were found
+/// in so that they are removed and counted once, e.g. if they moved while the channels were
+/// searched.
+fn distinct(prunes: impl IntoIterator<Item = ChannelPrune>) -> Vec<ChannelPrune> {
+	let mut seen = HashSet::new();
+	let mut duplicates = 0;
+	let prunes = prunes
+		.into_iter()
+		.filter_map(|mut prune| {
+			let found = prune.users.len();
+			prune.users.retain(|&user| seen.insert(user));
+			duplicates += found - prune.users.len();
+			(!prune.users.is_empty()).then_some(prune)
 		})
-		.unwrap_or_default();
+		.collect();
+	if duplicates != 0 {
+		tracing::debug!(duplicates, "collapsed users found in several channels");
+	}
+	prunes
+}
 
-	BOT.remove(guild, users.into_iter()).await
+/// Users a prune of the channel would currently remove, without removing them.
+pub fn channel_offenders(channel: Id<ChannelMarker>) -> Vec<Id<UserMarker>> {
+	let Some(guild) = guild_of(channel) else {
+		return Vec::new();
+	};
+	let mut memo = Memo::new(BOT.settings.get(guild));
+	let bots = includes_bots(&memo.settings, None);
+	search(
+		channel,
+		&mut memo,
+		&mut Summary::default(),
+		bots,
+		|memo, state| memo.check(state.user_id(), channel, || true),
+	)
+	.users
 }
 
-/// Prune users in the guild that are not permitted and where the `kick` closure returns `true`.
-pub async fn guild<F>(guild: Id<GuildMarker>, kick: F) -> u16
+/// Users a guild prune would currently remove, without removing them, or `None` if the guild is
+/// not cached.
+pub fn offenders(guild: Id<GuildMarker>) -> Option<Vec<ChannelPrune>> {
+	unpermitted(guild, None, &mut Summary::default(), None, |_| true)
+}
+
+/// Prune users in the guild that are not permitted and where the `kick` closure returns `true`,
+/// only in the `channels` if given.
+#[tracing::instrument(skip_all, fields(guild_id = %guild, %trigger, pruned, failed))]
+pub async fn guild<F>(
+	guild: Id<GuildMarker>,
+	trigger: Trigger,
+	channels: Option<&[Id<ChannelMarker>]>,
+	kick: F,
+	manual: Option<&Manual>,
+) -> Result<Summary, Error>
 where
 	F: Fn(&CachedVoiceState) -> bool + Copy,
 {
-	let channels = BOT.cache.guild_channels(guild).expect("cached");
+	let mut summary = Summary::new(guild);
+	let prunes =
+		unpermitted(guild, channels, &mut summary, manual, kick).ok_or(Error::CacheMiss {
+			resource: "guild",
+			id: guild.get(),
+		})?;
 
-	stream::iter(channels.iter())
-		.map(|&id| channel(id, guild, kick))
-		.fold(0, |a, b| async move { a + b.await })
-		.await
+	if aborts(guild, trigger, &prunes, &mut summary) {
+		return Ok(summary);
+	}
+	let progress = manual.map(|manual| &manual.progress);
+	Progress::start(progress, &prunes);
+	for prune in prunes {
+		remove(guild, trigger, prune, &mut summary, progress).await;
+	}
+
+	finish(guild, trigger, &summary);
+	Ok(summary)
+}
+
+/// Prune users in the guild deafened for at least `threshold` and where the `kick` closure returns
+/// `true`.
+#[tracing::instrument(skip_all, fields(guild_id = %guild, ?threshold, %trigger, pruned, failed))]
+pub async fn idle<F>(
+	guild: Id<GuildMarker>,
+	threshold: Duration,
+	trigger: Trigger,
+	kick: F,
+	manual: Option<&Manual>,
+) -> Result<Summary, Error>
+where
+	F: Fn(&CachedVoiceState) -> bool,
+{
+	let mut memo = Memo::new(BOT.settings.get(guild));
+	let mut summary = Summary::new(guild);
+	let bots = includes_bots(&memo.settings, manual);
+	let prunes = distinct(
+		BOT.cache
+			.guild_channels(guild)
+			.ok_or(Error::CacheMiss {
+				resource: "guild",
+				id: guild.get(),
+			})?
+			.iter()
+			.map(|&id| {
+				search(id, &mut memo, &mut summary, bots, |_, state| {
+					let idle = BOT
+						.deafened
+						.duration(guild, state.user_id())
+						.is_some_and(|duration| duration >= threshold);
+					if idle && kick(state) {
+						Ok(())
+					} else {
+						Err(SkipReason::Unmatched)
+					}
+				})
+			}),
+	);
+
+	if aborts(guild, trigger, &prunes, &mut summary) {
+		return Ok(summary);
+	}
+	let progress = manual.map(|manual| &manual.progress);
+	Progress::start(progress, &prunes);
+	for prune in prunes {
+		remove(guild, trigger, prune, &mut summary, progress).await;
+	}
+
+	finish(guild, trigger, &summary);
+	Ok(summary)
 }
 
-pub async fn user(guild: Id<GuildMarker>, user: Id<UserMarker>) {
-	if matches!(BOT.cache.voice_state(user, guild), Some(state) if !is_permitted(&state)) {
-		BOT.remove(guild, Some(user)).await;
+#[tracing::instrument(skip_all, fields(guild_id = %guild, user_id = %user, pruned, failed))]
+pub async fn user(guild: Id<GuildMarker>, user: Id<UserMarker>) -> Summary {
+	let settings = BOT.settings.get(guild);
+	let channel = match BOT.cache.voice_state(user, guild) {
+		Some(state) if state.is_denied(&BOT.cache, &settings) => state.channel_id(),
+		_ => return Summary::default(),
+	};
+	if BOT.is_afk(channel) && !settings.prune_afk {
+		return Summary::default();
+	}
+	if !settings.is_auto_channel(channel) {
+		tracing::debug!(user_id = %user, "skipping: channel not selected for auto pruning");
+		return Summary::default();
+	}
+	if !includes_bots(&settings, None) && is_bot(user) {
+		tracing::debug!(user_id = %user, "skipping: bot");
+		return Summary::default();
+	}
+
+	let mut summary = Summary::new(guild);
+	if Memo::new(settings).outranks(guild, user) {
+		summary.checked += 1;
+		let prune = ChannelPrune {
+			channel,
+			kind: ChannelKind::of(channel),
+			users: vec![user],
+		};
+		remove(guild, Trigger::Member, prune, &mut summary, None).await;
+	} else {
+		summary.skip(SkipReason::Hierarchy, 1);
+	}
+	finish(guild, Trigger::Member, &summary);
+	summary
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		collections::HashMap,
+		fmt,
+		sync::{Arc, Mutex},
+	};
+
+	use tracing::{
+		field::{Field, Visit},
+		span::{self, Attributes, Record},
+		Instrument, Subscriber,
+	};
+	use tracing_subscriber::{
+		layer::{Context, SubscriberExt},
+		registry::LookupSpan,
+		Layer,
+	};
+	use twilight_model::{
+		channel::Channel,
+		channel::ChannelType,
+		gateway::payload::incoming::{ChannelDelete, RoleDelete, VoiceStateUpdate},
+		guild::Permissions,
+		id::{
+			marker::{ChannelMarker, GuildMarker, UserMarker},
+			Id,
+		},
+	};
+
+	use super::{
+		aborts, offenders, root, soft_target, ChannelKind, ChannelPrune, Manual, Memo, SkipReason,
+		Summary, Trigger, DENIAL_LIMIT,
+	};
+	use crate::{
+		settings::{PruneOrder, Settings},
+		test::{member_overwrite, role_overwrite, Guild},
+		voice, BOT,
+	};
+
+	const GUILD: u64 = 3620000;
+
+	/// Guild whose member `GUILD + 100`, connected to `GUILD + 10`, has the uncached role
+	/// `GUILD + 2`, and where `@everyone` may not connect.
+	fn uncached_role() -> Id<GuildMarker> {
+		Guild::new(GUILD)
+			.voice(
+				GUILD + 10,
+				&[role_overwrite(
+					GUILD,
+					Permissions::empty(),
+					Permissions::CONNECT,
+				)],
+			)
+			.member(GUILD + 100, &[GUILD + 2])
+			.connect(GUILD + 100, GUILD + 10)
+			.create()
+	}
+
+	#[test]
+	fn root_requires_cached_roles() {
+		let guild = uncached_role();
+		let user = Id::new(GUILD + 100);
+
+		assert_eq!(root(guild, user, &[Id::new(GUILD + 2)]), None);
+		assert_eq!(
+			root(guild, user, &[]),
+			Some(Permissions::VIEW_CHANNEL | Permissions::CONNECT)
+		);
+	}
+
+	#[test]
+	fn memo_is_conservative_with_uncached_roles() {
+		uncached_role();
+		let mut memo = Memo::new(BOT.settings.get(Id::new(GUILD)));
+
+		assert!(memo.can_connect(Id::new(GUILD + 100), Id::new(GUILD + 10)));
+		assert!(!memo.outranks(Id::new(GUILD), Id::new(GUILD + 100)));
+	}
+
+	#[test]
+	fn auto_prune_skips_uncached_roles() {
+		let guild = Guild::new(GUILD + 1000).create();
+		BOT.cache.update(&RoleDelete {
+			guild_id: guild,
+			role_id: Id::new(GUILD + 1001),
+		});
+
+		assert!(BOT.auto_prune(guild));
+	}
+
+	/// Guild with `channels` voice channels of `users` users each, spread over five role sets and
+	/// every kind of channel overwrite.
+	fn crowded(id: u64, channels: u64, users: u64) -> Id<GuildMarker> {
+		let mut guild = Guild::new(id)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.role(id + 2, Permissions::CONNECT, 1)
+			.role(id + 3, Permissions::empty(), 2)
+			.role(id + 4, Permissions::ADMINISTRATOR, 3)
+			.owner(id + 100);
+		for channel in 0..channels {
+			let channel = id + 10 + channel;
+			let overwrites = match channel % 4 {
+				0 => vec![],
+				1 => vec![role_overwrite(
+					id + 3,
+					Permissions::CONNECT,
+					Permissions::empty(),
+				)],
+				2 => vec![role_overwrite(
+					id + 2,
+					Permissions::empty(),
+					Permissions::CONNECT,
+				)],
+				_ => vec![member_overwrite(
+					id + 100 + channel,
+					Permissions::CONNECT,
+					Permissions::empty(),
+				)],
+			};
+			guild = guild.voice(channel, &overwrites);
+		}
+		let mut user = id + 100;
+		for channel in 0..channels {
+			for _ in 0..users {
+				let roles: &[u64] = match user % 5 {
+					0 => &[],
+					1 => &[id + 2],
+					2 => &[id + 3],
+					3 => &[id + 2, id + 3],
+					_ => &[id + 4],
+				};
+				guild = guild.member(user, roles);
+				if user % 7 == 0 {
+					guild = guild.pending();
+				}
+				guild = guild.connect(user, id + 10 + channel);
+				user += 1;
+			}
+		}
+		guild.create()
+	}
+
+	/// Users connected to the guild with their channel, by user ID.
+	fn connected(guild: Id<GuildMarker>) -> Vec<(Id<UserMarker>, Id<ChannelMarker>)> {
+		let mut states: Vec<_> = BOT
+			.cache
+			.guild_voice_states(guild)
+			.expect("cached")
+			.iter()
+			.map(|&user| {
+				let state = BOT.cache.voice_state(user, guild).expect("cached");
+				(user, state.channel_id())
+			})
+			.collect();
+		states.sort_unstable();
+		states
+	}
+
+	/// Whether each user may connect, according to one memo shared between them.
+	fn memoized(
+		states: &[(Id<UserMarker>, Id<ChannelMarker>)],
+		settings: &Settings,
+	) -> Vec<(Id<UserMarker>, bool)> {
+		let mut memo = Memo::new(settings.clone());
+		states
+			.iter()
+			.map(|&(user, channel)| (user, memo.can_connect(user, channel)))
+			.collect()
+	}
+
+	/// Whether each user may connect, calculated from scratch.
+	fn uncached(
+		states: &[(Id<UserMarker>, Id<ChannelMarker>)],
+		settings: &Settings,
+	) -> Vec<(Id<UserMarker>, bool)> {
+		states
+			.iter()
+			.map(|&(user, channel)| {
+				let check = voice::can_connect(&BOT.cache, settings, user, channel);
+				(user, !check.is_denied())
+			})
+			.collect()
+	}
+
+	#[test]
+	fn memo_is_identical_to_uncached() {
+		let guild = crowded(3150000, 8, 10);
+		let states = connected(guild);
+
+		for prune_pending in [false, true] {
+			let settings = Settings {
+				prune_pending,
+				..Settings::default()
+			};
+			assert_eq!(
+				format!("{:?}", memoized(&states, &settings)),
+				format!("{:?}", uncached(&states, &settings)),
+				"prune_pending: {prune_pending}"
+			);
+		}
+	}
+
+	#[test]
+	#[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+	fn memo_benchmark() {
+		use std::time::Instant;
+
+		const RUNS: u32 = 100;
+		let guild = crowded(3151000, 50, 10);
+		let states = connected(guild);
+		let settings = Settings::default();
+
+		let start = Instant::now();
+		for _ in 0..RUNS {
+			memoized(&states, &settings);
+		}
+		let memo = start.elapsed() / RUNS;
+		let start = Instant::now();
+		for _ in 0..RUNS {
+			uncached(&states, &settings);
+		}
+		let scratch = start.elapsed() / RUNS;
+		let start = Instant::now();
+		let found: usize = offenders(guild)
+			.expect("cached")
+			.iter()
+			.map(|prune| prune.users.len())
+			.sum();
+		let search = start.elapsed();
+
+		println!(
+			"{} users in 50 channels: memoized {memo:?}, uncached {scratch:?}, guild search \
+			 {search:?} finding {found}",
+			states.len()
+		);
+	}
+
+	#[tokio::test]
+	async fn concurrent_channel_prunes_merge() {
+		const GUILD: u64 = 4070000;
+		let locked = [role_overwrite(
+			GUILD,
+			Permissions::empty(),
+			Permissions::CONNECT,
+		)];
+		let guild = Guild::new(GUILD)
+			.voice(GUILD + 10, &locked)
+			.voice(GUILD + 11, &locked)
+			.member(GUILD + 100, &[])
+			.member(GUILD + 101, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.connect(GUILD + 101, GUILD + 11)
+			.create();
+
+		let summary = futures_util::future::join_all([GUILD + 10, GUILD + 11].map(|channel| {
+			super::channel(Id::new(channel), guild, Trigger::Command, |_| true, None)
+		}))
+		.await
+		.into_iter()
+		.reduce(Summary::merge)
+		.expect("two channels");
+
+		assert_eq!(summary.pruned(), 2);
+		assert_eq!(summary.checked, 2);
+		assert_eq!(summary.channels.len(), 2);
+		let mut removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user.get())
+			.collect();
+		removed.sort_unstable();
+		assert_eq!(removed, [GUILD + 100, GUILD + 101]);
+	}
+
+	/// Guild with `users` users, who may not connect, in the voice channel `id + 10` and a limit of
+	/// `limit` users per auto prune.
+	fn limited(id: u64, users: u64, limit: u32) -> Id<GuildMarker> {
+		let mut guild = Guild::new(id)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(id + 10, &[]);
+		for user in id + 100..id + 100 + users {
+			guild = guild.member(user, &[]).connect(user, id + 10);
+		}
+		let guild = guild.create();
+		BOT.settings
+			.update(guild, |settings| settings.max_auto_prune = Some(limit));
+		guild
+	}
+
+	#[test]
+	fn aborts_over_limit() {
+		let guild = limited(3550000, 0, 2);
+		let prune = |users: u64| ChannelPrune {
+			channel: Id::new(3550010),
+			kind: ChannelKind::Voice,
+			users: (1..=users).map(Id::new).collect(),
+		};
+
+		let mut summary = Summary::default();
+		assert!(!aborts(guild, Trigger::Role, &[prune(2)], &mut summary));
+		assert!(!summary.aborted);
+		// across channels
+		assert!(aborts(
+			guild,
+			Trigger::Role,
+			&[prune(2), prune(1)],
+			&mut summary
+		));
+		assert!(summary.aborted);
+		// manual prunes are never limited
+		let mut summary = Summary::default();
+		assert!(!aborts(guild, Trigger::Command, &[prune(3)], &mut summary));
+		assert!(!summary.aborted);
+	}
+
+	#[tokio::test]
+	async fn auto_prune_at_limit_removes() {
+		let guild = limited(3551000, 3, 3);
+
+		let summary =
+			super::channel(Id::new(3551010), guild, Trigger::Channel, |_| true, None).await;
+
+		assert!(!summary.aborted);
+		assert_eq!(summary.pruned(), 3);
+		assert_eq!(crate::test::removed(guild).len(), 3);
+	}
+
+	#[tokio::test]
+	async fn auto_prune_over_limit_aborts() {
+		let guild = limited(3552000, 3, 2);
+
+		let summary =
+			super::channel(Id::new(3552010), guild, Trigger::Channel, |_| true, None).await;
+
+		assert!(summary.aborted);
+		assert_eq!(summary.pruned(), 0);
+		assert!(crate::test::removed(guild).is_empty());
+	}
+
+	#[test]
+	fn counts_skip_reasons() {
+		const GUILD: u64 = 4090000;
+		const PRUNED: u64 = GUILD + 100;
+		const PERMITTED: u64 = GUILD + 101;
+		const UNMATCHED: u64 = GUILD + 102;
+		const BOT_USER: u64 = GUILD + 103;
+		const OUTRANKING: u64 = GUILD + 104;
+		const AFK: u64 = GUILD + 105;
+		const UNCACHED: u64 = GUILD + 106;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.role(GUILD + 2, Permissions::empty(), 11)
+			.voice(
+				GUILD + 10,
+				&[member_overwrite(
+					PERMITTED,
+					Permissions::CONNECT,
+					Permissions::empty(),
+				)],
+			)
+			.voice(GUILD + 11, &[])
+			.afk(GUILD + 11)
+			.voice(GUILD + 12, &[])
+			.member(PRUNED, &[])
+			.member(PERMITTED, &[])
+			.member(UNMATCHED, &[])
+			.bot(BOT_USER)
+			.member(OUTRANKING, &[GUILD + 2])
+			.member(AFK, &[])
+			.connect(PRUNED, GUILD + 10)
+			.connect(PERMITTED, GUILD + 10)
+			.connect(UNMATCHED, GUILD + 10)
+			.connect(BOT_USER, GUILD + 10)
+			.connect(OUTRANKING, GUILD + 10)
+			.connect(AFK, GUILD + 11)
+			.connect(UNCACHED, GUILD + 12)
+			.create();
+
+		let mut summary = Summary::default();
+		let prunes = super::unpermitted(guild, None, &mut summary, None, |state| {
+			state.user_id().get() != UNMATCHED
+		})
+		.expect("cached");
+
+		let found: Vec<_> = prunes.iter().flat_map(|prune| &prune.users).collect();
+		assert_eq!(found, [&Id::new(PRUNED)]);
+		assert_eq!(summary.checked, 7);
+		for reason in SkipReason::ALL {
+			assert_eq!(summary.skipped(reason), 1, "{reason}");
+		}
+	}
+
+	#[test]
+	fn reset_denials() {
+		let mut summary = Summary::default();
+
+		for _ in 1..DENIAL_LIMIT {
+			assert!(!summary.denied(0));
+		}
+		summary.reset_denials();
+		for _ in 1..DENIAL_LIMIT {
+			assert!(!summary.denied(0));
+		}
+		assert!(summary.denied(0));
+		assert_eq!(summary.lost_permissions, Some(0));
+	}
+
+	/// Guild whose user `id + 100` is in `id + 20` of the category `id + 5`, whose voice channels
+	/// by position are: `id + 18` and `id + 20` denying `@everyone` to connect, `id + 21` full
+	/// with `id + 101`, and `id + 23` for two users and `id + 25`, both permitted. `id + 19` is
+	/// outside the category.
+	fn siblings(id: u64) -> Id<GuildMarker> {
+		let denied = [role_overwrite(
+			id,
+			Permissions::empty(),
+			Permissions::CONNECT,
+		)];
+		Guild::new(id)
+			.category(id + 5)
+			.channel(id + 18, ChannelType::GuildVoice, Some(id + 5), &denied)
+			.voice(id + 19, &[])
+			.channel(id + 20, ChannelType::GuildVoice, Some(id + 5), &denied)
+			.channel(id + 21, ChannelType::GuildVoice, Some(id + 5), &[])
+			.user_limit(1)
+			.channel(id + 23, ChannelType::GuildVoice, Some(id + 5), &[])
+			.user_limit(2)
+			.channel(id + 25, ChannelType::GuildVoice, Some(id + 5), &[])
+			.member(id + 100, &[])
+			.member(id + 101, &[])
+			.connect(id + 100, id + 20)
+			.connect(id + 101, id + 21)
+			.create()
+	}
+
+	#[test]
+	fn soft_target_is_closest_permitted_sibling_with_room() {
+		const GUILD: u64 = 3110000;
+		siblings(GUILD);
+		let channel = Id::new(GUILD + 20);
+		let user = Id::new(GUILD + 100);
+
+		assert_eq!(soft_target(channel, user, |_| 0), Some(Id::new(GUILD + 23)));
+		// users moved there earlier in the prune fill it up
+		let moved = |id: Id<ChannelMarker>| if id.get() == GUILD + 23 { 2 } else { 0 };
+		assert_eq!(soft_target(channel, user, moved), Some(Id::new(GUILD + 25)));
+		// without siblings
+		assert_eq!(soft_target(Id::new(GUILD + 19), user, |_| 0), None);
+	}
+
+	#[tokio::test]
+	async fn soft_prune_relocates_auto_prunes_only() {
+		const GUILD: u64 = 3111000;
+		let guild = siblings(GUILD);
+		BOT.settings
+			.update(guild, |settings| settings.soft_prune = true);
+		let channel = Id::new(GUILD + 20);
+		let user = Id::new(GUILD + 100);
+
+		super::channel(channel, guild, Trigger::Channel, |_| true, None).await;
+		assert_eq!(
+			crate::test::removed(guild),
+			[(guild, user, Some(Id::new(GUILD + 23)))]
+		);
+
+		super::channel(channel, guild, Trigger::Command, |_| true, None).await;
+		assert_eq!(crate::test::removed(guild)[1], (guild, user, None));
+	}
+
+	/// Guild where nobody but administrators and the owner may connect to `id + 10`, in which
+	/// are the administrator `id + 100`, `id + 101` with a role at the bot's position, `id + 102`
+	/// with a role below it and the owner `id + 103`.
+	fn ranked(id: u64) -> Id<GuildMarker> {
+		Guild::new(id)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.role(id + 2, Permissions::ADMINISTRATOR, 1)
+			.role(id + 3, Permissions::empty(), 10)
+			.role(id + 4, Permissions::empty(), 5)
+			.owner(id + 103)
+			.voice(id + 10, &[])
+			.member(id + 100, &[id + 2])
+			.member(id + 101, &[id + 3])
+			.member(id + 102, &[id + 4])
+			.member(id + 103, &[])
+			.connect(id + 100, id + 10)
+			.connect(id + 101, id + 10)
+			.connect(id + 102, id + 10)
+			.connect(id + 103, id + 10)
+			.create()
+	}
+
+	#[test]
+	fn outranks_only_lower_members() {
+		const GUILD: u64 = 3170000;
+		let guild = ranked(GUILD);
+		let mut memo = Memo::new(BOT.settings.get(guild));
+
+		assert!(!memo.outranks(guild, Id::new(GUILD + 100)), "administrator");
+		assert!(
+			!memo.outranks(guild, Id::new(GUILD + 101)),
+			"equal position"
+		);
+		assert!(memo.outranks(guild, Id::new(GUILD + 102)), "lower position");
+		assert!(!memo.outranks(guild, Id::new(GUILD + 103)), "owner");
+	}
+
+	#[test]
+	fn search_skips_outranking_members() {
+		let guild = ranked(3171000);
+
+		let mut summary = Summary::default();
+		let prunes = super::unpermitted(guild, None, &mut summary, None, |_| true).expect("cached");
+
+		let found: Vec<_> = prunes.iter().flat_map(|prune| &prune.users).collect();
+		assert_eq!(found, [&Id::new(3171102)]);
+		assert_eq!(summary.skipped(SkipReason::Hierarchy), 1);
+		// administrators and the owner may connect anyway
+		assert_eq!(summary.skipped(SkipReason::Permitted), 2);
+	}
+
+	#[tokio::test]
+	async fn groups_users_by_channel() {
+		const GUILD: u64 = 3220000;
+		let mut guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(GUILD + 10, &[])
+			.voice(GUILD + 11, &[])
+			.voice(GUILD + 12, &[]);
+		// one, two and three users
+		for (user, channel) in [(0, 10), (1, 11), (2, 11), (3, 12), (4, 12), (5, 12)] {
+			guild = guild
+				.member(GUILD + 100 + user, &[])
+				.connect(GUILD + 100 + user, GUILD + channel);
+		}
+		let guild = guild.create();
+		let grouped = |prunes: &[ChannelPrune]| {
+			let mut grouped: Vec<_> = prunes
+				.iter()
+				.map(|prune| {
+					let mut users: Vec<_> =
+						prune.users.iter().map(|user| user.get() - GUILD).collect();
+					users.sort_unstable();
+					(prune.channel.get() - GUILD, users)
+				})
+				.collect();
+			grouped.sort_unstable();
+			grouped
+		};
+		let expected = [
+			(10, vec![100]),
+			(11, vec![101, 102]),
+			(12, vec![103, 104, 105]),
+		];
+
+		assert_eq!(grouped(&offenders(guild).expect("cached")), expected);
+		let summary = super::guild(guild, Trigger::Command, None, |_| true, None)
+			.await
+			.expect("cached");
+		assert_eq!(grouped(&summary.channels), expected);
+		assert_eq!(summary.pruned(), 6);
+		assert_eq!(crate::test::removed(guild).len(), 6);
+		for (channel, users) in expected {
+			let prune = BOT.history.channel(guild, Id::new(GUILD + channel));
+			assert_eq!(prune.map(|prune| prune.users), Some(users.len() as u16));
+		}
+		assert_eq!(BOT.history.guild(guild).map(|prune| prune.users), Some(6));
+	}
+
+	#[test]
+	fn prunes_afk_channel_only_if_enabled() {
+		const GUILD: u64 = 3_420_000;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(GUILD + 10, &[])
+			.afk(GUILD + 10)
+			.member(GUILD + 100, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.create();
+		let found = || {
+			let mut summary = Summary::default();
+			let prunes =
+				super::unpermitted(guild, None, &mut summary, None, |_| true).expect("cached");
+			let users: Vec<_> = prunes.into_iter().flat_map(|prune| prune.users).collect();
+			(users, summary.skipped(SkipReason::Afk))
+		};
+
+		assert_eq!(found(), (Vec::new(), 1));
+
+		BOT.settings
+			.update(guild, |settings| settings.prune_afk = true);
+		assert_eq!(found(), (vec![Id::new(GUILD + 100)], 0));
+	}
+
+	#[tokio::test]
+	async fn user_prune_skips_afk_channel_unless_enabled() {
+		const GUILD: u64 = 3_421_000;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(GUILD + 10, &[])
+			.afk(GUILD + 10)
+			.member(GUILD + 100, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.create();
+
+		assert_eq!(super::user(guild, Id::new(GUILD + 100)).await.pruned(), 0);
+
+		BOT.settings
+			.update(guild, |settings| settings.prune_afk = true);
+		assert_eq!(super::user(guild, Id::new(GUILD + 100)).await.pruned(), 1);
+	}
+
+	#[test]
+	fn ignores_bots_unless_included() {
+		const GUILD: u64 = 3_450_000;
+		const HUMAN: u64 = GUILD + 100;
+		const BOT_USER: u64 = GUILD + 101;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(GUILD + 10, &[])
+			.member(HUMAN, &[])
+			.bot(BOT_USER)
+			.connect(HUMAN, GUILD + 10)
+			.connect(BOT_USER, GUILD + 10)
+			.create();
+		let found = |manual: Option<&Manual>| {
+			let mut summary = Summary::default();
+			let prunes =
+				super::unpermitted(guild, None, &mut summary, manual, |_| true).expect("cached");
+			let mut users: Vec<_> = prunes
+				.into_iter()
+				.flat_map(|prune| prune.users)
+				.map(Id::get)
+				.collect();
+			users.sort_unstable();
+			users
+		};
+		let include = Manual {
+			include_bots: true,
+			..Manual::default()
+		};
+
+		assert_eq!(found(None), [HUMAN]);
+		assert_eq!(found(Some(&Manual::default())), [HUMAN]);
+		assert_eq!(found(Some(&include)), [HUMAN, BOT_USER]);
+
+		BOT.settings
+			.update(guild, |settings| settings.ignore_bots = false);
+		assert_eq!(found(None), [HUMAN, BOT_USER]);
+	}
+
+	#[tokio::test]
+	async fn skips_users_moved_before_removal() {
+		const GUILD: u64 = 3_540_000;
+		let guild = Guild::new(GUILD)
+			.voice(
+				GUILD + 10,
+				&[role_overwrite(
+					GUILD,
+					Permissions::empty(),
+					Permissions::CONNECT,
+				)],
+			)
+			.voice(GUILD + 11, &[])
+			.member(GUILD + 100, &[])
+			.member(GUILD + 101, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.connect(GUILD + 101, GUILD + 10)
+			.create();
+		let mut summary = Summary::new(guild);
+		let prunes = super::unpermitted(guild, None, &mut summary, None, |_| true).expect("cached");
+
+		let state = crate::test::voice_state_payload(GUILD, GUILD + 101, GUILD + 11, false);
+		BOT.cache.update(&VoiceStateUpdate(
+			serde_json::from_value(state).expect("valid voice state"),
+		));
+		for prune in prunes {
+			super::remove(guild, Trigger::Command, prune, &mut summary, None).await;
+		}
+
+		let removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user)
+			.collect();
+		assert_eq!(removed, [Id::new(GUILD + 100)]);
+		assert_eq!(summary.pruned(), 1);
+		assert_eq!(summary.resolved, 1);
+	}
+
+	/// Span recorded by [`Spans`].
+	#[derive(Debug, Default)]
+	struct RecordedSpan {
+		name: &'static str,
+		parent: Option<&'static str>,
+		fields: HashMap<&'static str, String>,
+	}
+
+	impl Visit for RecordedSpan {
+		fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+			self.fields.insert(field.name(), format!("{value:?}"));
+		}
+	}
+
+	/// Layer recording every span with its parent and fields.
+	#[derive(Clone, Default)]
+	struct Spans(Arc<Mutex<Vec<RecordedSpan>>>);
+
+	impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Spans {
+		fn on_new_span(&self, attrs: &Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+			let span = ctx.span(id).expect("new span");
+			let mut recorded = RecordedSpan {
+				name: span.name(),
+				parent: span.parent().map(|parent| parent.name()),
+				..RecordedSpan::default()
+			};
+			attrs.record(&mut recorded);
+			let mut spans = self.0.lock().unwrap();
+			span.extensions_mut().insert(spans.len());
+			spans.push(recorded);
+		}
+
+		fn on_record(&self, id: &span::Id, values: &Record<'_>, ctx: Context<'_, S>) {
+			let span = ctx.span(id).expect("recorded span");
+			let index = *span.extensions().get::<usize>().expect("recorded");
+			values.record(&mut self.0.lock().unwrap()[index]);
+		}
+	}
+
+	#[tokio::test]
+	async fn channel_prune_spans() {
+		const GUILD: u64 = 3_700_000;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(GUILD + 10, &[])
+			.member(GUILD + 100, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.create();
+		let spans = Spans::default();
+		let _default =
+			tracing::subscriber::set_default(tracing_subscriber::registry().with(spans.clone()));
+
+		super::channel(Id::new(GUILD + 10), guild, Trigger::Channel, |_| true, None)
+			.instrument(tracing::info_span!("handle", event = "ChannelUpdate"))
+			.await;
+
+		let spans = spans.0.lock().unwrap();
+		let find = |name| {
+			spans
+				.iter()
+				.find(|span| span.name == name)
+				.unwrap_or_else(|| panic!("{name} span"))
+		};
+		let channel = find("channel");
+		assert_eq!(channel.parent, Some("handle"));
+		for (field, value) in [
+			("guild_id", GUILD.to_string()),
+			("channel_id", (GUILD + 10).to_string()),
+			("trigger", "channel update".to_owned()),
+			("pruned", "1".to_owned()),
+			("failed", "0".to_owned()),
+		] {
+			assert_eq!(channel.fields.get(field), Some(&value), "{field}");
+		}
+		let remove = find("remove");
+		assert_eq!(remove.parent, Some("channel"));
+		assert_eq!(remove.fields.get("removed").map(String::as_str), Some("1"));
+		assert_eq!(remove.fields.get("failed").map(String::as_str), Some("0"));
+	}
+
+	#[test]
+	fn orders_by_role_position() {
+		const GUILD: u64 = 3_710_000;
+		let mut guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.role(GUILD + 2, Permissions::empty(), 2)
+			.role(GUILD + 5, Permissions::empty(), 5)
+			.voice(GUILD + 10, &[]);
+		let members: [(u64, &[u64]); 5] = [
+			(GUILD + 104, &[]),
+			(GUILD + 100, &[GUILD + 5]),
+			(GUILD + 103, &[GUILD + 2, GUILD + 5]),
+			(GUILD + 102, &[GUILD + 2]),
+			(GUILD + 101, &[]),
+		];
+		for (user, roles) in members {
+			guild = guild.member(user, roles).connect(user, GUILD + 10);
+		}
+		let guild = guild.create();
+		let expected = [101, 104, 102, 100, 103].map(|offset| Id::new(GUILD + offset));
+
+		let mut users = members.map(|(user, _)| Id::new(user));
+		super::order(guild, &mut users);
+		assert_eq!(users, expected);
+
+		BOT.settings.update(guild, |settings| {
+			settings.prune_order = PruneOrder::RolePosition;
+		});
+		let mut summary = Summary::default();
+		let prunes = super::unpermitted(guild, None, &mut summary, None, |_| true).expect("cached");
+		assert_eq!(prunes[0].users, expected);
+	}
+
+	#[tokio::test]
+	async fn user_prune_keeps_uncached_members() {
+		const GUILD: u64 = 3_780_000;
+		// only `GUILD + 100` is a cached member
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(
+				GUILD + 10,
+				&[
+					member_overwrite(GUILD + 101, Permissions::CONNECT, Permissions::empty()),
+					member_overwrite(GUILD + 102, Permissions::empty(), Permissions::CONNECT),
+				],
+			)
+			.member(GUILD + 100, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.connect(GUILD + 101, GUILD + 10)
+			.connect(GUILD + 102, GUILD + 10)
+			.connect(GUILD + 103, GUILD + 10)
+			.create();
+		let check = |user| {
+			voice::can_connect(
+				&BOT.cache,
+				&Settings::default(),
+				Id::new(user),
+				Id::new(GUILD + 10),
+			)
+		};
+
+		assert_eq!(check(GUILD + 100), voice::PermissionCheck::NotPermitted);
+		// permitted by the overwrite alone
+		assert_eq!(check(GUILD + 101), voice::PermissionCheck::Permitted);
+		// an overwrite denying is inconclusive, as roles may still allow
+		assert_eq!(check(GUILD + 102), voice::PermissionCheck::Unknown);
+		assert_eq!(check(GUILD + 103), voice::PermissionCheck::Unknown);
+
+		for user in [GUILD + 101, GUILD + 102, GUILD + 103] {
+			let summary = super::user(guild, Id::new(user)).await;
+			assert_eq!(summary.checked, 0, "user {user} skipped");
+		}
+		assert!(crate::test::removed(guild).is_empty());
+
+		super::user(guild, Id::new(GUILD + 100)).await;
+		let removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user)
+			.collect();
+		assert_eq!(removed, [Id::new(GUILD + 100)]);
+	}
+
+	/// Guild whose pending member `id + 100` and member `id + 101` are both connected to the voice
+	/// channel `id + 10`, which everyone may connect to.
+	fn pending(id: u64) -> Id<GuildMarker> {
+		Guild::new(id)
+			.voice(id + 10, &[])
+			.member(id + 100, &[])
+			.pending()
+			.member(id + 101, &[])
+			.connect(id + 100, id + 10)
+			.connect(id + 101, id + 10)
+			.create()
+	}
+
+	/// Users removed in the guild.
+	fn removed(guild: Id<GuildMarker>) -> Vec<Id<UserMarker>> {
+		crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user)
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn channel_prune_removes_pending_members_if_enabled() {
+		const GUILD: u64 = 3_870_000;
+		let guild = pending(GUILD);
+		let channel = Id::new(GUILD + 10);
+
+		super::channel(channel, guild, Trigger::Command, |_| true, None).await;
+		assert!(removed(guild).is_empty());
+
+		BOT.settings
+			.update(guild, |settings| settings.prune_pending = true);
+		super::channel(channel, guild, Trigger::Command, |_| true, None).await;
+		assert_eq!(removed(guild), [Id::new(GUILD + 100)]);
+	}
+
+	#[tokio::test]
+	async fn user_prune_removes_pending_members_if_enabled() {
+		const GUILD: u64 = 3_871_000;
+		let guild = pending(GUILD);
+
+		super::user(guild, Id::new(GUILD + 100)).await;
+		assert!(removed(guild).is_empty());
+
+		BOT.settings
+			.update(guild, |settings| settings.prune_pending = true);
+		super::user(guild, Id::new(GUILD + 101)).await;
+		super::user(guild, Id::new(GUILD + 100)).await;
+		assert_eq!(removed(guild), [Id::new(GUILD + 100)]);
+	}
+
+	#[tokio::test]
+	async fn permits_owner_and_administrators() {
+		const GUILD: u64 = 3_910_000;
+		let deny = |user| member_overwrite(user, Permissions::empty(), Permissions::CONNECT);
+		// `GUILD + 101` owns the guild without any roles
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.role(GUILD + 2, Permissions::ADMINISTRATOR, 1)
+			.voice(GUILD + 10, &[deny(GUILD + 100), deny(GUILD + 101)])
+			.member(GUILD + 100, &[GUILD + 2])
+			.member(GUILD + 101, &[])
+			.owner(GUILD + 101)
+			.member(GUILD + 102, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.connect(GUILD + 101, GUILD + 10)
+			.connect(GUILD + 102, GUILD + 10)
+			.create();
+		let channel = Id::new(GUILD + 10);
+		let check =
+			|user| voice::can_connect(&BOT.cache, &Settings::default(), Id::new(user), channel);
+
+		assert_eq!(check(GUILD + 100), voice::PermissionCheck::Permitted);
+		assert_eq!(check(GUILD + 101), voice::PermissionCheck::Permitted);
+		assert_eq!(check(GUILD + 102), voice::PermissionCheck::NotPermitted);
+		let mut memo = super::Memo::new(Settings::default());
+		assert!(memo.can_connect(Id::new(GUILD + 100), channel));
+		assert!(memo.can_connect(Id::new(GUILD + 101), channel));
+		assert!(!memo.can_connect(Id::new(GUILD + 102), channel));
+
+		super::channel(channel, guild, Trigger::Command, |_| true, None).await;
+		let removed: Vec<_> = crate::test::removed(guild)
+			.into_iter()
+			.map(|(_, user, _)| user)
+			.collect();
+		assert_eq!(removed, [Id::new(GUILD + 102)]);
+	}
+
+	#[tokio::test]
+	async fn breaks_down_by_channel_kind() {
+		const GUILD: u64 = 3_960_000;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(GUILD + 10, &[])
+			.channel(GUILD + 11, ChannelType::GuildStageVoice, None, &[])
+			.member(GUILD + 100, &[])
+			.member(GUILD + 101, &[])
+			.member(GUILD + 102, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.connect(GUILD + 101, GUILD + 10)
+			.connect(GUILD + 102, GUILD + 11)
+			.create();
+
+		let mut summary = super::guild(guild, Trigger::Command, None, |_| true, None)
+			.await
+			.unwrap();
+		let describe =
+			|summary: &Summary| super::describe_kinds(|kind| u32::from(summary.pruned_in(kind)));
+		assert_eq!(summary.pruned_in(ChannelKind::Voice), 2);
+		assert_eq!(summary.pruned_in(ChannelKind::Stage), 1);
+		assert_eq!(describe(&summary).as_deref(), Some("2 voice, 1 stage"));
+
+		// the stage is deleted before the summary is rendered
+		let stage: Channel = serde_json::from_value(crate::test::channel_payload(
+			GUILD,
+			GUILD + 11,
+			ChannelType::GuildStageVoice,
+			None,
+			&[],
+		))
+		.unwrap();
+		BOT.cache.update(&ChannelDelete(stage));
+		assert_eq!(ChannelKind::of(Id::new(GUILD + 11)), ChannelKind::Unknown);
+		assert_eq!(
+			describe(&summary).as_deref(),
+			Some("2 voice, 1 stage"),
+			"kind as searched"
+		);
+
+		// while a channel deleted before being searched is of an unknown kind
+		summary.channels.push(ChannelPrune {
+			channel: Id::new(GUILD + 11),
+			kind: ChannelKind::of(Id::new(GUILD + 11)),
+			users: vec![Id::new(GUILD + 102)],
+		});
+		assert_eq!(
+			describe(&summary).as_deref(),
+			Some("2 voice, 1 stage, 1 unknown")
+		);
+		// a single kind isn't broken down
+		assert_eq!(
+			super::describe_kinds(|kind| u32::from(kind == ChannelKind::Voice)),
+			None
+		);
+	}
+
+	#[test]
+	fn distinct() {
+		let prune = |channel, users: &[u64]| ChannelPrune {
+			channel: Id::new(channel),
+			kind: ChannelKind::Voice,
+			users: users.iter().copied().map(Id::new).collect(),
+		};
+
+		let prunes = super::distinct([
+			prune(10, &[100, 101]),
+			prune(11, &[101, 102]),
+			prune(12, &[100]),
+		]);
+		assert_eq!(prunes.len(), 2);
+		assert_eq!(prunes[0].users, prune(10, &[100, 101]).users);
+		assert_eq!(prunes[1].channel, Id::new(11));
+		assert_eq!(prunes[1].users, [Id::new(102)]);
+	}
+
+	#[tokio::test]
+	async fn channels_prune_users_once() {
+		const GUILD: u64 = 4_060_000;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(
+				GUILD + 10,
+				&[role_overwrite(
+					GUILD,
+					Permissions::empty(),
+					Permissions::CONNECT,
+				)],
+			)
+			.voice(
+				GUILD + 11,
+				&[role_overwrite(
+					GUILD,
+					Permissions::empty(),
+					Permissions::CONNECT,
+				)],
+			)
+			.member(GUILD + 100, &[])
+			.member(GUILD + 101, &[])
+			.connect(GUILD + 100, GUILD + 10)
+			.connect(GUILD + 101, GUILD + 11)
+			.create();
+		// overlapping triggers of the same channel
+		let channels = [
+			Id::new(GUILD + 10),
+			Id::new(GUILD + 11),
+			Id::new(GUILD + 10),
+		];
+
+		let summary = super::channels(&channels, guild, Trigger::Command, |_, _| true, None).await;
+
+		assert_eq!(summary.pruned(), 2);
+		assert_eq!(summary.channels.len(), 2);
+		assert_eq!(removed(guild).len(), 2);
+		let days = BOT.stats.guild(guild);
+		assert_eq!(days.len(), 1);
+		assert_eq!(days[0].users, 2);
+		assert_eq!(days[0].channels[&Id::new(GUILD + 10)], 1);
 	}
 }
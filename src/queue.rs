@@ -0,0 +1,387 @@
+//! Bounded queue of member removals, configured through the `REMOVAL_QUEUE` (capacity) and
+//! `REMOVAL_WORKERS` environment variables.
+//!
+//! Every prune submits its removals here, so the number of concurrent removal requests is limited
+//! to the number of workers however many prunes run. When the queue is full, auto prunes wait for
+//! room whereas manual prunes fail right away, reporting the removals as [`Error::Full`]. A user
+//! already submitted by the same [`Run`] is skipped, so that a prune removes them at most once.
+
+use std::{
+	collections::{HashMap, HashSet},
+	env, fmt,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, PoisonError,
+	},
+	time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use tokio::sync::{
+	mpsc::{self, error::TrySendError},
+	oneshot, Mutex,
+};
+use twilight_http::request::AuditLogReason;
+use twilight_model::id::{
+	marker::{ChannelMarker, GuildMarker, UserMarker},
+	Id,
+};
+
+use crate::{halt, prune::Trigger, BOT};
+
+/// Time after which a run's submitted users are forgotten, well beyond any prune's duration.
+const RUN_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Prune submitting removals.
+#[derive(Clone, Copy, Debug)]
+pub struct Run {
+	id: u64,
+	/// Token of the guild, dropping the run's removals once cancelled.
+	halt: halt::Token,
+}
+
+impl Run {
+	/// A run distinct from all others, stopped along with `halt`.
+	pub fn new(halt: halt::Token) -> Self {
+		static LAST: AtomicU64 = AtomicU64::new(0);
+		Self {
+			id: LAST.fetch_add(1, Ordering::Relaxed),
+			halt,
+		}
+	}
+
+	/// Whether the guild was halted since the run started.
+	pub fn is_halted(self) -> bool {
+		self.halt.is_cancelled()
+	}
+}
+
+/// Removal of a user from their voice channel.
+#[derive(Debug)]
+struct Job {
+	guild: Id<GuildMarker>,
+	user: Id<UserMarker>,
+	/// Channel to move the user to, or `None` to disconnect them.
+	target: Option<Id<ChannelMarker>>,
+	/// Audit log reason of the removal.
+	reason: Option<String>,
+	/// Prune queueing the removal.
+	run: Run,
+	reply: oneshot::Sender<Result<(), Error>>,
+}
+
+/// Error of a queued removal.
+#[derive(Debug)]
+pub enum Error {
+	/// The queue is full and the prune is manual.
+	Full,
+	/// The workers stopped.
+	Stopped,
+	/// The guild was halted while the removal was queued.
+	Halted,
+	/// The user was already submitted by the same run.
+	Duplicate,
+	/// The request to Discord failed.
+	Http(twilight_http::Error),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Full => f.write_str("removal queue is full"),
+			Self::Stopped => f.write_str("removal workers stopped"),
+			Self::Halted => f.write_str("guild halted"),
+			Self::Duplicate => f.write_str("already removed by this prune"),
+			Self::Http(_) => f.write_str("request to Discord failed"),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Http(e) => Some(e),
+			Self::Full | Self::Stopped | Self::Halted | Self::Duplicate => None,
+		}
+	}
+}
+
+/// Sending half of the queue.
+#[derive(Debug)]
+pub struct Queue {
+	sender: mpsc::Sender<Job>,
+	/// Number of workers, i.e. of removals executed concurrently.
+	workers: usize,
+	/// Users submitted per run id, along with when the run first submitted one.
+	submitted: std::sync::Mutex<HashMap<u64, Submitted>>,
+}
+
+/// Time a run first submitted a removal, and the guilds and users it submitted.
+type Submitted = (Instant, HashSet<(Id<GuildMarker>, Id<UserMarker>)>);
+
+/// Receiving half of the queue, consumed by [`run`].
+#[derive(Debug)]
+pub struct Workers {
+	count: usize,
+	receiver: mpsc::Receiver<Job>,
+}
+
+impl Queue {
+	/// Parses the queue's limits from the environment, defaulting to 256 queued removals and 4
+	/// workers.
+	pub fn from_env() -> Result<(Self, Workers), anyhow::Error> {
+		let capacity = env::var("REMOVAL_QUEUE")
+			.map_or(Ok(256), |var| var.parse())
+			.context("\"REMOVAL_QUEUE\" must be a positive integer")?;
+		let count = env::var("REMOVAL_WORKERS")
+			.map_or(Ok(4), |var| var.parse())
+			.context("\"REMOVAL_WORKERS\" must be a positive integer")?;
+		anyhow::ensure!(
+			capacity != 0,
+			"\"REMOVAL_QUEUE\" must be a positive integer"
+		);
+		anyhow::ensure!(count != 0, "\"REMOVAL_WORKERS\" must be a positive integer");
+
+		Ok(Self::new(capacity, count))
+	}
+
+	fn new(capacity: usize, workers: usize) -> (Self, Workers) {
+		let (sender, receiver) = mpsc::channel(capacity);
+		(
+			Self {
+				sender,
+				workers,
+				submitted: std::sync::Mutex::default(),
+			},
+			Workers {
+				count: workers,
+				receiver,
+			},
+		)
+	}
+
+	/// Records the user as submitted by the run, returning whether they already were.
+	fn is_duplicate(&self, run: Run, guild: Id<GuildMarker>, user: Id<UserMarker>) -> bool {
+		let now = Instant::now();
+		let mut submitted = self
+			.submitted
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner);
+		submitted.retain(|_, (started, _)| now.duration_since(*started) < RUN_TTL);
+		!submitted
+			.entry(run.id)
+			.or_insert_with(|| (now, HashSet::new()))
+			.1
+			.insert((guild, user))
+	}
+
+	/// Number of removals executed concurrently, and so worth submitting ahead of their results.
+	pub const fn workers(&self) -> usize {
+		self.workers
+	}
+
+	/// Moves the user to the target voice channel, or disconnects them if `None`, once a worker
+	/// is available, with the audit log reason if given.
+	///
+	/// Waits for room in a full queue on auto prunes, and fails otherwise. The removal is dropped
+	/// once the run is halted, and skipped if the run already submitted the user.
+	pub async fn remove(
+		&self,
+		guild: Id<GuildMarker>,
+		user: Id<UserMarker>,
+		target: Option<Id<ChannelMarker>>,
+		trigger: Trigger,
+		reason: Option<String>,
+		run: Run,
+	) -> Result<(), Error> {
+		if self.is_duplicate(run, guild, user) {
+			return Err(Error::Duplicate);
+		}
+		let (reply, result) = oneshot::channel();
+		let job = Job {
+			guild,
+			user,
+			target,
+			reason,
+			run,
+			reply,
+		};
+		if trigger.is_auto() {
+			self.sender.send(job).await.map_err(|_| Error::Stopped)?;
+		} else {
+			self.sender.try_send(job).map_err(|e| match e {
+				TrySendError::Full(_) => Error::Full,
+				TrySendError::Closed(_) => Error::Stopped,
+			})?;
+		}
+
+		result.await.map_err(|_| Error::Stopped)?
+	}
+}
+
+/// Runs the workers, returning once they all stopped.
+pub async fn run(workers: Workers) {
+	let receiver = Arc::new(Mutex::new(workers.receiver));
+	let handles: Vec<_> = (0..workers.count)
+		.map(|_| tokio::spawn(work(Arc::clone(&receiver))))
+		.collect();
+	for handle in handles {
+		_ = handle.await;
+	}
+}
+
+/// Executes jobs until the queue closes.
+async fn work(receiver: Arc<Mutex<mpsc::Receiver<Job>>>) {
+	loop {
+		let Some(job) = receiver.lock().await.recv().await else {
+			return;
+		};
+		if job.run.is_halted() {
+			_ = job.reply.send(Err(Error::Halted));
+			continue;
+		}
+		let result = request(&job).await;
+		// the prune may have been cancelled
+		_ = job.reply.send(result);
+	}
+}
+
+/// Moves the job's user to its target.
+async fn request(job: &Job) -> Result<(), Error> {
+	let request = BOT
+		.http
+		.update_guild_member(job.guild, job.user)
+		.channel_id(job.target);
+	match &job.reason {
+		Some(reason) => request.reason(reason).await,
+		None => request.await,
+	}
+	.map(drop)
+	.map_err(Error::Http)
+}
+
+#[cfg(test)]
+impl Workers {
+	/// Executes jobs until the queue closes, calling `removed` with the guild, user and target of
+	/// each and only requesting Discord if it returns `false`.
+	pub async fn fake(
+		mut self,
+		removed: impl Fn(Id<GuildMarker>, Id<UserMarker>, Option<Id<ChannelMarker>>) -> bool,
+	) {
+		while let Some(job) = self.receiver.recv().await {
+			if job.run.is_halted() {
+				_ = job.reply.send(Err(Error::Halted));
+				continue;
+			}
+			let result = if removed(job.guild, job.user, job.target) {
+				Ok(())
+			} else {
+				request(&job).await
+			};
+			_ = job.reply.send(result);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::task::Poll;
+
+	use futures_util::poll;
+
+	use super::*;
+
+	const GUILD: u64 = 3_660_000;
+
+	#[tokio::test]
+	async fn manual_fails_when_full() {
+		crate::test::init();
+		let (queue, _workers) = Queue::new(1, 1);
+		let queued = queue.remove(
+			Id::new(GUILD),
+			Id::new(GUILD + 100),
+			None,
+			Trigger::Command,
+			None,
+			Run::new(BOT.halts.token(Id::new(GUILD))),
+		);
+		tokio::pin!(queued);
+		assert!(poll!(&mut queued).is_pending());
+
+		let full = queue
+			.remove(
+				Id::new(GUILD),
+				Id::new(GUILD + 101),
+				None,
+				Trigger::Command,
+				None,
+				Run::new(BOT.halts.token(Id::new(GUILD))),
+			)
+			.await;
+		assert!(matches!(full, Err(Error::Full)), "{full:?}");
+	}
+
+	#[tokio::test]
+	async fn auto_waits_for_room() {
+		crate::test::init();
+		let (queue, mut workers) = Queue::new(1, 1);
+		let queued = queue.remove(
+			Id::new(GUILD),
+			Id::new(GUILD + 100),
+			None,
+			Trigger::Channel,
+			None,
+			Run::new(BOT.halts.token(Id::new(GUILD))),
+		);
+		tokio::pin!(queued);
+		assert!(poll!(&mut queued).is_pending());
+
+		let waiting = queue.remove(
+			Id::new(GUILD),
+			Id::new(GUILD + 101),
+			None,
+			Trigger::Channel,
+			None,
+			Run::new(BOT.halts.token(Id::new(GUILD))),
+		);
+		tokio::pin!(waiting);
+		assert!(poll!(&mut waiting).is_pending());
+		assert!(workers.receiver.try_recv().is_ok(), "first queued");
+		assert!(workers.receiver.try_recv().is_err(), "second waiting");
+
+		// room freed up
+		assert!(poll!(&mut waiting).is_pending());
+		let job = workers.receiver.try_recv().expect("second queued");
+		assert_eq!(job.user, Id::new(GUILD + 101));
+		job.reply.send(Ok(())).unwrap();
+		assert!(matches!(poll!(&mut waiting), Poll::Ready(Ok(()))));
+	}
+
+	#[tokio::test]
+	async fn skips_duplicates_of_run() {
+		crate::test::init();
+		let (guild, user) = (Id::new(GUILD), Id::new(GUILD + 102));
+		let (queue, workers) = Queue::new(2, 1);
+		tokio::spawn(workers.fake(|_, _, _| false));
+		let run = Run::new(BOT.halts.token(guild));
+
+		let first = queue
+			.remove(guild, user, None, Trigger::Command, None, run)
+			.await;
+		assert!(first.is_ok(), "{first:?}");
+		let second = queue
+			.remove(guild, user, None, Trigger::Command, None, run)
+			.await;
+		assert!(matches!(second, Err(Error::Duplicate)), "{second:?}");
+		assert_eq!(
+			crate::test::requests(&crate::test::member_path(guild, user)).len(),
+			1
+		);
+
+		let other = Run::new(BOT.halts.token(guild));
+		let third = queue
+			.remove(guild, user, None, Trigger::Command, None, other)
+			.await;
+		assert!(third.is_ok(), "{third:?}");
+	}
+}
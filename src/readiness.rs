@@ -0,0 +1,149 @@
+//! Tracking of whether guilds are fully cached, deferring auto prunes of guilds that are not.
+//!
+//! Guilds are cold from the ready event until their guild create event, or for large guilds until
+//! the members in voice channels are received (see [`crate::chunk`]), and while unavailable.
+//! Auto prunes of cold guilds are collapsed into one guild prune, replayed once warm unless the
+//! guild stayed cold for longer than [`TIMEOUT`].
+
+use std::{
+	collections::HashMap,
+	sync::{Mutex, PoisonError},
+	time::{Duration, Instant},
+};
+
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::{cleanup::Cleanup, prune::Trigger};
+
+/// Maximum time a deferred auto prune is replayed after.
+const TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Cold guild.
+#[derive(Debug)]
+struct Cold {
+	since: Instant,
+	/// Trigger of the first deferred auto prune.
+	deferred: Option<Trigger>,
+}
+
+/// Cold guilds, every other guild is warm.
+#[derive(Debug, Default)]
+pub struct Readiness(Mutex<HashMap<Id<GuildMarker>, Cold>>);
+
+impl Readiness {
+	/// Marks the guilds as cold.
+	pub fn cool(&self, guilds: impl IntoIterator<Item = Id<GuildMarker>>) {
+		let now = Instant::now();
+		let mut cold = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		for guild in guilds {
+			cold.entry(guild).or_insert(Cold {
+				since: now,
+				deferred: None,
+			});
+		}
+	}
+
+	/// Defers the auto prune if the guild is cold, returning whether it was.
+	pub fn defer(&self, guild: Id<GuildMarker>, trigger: Trigger) -> bool {
+		let mut cold = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		let Some(state) = cold.get_mut(&guild) else {
+			return false;
+		};
+
+		tracing::debug!(guild_id = %guild, %trigger, "deferring auto prune of cold guild");
+		state.deferred.get_or_insert(trigger);
+		true
+	}
+
+	/// Marks the guild as warm, returning the trigger of the deferred auto prune to replay.
+	pub fn warm(&self, guild: Id<GuildMarker>) -> Option<Trigger> {
+		let state = self
+			.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&guild)?;
+		let trigger = state.deferred?;
+		if state.since.elapsed() > TIMEOUT {
+			tracing::info!(guild_id = %guild, %trigger, "dropping deferred auto prune, guild was cold for too long");
+			None
+		} else {
+			Some(trigger)
+		}
+	}
+}
+
+impl Cleanup for Readiness {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		self.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&guild);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn replays_first_deferred_trigger() {
+		let readiness = Readiness::default();
+		let guild = Id::new(3_280_000);
+		readiness.cool([guild]);
+
+		assert!(readiness.defer(guild, Trigger::Channel));
+		assert!(readiness.defer(guild, Trigger::Role));
+
+		assert!(matches!(readiness.warm(guild), Some(Trigger::Channel)));
+		// warm guilds don't defer
+		assert!(!readiness.defer(guild, Trigger::Channel));
+		assert!(readiness.warm(guild).is_none());
+	}
+
+	#[test]
+	fn warm_without_deferred_prune() {
+		let readiness = Readiness::default();
+		let guild = Id::new(3_280_000);
+		readiness.cool([guild]);
+
+		assert!(readiness.warm(guild).is_none());
+	}
+
+	#[test]
+	fn cooling_keeps_deferred_prune() {
+		let readiness = Readiness::default();
+		let guild = Id::new(3_280_000);
+		readiness.cool([guild]);
+		readiness.defer(guild, Trigger::Member);
+
+		readiness.cool([guild]);
+
+		assert!(matches!(readiness.warm(guild), Some(Trigger::Member)));
+	}
+
+	#[test]
+	fn drops_prune_deferred_past_timeout() {
+		let readiness = Readiness::default();
+		let guild = Id::new(3_280_000);
+		readiness.cool([guild]);
+		readiness.defer(guild, Trigger::Channel);
+		let mut cold = readiness.0.lock().unwrap();
+		cold.get_mut(&guild).unwrap().since -= TIMEOUT + Duration::from_secs(1);
+		drop(cold);
+
+		assert!(readiness.warm(guild).is_none());
+	}
+
+	#[test]
+	fn cleanup_forgets_guild() {
+		let readiness = Readiness::default();
+		let guild = Id::new(3_280_000);
+		readiness.cool([guild]);
+		readiness.defer(guild, Trigger::Channel);
+
+		Cleanup::guild(&readiness, guild);
+
+		assert!(!readiness.defer(guild, Trigger::Channel));
+		assert!(readiness.warm(guild).is_none());
+	}
+}
@@ -0,0 +1,145 @@
+//! Audit log reasons of removals, rendered from the guild's template set through
+//! `/settings kick-reason`.
+//!
+//! Templates are plain text with the placeholders `{channel}`, `{trigger}` and `{user}`, replaced
+//! by the channel's name, the prune's trigger and the user's name without any escaping.
+
+use std::fmt;
+
+use crate::prune::Trigger;
+
+/// Maximum length of an audit log reason.
+pub const LIMIT: usize = 512;
+
+/// Known placeholders, with the maximum length of their value.
+///
+/// Channel names are at most 100 characters, usernames at most 32 and the longest [`Trigger`] is
+/// "control socket".
+const PLACEHOLDERS: [(&str, usize); 3] = [("{channel}", 100), ("{trigger}", 14), ("{user}", 32)];
+
+/// Why a template is invalid.
+#[derive(Debug)]
+pub enum Invalid {
+	/// The template uses an unknown placeholder.
+	Placeholder(String),
+	/// The template may render longer than [`LIMIT`], at most to the length.
+	TooLong(usize),
+}
+
+impl fmt::Display for Invalid {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Placeholder(placeholder) => {
+				let known: Vec<_> = PLACEHOLDERS.iter().map(|&(name, _)| name).collect();
+				write!(
+					f,
+					"unknown placeholder \"{placeholder}\", valid ones are {}",
+					known.join(", ")
+				)
+			}
+			Self::TooLong(len) => write!(
+				f,
+				"the reason may be up to {len} characters long, exceeding the limit of {LIMIT}"
+			),
+		}
+	}
+}
+
+/// Validates that the template only uses known placeholders and never renders longer than
+/// [`LIMIT`].
+pub fn validate(template: &str) -> Result<(), Invalid> {
+	let mut len = 0;
+	let mut rest = template;
+	while let Some(start) = rest.find('{') {
+		len += rest[..start].chars().count();
+		rest = &rest[start..];
+		let Some(end) = rest.find('}') else {
+			break;
+		};
+		let placeholder = &rest[..=end];
+		let &(_, max) = PLACEHOLDERS
+			.iter()
+			.find(|&&(name, _)| name == placeholder)
+			.ok_or_else(|| Invalid::Placeholder(placeholder.to_owned()))?;
+		len += max;
+		rest = &rest[end + 1..];
+	}
+	len += rest.chars().count();
+
+	if len > LIMIT {
+		return Err(Invalid::TooLong(len));
+	}
+	Ok(())
+}
+
+/// Renders the template, leaving text that isn't a placeholder as is.
+pub fn render(template: &str, channel: &str, trigger: Trigger, user: &str) -> String {
+	let trigger = trigger.to_string();
+	let mut reason = String::with_capacity(template.len());
+	let mut rest = template;
+	while let Some(start) = rest.find('{') {
+		reason.push_str(&rest[..start]);
+		rest = &rest[start..];
+		let (placeholder, value) = [
+			("{channel}", channel),
+			("{trigger}", trigger.as_str()),
+			("{user}", user),
+		]
+		.into_iter()
+		.find(|(placeholder, _)| rest.starts_with(placeholder))
+		.unwrap_or(("{", "{"));
+		reason.push_str(value);
+		rest = &rest[placeholder.len()..];
+	}
+	reason.push_str(rest);
+	reason
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Invalid, LIMIT};
+	use crate::prune::Trigger;
+
+	fn render(template: &str) -> String {
+		super::render(template, "General", Trigger::Role, "<@everyone>")
+	}
+
+	#[test]
+	fn renders_placeholders() {
+		assert_eq!(render("left {channel}"), "left General");
+		assert_eq!(render("by {trigger}"), "by role update");
+		assert_eq!(render("removed {user}"), "removed <@everyone>", "unescaped");
+		assert_eq!(
+			render("{user} in {channel} ({trigger}, {trigger})"),
+			"<@everyone> in General (role update, role update)"
+		);
+		// other braces are kept
+		assert_eq!(render("{ {unknown} }"), "{ {unknown} }");
+	}
+
+	#[test]
+	fn rejects_unknown_placeholders() {
+		assert!(super::validate("{user} left {channel} due to a {trigger}").is_ok());
+
+		let e = super::validate("{user} left {guild}").unwrap_err();
+		assert!(matches!(&e, Invalid::Placeholder(placeholder) if placeholder == "{guild}"));
+		assert_eq!(
+			e.to_string(),
+			"unknown placeholder \"{guild}\", valid ones are {channel}, {trigger}, {user}"
+		);
+	}
+
+	#[test]
+	fn rejects_over_length() {
+		// the user placeholder may render 32 characters long
+		let fits = format!("{}{{user}}", "a".repeat(LIMIT - 32));
+		assert!(super::validate(&fits).is_ok());
+
+		let over = format!("{}{{user}}", "a".repeat(LIMIT - 31));
+		assert!(matches!(
+			super::validate(&over),
+			Err(Invalid::TooLong(len)) if len == LIMIT + 1
+		));
+		assert!(super::validate(&"a".repeat(LIMIT + 1)).is_err());
+	}
+}
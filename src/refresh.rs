@@ -0,0 +1,157 @@
+//! Reconciliation of the cached voice states with Discord's, recovering from missed gateway
+//! events, e.g. after a resume gap.
+//!
+//! Discord has no endpoint listing a guild's voice states, so every cached member's voice state is
+//! fetched on its own, which is why only guilds with at most [`MEMBER_LIMIT`] members are
+//! refreshed.
+
+use std::fmt;
+
+use twilight_http::{
+	error::ErrorType,
+	request::{Method, RequestBuilder},
+	routing::Path,
+	Response,
+};
+use twilight_model::{
+	gateway::payload::incoming::VoiceStateUpdate,
+	id::{
+		marker::{GuildMarker, UserMarker},
+		Id,
+	},
+	voice::VoiceState,
+};
+
+use crate::{error::Error, BOT};
+
+/// Maximum number of members of a refreshed guild.
+pub const MEMBER_LIMIT: u64 = 250;
+
+/// Changes made to the cached voice states.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Drift {
+	/// Connected users missing from the cache.
+	pub added: u32,
+	/// Cached users who were no longer connected.
+	pub removed: u32,
+	/// Users cached in another channel.
+	pub moved: u32,
+}
+
+impl fmt::Display for Drift {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{} added, {} removed, {} moved",
+			self.added, self.removed, self.moved
+		)
+	}
+}
+
+/// Fetches the voice state of every cached member of the guild, updating the cache to match.
+///
+/// Returns `None` without refreshing if the guild isn't cached or has more than
+/// [`MEMBER_LIMIT`] members.
+#[tracing::instrument(skip_all, fields(guild_id = %guild))]
+pub async fn voice_states(guild: Id<GuildMarker>) -> Result<Option<Drift>, Error> {
+	let Some(count) = BOT
+		.cache
+		.guild(guild)
+		.and_then(|guild| guild.member_count())
+	else {
+		tracing::debug!("skipping: guild not cached");
+		return Ok(None);
+	};
+	if count > MEMBER_LIMIT {
+		tracing::warn!(members = count, "skipping: too many members to refresh");
+		return Ok(None);
+	}
+
+	let members: Vec<_> = BOT
+		.cache
+		.guild_members(guild)
+		.map_or_else(Vec::new, |members| members.iter().copied().collect());
+
+	let mut drift = Drift::default();
+	for user in members {
+		let cached = BOT
+			.cache
+			.voice_state(user, guild)
+			.map(|state| state.channel_id());
+		let Some(response) = fetch(guild, user).await? else {
+			if cached.is_some() {
+				drift.removed += 1;
+				disconnect(guild, user);
+			}
+			continue;
+		};
+		let state = match response.model().await {
+			Ok(state) => state,
+			Err(e) => {
+				tracing::warn!(user_id = %user, error = &e as &dyn std::error::Error, "unable to parse voice state");
+				continue;
+			}
+		};
+
+		match (cached, state.channel_id) {
+			(None, Some(_)) => drift.added += 1,
+			(Some(_), None) => drift.removed += 1,
+			(Some(cached), Some(channel)) if cached != channel => drift.moved += 1,
+			_ => continue,
+		}
+		BOT.cache.update(&VoiceStateUpdate(VoiceState {
+			guild_id: Some(guild),
+			member: None,
+			..state
+		}));
+	}
+
+	tracing::info!(%drift, "refreshed voice states");
+	Ok(Some(drift))
+}
+
+/// Response with the voice state of the user, `None` if not connected.
+async fn fetch(
+	guild: Id<GuildMarker>,
+	user: Id<UserMarker>,
+) -> Result<Option<Response<VoiceState>>, Error> {
+	// not provided by twilight
+	let request = RequestBuilder::raw(
+		Method::Get,
+		Path::GuildsIdVoiceStates(guild.get()),
+		format!("guilds/{guild}/voice-states/{user}"),
+	)
+	.build()?;
+
+	match BOT.http.request(request).await {
+		Ok(response) => Ok(Some(response)),
+		Err(e) if matches!(e.kind(), ErrorType::Response { status, .. } if status.get() == 404) => {
+			Ok(None)
+		}
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Removes the user's cached voice state.
+fn disconnect(guild: Id<GuildMarker>, user: Id<UserMarker>) {
+	let Some(state) = BOT.cache.voice_state(user, guild) else {
+		return;
+	};
+	let update = VoiceStateUpdate(VoiceState {
+		channel_id: None,
+		deaf: state.deaf(),
+		guild_id: Some(guild),
+		member: None,
+		mute: state.mute(),
+		self_deaf: state.self_deaf(),
+		self_mute: state.self_mute(),
+		self_stream: state.self_stream(),
+		self_video: state.self_video(),
+		session_id: state.session_id().to_owned(),
+		suppress: state.suppress(),
+		user_id: user,
+		request_to_speak_timestamp: None,
+	});
+	drop(state);
+	BOT.cache.update(&update);
+}
@@ -0,0 +1,123 @@
+//! Retrying of requests failing with transient errors, i.e. server errors and network failures.
+
+use std::{future::IntoFuture, time::Duration};
+
+use twilight_http::error::{Error, ErrorType};
+
+/// Number of attempts made.
+const ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry, doubled for every subsequent retry.
+const BACKOFF: Duration = Duration::from_secs(2);
+
+/// Whether the error is transient, so that retrying may succeed.
+pub fn is_transient(error: &Error) -> bool {
+	match error.kind() {
+		ErrorType::Response { status, .. } => status.is_server_error(),
+		ErrorType::RequestError
+		| ErrorType::RequestTimedOut
+		| ErrorType::ServiceUnavailable { .. } => true,
+		_ => false,
+	}
+}
+
+/// Sends the request, retrying transient errors with exponential backoff.
+pub async fn transient<F, R, T>(request: F) -> Result<T, Error>
+where
+	F: FnMut() -> R,
+	R: IntoFuture<Output = Result<T, Error>>,
+{
+	retry(request, is_transient).await
+}
+
+/// Sends the request, retrying errors the predicate returns `true` for with exponential backoff.
+async fn retry<F, R, T, E>(mut request: F, retries: fn(&E) -> bool) -> Result<T, E>
+where
+	F: FnMut() -> R,
+	R: IntoFuture<Output = Result<T, E>>,
+	E: std::error::Error + 'static,
+{
+	let mut backoff = BACKOFF;
+	for _ in 1..ATTEMPTS {
+		match request().await {
+			Err(e) if retries(&e) => {
+				tracing::info!(error = &e as &dyn std::error::Error, ?backoff, "retrying");
+				tokio::time::sleep(backoff).await;
+				backoff *= 2;
+			}
+			result => return result,
+		}
+	}
+	request().await
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{fmt, future};
+
+	use tokio::time::Instant;
+
+	use super::*;
+
+	/// Error that is transient if `true`.
+	#[derive(Clone, Debug, PartialEq)]
+	struct Transient(bool);
+
+	impl fmt::Display for Transient {
+		fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+			f.write_str("failed")
+		}
+	}
+
+	impl std::error::Error for Transient {}
+
+	/// Retries the results in order, returning the result and the number of attempts.
+	async fn attempts(results: Vec<Result<(), Transient>>) -> (Result<(), Transient>, usize) {
+		let mut results = results.into_iter();
+		let mut attempts = 0;
+		let result = retry(
+			|| {
+				attempts += 1;
+				future::ready(results.next().expect("not retried too often"))
+			},
+			|e: &Transient| e.0,
+		)
+		.await;
+		(result, attempts)
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn succeeds_after_transient_errors() {
+		let start = Instant::now();
+
+		let results = vec![Err(Transient(true)), Err(Transient(true)), Ok(())];
+		assert_eq!(attempts(results).await, (Ok(()), 3));
+		assert_eq!(start.elapsed(), BACKOFF + BACKOFF * 2);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn gives_up_after_attempts() {
+		let results = vec![Err(Transient(true)); ATTEMPTS as usize];
+		assert_eq!(attempts(results).await, (Err(Transient(true)), 3));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn fails_on_other_errors() {
+		let start = Instant::now();
+
+		let results = vec![Err(Transient(false)), Ok(())];
+		assert_eq!(attempts(results).await, (Err(Transient(false)), 1));
+		assert_eq!(start.elapsed(), Duration::ZERO);
+	}
+
+	#[tokio::test]
+	async fn network_errors_are_transient() {
+		// nothing listens on the discard port, so the request fails without leaving the host
+		let http = twilight_http::Client::builder()
+			.proxy("127.0.0.1:9".to_owned(), true)
+			.build();
+		let e = http.gateway().await.expect_err("connection refused");
+
+		assert!(is_transient(&e));
+	}
+}
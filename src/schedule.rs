@@ -0,0 +1,252 @@
+//! One-shot prunes of a channel at a later time, scheduled through `/schedule-prune` and lost on
+//! restart.
+
+use std::{
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Mutex, PoisonError,
+	},
+	time::Duration,
+};
+
+use tokio::task::JoinHandle;
+use twilight_model::id::{
+	marker::{ChannelMarker, GuildMarker},
+	Id,
+};
+
+use crate::{cleanup::Cleanup, clock, prune::Trigger, BOT};
+
+/// Maximum number of pending jobs per guild.
+pub const LIMIT: usize = 5;
+
+/// Scheduled prune of a channel.
+#[derive(Clone, Copy, Debug)]
+pub struct Job {
+	pub id: u64,
+	pub channel: Id<ChannelMarker>,
+	/// Unix timestamp in seconds the job fires at.
+	pub at: u64,
+}
+
+/// Job whose task has yet to fire.
+#[derive(Debug)]
+struct Pending {
+	job: Job,
+	task: JoinHandle<()>,
+}
+
+/// Pending jobs of every guild.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+	/// ID of the last scheduled job.
+	last: AtomicU64,
+	guilds: Mutex<HashMap<Id<GuildMarker>, Vec<Pending>>>,
+}
+
+impl Scheduler {
+	/// Schedules a prune of the channel after the delay, `None` if the guild has [`LIMIT`]
+	/// pending jobs.
+	pub fn schedule(
+		&self,
+		guild: Id<GuildMarker>,
+		channel: Id<ChannelMarker>,
+		delay: Duration,
+	) -> Option<Job> {
+		let mut guilds = self.guilds.lock().unwrap_or_else(PoisonError::into_inner);
+		let jobs = guilds.entry(guild).or_default();
+		if jobs.len() >= LIMIT {
+			return None;
+		}
+
+		let job = Job {
+			id: self.last.fetch_add(1, Ordering::Relaxed) + 1,
+			channel,
+			at: clock::now().as_secs() + delay.as_secs(),
+		};
+		let task = tokio::spawn(fire(guild, job, delay));
+		jobs.push(Pending { job, task });
+		Some(job)
+	}
+
+	/// Cancels the guild's job, returning whether it was pending.
+	pub fn cancel(&self, guild: Id<GuildMarker>, id: u64) -> bool {
+		self.take(guild, id).map(|task| task.abort()).is_some()
+	}
+
+	/// Cancels every job of the guild, returning how many were pending.
+	pub fn cancel_all(&self, guild: Id<GuildMarker>) -> usize {
+		let jobs = self
+			.guilds
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&guild)
+			.unwrap_or_default();
+		for pending in &jobs {
+			pending.task.abort();
+		}
+		jobs.len()
+	}
+
+	/// Pending jobs of the guild, soonest first.
+	pub fn jobs(&self, guild: Id<GuildMarker>) -> Vec<Job> {
+		let mut jobs: Vec<_> = self
+			.guilds
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&guild)
+			.map_or_else(Vec::new, |jobs| {
+				jobs.iter().map(|pending| pending.job).collect()
+			});
+		jobs.sort_unstable_by_key(|job| (job.at, job.id));
+		jobs
+	}
+
+	/// Removes the guild's job, returning its task.
+	fn take(&self, guild: Id<GuildMarker>, id: u64) -> Option<JoinHandle<()>> {
+		let mut guilds = self.guilds.lock().unwrap_or_else(PoisonError::into_inner);
+		let jobs = guilds.get_mut(&guild)?;
+		let index = jobs.iter().position(|pending| pending.job.id == id)?;
+		let pending = jobs.swap_remove(index);
+		if jobs.is_empty() {
+			guilds.remove(&guild);
+		}
+		Some(pending.task)
+	}
+}
+
+impl Cleanup for Scheduler {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		self.cancel_all(guild);
+	}
+
+	fn channel(&self, guild: Id<GuildMarker>, channel: Id<ChannelMarker>) {
+		let mut guilds = self.guilds.lock().unwrap_or_else(PoisonError::into_inner);
+		let Some(jobs) = guilds.get_mut(&guild) else {
+			return;
+		};
+		jobs.retain(|pending| {
+			let keep = pending.job.channel != channel;
+			if !keep {
+				pending.task.abort();
+			}
+			keep
+		});
+		if jobs.is_empty() {
+			guilds.remove(&guild);
+		}
+	}
+}
+
+/// Prunes the job's channel after the delay, unless the guild has auto pruning disabled or paused
+/// by then.
+#[tracing::instrument(skip_all, fields(guild_id = %guild, job = job.id, channel_id = %job.channel))]
+async fn fire(guild: Id<GuildMarker>, job: Job, delay: Duration) {
+	tokio::time::sleep(delay).await;
+	BOT.scheduler.take(guild, job.id);
+
+	if !BOT.auto_prune(guild) {
+		tracing::info!("skipping scheduled prune: auto pruning disabled or paused");
+		return;
+	}
+	let summary =
+		crate::prune::channel(job.channel, guild, Trigger::Schedule, |_| true, None).await;
+	tracing::info!(users = summary.pruned(), "ran scheduled prune");
+}
+
+#[cfg(test)]
+mod tests {
+	use twilight_model::guild::Permissions;
+
+	use super::*;
+	use crate::test::{removed, Guild};
+
+	const DELAY: Duration = Duration::from_secs(60);
+
+	/// Guild with a user lacking permission to connect to its voice channel `guild + 10`.
+	fn guild(id: u64) -> Id<GuildMarker> {
+		Guild::new(id)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(id + 10, &[])
+			.member(id + 100, &[])
+			.connect(id + 100, id + 10)
+			.create()
+	}
+
+	/// Waits past the delay and for removals to be executed.
+	async fn elapse() {
+		tokio::time::sleep(DELAY).await;
+		for _ in 0..100 {
+			tokio::time::sleep(Duration::from_millis(1)).await;
+		}
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn fires_once() {
+		let guild = guild(3_790_000);
+		let channel = Id::new(3_790_010);
+
+		let before = clock::now().as_secs();
+		let job = BOT.scheduler.schedule(guild, channel, DELAY).unwrap();
+		assert!((before..=clock::now().as_secs()).contains(&(job.at - DELAY.as_secs())));
+		assert_eq!(BOT.scheduler.jobs(guild).len(), 1);
+		elapse().await;
+
+		assert!(BOT.scheduler.jobs(guild).is_empty());
+		assert_eq!(removed(guild), [(guild, Id::new(3_790_100), None)]);
+		assert!(!BOT.scheduler.cancel(guild, job.id));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn limits_pending_jobs() {
+		let guild = guild(3_791_000);
+		let channel = Id::new(3_791_010);
+
+		let jobs: Vec<_> = (0..LIMIT)
+			.map(|_| BOT.scheduler.schedule(guild, channel, DELAY).unwrap())
+			.collect();
+		assert!(BOT.scheduler.schedule(guild, channel, DELAY).is_none());
+		// other guilds have their own limit
+		assert!(BOT
+			.scheduler
+			.schedule(Id::new(3_791_500), channel, DELAY)
+			.is_some());
+		BOT.scheduler.cancel_all(Id::new(3_791_500));
+
+		assert!(BOT.scheduler.cancel(guild, jobs[0].id));
+		assert!(!BOT.scheduler.cancel(guild, jobs[0].id));
+		assert!(BOT.scheduler.schedule(guild, channel, DELAY).is_some());
+		assert_eq!(BOT.scheduler.cancel_all(guild), LIMIT);
+		elapse().await;
+
+		assert!(removed(guild).is_empty());
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn cancelled_with_channel() {
+		let guild = guild(3_792_000);
+		let channel = Id::new(3_792_010);
+		let other = BOT
+			.scheduler
+			.schedule(guild, Id::new(3_792_011), DELAY * 2)
+			.unwrap();
+		BOT.scheduler.schedule(guild, channel, DELAY).unwrap();
+
+		Cleanup::channel(&BOT.scheduler, guild, channel);
+		assert_eq!(
+			BOT.scheduler
+				.jobs(guild)
+				.iter()
+				.map(|job| job.id)
+				.collect::<Vec<_>>(),
+			[other.id]
+		);
+		Cleanup::guild(&BOT.scheduler, guild);
+		assert!(BOT.scheduler.jobs(guild).is_empty());
+		elapse().await;
+		elapse().await;
+
+		assert!(removed(guild).is_empty());
+	}
+}
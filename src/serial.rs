@@ -0,0 +1,152 @@
+//! Per-guild ordering of event handling.
+//!
+//! The handling of a guild's events runs one at a time, in the order the events were received,
+//! so that one event's auto prune never overlaps the handling of the next. Different guilds are
+//! still handled concurrently.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	fmt,
+	future::Future,
+	panic::AssertUnwindSafe,
+	sync::{Mutex, PoisonError},
+};
+
+use futures_util::{future::BoxFuture, FutureExt};
+use twilight_model::id::{marker::GuildMarker, Id};
+
+/// Queued handling per guild, present while a task is running it.
+#[derive(Default)]
+pub struct Serial(Mutex<HashMap<Id<GuildMarker>, VecDeque<BoxFuture<'static, ()>>>>);
+
+impl fmt::Debug for Serial {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let guilds = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		f.debug_map()
+			.entries(guilds.iter().map(|(guild, queue)| (guild, queue.len())))
+			.finish()
+	}
+}
+
+impl Serial {
+	/// Runs the future once the guild's previously queued futures finished.
+	pub fn run(
+		&'static self,
+		guild: Id<GuildMarker>,
+		future: impl Future<Output = ()> + Send + 'static,
+	) {
+		let future = future.boxed();
+		let mut guilds = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		if let Some(queue) = guilds.get_mut(&guild) {
+			queue.push_back(future);
+			return;
+		}
+		guilds.insert(guild, VecDeque::new());
+		drop(guilds);
+		tokio::spawn(self.drain(guild, future));
+	}
+
+	/// Whether no handling of the guild is queued or running.
+	#[cfg(test)]
+	pub fn is_idle(&self, guild: Id<GuildMarker>) -> bool {
+		!self
+			.0
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.contains_key(&guild)
+	}
+
+	/// Runs the future and then the guild's queued futures, until none are left.
+	async fn drain(&self, guild: Id<GuildMarker>, mut future: BoxFuture<'static, ()>) {
+		loop {
+			// a panicking handler must not stall the guild's later events
+			if AssertUnwindSafe(future).catch_unwind().await.is_err() {
+				tracing::error!(guild_id = %guild, "event handling panicked");
+			}
+			let mut guilds = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+			let queue = guilds.get_mut(&guild).expect("draining guild is present");
+			match queue.pop_front() {
+				Some(next) => future = next,
+				None => {
+					guilds.remove(&guild);
+					return;
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		sync::{
+			atomic::{AtomicBool, Ordering},
+			Arc,
+		},
+		time::Duration,
+	};
+
+	use super::*;
+
+	/// Waits for the handling of the guilds to finish.
+	async fn idle(serial: &Serial, guilds: impl Iterator<Item = u64> + Clone) {
+		while !guilds.clone().all(|guild| serial.is_idle(Id::new(guild))) {
+			tokio::time::sleep(Duration::from_millis(1)).await;
+		}
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn runs_in_order_per_guild() {
+		const GUILDS: u64 = 4;
+		const EVENTS: u64 = 100;
+		let serial: &'static Serial = Box::leak(Box::default());
+		let log = Arc::new(Mutex::new(Vec::new()));
+
+		// interleaved as the gateway delivers events of several guilds
+		for event in 0..EVENTS {
+			for guild in 1..=GUILDS {
+				let log = Arc::clone(&log);
+				// later events finish sooner if run concurrently
+				let delay = Duration::from_millis((EVENTS - event) * guild % 7);
+				serial.run(Id::new(guild), async move {
+					tokio::time::sleep(delay).await;
+					log.lock().unwrap().push((guild, event));
+				});
+			}
+		}
+		idle(serial, 1..=GUILDS).await;
+
+		let log = log.lock().unwrap();
+		assert_eq!(log.len() as u64, GUILDS * EVENTS);
+		for guild in 1..=GUILDS {
+			let events: Vec<_> = log
+				.iter()
+				.filter(|&&(id, _)| id == guild)
+				.map(|&(_, event)| event)
+				.collect();
+			assert_eq!(events, (0..EVENTS).collect::<Vec<_>>(), "guild {guild}");
+		}
+		// guilds are handled concurrently
+		let last = log
+			.iter()
+			.position(|&entry| entry == (1, EVENTS - 1))
+			.unwrap();
+		assert!(log[..last].iter().any(|&(guild, _)| guild != 1));
+	}
+
+	#[tokio::test]
+	async fn continues_after_panic() {
+		let serial: &'static Serial = Box::leak(Box::default());
+		let ran = Arc::new(AtomicBool::new(false));
+
+		serial.run(Id::new(1), async { panic!("handler bug") });
+		let after = Arc::clone(&ran);
+		serial.run(
+			Id::new(1),
+			async move { after.store(true, Ordering::Relaxed) },
+		);
+		idle(serial, 1..=1).await;
+
+		assert!(ran.load(Ordering::Relaxed));
+	}
+}
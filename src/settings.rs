@@ -0,0 +1,393 @@
+//! Per-guild settings.
+//!
+//! Settings are persisted to `settings.json` inside the [state directory](crate::state) if
+//! available, otherwise they are lost on restart.
+
+use std::{
+	collections::HashMap,
+	fmt,
+	sync::{Mutex, MutexGuard, PoisonError},
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{
+	marker::{ChannelMarker, GuildMarker, RoleMarker},
+	Id,
+};
+
+use crate::{cleanup::Cleanup, clock, state};
+
+/// Whether prunes remove users.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+	/// Remove users.
+	#[default]
+	Enforce,
+	/// Only report which users would have been removed.
+	Report,
+}
+
+impl fmt::Display for Mode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Enforce => "enforce",
+			Self::Report => "report",
+		})
+	}
+}
+
+/// Channels auto prunes act on.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoPruneScope {
+	/// Every monitored channel.
+	#[default]
+	All,
+	/// Only the guild's [`Settings::auto_channels`].
+	Selected,
+}
+
+impl fmt::Display for AutoPruneScope {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::All => "all",
+			Self::Selected => "selected",
+		})
+	}
+}
+
+/// Order prune candidates are evaluated and removed in.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PruneOrder {
+	/// Whichever order the cache yields them in.
+	#[default]
+	Unordered,
+	/// Lowest highest role position first, then by user ID.
+	RolePosition,
+}
+
+impl fmt::Display for PruneOrder {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Unordered => "unordered",
+			Self::RolePosition => "role-position",
+		})
+	}
+}
+
+/// Settings of a guild.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Settings {
+	/// Whether auto prunes log the moderator who made the change causing them, found through the
+	/// audit log.
+	pub attribute_changes: bool,
+	/// Channels auto prunes are restricted to if [`Self::auto_prune_scope`] is
+	/// [`AutoPruneScope::Selected`].
+	pub auto_channels: Vec<Id<ChannelMarker>>,
+	/// Channels auto prunes act on.
+	pub auto_prune_scope: AutoPruneScope,
+	/// Whether [`Self::clear_stage_on_end`] also removes permitted users who aren't stage
+	/// moderators.
+	pub clear_stage_audience: bool,
+	/// Whether users who stay in a stage channel after its stage ends are pruned.
+	pub clear_stage_on_end: bool,
+	/// Whether pruned users are notified through a DM.
+	pub dm_on_prune: bool,
+	/// Minutes users may be deafened before being periodically pruned, disabled if `None`.
+	pub idle_timeout: Option<u32>,
+	/// Whether bots are never pruned, except by `/prune` with `include-bots`.
+	pub ignore_bots: bool,
+	/// Template of the audit log reason of removals, see [`crate::reason`], without a reason if
+	/// `None`.
+	pub kick_reason: Option<String>,
+	/// Maximum number of users an auto prune may remove, aborting it otherwise.
+	pub max_auto_prune: Option<u32>,
+	/// Whether prunes remove users or only report them.
+	pub mode: Mode,
+	/// Whether stage channels are monitored, overriding the default if set.
+	pub monitor_stage: Option<bool>,
+	/// Suffix of the names of voice channels that are never monitored, disabled if `None`.
+	pub name_marker: Option<String>,
+	/// Unix timestamp in seconds until which auto pruning is paused.
+	pub paused_until: Option<u64>,
+	/// Order prune candidates are removed in.
+	pub prune_order: PruneOrder,
+	/// Whether members who haven't completed membership screening are pruned regardless of their
+	/// permissions.
+	pub prune_pending: bool,
+	/// Roles whose members may use `/prune`, anyone permitted by Discord if empty.
+	pub prune_roles: Vec<Id<RoleMarker>>,
+	/// Whether users in the AFK channel are pruned, although the client lets anyone be moved
+	/// there.
+	pub prune_afk: bool,
+	/// Minutes the status of pruned voice channels notes the prune for, disabled if `None`.
+	pub prune_status: Option<u32>,
+	/// Whether auto pruning moves users to a permitted sibling voice channel instead of
+	/// disconnecting them.
+	pub soft_prune: bool,
+	/// Whether users are verified against freshly fetched data before being disconnected.
+	pub verify_before_kick: bool,
+}
+
+impl Default for Settings {
+	fn default() -> Self {
+		Self {
+			attribute_changes: false,
+			auto_channels: Vec::new(),
+			auto_prune_scope: AutoPruneScope::default(),
+			clear_stage_audience: false,
+			clear_stage_on_end: false,
+			dm_on_prune: false,
+			idle_timeout: None,
+			ignore_bots: true,
+			kick_reason: None,
+			max_auto_prune: None,
+			mode: Mode::default(),
+			monitor_stage: None,
+			name_marker: None,
+			paused_until: None,
+			prune_afk: false,
+			prune_order: PruneOrder::default(),
+			prune_pending: false,
+			prune_roles: Vec::new(),
+			prune_status: None,
+			soft_prune: false,
+			verify_before_kick: false,
+		}
+	}
+}
+
+/// Setting listed by [`Fields::fields`].
+#[derive(Debug)]
+pub struct Field {
+	/// Kebab-case name.
+	pub name: String,
+	pub value: serde_json::Value,
+	/// Whether the value is the default one.
+	pub default: bool,
+}
+
+/// Lists every setting of a struct through its serialized form, so new settings are included.
+pub trait Fields: Default + Serialize {
+	/// Every setting, ordered by name.
+	fn fields(&self) -> Vec<Field> {
+		let serde_json::Value::Object(defaults) =
+			serde_json::to_value(Self::default()).expect("serializable")
+		else {
+			unreachable!("serialized as a map")
+		};
+		let serde_json::Value::Object(values) = serde_json::to_value(self).expect("serializable")
+		else {
+			unreachable!("serialized as a map")
+		};
+		values
+			.into_iter()
+			.map(|(name, value)| Field {
+				default: defaults.get(&name) == Some(&value),
+				name,
+				value,
+			})
+			.collect()
+	}
+}
+
+impl Fields for Settings {}
+
+impl Settings {
+	/// Channels auto prunes are restricted to, `None` if every channel.
+	pub fn auto_channels(&self) -> Option<&[Id<ChannelMarker>]> {
+		(self.auto_prune_scope == AutoPruneScope::Selected).then_some(self.auto_channels.as_slice())
+	}
+
+	/// Whether auto prunes act on the channel.
+	pub fn is_auto_channel(&self, channel: Id<ChannelMarker>) -> bool {
+		self.auto_channels()
+			.map_or(true, |channels| channels.contains(&channel))
+	}
+
+	/// Whether the channel name ends with the [name marker](Self::name_marker).
+	pub fn is_marked(&self, name: &str) -> bool {
+		self.name_marker
+			.as_deref()
+			.is_some_and(|marker| !marker.is_empty() && name.ends_with(marker))
+	}
+
+	/// Remaining time auto pruning is paused for, `None` if not paused or expired.
+	pub fn paused_for(&self) -> Option<Duration> {
+		let remaining = self.paused_until?.checked_sub(clock::now().as_secs())?;
+		(remaining != 0).then(|| Duration::from_secs(remaining))
+	}
+
+	/// Pauses auto pruning for the duration.
+	pub fn pause(&mut self, duration: Duration) {
+		self.paused_until = Some(clock::now().as_secs() + duration.as_secs());
+	}
+}
+
+/// Current version of [`Export`].
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Settings of a guild to be imported into another, e.g. through `/settings import`.
+///
+/// Guild specific state, i.e. an auto prune pause, is excluded. Unknown fields are ignored so that
+/// exports of newer versions with added settings still import.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Export {
+	pub version: u32,
+	#[serde(flatten)]
+	pub settings: Settings,
+}
+
+impl Export {
+	pub fn new(settings: &Settings) -> Self {
+		Self {
+			version: EXPORT_VERSION,
+			settings: Settings {
+				paused_until: None,
+				..settings.clone()
+			},
+		}
+	}
+
+	/// Applies the exported settings, keeping guild specific state.
+	pub fn apply(self, settings: &mut Settings) {
+		*settings = Settings {
+			paused_until: settings.paused_until,
+			..self.settings
+		};
+	}
+}
+
+/// Storage of every guild's [`Settings`].
+#[derive(Debug)]
+pub struct Store {
+	guilds: Mutex<HashMap<Id<GuildMarker>, Settings>>,
+	/// File the settings are persisted to.
+	file: Option<state::File>,
+}
+
+impl Store {
+	/// Loads the settings from the state directory.
+	#[tracing::instrument(name = "load settings")]
+	pub fn load() -> Result<Self, anyhow::Error> {
+		let Some(file) = state::File::open("settings.json") else {
+			tracing::info!("no state directory, settings are not persisted");
+			return Ok(Self {
+				guilds: Mutex::default(),
+				file: None,
+			});
+		};
+
+		Ok(Self {
+			guilds: Mutex::new(file.read()?),
+			file: Some(file),
+		})
+	}
+
+	/// Settings of the guild.
+	pub fn get(&self, guild: Id<GuildMarker>) -> Settings {
+		self.guilds
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&guild)
+			.cloned()
+			.unwrap_or_default()
+	}
+
+	/// Every guild with non-default settings.
+	pub fn guilds(&self) -> Vec<(Id<GuildMarker>, Settings)> {
+		self.guilds
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.iter()
+			.map(|(&guild, settings)| (guild, settings.clone()))
+			.collect()
+	}
+
+	/// Modify the settings of the guild, persisting them on success.
+	pub fn update(&self, guild: Id<GuildMarker>, f: impl FnOnce(&mut Settings)) {
+		let mut guilds = self.guilds.lock().unwrap_or_else(PoisonError::into_inner);
+		let settings = guilds.entry(guild).or_default();
+		f(settings);
+		if *settings == Settings::default() {
+			guilds.remove(&guild);
+		}
+
+		self.persist(guilds);
+	}
+
+	/// Writes the settings to the state directory after releasing their lock, logging on error.
+	fn persist(&self, guilds: MutexGuard<'_, HashMap<Id<GuildMarker>, Settings>>) {
+		if let Some(file) = &self.file {
+			let snapshot = file.snapshot(&*guilds);
+			drop(guilds);
+			file.write(snapshot);
+		}
+	}
+}
+
+impl Cleanup for Store {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		let mut guilds = self.guilds.lock().unwrap_or_else(PoisonError::into_inner);
+		if guilds.remove(&guild).is_some() {
+			self.persist(guilds);
+		}
+	}
+
+	fn channel(&self, guild: Id<GuildMarker>, channel: Id<ChannelMarker>) {
+		let selected = self
+			.guilds
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&guild)
+			.is_some_and(|settings| settings.auto_channels.contains(&channel));
+		if selected {
+			self.update(guild, |settings| {
+				settings.auto_channels.retain(|&id| id != channel);
+			});
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn pause_expires() {
+		let mut settings = Settings::default();
+		assert_eq!(settings.paused_for(), None);
+
+		settings.pause(Duration::from_secs(60 * 60));
+		let remaining = settings.paused_for().expect("paused");
+		assert!(remaining <= Duration::from_secs(60 * 60));
+		assert!(remaining >= Duration::from_secs(60 * 60 - 5));
+
+		let now = clock::now().as_secs();
+		settings.paused_until = Some(now);
+		assert_eq!(settings.paused_for(), None, "expired exactly now");
+		settings.paused_until = Some(now - 1);
+		assert_eq!(settings.paused_for(), None);
+	}
+
+	#[test]
+	fn fields() {
+		let mut settings = Settings::default();
+		let fields = settings.fields();
+		assert!(fields.iter().all(|field| field.default));
+		assert!(fields.windows(2).all(|pair| pair[0].name < pair[1].name));
+
+		settings.mode = Mode::Report;
+		let fields = settings.fields();
+		let set: Vec<_> = fields
+			.iter()
+			.filter(|field| !field.default)
+			.map(|field| (field.name.as_str(), &field.value))
+			.collect();
+		assert_eq!(set, [("mode", &serde_json::json!("report"))]);
+	}
+}
@@ -0,0 +1,159 @@
+//! Files inside systemd's [state directory], persisting data across restarts.
+//!
+//! Files are replaced atomically by writing a temporary file and renaming it over the previous
+//! one, so a crash mid-write leaves the previous contents intact.
+//!
+//! [state directory]: https://www.freedesktop.org/software/systemd/man/systemd.exec.html#RuntimeDirectory=
+
+use std::{
+	env, fs,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Mutex, PoisonError,
+	},
+};
+
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// File in the state directory.
+#[derive(Debug)]
+pub struct File {
+	path: PathBuf,
+	/// Sequence number of the last taken snapshot.
+	taken: AtomicU64,
+	/// Sequence number of the last written snapshot, held while writing.
+	written: Mutex<u64>,
+}
+
+/// Serialized contents to write, see [`File::snapshot`].
+#[derive(Debug)]
+pub struct Snapshot {
+	sequence: u64,
+	contents: Vec<u8>,
+}
+
+impl File {
+	/// The file with the name in the state directory, `None` without a state directory.
+	pub fn open(name: &str) -> Option<Self> {
+		let directory = env::var_os("STATE_DIRECTORY")?;
+		Some(Self::new(Path::new(&directory).join(name)))
+	}
+
+	fn new(path: PathBuf) -> Self {
+		Self {
+			path,
+			taken: AtomicU64::new(0),
+			written: Mutex::new(0),
+		}
+	}
+
+	/// Reads and parses the file, or the default value if it doesn't exist.
+	pub fn read<T: DeserializeOwned + Default>(&self) -> Result<T, anyhow::Error> {
+		match fs::read(&self.path) {
+			Ok(contents) => serde_json::from_slice(&contents)
+				.with_context(|| format!("unable to parse {}", self.path.display())),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+			Err(e) => Err(e).with_context(|| format!("unable to read {}", self.path.display())),
+		}
+	}
+
+	/// Serializes the value to be written.
+	///
+	/// Must be called while holding the value's lock, so that snapshots are ordered like the
+	/// value's changes.
+	pub fn snapshot(&self, value: &impl Serialize) -> Snapshot {
+		Snapshot {
+			sequence: self.taken.fetch_add(1, Ordering::Relaxed) + 1,
+			contents: serde_json::to_vec(value).expect("serializable"),
+		}
+	}
+
+	/// Replaces the file with the snapshot, unless a later one was already written, logging on
+	/// error.
+	pub fn write(&self, snapshot: Snapshot) {
+		let mut written = self.written.lock().unwrap_or_else(PoisonError::into_inner);
+		if snapshot.sequence <= *written {
+			return;
+		}
+
+		let mut temporary = self.path.clone().into_os_string();
+		temporary.push(".tmp");
+		let result = fs::write(&temporary, snapshot.contents)
+			.and_then(|()| fs::rename(&temporary, &self.path));
+		match result {
+			Ok(()) => *written = snapshot.sequence,
+			Err(e) => tracing::warn!(
+				path = %self.path.display(),
+				error = &e as &dyn std::error::Error,
+				"unable to persist"
+			),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use super::*;
+
+	/// File in a directory unique to the test.
+	fn file(test: &str) -> File {
+		let directory = env::temp_dir().join(format!("voice-pruner-{}-{test}", std::process::id()));
+		_ = fs::remove_dir_all(&directory);
+		fs::create_dir_all(&directory).unwrap();
+		File::new(directory.join("state.json"))
+	}
+
+	#[test]
+	fn round_trips() {
+		let file = file("round-trips");
+		assert_eq!(file.read::<HashMap<u64, u64>>().unwrap(), HashMap::new());
+
+		let value = HashMap::from([(1, 2)]);
+		file.write(file.snapshot(&value));
+
+		assert_eq!(file.read::<HashMap<u64, u64>>().unwrap(), value);
+		let entries: Vec<_> = fs::read_dir(file.path.parent().unwrap())
+			.unwrap()
+			.map(|entry| entry.unwrap().file_name())
+			.collect();
+		assert_eq!(entries, ["state.json"], "temporary file renamed");
+	}
+
+	#[test]
+	fn skips_stale_snapshots() {
+		let file = file("skips-stale-snapshots");
+		let old = file.snapshot(&1);
+		let new = file.snapshot(&2);
+
+		file.write(new);
+		file.write(old);
+
+		assert_eq!(file.read::<u64>().unwrap(), 2);
+	}
+
+	#[test]
+	fn keeps_contents_on_failed_write() {
+		let file = file("keeps-contents-on-failed-write");
+		file.write(file.snapshot(&1));
+		// the temporary file can't be created in place of a directory
+		let mut temporary = file.path.clone().into_os_string();
+		temporary.push(".tmp");
+		fs::create_dir(&temporary).unwrap();
+
+		file.write(file.snapshot(&2));
+
+		assert_eq!(file.read::<u64>().unwrap(), 1);
+	}
+
+	#[test]
+	fn rejects_invalid_contents() {
+		let file = file("rejects-invalid-contents");
+		fs::write(&file.path, "{").unwrap();
+
+		assert!(file.read::<u64>().is_err());
+	}
+}
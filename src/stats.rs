@@ -0,0 +1,228 @@
+//! Daily prune statistics of the last [`RETENTION`] days.
+//!
+//! Statistics are persisted to `stats.json` inside the [state directory](crate::state) if
+//! available, otherwise they are lost on restart.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{
+	marker::{ChannelMarker, GuildMarker},
+	Id,
+};
+
+use crate::{
+	cleanup::Cleanup,
+	clock,
+	prune::{ChannelKind, Summary, Trigger},
+	state,
+};
+
+/// Number of days statistics are kept for.
+pub const RETENTION: u64 = 30;
+
+/// Seconds per day.
+const DAY: u64 = 24 * 60 * 60;
+
+/// Statistics of a day.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Day {
+	/// Days since the Unix epoch.
+	pub day: u64,
+	/// Number of auto prunes.
+	pub auto: u32,
+	/// Number of `/prune` commands.
+	pub manual: u32,
+	/// Number of users pruned.
+	pub users: u32,
+	/// Number of users pruned per channel.
+	pub channels: HashMap<Id<ChannelMarker>, u32>,
+	/// Number of users pruned per channel kind, absent from statistics recorded before kinds were.
+	#[serde(default)]
+	pub kinds: HashMap<ChannelKind, u32>,
+}
+
+/// Current day, as days since the Unix epoch.
+pub fn today() -> u64 {
+	clock::now().as_secs() / DAY
+}
+
+/// Daily statistics of every guild, oldest first.
+#[derive(Debug)]
+pub struct Stats {
+	guilds: Mutex<HashMap<Id<GuildMarker>, VecDeque<Day>>>,
+	/// File the statistics are persisted to.
+	file: Option<state::File>,
+}
+
+impl Stats {
+	/// Loads the statistics from the state directory.
+	#[tracing::instrument(name = "load stats")]
+	pub fn load() -> Result<Self, anyhow::Error> {
+		let Some(file) = state::File::open("stats.json") else {
+			return Ok(Self {
+				guilds: Mutex::default(),
+				file: None,
+			});
+		};
+
+		Ok(Self {
+			guilds: Mutex::new(file.read()?),
+			file: Some(file),
+		})
+	}
+
+	/// Whether the statistics survive restarts.
+	pub const fn is_persisted(&self) -> bool {
+		self.file.is_some()
+	}
+
+	/// Counts the prune if it pruned any users.
+	pub fn record(&self, guild: Id<GuildMarker>, trigger: Trigger, summary: &Summary) {
+		self.record_on(today(), guild, trigger, summary);
+	}
+
+	fn record_on(&self, today: u64, guild: Id<GuildMarker>, trigger: Trigger, summary: &Summary) {
+		if summary.channels.is_empty() {
+			return;
+		}
+
+		let mut guilds = self.guilds.lock().unwrap_or_else(PoisonError::into_inner);
+		let days = guilds.entry(guild).or_default();
+		while days.front().is_some_and(|day| day.day + RETENTION <= today) {
+			days.pop_front();
+		}
+		if days.back().map_or(true, |day| day.day != today) {
+			days.push_back(Day {
+				day: today,
+				..Day::default()
+			});
+		}
+
+		let day = days.back_mut().expect("not empty");
+		if trigger.is_auto() {
+			day.auto += 1;
+		} else {
+			day.manual += 1;
+		}
+		for prune in &summary.channels {
+			let users = prune.users.len() as u32;
+			day.users += users;
+			*day.channels.entry(prune.channel).or_default() += users;
+			*day.kinds.entry(prune.kind).or_default() += users;
+		}
+
+		self.persist(guilds);
+	}
+
+	/// Statistics of the guild's last [`RETENTION`] days, oldest first, omitting days without
+	/// prunes.
+	pub fn guild(&self, guild: Id<GuildMarker>) -> Vec<Day> {
+		self.guild_on(today(), guild)
+	}
+
+	fn guild_on(&self, today: u64, guild: Id<GuildMarker>) -> Vec<Day> {
+		self.guilds
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&guild)
+			.map_or_else(Vec::new, |days| {
+				days.iter()
+					.filter(|day| day.day + RETENTION > today)
+					.cloned()
+					.collect()
+			})
+	}
+
+	/// Writes the statistics to the state directory after releasing their lock, logging on
+	/// error.
+	fn persist(&self, guilds: MutexGuard<'_, HashMap<Id<GuildMarker>, VecDeque<Day>>>) {
+		if let Some(file) = &self.file {
+			let snapshot = file.snapshot(&*guilds);
+			drop(guilds);
+			file.write(snapshot);
+		}
+	}
+}
+
+impl Cleanup for Stats {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		let mut guilds = self.guilds.lock().unwrap_or_else(PoisonError::into_inner);
+		if guilds.remove(&guild).is_some() {
+			self.persist(guilds);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::prune::ChannelPrune;
+
+	const TODAY: u64 = 20_000;
+
+	fn stats() -> Stats {
+		Stats {
+			guilds: Mutex::default(),
+			file: None,
+		}
+	}
+
+	/// Summary of a prune of the users from channel `3440010`.
+	fn pruned(users: &[u64]) -> Summary {
+		let mut summary = Summary::default();
+		summary.channels.push(ChannelPrune {
+			channel: Id::new(3_440_010),
+			kind: ChannelKind::Voice,
+			users: users.iter().copied().map(Id::new).collect(),
+		});
+		summary
+	}
+
+	#[test]
+	fn buckets_by_day() {
+		let stats = stats();
+		let guild = Id::new(3_440_000);
+
+		stats.record_on(TODAY, guild, Trigger::Role, &pruned(&[1, 2]));
+		stats.record_on(TODAY, guild, Trigger::Command, &pruned(&[3]));
+		stats.record_on(TODAY + 1, guild, Trigger::Role, &pruned(&[1]));
+		// prunes without pruned users are not counted
+		stats.record_on(TODAY + 1, guild, Trigger::Role, &Summary::default());
+
+		let days = stats.guild_on(TODAY + 1, guild);
+		let counts: Vec<_> = days
+			.iter()
+			.map(|day| (day.day, day.auto, day.manual, day.users))
+			.collect();
+		assert_eq!(counts, [(TODAY, 1, 1, 3), (TODAY + 1, 1, 0, 1)]);
+		assert_eq!(days[0].channels[&Id::new(3_440_010)], 3);
+		assert_eq!(days[0].kinds[&ChannelKind::Voice], 3);
+	}
+
+	#[test]
+	fn keeps_retention_days() {
+		let stats = stats();
+		let guild = Id::new(3_440_000);
+		stats.record_on(TODAY, guild, Trigger::Role, &pruned(&[1]));
+		stats.record_on(TODAY + 1, guild, Trigger::Role, &pruned(&[1]));
+
+		let days = |today| -> Vec<_> {
+			stats
+				.guild_on(today, guild)
+				.iter()
+				.map(|day| day.day)
+				.collect()
+		};
+		assert_eq!(days(TODAY + RETENTION - 1), [TODAY, TODAY + 1]);
+		assert_eq!(days(TODAY + RETENTION), [TODAY + 1]);
+
+		stats.record_on(TODAY + RETENTION, guild, Trigger::Role, &pruned(&[1]));
+		let stored = stats.guilds.lock().unwrap()[&guild].len();
+		assert_eq!(stored, 2, "expired days are dropped");
+	}
+}
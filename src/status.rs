@@ -0,0 +1,265 @@
+//! Voice channel statuses noting the channel's last prune, enabled through
+//! `/settings prune-status`.
+//!
+//! A status is cleared once the guild's duration elapses, unless a later prune of the channel
+//! replaced it. Failing to set or clear a status is only logged.
+
+use std::{
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Mutex, PoisonError,
+	},
+	time::Duration,
+};
+
+use serde::Serialize;
+use twilight_http::{
+	request::{Method, RequestBuilder},
+	routing::Path,
+};
+use twilight_model::{
+	guild::Permissions,
+	id::{
+		marker::{ChannelMarker, GuildMarker},
+		Id,
+	},
+};
+
+use crate::{cleanup::Cleanup, clock, prune::Summary, BOT};
+
+/// Body of the voice channel status request.
+#[derive(Serialize)]
+struct Body<'a> {
+	status: &'a str,
+}
+
+/// Generation of the last status set per channel.
+type Generations = HashMap<Id<ChannelMarker>, u64>;
+
+/// Statuses set by the bot that are still to be cleared.
+#[derive(Debug, Default)]
+pub struct Statuses {
+	channels: Mutex<HashMap<Id<GuildMarker>, Generations>>,
+	next: AtomicU64,
+}
+
+impl Statuses {
+	/// Starts a new generation of the channel's status, superseding the current one.
+	fn supersede(&self, guild: Id<GuildMarker>, channel: Id<ChannelMarker>) -> u64 {
+		let generation = self.next.fetch_add(1, Ordering::Relaxed);
+		self.channels
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.entry(guild)
+			.or_default()
+			.insert(channel, generation);
+		generation
+	}
+
+	/// Whether the generation is still the channel's current one, forgetting it if so.
+	fn take(&self, guild: Id<GuildMarker>, channel: Id<ChannelMarker>, generation: u64) -> bool {
+		let mut guilds = self.channels.lock().unwrap_or_else(PoisonError::into_inner);
+		let Some(channels) = guilds.get_mut(&guild) else {
+			return false;
+		};
+		if channels.get(&channel) != Some(&generation) {
+			return false;
+		}
+		channels.remove(&channel);
+		if channels.is_empty() {
+			guilds.remove(&guild);
+		}
+		true
+	}
+}
+
+impl Cleanup for Statuses {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		self.channels
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&guild);
+	}
+
+	fn channel(&self, guild: Id<GuildMarker>, channel: Id<ChannelMarker>) {
+		if let Some(channels) = self
+			.channels
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get_mut(&guild)
+		{
+			channels.remove(&channel);
+		}
+	}
+}
+
+/// Sets the status of the channels users were removed from, if enabled by the guild.
+pub fn update(guild: Id<GuildMarker>, summary: &Summary) {
+	let Some(minutes) = BOT.settings.get(guild).prune_status else {
+		return;
+	};
+	let keep = Duration::from_secs(u64::from(minutes) * 60);
+	let time = clock();
+	for prune in &summary.channels {
+		let generation = BOT.statuses.supersede(guild, prune.channel);
+		let status = format!("🧹 pruned {} users · {time} UTC", prune.users.len());
+		tokio::spawn(show(guild, prune.channel, generation, status, keep));
+	}
+}
+
+/// Sets the status, then clears it after `keep` unless superseded.
+async fn show(
+	guild: Id<GuildMarker>,
+	channel: Id<ChannelMarker>,
+	generation: u64,
+	status: String,
+	keep: Duration,
+) {
+	if !set(channel, &status).await {
+		BOT.statuses.take(guild, channel, generation);
+		return;
+	}
+	tokio::time::sleep(keep).await;
+	if BOT.statuses.take(guild, channel, generation) {
+		set(channel, "").await;
+	}
+}
+
+/// Sets the channel's status, returning whether it succeeded.
+async fn set(channel: Id<ChannelMarker>, status: &str) -> bool {
+	// setting the status of a channel the bot isn't connected to requires managing it
+	let permitted = BOT
+		.cache
+		.permissions()
+		.in_channel(BOT.id, channel)
+		.is_ok_and(|permissions| permissions.contains(Permissions::MANAGE_CHANNELS));
+	if !permitted {
+		tracing::warn!(channel_id = %channel, "unable to set voice channel status: missing permissions");
+		return false;
+	}
+
+	// not provided by twilight
+	let request = RequestBuilder::raw(
+		Method::Put,
+		Path::ChannelsId(channel.get()),
+		format!("channels/{channel}/voice-status"),
+	)
+	.json(&Body { status })
+	.build();
+	let result = match request {
+		Ok(request) => BOT.http.request::<()>(request).await.map(drop),
+		Err(e) => Err(e),
+	};
+	if let Err(e) = result {
+		tracing::warn!(channel_id = %channel, error = &e as &dyn std::error::Error, "unable to set voice channel status");
+		return false;
+	}
+	true
+}
+
+/// Current UTC time of day as `HH:MM`.
+fn clock() -> String {
+	let secs = clock::now().as_secs();
+	format!("{:02}:{:02}", secs / 3600 % 24, secs / 60 % 60)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use twilight_model::{
+		guild::Permissions,
+		id::{marker::ChannelMarker, Id},
+	};
+
+	use super::Statuses;
+	use crate::{
+		test::{role_overwrite, Guild},
+		BOT,
+	};
+
+	#[test]
+	fn superseded_generation_is_not_taken() {
+		let statuses = Statuses::default();
+		let (guild, channel) = (Id::new(3_770_000), Id::new(3_770_010));
+
+		let first = statuses.supersede(guild, channel);
+		let second = statuses.supersede(guild, channel);
+		assert!(!statuses.take(guild, channel, first));
+		assert!(statuses.take(guild, channel, second));
+		assert!(!statuses.take(guild, channel, second), "forgotten");
+		assert!(statuses.channels.lock().unwrap().is_empty());
+	}
+
+	/// Statuses set for the channel by the bot, in order.
+	fn statuses(channel: Id<ChannelMarker>) -> Vec<String> {
+		crate::test::requests(&format!("/channels/{channel}/voice-status"))
+			.into_iter()
+			.inspect(|request| assert_eq!(request.method, "PUT"))
+			.map(|request| request.body["status"].as_str().expect("status").to_owned())
+			.collect()
+	}
+
+	/// Voice channel `guild + 10`, which the bot may manage if `manage`.
+	fn channel(guild: u64, manage: bool) -> Id<ChannelMarker> {
+		let overwrites = if manage {
+			vec![role_overwrite(
+				guild + 1,
+				Permissions::MANAGE_CHANNELS,
+				Permissions::empty(),
+			)]
+		} else {
+			vec![]
+		};
+		Guild::new(guild).voice(guild + 10, &overwrites).create();
+		Id::new(guild + 10)
+	}
+
+	#[tokio::test]
+	async fn sets_then_clears_status() {
+		const GUILD: u64 = 3_771_000;
+		let channel = channel(GUILD, true);
+		let guild = Id::new(GUILD);
+
+		let generation = BOT.statuses.supersede(guild, channel);
+		let status = "🧹 pruned 2 users · 14:05 UTC".to_owned();
+		super::show(guild, channel, generation, status.clone(), Duration::ZERO).await;
+
+		assert_eq!(statuses(channel), [status, String::new()]);
+	}
+
+	#[tokio::test]
+	async fn superseded_status_is_not_cleared() {
+		const GUILD: u64 = 3_772_000;
+		let channel = channel(GUILD, true);
+		let guild = Id::new(GUILD);
+
+		let generation = BOT.statuses.supersede(guild, channel);
+		BOT.statuses.supersede(guild, channel);
+		let status = "🧹 pruned 1 users · 14:05 UTC".to_owned();
+		super::show(guild, channel, generation, status.clone(), Duration::ZERO).await;
+
+		assert_eq!(statuses(channel), [status]);
+	}
+
+	#[tokio::test]
+	async fn missing_permissions_set_nothing() {
+		const GUILD: u64 = 3_773_000;
+		let channel = channel(GUILD, false);
+		let guild = Id::new(GUILD);
+
+		let generation = BOT.statuses.supersede(guild, channel);
+		super::show(
+			guild,
+			channel,
+			generation,
+			"status".to_owned(),
+			Duration::ZERO,
+		)
+		.await;
+
+		assert!(statuses(channel).is_empty());
+		assert!(!BOT.statuses.take(guild, channel, generation), "forgotten");
+	}
+}
@@ -0,0 +1,204 @@
+//! Status of the bot as JSON for external dashboards, written to `STATUS_FILE` every minute and
+//! on shutdown, and shown by `/info`.
+//!
+//! The file is replaced atomically by writing a temporary file next to it and renaming it.
+//! Failing to write it is only logged.
+
+use std::{
+	ffi::OsString,
+	fs, io,
+	path::Path,
+	sync::{atomic::Ordering, PoisonError},
+	time::Duration,
+};
+
+use serde::Serialize;
+use twilight_gateway::ShardState;
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::{clock, totals::Snapshot, BOT, SHUTDOWN};
+
+/// Interval between writes.
+const INTERVAL: Duration = Duration::from_secs(60);
+
+/// Status of the bot.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Status {
+	pub name: &'static str,
+	pub version: &'static str,
+	/// Seconds since the bot started.
+	pub uptime: u64,
+	/// State of the shard, e.g. `active` or `resuming`.
+	pub shard: &'static str,
+	/// Guilds the bot operates in.
+	pub policy: String,
+	pub guild_count: usize,
+	pub guilds: Vec<Guild>,
+	pub totals: Snapshot,
+}
+
+/// Status of a cached guild.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Guild {
+	pub id: Id<GuildMarker>,
+	/// Whether auto pruning is enabled and not paused.
+	pub auto_prune: bool,
+	pub paused: bool,
+	/// Unix timestamp in seconds of the last prune.
+	pub last_prune: Option<u64>,
+}
+
+impl Status {
+	/// Current status of the bot.
+	pub fn collect() -> Self {
+		let now = clock::now().as_secs();
+		let mut guilds: Vec<_> = BOT.cache.iter().guilds().map(|guild| guild.id()).collect();
+		guilds.sort_unstable();
+		let guilds: Vec<_> = guilds
+			.into_iter()
+			.map(|id| Guild {
+				id,
+				auto_prune: BOT.auto_prune(id),
+				paused: BOT.settings.get(id).paused_for().is_some(),
+				last_prune: BOT
+					.history
+					.guild(id)
+					.map(|prune| now.saturating_sub(prune.at.elapsed().as_secs())),
+			})
+			.collect();
+
+		Self {
+			name: env!("CARGO_PKG_NAME"),
+			version: env!("CARGO_PKG_VERSION"),
+			uptime: BOT.started.elapsed().as_secs(),
+			shard: shard_state(*BOT.shard.lock().unwrap_or_else(PoisonError::into_inner)),
+			policy: BOT.policy.to_string(),
+			guild_count: guilds.len(),
+			guilds,
+			totals: BOT.totals.snapshot(),
+		}
+	}
+}
+
+/// Name of the shard's state.
+const fn shard_state(state: ShardState) -> &'static str {
+	match state {
+		ShardState::Active => "active",
+		ShardState::Disconnected { .. } => "disconnected",
+		ShardState::FatallyClosed => "fatally-closed",
+		ShardState::Identifying => "identifying",
+		ShardState::Resuming => "resuming",
+	}
+}
+
+/// Periodically write the status file until shutdown, if enabled.
+pub async fn run() {
+	if BOT.status_file.is_none() {
+		return;
+	}
+
+	let mut interval = tokio::time::interval(INTERVAL);
+	loop {
+		interval.tick().await;
+		if SHUTDOWN.load(Ordering::Relaxed) {
+			return;
+		}
+		write();
+	}
+}
+
+/// Writes the status file, if enabled, logging on error.
+pub fn write() {
+	let Some(path) = &BOT.status_file else {
+		return;
+	};
+	let contents = serde_json::to_vec_pretty(&Status::collect()).expect("serializable");
+	if let Err(e) = replace(path, &contents) {
+		tracing::warn!(path = %path.display(), error = &e as &dyn std::error::Error, "unable to write status file");
+	}
+}
+
+/// Atomically replaces the file's contents.
+fn replace(path: &Path, contents: &[u8]) -> io::Result<()> {
+	let mut temporary = OsString::from(path.as_os_str());
+	temporary.push(".tmp");
+	fs::write(&temporary, contents)?;
+	fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{env, fs};
+
+	use twilight_model::guild::Permissions;
+
+	use super::Status;
+	use crate::test::Guild;
+
+	#[test]
+	fn schema() {
+		const GUILD: u64 = 3_840_000;
+		let guild = Guild::new(GUILD)
+			.everyone(Permissions::VIEW_CHANNEL | Permissions::CONNECT)
+			.create();
+
+		let status = serde_json::to_value(Status::collect()).unwrap();
+		let round_trip: serde_json::Value =
+			serde_json::from_str(&serde_json::to_string(&status).unwrap()).unwrap();
+		assert_eq!(round_trip, status);
+
+		let mut keys: Vec<_> = status.as_object().unwrap().keys().collect();
+		keys.sort_unstable();
+		assert_eq!(
+			keys,
+			[
+				"guild-count",
+				"guilds",
+				"name",
+				"policy",
+				"shard",
+				"totals",
+				"uptime",
+				"version"
+			]
+		);
+		let guilds = status["guilds"].as_array().unwrap();
+		assert_eq!(status["guild-count"], guilds.len());
+		let entry = guilds
+			.iter()
+			.find(|entry| entry["id"] == guild.to_string())
+			.expect("cached guild");
+		assert_eq!(
+			*entry,
+			serde_json::json!({
+				"id": guild.to_string(),
+				"auto-prune": true,
+				"paused": false,
+				"last-prune": null,
+			})
+		);
+	}
+
+	#[test]
+	fn replaces_atomically() {
+		let dir = env::temp_dir().join(format!("voice-pruner-{}-status", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("status.json");
+		fs::write(&path, "old").unwrap();
+
+		super::replace(&path, b"new").unwrap();
+		assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+		let files: Vec<_> = fs::read_dir(&dir)
+			.unwrap()
+			.map(|entry| entry.unwrap().file_name())
+			.collect();
+		assert_eq!(files, ["status.json"], "temporary file renamed");
+
+		// errors are returned to be logged
+		let missing = dir.join("missing").join("status.json");
+		assert!(super::replace(&missing, b"new").is_err());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,84 @@
+//! Service manager notifications through systemd's `NOTIFY_SOCKET`, see
+//! <https://www.freedesktop.org/software/systemd/man/sd_notify.html>.
+//!
+//! Failing to notify is only logged, and nothing is sent when not run by systemd.
+
+use std::{env, ffi::OsStr, io, os::unix::net::UnixDatagram};
+
+/// The bot finished starting up.
+pub const READY: &str = "READY=1";
+
+/// The bot is shutting down.
+pub const STOPPING: &str = "STOPPING=1";
+
+/// Sends the state to the service manager, if any.
+pub fn notify(state: &str) {
+	let Some(socket) = env::var_os("NOTIFY_SOCKET") else {
+		return;
+	};
+	if let Err(e) = send(&socket, state) {
+		tracing::warn!(
+			state,
+			error = &e as &dyn std::error::Error,
+			"unable to notify systemd"
+		);
+	}
+}
+
+/// Sends the state as one datagram to the socket, abstract if prefixed with `@`.
+fn send(socket: &OsStr, state: &str) -> io::Result<()> {
+	let datagram = UnixDatagram::unbound()?;
+	match socket.to_str().and_then(|socket| socket.strip_prefix('@')) {
+		#[cfg(target_os = "linux")]
+		Some(name) => {
+			use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+			let address = SocketAddr::from_abstract_name(name)?;
+			datagram.send_to_addr(state.as_bytes(), &address)?;
+		}
+		_ => {
+			datagram.send_to(state.as_bytes(), socket)?;
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{env, ffi::OsStr, fs, os::unix::net::UnixDatagram};
+
+	/// Datagram received by the socket.
+	fn received(socket: &UnixDatagram) -> String {
+		let mut buf = [0; 64];
+		let len = socket.recv(&mut buf).unwrap();
+		String::from_utf8(buf[..len].to_vec()).unwrap()
+	}
+
+	#[test]
+	fn sends_state() {
+		let path = env::temp_dir().join(format!("voice-pruner-{}-notify", std::process::id()));
+		let _ = fs::remove_file(&path);
+		let socket = UnixDatagram::bind(&path).unwrap();
+
+		super::send(path.as_os_str(), super::READY).unwrap();
+		super::send(path.as_os_str(), super::STOPPING).unwrap();
+
+		assert_eq!(received(&socket), "READY=1");
+		assert_eq!(received(&socket), "STOPPING=1");
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn sends_to_abstract_socket() {
+		use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+		let name = format!("voice-pruner-{}-notify", std::process::id());
+		let address = SocketAddr::from_abstract_name(&name).unwrap();
+		let socket = UnixDatagram::bind_addr(&address).unwrap();
+
+		super::send(OsStr::new(&format!("@{name}")), super::READY).unwrap();
+
+		assert_eq!(received(&socket), "READY=1");
+	}
+}
@@ -0,0 +1,580 @@
+//! Support shared by unit tests: an offline [`BOT`] backed by a fake Discord API, builders of
+//! cached guilds and parsing of gateway payloads.
+//!
+//! Tests share the bot and its cache, so each test uses its own guild, whose ID is a multiple of
+//! 1000, and IDs of its channels, roles and users between it and the next multiple.
+
+use std::{
+	io::{BufRead, BufReader, Read, Write},
+	net::TcpListener,
+	sync::{Mutex, PoisonError},
+	thread,
+	time::Instant,
+};
+
+use serde_json::{json, Value};
+use twilight_gateway::{Shard, ShardId, ShardState};
+use twilight_http::Client;
+use twilight_model::{
+	channel::ChannelType,
+	gateway::{event::Event, payload::incoming::GuildCreate},
+	guild::Permissions,
+	id::{
+		marker::{ChannelMarker, GuildMarker, UserMarker},
+		Id,
+	},
+};
+
+use crate::{BotRef, BOT, INTENTS, RESOURCES};
+
+#[cfg(feature = "heap")]
+pub mod heap;
+
+/// User ID of the bot.
+pub const BOT_ID: u64 = 1;
+
+/// Removal executed by the fake removal workers.
+pub type Removed = (Id<GuildMarker>, Id<UserMarker>, Option<Id<ChannelMarker>>);
+
+/// Every removal executed by the fake removal workers.
+static REMOVED: Mutex<Vec<Removed>> = Mutex::new(Vec::new());
+
+/// Paths of members whose removal the fake Discord API denies, see [`deny`].
+static DENIED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Path of a member and what to run once they are next removed, see [`on_removal`].
+type Hook = (String, fn(Id<GuildMarker>));
+
+/// Every hook run by the fake removal workers.
+static HOOKS: Mutex<Vec<Hook>> = Mutex::new(Vec::new());
+
+/// HTTP request received by the fake Discord API.
+#[derive(Clone, Debug)]
+pub struct Request {
+	pub method: String,
+	/// Path after the API version, e.g. `/interactions/1/token/callback`.
+	pub path: String,
+	pub body: Value,
+}
+
+/// Every request received by the fake Discord API.
+static REQUESTS: Mutex<Vec<Request>> = Mutex::new(Vec::new());
+
+/// Initializes [`BOT`] without connecting to Discord, if not already.
+///
+/// Removals are executed by fake workers, recording them instead, see [`removed`], unless
+/// [`deny`]ed.
+pub fn init() {
+	BOT.0.get_or_init(|| {
+		let (queue, workers) = crate::queue::Queue::from_env().expect("default limits");
+		let (sender, shard) = std::sync::mpsc::channel();
+		// the shard's gateway queue and the workers require a runtime outliving every test's
+		thread::spawn(move || {
+			let runtime = tokio::runtime::Builder::new_current_thread()
+				.enable_all()
+				.build()
+				.expect("runtime");
+			runtime.block_on(async {
+				let shard = Shard::new(ShardId::ONE, String::new(), INTENTS);
+				sender.send(shard.sender()).expect("test thread waiting");
+				workers
+					.fake(|guild, user, target| {
+						if is_denied(&member_path(guild, user)) {
+							return false;
+						}
+						REMOVED
+							.lock()
+							.unwrap_or_else(PoisonError::into_inner)
+							.push((guild, user, target));
+						let path = member_path(guild, user);
+						let mut hooks = Vec::new();
+						HOOKS.lock().unwrap_or_else(PoisonError::into_inner).retain(
+							|&(ref hooked, hook)| {
+								if *hooked == path {
+									hooks.push(hook);
+								}
+								*hooked != path
+							},
+						);
+						for hook in hooks {
+							hook(guild);
+						}
+						true
+					})
+					.await;
+				drop(shard);
+			});
+		});
+
+		BotRef {
+			application_id: Id::new(BOT_ID),
+			attributions: crate::attribution::Attributions::default(),
+			cache: crate::cache::Cache::builder()
+				.resource_types(RESOURCES)
+				.build(),
+			chunks: crate::chunk::Chunks::default(),
+			coalescer: crate::coalesce::Coalescer::default(),
+			commands_registered: true.into(),
+			cooldowns: crate::cooldown::Cooldowns::default(),
+			decisions: crate::decision::Decisions::default(),
+			deafened: crate::idle::Deafened::default(),
+			halts: crate::halt::Halts::default(),
+			history: crate::history::History::default(),
+			http: Client::builder()
+				.proxy(api(), true)
+				.ratelimiter(None)
+				.build(),
+			id: Id::new(BOT_ID),
+			locks: crate::dedup::Locks::default(),
+			monitor_stage: true,
+			onboarding: crate::onboarding::Onboarding::default(),
+			owners: [Id::new(BOT_ID + 1)].into(),
+			pages: crate::commands::components::Pages::default(),
+			policy: crate::policy::GuildPolicy::All,
+			presence: None,
+			proxy: None,
+			queue,
+			readiness: crate::readiness::Readiness::default(),
+			scheduler: crate::schedule::Scheduler::default(),
+			seen: crate::dedup::Seen::default(),
+			serial: crate::serial::Serial::default(),
+			sender: shard.recv().expect("shard created"),
+			settings: crate::settings::Store::load().expect("not persisted"),
+			shard: Mutex::new(ShardState::Identifying),
+			started: Instant::now(),
+			stats: crate::stats::Stats::load().expect("not persisted"),
+			status_file: None,
+			statuses: crate::status::Statuses::default(),
+			throttle: crate::throttle::Throttle::from_env().expect("default limits"),
+			totals: crate::totals::Totals::default(),
+			verify_limit: 10,
+			wizards: crate::commands::setup::Wizards::default(),
+		}
+	});
+}
+
+/// Starts the fake Discord API, returning its address.
+///
+/// Every request succeeds without content, except [`deny`]ed removals failing with Missing
+/// Permissions, and is recorded, see [`requests`].
+fn api() -> String {
+	let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+	let address = listener.local_addr().expect("bound").to_string();
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			let Ok(stream) = stream else {
+				continue;
+			};
+			let mut reader = BufReader::new(stream);
+			let mut line = String::new();
+			if reader.read_line(&mut line).is_err() {
+				continue;
+			}
+			let mut parts = line.split_whitespace();
+			let method = parts.next().unwrap_or_default().to_owned();
+			let path = parts.next().unwrap_or_default();
+			let path = path.strip_prefix("/api/v10").unwrap_or(path).to_owned();
+			let mut length = 0;
+			loop {
+				let mut header = String::new();
+				if reader.read_line(&mut header).unwrap_or_default() == 0 || header == "\r\n" {
+					break;
+				}
+				if let Some((name, value)) = header.split_once(':') {
+					if name.eq_ignore_ascii_case("content-length") {
+						length = value.trim().parse().unwrap_or_default();
+					}
+				}
+			}
+			let mut body = vec![0; length];
+			if reader.read_exact(&mut body).is_err() {
+				continue;
+			}
+			let response = if is_denied(&path) {
+				let body = r#"{"code":50013,"message":"Missing Permissions"}"#;
+				format!(
+					"HTTP/1.1 403 Forbidden\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+					body.len()
+				)
+			} else {
+				"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n".to_owned()
+			};
+			REQUESTS
+				.lock()
+				.unwrap_or_else(PoisonError::into_inner)
+				.push(Request {
+					method,
+					path,
+					body: serde_json::from_slice(&body).unwrap_or_default(),
+				});
+			_ = reader.get_mut().write_all(response.as_bytes());
+		}
+	});
+	address
+}
+
+/// Requests received by the fake Discord API so far whose path contains `path`, e.g. an
+/// interaction's token.
+pub fn requests(path: &str) -> Vec<Request> {
+	REQUESTS
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.iter()
+		.filter(|request| request.path.contains(path))
+		.cloned()
+		.collect()
+}
+
+/// Makes removing the member fail with Missing Permissions, as if the bot lost its permissions.
+pub fn deny(guild: Id<GuildMarker>, user: Id<UserMarker>) {
+	DENIED
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.push(member_path(guild, user));
+}
+
+/// Runs `hook` with the guild once the member is next removed, e.g. to halt the guild mid-prune.
+pub fn on_removal(guild: Id<GuildMarker>, user: Id<UserMarker>, hook: fn(Id<GuildMarker>)) {
+	HOOKS
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.push((member_path(guild, user), hook));
+}
+
+/// Whether requests to the path are [`deny`]ed.
+fn is_denied(path: &str) -> bool {
+	DENIED
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.iter()
+		.any(|denied| denied == path)
+}
+
+/// Path of the member's endpoint.
+pub fn member_path(guild: Id<GuildMarker>, user: Id<UserMarker>) -> String {
+	format!("/guilds/{guild}/members/{user}")
+}
+
+/// Removals of the guild executed so far.
+pub fn removed(guild: Id<GuildMarker>) -> Vec<Removed> {
+	REMOVED
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.iter()
+		.filter(|&&(id, ..)| id == guild)
+		.copied()
+		.collect()
+}
+
+/// Permission overwrite of a role.
+pub fn role_overwrite(role: u64, allow: Permissions, deny: Permissions) -> Value {
+	json!({
+		"id": role.to_string(),
+		"type": 0,
+		"allow": allow.bits().to_string(),
+		"deny": deny.bits().to_string(),
+	})
+}
+
+/// Permission overwrite of a member.
+pub fn member_overwrite(user: u64, allow: Permissions, deny: Permissions) -> Value {
+	json!({
+		"id": user.to_string(),
+		"type": 1,
+		"allow": allow.bits().to_string(),
+		"deny": deny.bits().to_string(),
+	})
+}
+
+/// Guild payload with every field the gateway always sends.
+pub fn guild_payload(id: u64, owner: u64) -> Value {
+	json!({
+		"afk_channel_id": null,
+		"afk_timeout": 300,
+		"application_id": null,
+		"banner": null,
+		"channels": [],
+		"default_message_notifications": 0,
+		"description": null,
+		"discovery_splash": null,
+		"emojis": [],
+		"explicit_content_filter": 0,
+		"features": [],
+		"icon": null,
+		"id": id.to_string(),
+		"large": false,
+		"members": [],
+		"mfa_level": 0,
+		"name": format!("guild {id}"),
+		"nsfw_level": 0,
+		"owner_id": owner.to_string(),
+		"preferred_locale": "en-US",
+		"premium_progress_bar_enabled": false,
+		"public_updates_channel_id": null,
+		"roles": [],
+		"rules_channel_id": null,
+		"splash": null,
+		"system_channel_flags": 0,
+		"system_channel_id": null,
+		"vanity_url_code": null,
+		"verification_level": 0,
+		"voice_states": [],
+	})
+}
+
+/// Role payload.
+pub fn role_payload(id: u64, name: &str, permissions: Permissions, position: i64) -> Value {
+	json!({
+		"color": 0,
+		"hoist": false,
+		"id": id.to_string(),
+		"managed": false,
+		"mentionable": false,
+		"name": name,
+		"permissions": permissions.bits().to_string(),
+		"position": position,
+		"flags": 0,
+	})
+}
+
+/// Channel payload.
+pub fn channel_payload(
+	guild: u64,
+	id: u64,
+	kind: ChannelType,
+	parent: Option<u64>,
+	overwrites: &[Value],
+) -> Value {
+	json!({
+		"guild_id": guild.to_string(),
+		"id": id.to_string(),
+		"name": format!("channel {id}"),
+		"parent_id": parent.map(|parent| parent.to_string()),
+		"permission_overwrites": overwrites,
+		"position": id % 1000,
+		"type": u8::from(kind),
+	})
+}
+
+/// Member payload.
+pub fn member_payload(guild: u64, user: u64, roles: &[u64], bot: bool) -> Value {
+	json!({
+		"deaf": false,
+		"flags": 0,
+		"guild_id": guild.to_string(),
+		"joined_at": "2021-01-01T00:00:00.000000+00:00",
+		"mute": false,
+		"nick": null,
+		"pending": false,
+		"roles": roles.iter().map(ToString::to_string).collect::<Vec<_>>(),
+		"user": {
+			"avatar": null,
+			"bot": bot,
+			"discriminator": "0",
+			"global_name": null,
+			"id": user.to_string(),
+			"username": format!("user {user}"),
+		},
+	})
+}
+
+/// Voice state payload.
+pub fn voice_state_payload(guild: u64, user: u64, channel: u64, self_deaf: bool) -> Value {
+	json!({
+		"channel_id": channel.to_string(),
+		"deaf": false,
+		"guild_id": guild.to_string(),
+		"mute": false,
+		"request_to_speak_timestamp": null,
+		"self_deaf": self_deaf,
+		"self_mute": false,
+		"self_stream": false,
+		"self_video": false,
+		"session_id": format!("session {user}"),
+		"suppress": false,
+		"user_id": user.to_string(),
+	})
+}
+
+/// Guild to cache.
+///
+/// It starts with an `@everyone` role permitting everyone to view and connect to channels, and the
+/// bot as a member whose role, ID `guild + 1` at position 10, permits moving members.
+pub struct Guild {
+	id: u64,
+	payload: Value,
+}
+
+impl Guild {
+	pub fn new(id: u64) -> Self {
+		let mut payload = guild_payload(id, id + 999);
+		payload["roles"] = json!([
+			role_payload(
+				id,
+				"@everyone",
+				Permissions::VIEW_CHANNEL | Permissions::CONNECT,
+				0
+			),
+			role_payload(
+				id + 1,
+				"bot",
+				Permissions::VIEW_CHANNEL | Permissions::CONNECT | Permissions::MOVE_MEMBERS,
+				10
+			),
+		]);
+		payload["members"] = json!([member_payload(id, BOT_ID, &[id + 1], true)]);
+		Self { id, payload }
+	}
+
+	/// Sets the permissions of `@everyone`.
+	pub fn everyone(mut self, permissions: Permissions) -> Self {
+		self.payload["roles"][0]["permissions"] = json!(permissions.bits().to_string());
+		self
+	}
+
+	pub fn voice(self, id: u64, overwrites: &[Value]) -> Self {
+		self.channel(id, ChannelType::GuildVoice, None, overwrites)
+	}
+
+	pub fn channel(
+		mut self,
+		id: u64,
+		kind: ChannelType,
+		parent: Option<u64>,
+		overwrites: &[Value],
+	) -> Self {
+		push(
+			&mut self.payload["channels"],
+			channel_payload(self.id, id, kind, parent, overwrites),
+		);
+		self
+	}
+
+	/// Sets the user limit of the last added channel.
+	pub fn user_limit(mut self, limit: u16) -> Self {
+		last(&mut self.payload["channels"])["user_limit"] = json!(limit);
+		self
+	}
+
+	pub fn member(mut self, user: u64, roles: &[u64]) -> Self {
+		push(
+			&mut self.payload["members"],
+			member_payload(self.id, user, roles, false),
+		);
+		self
+	}
+
+	/// Connects the user to the voice channel.
+	pub fn connect(mut self, user: u64, channel: u64) -> Self {
+		push(
+			&mut self.payload["voice_states"],
+			voice_state_payload(self.id, user, channel, false),
+		);
+		self
+	}
+
+	pub fn owner(mut self, user: u64) -> Self {
+		self.payload["owner_id"] = json!(user.to_string());
+		self
+	}
+
+	pub fn role(mut self, id: u64, permissions: Permissions, position: i64) -> Self {
+		push(
+			&mut self.payload["roles"],
+			role_payload(id, &format!("role {id}"), permissions, position),
+		);
+		self
+	}
+
+	pub fn category(mut self, id: u64) -> Self {
+		push(
+			&mut self.payload["channels"],
+			channel_payload(self.id, id, ChannelType::GuildCategory, None, &[]),
+		);
+		self
+	}
+
+	/// Marks the last added member as pending membership screening.
+	pub fn pending(mut self) -> Self {
+		last(&mut self.payload["members"])["pending"] = json!(true);
+		self
+	}
+
+	/// Sets the nickname of the last added member.
+	pub fn nick(mut self, nick: &str) -> Self {
+		last(&mut self.payload["members"])["nick"] = json!(nick);
+		self
+	}
+
+	/// Marks the last connected user as streaming.
+	pub fn streaming(mut self) -> Self {
+		last(&mut self.payload["voice_states"])["self_stream"] = json!(true);
+		self
+	}
+
+	/// Marks the last connected user as server muted.
+	pub fn muted(mut self) -> Self {
+		last(&mut self.payload["voice_states"])["mute"] = json!(true);
+		self
+	}
+
+	/// Marks the last connected user as server deafened.
+	pub fn deafened(mut self) -> Self {
+		last(&mut self.payload["voice_states"])["deaf"] = json!(true);
+		self
+	}
+
+	/// Marks the last connected user as suppressed.
+	pub fn suppressed(mut self) -> Self {
+		last(&mut self.payload["voice_states"])["suppress"] = json!(true);
+		self
+	}
+
+	pub fn afk(mut self, channel: u64) -> Self {
+		self.payload["afk_channel_id"] = json!(channel.to_string());
+		self
+	}
+
+	pub fn bot(mut self, user: u64) -> Self {
+		push(
+			&mut self.payload["members"],
+			member_payload(self.id, user, &[], true),
+		);
+		self
+	}
+
+	/// The guild create event of the guild.
+	pub fn event(self) -> Event {
+		Event::GuildCreate(Box::new(GuildCreate(
+			serde_json::from_value(self.payload).expect("valid guild"),
+		)))
+	}
+
+	/// Caches the guild, initializing [`BOT`] if needed.
+	pub fn create(self) -> Id<GuildMarker> {
+		init();
+		let id = Id::new(self.id);
+		BOT.cache.update(&self.event());
+		id
+	}
+}
+
+fn push(array: &mut Value, value: Value) {
+	array.as_array_mut().expect("array").push(value);
+}
+
+fn last(array: &mut Value) -> &mut Value {
+	array
+		.as_array_mut()
+		.expect("array")
+		.last_mut()
+		.expect("not empty")
+}
+
+/// Parses a gateway dispatch payload, i.e. `{"op": 0, "t": .., "d": ..}`, into its event.
+pub fn parse(payload: &str) -> Event {
+	twilight_gateway::parse(payload.to_owned(), twilight_gateway::EventTypeFlags::all())
+		.expect("valid payload")
+		.expect("wanted event")
+		.into()
+}
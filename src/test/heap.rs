@@ -0,0 +1,44 @@
+//! Heap usage of the whole process, measured by replacing the global allocator.
+//!
+//! Only built with the `heap` feature, as measurements are skewed by tests running in parallel:
+//! `cargo test --release --features heap large_guild_footprint`.
+
+use std::{
+	alloc::{GlobalAlloc, Layout, System},
+	sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting(AtomicUsize::new(0));
+
+/// System allocator counting the bytes in use.
+struct Counting(AtomicUsize);
+
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		let ptr = System.alloc(layout);
+		if !ptr.is_null() {
+			self.0.fetch_add(layout.size(), Ordering::Relaxed);
+		}
+		ptr
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout);
+		self.0.fetch_sub(layout.size(), Ordering::Relaxed);
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		let new = System.realloc(ptr, layout, new_size);
+		if !new.is_null() {
+			self.0.fetch_add(new_size, Ordering::Relaxed);
+			self.0.fetch_sub(layout.size(), Ordering::Relaxed);
+		}
+		new
+	}
+}
+
+/// Heap bytes in use by the whole process, so only meaningful while no other test runs.
+pub fn allocated() -> usize {
+	ALLOCATOR.0.load(Ordering::Relaxed)
+}
@@ -0,0 +1,230 @@
+//! Per-guild rate limiting of auto prunes, configured through the `AUTO_PRUNE_LIMIT` and
+//! `AUTO_PRUNE_WINDOW` (seconds) environment variables.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	env,
+	future::Future,
+	sync::{Mutex, PoisonError},
+	time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::{cleanup::Cleanup, prune::Trigger, BOT};
+
+/// Recent auto prunes of a guild.
+#[derive(Debug, Default)]
+struct Bucket {
+	/// Start of the auto prunes within the window, oldest first.
+	runs: VecDeque<Instant>,
+	/// Whether a trailing prune is scheduled.
+	trailing: bool,
+}
+
+/// Outcome of [`Throttle::acquire`].
+enum Acquire {
+	/// Run the auto prune.
+	Run,
+	/// Throttled, run a trailing guild prune after the delay instead.
+	Trailing(Duration),
+	/// Throttled, collapsed into the scheduled trailing prune.
+	Collapsed,
+}
+
+/// Sliding window limiter of auto prunes per guild.
+#[derive(Debug)]
+pub struct Throttle {
+	guilds: Mutex<HashMap<Id<GuildMarker>, Bucket>>,
+	/// Maximum number of auto prunes per window.
+	limit: usize,
+	window: Duration,
+}
+
+impl Throttle {
+	/// Parses the limits from the environment, defaulting to 5 auto prunes per 60 seconds.
+	pub fn from_env() -> Result<Self, anyhow::Error> {
+		let limit = env::var("AUTO_PRUNE_LIMIT")
+			.map_or(Ok(5), |var| var.parse())
+			.context("\"AUTO_PRUNE_LIMIT\" must be a positive integer")?;
+		let window = env::var("AUTO_PRUNE_WINDOW")
+			.map_or(Ok(60), |var| var.parse())
+			.context("\"AUTO_PRUNE_WINDOW\" must be a number of seconds")?;
+		anyhow::ensure!(
+			limit != 0,
+			"\"AUTO_PRUNE_LIMIT\" must be a positive integer"
+		);
+
+		Ok(Self {
+			guilds: Mutex::default(),
+			limit,
+			window: Duration::from_secs(window),
+		})
+	}
+
+	/// Counts an auto prune of the guild towards the limit, unless throttled.
+	fn acquire(&self, guild: Id<GuildMarker>) -> Acquire {
+		let now = Instant::now();
+		let mut guilds = self.guilds.lock().unwrap_or_else(PoisonError::into_inner);
+		let bucket = guilds.entry(guild).or_default();
+		while bucket
+			.runs
+			.front()
+			.is_some_and(|&run| now.duration_since(run) >= self.window)
+		{
+			bucket.runs.pop_front();
+		}
+
+		if bucket.trailing {
+			Acquire::Collapsed
+		} else if bucket.runs.len() < self.limit {
+			bucket.runs.push_back(now);
+			Acquire::Run
+		} else {
+			bucket.trailing = true;
+			Acquire::Trailing(self.window - now.duration_since(bucket.runs[0]))
+		}
+	}
+
+	/// Marks the scheduled trailing prune as started.
+	fn start_trailing(&self, guild: Id<GuildMarker>) {
+		let mut guilds = self.guilds.lock().unwrap_or_else(PoisonError::into_inner);
+		let bucket = guilds.entry(guild).or_default();
+		bucket.trailing = false;
+		bucket.runs.push_back(Instant::now());
+	}
+}
+
+impl Cleanup for Throttle {
+	fn guild(&self, guild: Id<GuildMarker>) {
+		self.guilds
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&guild);
+	}
+}
+
+/// Runs the auto prune unless throttled.
+///
+/// The first throttled auto prune in a window is replaced with a guild prune once the window frees
+/// up, into which the following throttled auto prunes are collapsed.
+pub async fn auto_prune(guild: Id<GuildMarker>, trigger: Trigger, prune: impl Future<Output = ()>) {
+	match BOT.throttle.acquire(guild) {
+		Acquire::Run => prune.await,
+		Acquire::Trailing(delay) => {
+			tracing::warn!(guild_id = %guild, %trigger, "throttling auto prunes");
+			// waiting must not hold up the handling of the guild's following events
+			tokio::spawn(trailing(guild, trigger, delay));
+		}
+		Acquire::Collapsed => tracing::debug!(guild_id = %guild, %trigger, "throttled"),
+	}
+}
+
+/// Runs the trailing guild prune after the delay, unless the guild was halted or auto pruning
+/// disabled meanwhile.
+async fn trailing(guild: Id<GuildMarker>, trigger: Trigger, delay: Duration) {
+	let halt = BOT.halts.token(guild);
+	tokio::time::sleep(delay).await;
+	BOT.throttle.start_trailing(guild);
+	if halt.is_cancelled() {
+		tracing::info!(guild_id = %guild, %trigger, "skipping trailing prune: guild halted");
+		return;
+	}
+	// auto pruning may have been paused or disabled while waiting
+	if !BOT.auto_prune(guild) {
+		tracing::info!(guild_id = %guild, %trigger, "skipping trailing prune: auto pruning disabled");
+		return;
+	}
+	let settings = BOT.settings.get(guild);
+	let channels = settings.auto_channels();
+	if let Err(e) = crate::prune::guild(guild, trigger, channels, |_| true, None).await {
+		tracing::warn!(guild_id = %guild, error = &e as &dyn std::error::Error);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	use futures_util::future::join_all;
+	use twilight_model::guild::Permissions;
+
+	use super::*;
+	use crate::test::Guild;
+
+	/// Guild whose only connected user lacks permission to connect, so each guild prune removes
+	/// them once.
+	fn guild(id: u64) -> Id<GuildMarker> {
+		Guild::new(id)
+			.everyone(Permissions::VIEW_CHANNEL)
+			.voice(id + 10, &[])
+			.member(id + 100, &[])
+			.connect(id + 100, id + 10)
+			.create()
+	}
+
+	/// Runs `count` auto prunes of the guild at once, returning how many ran immediately.
+	async fn burst(guild: Id<GuildMarker>, count: usize) -> usize {
+		let immediate = AtomicUsize::new(0);
+		join_all((0..count).map(|_| {
+			auto_prune(guild, Trigger::Role, async {
+				immediate.fetch_add(1, Ordering::Relaxed);
+			})
+		}))
+		.await;
+		immediate.into_inner()
+	}
+
+	/// Waits for the window to pass and the spawned trailing prune to remove users, if it does.
+	async fn trailing_prune(guild: Id<GuildMarker>) -> usize {
+		tokio::time::sleep(BOT.throttle.window * 2).await;
+		// removals are executed on another runtime, whose reply the paused clock doesn't wait for
+		for _ in 0..1000 {
+			if !crate::test::removed(guild).is_empty() {
+				break;
+			}
+			tokio::time::sleep(Duration::from_millis(1)).await;
+		}
+		crate::test::removed(guild).len()
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn burst_runs_limit_then_one_trailing_prune() {
+		let guild = guild(3270000);
+
+		assert_eq!(burst(guild, 20).await, BOT.throttle.limit);
+		// the trailing guild prune, replacing every throttled auto prune
+		assert_eq!(trailing_prune(guild).await, 1);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn trailing_prune_skipped_once_paused() {
+		let guild = guild(3271000);
+
+		let (immediate, ()) = tokio::join!(burst(guild, 20), async {
+			// the trailing prune is waiting for the window
+			BOT.settings
+				.update(guild, |settings| settings.pause(Duration::from_secs(3600)));
+		});
+
+		assert_eq!(immediate, BOT.throttle.limit);
+		assert_eq!(trailing_prune(guild).await, 0);
+	}
+
+	#[test]
+	fn acquire_collapses_into_trailing_prune() {
+		let throttle = Throttle {
+			guilds: Mutex::default(),
+			limit: 1,
+			window: Duration::from_secs(60),
+		};
+		let guild = Id::new(3272000);
+
+		assert!(matches!(throttle.acquire(guild), Acquire::Run));
+		assert!(matches!(throttle.acquire(guild), Acquire::Trailing(_)));
+		assert!(matches!(throttle.acquire(guild), Acquire::Collapsed));
+		throttle.start_trailing(guild);
+		assert!(matches!(throttle.acquire(guild), Acquire::Trailing(_)));
+	}
+}
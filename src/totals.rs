@@ -0,0 +1,116 @@
+//! Totals since the bot started, logged on shutdown and shown by `/info` and the status file.
+
+use std::{
+	fmt,
+	sync::atomic::{AtomicU64, Ordering},
+};
+
+use serde::Serialize;
+
+/// Counters of the bot's activity.
+#[derive(Debug, Default)]
+pub struct Totals {
+	events: AtomicU64,
+	prunes: AtomicU64,
+	users: AtomicU64,
+	commands: AtomicU64,
+	http_failures: AtomicU64,
+}
+
+/// Values of [`Totals`] at one point in time.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Snapshot {
+	/// Gateway events handled.
+	pub events: u64,
+	/// Prunes finished, including those in report mode.
+	pub prunes: u64,
+	/// Users removed.
+	pub users: u64,
+	/// Commands invoked.
+	pub commands: u64,
+	/// Failed requests to Discord, by commands or removals.
+	pub http_failures: u64,
+}
+
+impl Totals {
+	pub fn event(&self) {
+		self.events.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Counts a finished prune that removed the users.
+	pub fn prune(&self, users: u16) {
+		self.prunes.fetch_add(1, Ordering::Relaxed);
+		self.users.fetch_add(u64::from(users), Ordering::Relaxed);
+	}
+
+	pub fn command(&self) {
+		self.commands.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn http_failure(&self) {
+		self.http_failures.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn snapshot(&self) -> Snapshot {
+		Snapshot {
+			events: self.events.load(Ordering::Relaxed),
+			prunes: self.prunes.load(Ordering::Relaxed),
+			users: self.users.load(Ordering::Relaxed),
+			commands: self.commands.load(Ordering::Relaxed),
+			http_failures: self.http_failures.load(Ordering::Relaxed),
+		}
+	}
+}
+
+impl fmt::Display for Snapshot {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{} events, {} prunes removing {} users, {} commands, {} failed requests",
+			self.events, self.prunes, self.users, self.commands, self.http_failures
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Totals;
+
+	#[test]
+	fn counts() {
+		let totals = Totals::default();
+
+		totals.event();
+		totals.event();
+		totals.prune(3);
+		totals.prune(0);
+		totals.command();
+		totals.http_failure();
+
+		let snapshot = totals.snapshot();
+		assert_eq!(
+			(
+				snapshot.events,
+				snapshot.prunes,
+				snapshot.users,
+				snapshot.commands,
+				snapshot.http_failures
+			),
+			(2, 2, 3, 1, 1)
+		);
+		assert_eq!(
+			snapshot.to_string(),
+			"2 events, 2 prunes removing 3 users, 1 commands, 1 failed requests"
+		);
+	}
+
+	#[test]
+	fn serializes_kebab_case() {
+		let totals = Totals::default();
+		totals.http_failure();
+
+		let value = serde_json::to_value(totals.snapshot()).unwrap();
+		assert_eq!(value["http-failures"], 1);
+	}
+}
@@ -0,0 +1,366 @@
+//! Lookups of a voice state's member and channel, shared by commands and auto prunes.
+
+use twilight_cache_inmemory::model::CachedVoiceState;
+use twilight_model::{
+	channel::permission_overwrite::PermissionOverwriteType,
+	guild::Permissions,
+	id::{
+		marker::{ChannelMarker, GenericMarker, GuildMarker, RoleMarker, UserMarker},
+		Id,
+	},
+};
+
+use crate::{cache::Cache, settings::Settings};
+
+/// Whether a user has a permission.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PermissionCheck {
+	Permitted,
+	NotPermitted,
+	/// The cache lacks the data to tell, so the user must not be removed.
+	Unknown,
+}
+
+impl PermissionCheck {
+	/// Whether the user is known to not be permitted, and may be removed.
+	pub fn is_denied(self) -> bool {
+		self == Self::NotPermitted
+	}
+}
+
+/// Whether the user has the permission in the channel.
+///
+/// Without the data to calculate the permissions, e.g. because the member is not cached, only an
+/// overwrite of the channel explicitly allowing the user is conclusive. The guild owner and
+/// administrators are always permitted.
+pub fn check(
+	cache: &Cache,
+	user: Id<UserMarker>,
+	channel: Id<ChannelMarker>,
+	permission: Permissions,
+) -> PermissionCheck {
+	let guild = cache.channel(channel).and_then(|channel| channel.guild_id);
+	if guild.is_some_and(|guild| is_administrator(cache, guild, user)) {
+		return PermissionCheck::Permitted;
+	}
+	match cache.permissions().in_channel(user, channel) {
+		Ok(permissions) if permissions.contains(permission) => PermissionCheck::Permitted,
+		Ok(_) => PermissionCheck::NotPermitted,
+		Err(e) => {
+			if allowed_by_overwrite(cache, user, channel, permission) {
+				tracing::debug!(user_id = %user, channel_id = %channel, kind = ?e.kind(), "permitted by member overwrite");
+				return PermissionCheck::Permitted;
+			}
+			tracing::debug!(user_id = %user, channel_id = %channel, kind = ?e.kind(), "unable to calculate permissions");
+			PermissionCheck::Unknown
+		}
+	}
+}
+
+/// Whether the user is permitted to connect to the channel of a guild with the settings.
+///
+/// Pending members of guilds pruning them are never permitted, see [`check`] otherwise.
+pub fn can_connect(
+	cache: &Cache,
+	settings: &Settings,
+	user: Id<UserMarker>,
+	channel: Id<ChannelMarker>,
+) -> PermissionCheck {
+	let pending = settings.prune_pending
+		&& cache
+			.channel(channel)
+			.and_then(|channel| channel.guild_id)
+			.and_then(|guild| cache.member(guild, user))
+			.is_some_and(|member| member.pending());
+	if pending {
+		return PermissionCheck::NotPermitted;
+	}
+	check(cache, user, channel, Permissions::CONNECT)
+}
+
+/// Whether the user owns the guild.
+pub fn is_owner(cache: &Cache, guild: Id<GuildMarker>, user: Id<UserMarker>) -> bool {
+	cache
+		.guild(guild)
+		.is_some_and(|guild| guild.owner_id() == user)
+}
+
+/// Whether the user owns the guild or has the administrator permission, bypassing channel
+/// overwrites.
+fn is_administrator(cache: &Cache, guild: Id<GuildMarker>, user: Id<UserMarker>) -> bool {
+	is_owner(cache, guild, user)
+		|| cache
+			.permissions()
+			.root(user, guild)
+			.is_ok_and(|root| root.contains(Permissions::ADMINISTRATOR))
+}
+
+/// Whether the channel has an overwrite of the user allowing the permission.
+fn allowed_by_overwrite(
+	cache: &Cache,
+	user: Id<UserMarker>,
+	channel: Id<ChannelMarker>,
+	permission: Permissions,
+) -> bool {
+	cache.channel(channel).is_some_and(|channel| {
+		channel
+			.permission_overwrites
+			.iter()
+			.flatten()
+			.any(|overwrite| {
+				overwrite.kind == PermissionOverwriteType::Member
+					&& overwrite.id == user.cast()
+					&& overwrite.allow.contains(permission)
+			})
+	})
+}
+
+/// Cached data of the user connected through a voice state.
+pub trait VoiceStateExt {
+	/// Whether the user has the permission in their voice channel, see [`check`].
+	fn is_permitted(&self, cache: &Cache, permission: Permissions) -> PermissionCheck;
+
+	/// Whether the user is known to not be permitted to connect to their voice channel, in a
+	/// guild with the settings.
+	fn is_denied(&self, cache: &Cache, settings: &Settings) -> bool;
+
+	/// Whether the user is streaming.
+	fn is_streaming(&self) -> bool;
+
+	/// Whether the user is suppressed, i.e. not a speaker of a stage.
+	fn is_suppressed(&self) -> bool;
+
+	/// Whether the user has the role, `false` if their member is not cached.
+	fn member_has_role(&self, cache: &Cache, role: Id<RoleMarker>) -> bool;
+
+	/// Whether the targets include the user or one of their roles.
+	fn is_target(&self, cache: &Cache, targets: &[Id<GenericMarker>]) -> bool;
+
+	/// Whether the user's voice channel is in the category.
+	fn in_category(&self, cache: &Cache, category: Id<ChannelMarker>) -> bool;
+
+	/// Nickname, global name or username of the user, their ID if not cached.
+	fn display_name(&self, cache: &Cache) -> String;
+}
+
+impl VoiceStateExt for CachedVoiceState {
+	fn is_permitted(&self, cache: &Cache, permission: Permissions) -> PermissionCheck {
+		check(cache, self.user_id(), self.channel_id(), permission)
+	}
+
+	fn is_denied(&self, cache: &Cache, settings: &Settings) -> bool {
+		can_connect(cache, settings, self.user_id(), self.channel_id()).is_denied()
+	}
+
+	fn is_streaming(&self) -> bool {
+		self.self_stream()
+	}
+
+	fn is_suppressed(&self) -> bool {
+		self.suppress()
+	}
+
+	fn member_has_role(&self, cache: &Cache, role: Id<RoleMarker>) -> bool {
+		cache
+			.member(self.guild_id(), self.user_id())
+			.is_some_and(|member| member.roles().contains(&role))
+	}
+
+	fn is_target(&self, cache: &Cache, targets: &[Id<GenericMarker>]) -> bool {
+		targets.contains(&self.user_id().cast())
+			|| cache
+				.member(self.guild_id(), self.user_id())
+				.is_some_and(|member| {
+					member
+						.roles()
+						.iter()
+						.any(|&role| targets.contains(&role.cast()))
+				})
+	}
+
+	fn in_category(&self, cache: &Cache, category: Id<ChannelMarker>) -> bool {
+		cache
+			.channel(self.channel_id())
+			.is_some_and(|channel| channel.parent_id == Some(category))
+	}
+
+	fn display_name(&self, cache: &Cache) -> String {
+		let nick = cache
+			.member(self.guild_id(), self.user_id())
+			.and_then(|member| member.nick().map(ToOwned::to_owned));
+		nick.or_else(|| {
+			cache.user(self.user_id()).map(|user| {
+				user.global_name
+					.clone()
+					.unwrap_or_else(|| user.name.clone())
+			})
+		})
+		.unwrap_or_else(|| self.user_id().to_string())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test::{member_overwrite, role_overwrite, Guild};
+
+	const GUILD: u64 = 4080000;
+	const SPEAKER: u64 = GUILD + 2;
+	const ADMIN: u64 = GUILD + 3;
+	const CATEGORY: u64 = GUILD + 5;
+	/// Voice channel in the category everyone may connect to.
+	const OPEN: u64 = GUILD + 10;
+	/// Voice channel nobody may connect to, except through a member overwrite.
+	const LOCKED: u64 = GUILD + 11;
+
+	/// Streaming member with the speaker role and a nickname, in the open channel.
+	const MEMBER: u64 = GUILD + 100;
+	/// Member without roles, in the locked channel.
+	const PLAIN: u64 = GUILD + 101;
+	const ADMINISTRATOR: u64 = GUILD + 102;
+	const OWNER: u64 = GUILD + 103;
+	/// Suppressed member pending membership screening, in the open channel.
+	const PENDING: u64 = GUILD + 104;
+	/// User without a cached member, in the open channel.
+	const UNCACHED: u64 = GUILD + 105;
+	/// User without a cached member, allowed by a member overwrite of the locked channel.
+	const OVERWRITTEN: u64 = GUILD + 106;
+
+	/// Cache of a synthetic guild, independent of the bot's.
+	fn cache() -> Cache {
+		let cache = Cache::builder().resource_types(crate::RESOURCES).build();
+		let guild = Guild::new(GUILD)
+			.owner(OWNER)
+			.role(SPEAKER, Permissions::empty(), 1)
+			.role(ADMIN, Permissions::ADMINISTRATOR, 2)
+			.category(CATEGORY)
+			.channel(
+				OPEN,
+				twilight_model::channel::ChannelType::GuildVoice,
+				Some(CATEGORY),
+				&[],
+			)
+			.voice(
+				LOCKED,
+				&[
+					role_overwrite(GUILD, Permissions::empty(), Permissions::CONNECT),
+					member_overwrite(OVERWRITTEN, Permissions::CONNECT, Permissions::empty()),
+				],
+			)
+			.member(MEMBER, &[SPEAKER])
+			.nick("Nick")
+			.member(PLAIN, &[])
+			.member(ADMINISTRATOR, &[ADMIN])
+			.member(OWNER, &[])
+			.member(PENDING, &[])
+			.pending()
+			.connect(MEMBER, OPEN)
+			.streaming()
+			.connect(PLAIN, LOCKED)
+			.connect(ADMINISTRATOR, LOCKED)
+			.connect(OWNER, LOCKED)
+			.connect(PENDING, OPEN)
+			.suppressed()
+			.connect(UNCACHED, OPEN)
+			.connect(OVERWRITTEN, LOCKED);
+		cache.update(&guild.event());
+		cache
+	}
+
+	fn state(cache: &Cache, user: u64) -> CachedVoiceState {
+		cache
+			.voice_state(Id::new(user), Id::new(GUILD))
+			.expect("connected")
+			.clone()
+	}
+
+	#[test]
+	fn is_permitted() {
+		let cache = cache();
+		let check = |user| state(&cache, user).is_permitted(&cache, Permissions::CONNECT);
+
+		assert_eq!(check(MEMBER), PermissionCheck::Permitted);
+		assert_eq!(check(PLAIN), PermissionCheck::NotPermitted);
+		assert_eq!(check(ADMINISTRATOR), PermissionCheck::Permitted);
+		assert_eq!(check(OWNER), PermissionCheck::Permitted);
+		assert_eq!(check(UNCACHED), PermissionCheck::Unknown);
+		assert_eq!(check(OVERWRITTEN), PermissionCheck::Permitted);
+		assert_eq!(
+			state(&cache, MEMBER).is_permitted(&cache, Permissions::MOVE_MEMBERS),
+			PermissionCheck::NotPermitted
+		);
+	}
+
+	#[test]
+	fn is_denied() {
+		let cache = cache();
+		let prune_pending = Settings {
+			prune_pending: true,
+			..Settings::default()
+		};
+
+		assert!(state(&cache, PLAIN).is_denied(&cache, &Settings::default()));
+		assert!(!state(&cache, MEMBER).is_denied(&cache, &Settings::default()));
+		// unknown permissions never deny
+		assert!(!state(&cache, UNCACHED).is_denied(&cache, &Settings::default()));
+		assert!(!state(&cache, PENDING).is_denied(&cache, &Settings::default()));
+		assert!(state(&cache, PENDING).is_denied(&cache, &prune_pending));
+	}
+
+	#[test]
+	fn is_streaming() {
+		let cache = cache();
+
+		assert!(state(&cache, MEMBER).is_streaming());
+		assert!(!state(&cache, PLAIN).is_streaming());
+	}
+
+	#[test]
+	fn is_suppressed() {
+		let cache = cache();
+
+		assert!(state(&cache, PENDING).is_suppressed());
+		assert!(!state(&cache, MEMBER).is_suppressed());
+	}
+
+	#[test]
+	fn member_has_role() {
+		let cache = cache();
+
+		assert!(state(&cache, MEMBER).member_has_role(&cache, Id::new(SPEAKER)));
+		assert!(!state(&cache, PLAIN).member_has_role(&cache, Id::new(SPEAKER)));
+		assert!(!state(&cache, UNCACHED).member_has_role(&cache, Id::new(SPEAKER)));
+	}
+
+	#[test]
+	fn is_target() {
+		let cache = cache();
+
+		assert!(state(&cache, MEMBER).is_target(&cache, &[Id::new(SPEAKER)]));
+		assert!(state(&cache, PLAIN).is_target(&cache, &[Id::new(PLAIN)]));
+		assert!(!state(&cache, PLAIN).is_target(&cache, &[Id::new(SPEAKER), Id::new(MEMBER)]));
+	}
+
+	#[test]
+	fn in_category() {
+		let cache = cache();
+
+		assert!(state(&cache, MEMBER).in_category(&cache, Id::new(CATEGORY)));
+		assert!(!state(&cache, PLAIN).in_category(&cache, Id::new(CATEGORY)));
+	}
+
+	#[test]
+	fn display_name() {
+		let cache = cache();
+
+		assert_eq!(state(&cache, MEMBER).display_name(&cache), "Nick");
+		assert_eq!(
+			state(&cache, PLAIN).display_name(&cache),
+			format!("user {PLAIN}")
+		);
+		assert_eq!(
+			state(&cache, UNCACHED).display_name(&cache),
+			UNCACHED.to_string()
+		);
+	}
+}